@@ -0,0 +1,1247 @@
+//! Bake terrain and object lightmaps of a map with a CPU ray tracer
+//!
+//! Map blocks are placed in client world coordinates: block `X_Y` starts at
+//! `(X * block_size, (65 - Y) * block_size)` with rows going south and IFO
+//! object positions are offset by 520000 on both axes.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use failure::{bail, Error};
+use image::{Rgba, RgbaImage};
+
+use roselib::files::ifo::ObjectData;
+use roselib::files::lit::{LightmapObject, LightmapPart};
+use roselib::files::{HIM, IFO, LIT, ZMS, ZSC};
+use roselib::io::RoseFile;
+use roselib::terrain::BLOCK_ROWS;
+use roselib::utils::{Color3, Matrix4, Quaternion, Vector2, Vector3};
+
+use crate::lightmap::repack_lightmaps;
+use crate::sprites::resolve_path;
+
+/// Offset of IFO object positions in world coordinates
+const OBJECT_OFFSET: f32 = 520000.0;
+
+/// Distance rays start away from the surface to avoid hitting it
+const RAY_BIAS: f32 = 2.0;
+
+/// Triangles per BVH leaf
+const LEAF_SIZE: usize = 4;
+
+/// Texels grown around rasterized lightmap islands to hide seams
+const DILATE_PASSES: usize = 4;
+
+/// Lighting settings of a bake
+#[derive(Clone, Debug)]
+pub struct BakeOptions {
+    /// Direction the sunlight travels in, pointing down for daylight
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: Color3,
+    pub ambient_color: Color3,
+
+    /// Hemisphere rays per texel for ambient occlusion, 0 disables it
+    pub ao_samples: u32,
+
+    /// Occluders further away than this do not darken a texel
+    pub ao_distance: f32,
+
+    /// Distance between heightmap vertices (`Zone::grid_size`)
+    pub grid_size: f32,
+
+    /// Width and height of the lightmap of each terrain block
+    pub terrain_size: u32,
+
+    /// Object lightmap texels per meter (100 world units)
+    pub texel_density: f32,
+    pub min_part_size: u32,
+    pub max_part_size: u32,
+    pub atlas_size: u32,
+
+    pub threads: usize,
+}
+
+impl Default for BakeOptions {
+    fn default() -> BakeOptions {
+        BakeOptions {
+            sun_direction: Vector3 {
+                x: -0.5,
+                y: 0.5,
+                z: -1.0,
+            },
+            sun_color: Color3::rgb(0.8, 0.8, 0.75),
+            ambient_color: Color3::rgb(0.35, 0.35, 0.4),
+            ao_samples: 16,
+            ao_distance: 1000.0,
+            grid_size: 250.0,
+            terrain_size: 256,
+            texel_density: 4.0,
+            min_part_size: 8,
+            max_part_size: 128,
+            atlas_size: 512,
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+/// A ZSC with its meshes, `None` for meshes that could not be loaded
+#[derive(Debug, Default)]
+pub struct BakeScene {
+    pub scene: ZSC,
+    pub meshes: Vec<Option<ZMS>>,
+}
+
+impl BakeScene {
+    /// Load a ZSC and the meshes it references from the data directory
+    pub fn load(path: &Path, data_dir: &Path) -> Result<BakeScene, Error> {
+        let scene = ZSC::from_path(path)?;
+
+        let mut meshes = Vec::new();
+        for mesh_path in scene.meshes.iter() {
+            let mesh = match resolve_path(mesh_path, &[data_dir]) {
+                Some(p) => Some(ZMS::from_path(&p)?),
+                None => None,
+            };
+            meshes.push(mesh);
+        }
+
+        Ok(BakeScene { scene, meshes })
+    }
+}
+
+/// Terrain and objects of one map block
+#[derive(Debug)]
+pub struct BakeBlock {
+    pub x: i32,
+    pub y: i32,
+    pub heightmap: HIM,
+    pub objects: Option<IFO>,
+}
+
+/// Load every `X_Y.HIM` of a map directory with its `X_Y.IFO`
+pub fn load_map(map_dir: &Path) -> Result<Vec<BakeBlock>, Error> {
+    let mut blocks = Vec::new();
+    for entry in fs::read_dir(map_dir)? {
+        let path = entry?.path();
+        let is_him = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("him"))
+            .unwrap_or(false);
+        if !is_him {
+            continue;
+        }
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let coords: Vec<&str> = stem.split('_').collect();
+        let (x, y) = match coords.as_slice() {
+            [x, y] => (x.parse()?, y.parse()?),
+            _ => bail!("Expected a heightmap named X_Y.HIM: {}", path.display()),
+        };
+
+        let ifo_name = PathBuf::from(format!("{}.IFO", stem));
+        let objects = match resolve_path(&ifo_name, &[map_dir]) {
+            Some(p) => Some(IFO::from_path(&p)?),
+            None => None,
+        };
+
+        blocks.push(BakeBlock {
+            x,
+            y,
+            heightmap: HIM::from_path(&path)?,
+            objects,
+        });
+    }
+
+    blocks.sort_by_key(|b| (b.y, b.x));
+    Ok(blocks)
+}
+
+/// Baked lightmaps of one map block
+#[derive(Debug)]
+pub struct BakedBlock {
+    pub x: i32,
+    pub y: i32,
+
+    /// Terrain lightmap, columns going east and rows going south
+    pub terrain: RgbaImage,
+
+    pub objects: LIT,
+    pub object_atlases: Vec<(String, RgbaImage)>,
+    pub buildings: LIT,
+    pub building_atlases: Vec<(String, RgbaImage)>,
+}
+
+impl BakedBlock {
+    /// Path of the terrain lightmap relative to the map directory
+    pub fn terrain_path(&self) -> PathBuf {
+        let name = format!("{}_{}", self.x, self.y);
+        Path::new(&name).join(format!("{}_PLANELIGHTINGMAP.DDS", name))
+    }
+
+    /// Directory of the object LIT files and atlases relative to the map directory
+    pub fn lightmap_dir(&self) -> PathBuf {
+        Path::new(&format!("{}_{}", self.x, self.y)).join("LIGHTMAP")
+    }
+}
+
+/// Bake the terrain lightmap of every block and the object lightmap of every
+/// IFO object and building part with `use_lightmap` set.
+///
+/// All terrain and object parts cast shadows. Object LITs use the 1-based
+/// index of the object in the IFO as id and the ZSC part index as part id.
+pub fn bake_map(
+    blocks: &[BakeBlock],
+    decorations: &BakeScene,
+    constructions: &BakeScene,
+    options: &BakeOptions,
+) -> Result<Vec<BakedBlock>, Error> {
+    if !options.terrain_size.is_power_of_two() || !options.atlas_size.is_power_of_two() {
+        bail!("Lightmap sizes must be powers of two");
+    }
+
+    let mut triangles: Vec<Triangle> = Vec::new();
+    let mut targets: Vec<Target> = Vec::new();
+    let mut parts: Vec<BakePart> = Vec::new();
+
+    for block in blocks.iter() {
+        let terrain = TerrainBlock::new(block, options.grid_size)?;
+        terrain.triangles(&mut triangles);
+        targets.push(terrain.target(options.terrain_size));
+    }
+
+    let max_size = options.max_part_size.min(options.atlas_size);
+    for (block_idx, block) in blocks.iter().enumerate() {
+        let ifo = match &block.objects {
+            Some(ifo) => ifo,
+            None => continue,
+        };
+
+        let lists = [
+            (false, &ifo.objects, decorations),
+            (true, &ifo.buildings, constructions),
+        ];
+        for (building, objects, scene) in lists.iter() {
+            for (object_idx, object) in objects.iter().enumerate() {
+                for mut part in place_object(object, scene, &mut triangles)? {
+                    part.size = part_size(&part.triangles, options).min(max_size);
+                    targets.push(Target::rasterize(&part.triangles, part.size));
+                    part.block = block_idx;
+                    part.building = *building;
+                    part.object_idx = object_idx;
+                    part.name = format!(
+                        "{}_Object_{}_{}_{}_{}_LightingMap.tga",
+                        if object.name.is_empty() {
+                            format!("{}_{}", block.x, block.y)
+                        } else {
+                            object.name.clone()
+                        },
+                        object_idx + 1,
+                        part.part_idx,
+                        part.size,
+                        part.size
+                    );
+                    parts.push(part);
+                }
+            }
+        }
+    }
+
+    let bvh = Bvh::new(triangles);
+    let mut images = shade_targets(&bvh, &targets, options).into_iter();
+
+    let mut baked: Vec<BakedBlock> = Vec::new();
+    for block in blocks.iter() {
+        baked.push(BakedBlock {
+            x: block.x,
+            y: block.y,
+            terrain: images.next().unwrap_or_default(),
+            objects: LIT::new(),
+            object_atlases: Vec::new(),
+            buildings: LIT::new(),
+            building_atlases: Vec::new(),
+        });
+    }
+
+    let mut lightmaps: Vec<(Vec<RgbaImage>, Vec<RgbaImage>)> = Vec::new();
+    lightmaps.resize_with(blocks.len(), Default::default);
+
+    for (part, image) in parts.iter().zip(images) {
+        let block = &mut baked[part.block];
+        let (lit, images) = if part.building {
+            (&mut block.buildings, &mut lightmaps[part.block].1)
+        } else {
+            (&mut block.objects, &mut lightmaps[part.block].0)
+        };
+
+        let id = part.object_idx as i32 + 1;
+        if lit.objects.last().map(|o| o.id) != Some(id) {
+            lit.objects.push(LightmapObject {
+                id,
+                parts: Vec::new(),
+            });
+        }
+        if let Some(object) = lit.objects.last_mut() {
+            object.parts.push(LightmapPart {
+                name: part.name.clone(),
+                id: part.part_idx as i32,
+                ..LightmapPart::new()
+            });
+        }
+        images.push(image);
+    }
+
+    for (block, (objects, buildings)) in baked.iter_mut().zip(lightmaps.iter()) {
+        block.object_atlases =
+            repack_lightmaps(&mut block.objects, objects, options.atlas_size, "Object")?;
+        block.building_atlases = repack_lightmaps(
+            &mut block.buildings,
+            buildings,
+            options.atlas_size,
+            "Building",
+        )?;
+    }
+
+    Ok(baked)
+}
+
+type Triangle = [Vector3<f32>; 3];
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    position: Vector3<f32>,
+    normal: Vector3<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SurfaceVertex {
+    position: Vector3<f32>,
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+}
+
+/// Object part with a lightmap
+#[derive(Debug, Default)]
+struct BakePart {
+    block: usize,
+    building: bool,
+    object_idx: usize,
+    part_idx: usize,
+    name: String,
+    size: u32,
+    triangles: Vec<[SurfaceVertex; 3]>,
+}
+
+/// Square lightmap with the surface point of each texel, if it has one
+struct Target {
+    size: u32,
+    samples: Vec<Option<Sample>>,
+}
+
+impl Target {
+    /// Find the surface point of each texel from the lightmap UVs
+    fn rasterize(triangles: &[[SurfaceVertex; 3]], size: u32) -> Target {
+        let mut samples = vec![None; (size * size) as usize];
+        let texels = size as f32;
+
+        for tri in triangles.iter() {
+            let uv: Vec<Vector2<f32>> = tri
+                .iter()
+                .map(|v| Vector2 {
+                    x: v.uv.x * texels,
+                    y: v.uv.y * texels,
+                })
+                .collect();
+
+            let area = edge(&uv[0], &uv[1], &uv[2]);
+            if area.abs() < 1e-8 {
+                continue;
+            }
+
+            let face_normal = tri[1]
+                .position
+                .sub(&tri[0].position)
+                .cross(&tri[2].position.sub(&tri[0].position))
+                .normalize();
+
+            let min_x = uv.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+            let max_x = uv.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+            let min_y = uv.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+            let max_y = uv.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+
+            let clamp = |v: f32| v.max(0.0).min(texels) as u32;
+            for y in clamp(min_y.floor())..clamp(max_y.ceil()) {
+                for x in clamp(min_x.floor())..clamp(max_x.ceil()) {
+                    let index = (y * size + x) as usize;
+                    if samples[index].is_some() {
+                        continue;
+                    }
+
+                    let p = Vector2 {
+                        x: x as f32 + 0.5,
+                        y: y as f32 + 0.5,
+                    };
+                    let w = [
+                        edge(&uv[1], &uv[2], &p) / area,
+                        edge(&uv[2], &uv[0], &p) / area,
+                        edge(&uv[0], &uv[1], &p) / area,
+                    ];
+                    if w.iter().any(|w| *w < -1e-4) {
+                        continue;
+                    }
+
+                    let mut position = Vector3::<f32>::new();
+                    let mut normal = Vector3::<f32>::new();
+                    for (v, w) in tri.iter().zip(w.iter()) {
+                        position = position.add(&v.position.scale(*w));
+                        normal = normal.add(&v.normal.scale(*w));
+                    }
+                    if normal.length() < 1e-6 {
+                        normal = face_normal;
+                    }
+
+                    samples[index] = Some(Sample {
+                        position,
+                        normal: normal.normalize(),
+                    });
+                }
+            }
+        }
+
+        Target { size, samples }
+    }
+}
+
+fn edge(a: &Vector2<f32>, b: &Vector2<f32>, p: &Vector2<f32>) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Heightmap of a block placed in the world
+struct TerrainBlock<'a> {
+    heightmap: &'a HIM,
+    width: usize,
+    length: usize,
+    origin: Vector2<f32>,
+    grid_size: f32,
+}
+
+impl<'a> TerrainBlock<'a> {
+    fn new(block: &'a BakeBlock, grid_size: f32) -> Result<TerrainBlock<'a>, Error> {
+        let heightmap = &block.heightmap;
+        let width = heightmap.width.max(0) as usize;
+        let length = heightmap.length.max(0) as usize;
+        if width < 2 || length < 2 || heightmap.heights.len() != width * length {
+            bail!(
+                "Invalid heightmap of block {}_{} ({}x{})",
+                block.x,
+                block.y,
+                heightmap.width,
+                heightmap.length
+            );
+        }
+
+        let block_size = (width - 1) as f32 * grid_size;
+        Ok(TerrainBlock {
+            heightmap,
+            width,
+            length,
+            origin: Vector2 {
+                x: block.x as f32 * block_size,
+                y: (BLOCK_ROWS - block.y) as f32 * block_size,
+            },
+            grid_size,
+        })
+    }
+
+    fn height(&self, col: usize, row: usize) -> f32 {
+        self.heightmap.heights[row * self.width + col]
+    }
+
+    fn vertex(&self, col: usize, row: usize) -> Vector3<f32> {
+        Vector3 {
+            x: self.origin.x + col as f32 * self.grid_size,
+            y: self.origin.y - row as f32 * self.grid_size,
+            z: self.height(col, row),
+        }
+    }
+
+    fn normal(&self, col: usize, row: usize) -> Vector3<f32> {
+        let (left, right) = (col.saturating_sub(1), (col + 1).min(self.width - 1));
+        let (top, bottom) = (row.saturating_sub(1), (row + 1).min(self.length - 1));
+
+        let dx = (self.height(right, row) - self.height(left, row))
+            / ((right - left) as f32 * self.grid_size);
+        // Rows go towards -Y
+        let dy = -(self.height(col, bottom) - self.height(col, top))
+            / ((bottom - top) as f32 * self.grid_size);
+
+        Vector3 {
+            x: -dx,
+            y: -dy,
+            z: 1.0,
+        }
+        .normalize()
+    }
+
+    /// Split each grid cell along the diagonal from its top left corner
+    fn triangles(&self, triangles: &mut Vec<Triangle>) {
+        for row in 0..self.length - 1 {
+            for col in 0..self.width - 1 {
+                let v00 = self.vertex(col, row);
+                let v10 = self.vertex(col + 1, row);
+                let v01 = self.vertex(col, row + 1);
+                let v11 = self.vertex(col + 1, row + 1);
+                triangles.push([v00, v10, v11]);
+                triangles.push([v00, v11, v01]);
+            }
+        }
+    }
+
+    /// Surface point of each texel, matching the triangles of `triangles`
+    fn target(&self, size: u32) -> Target {
+        let mut samples = Vec::with_capacity((size * size) as usize);
+        let cells = Vector2 {
+            x: (self.width - 1) as f32,
+            y: (self.length - 1) as f32,
+        };
+
+        for y in 0..size {
+            for x in 0..size {
+                let gx = (x as f32 + 0.5) / size as f32 * cells.x;
+                let gy = (y as f32 + 0.5) / size as f32 * cells.y;
+                let col = (gx as usize).min(self.width - 2);
+                let row = (gy as usize).min(self.length - 2);
+                let (fx, fy) = (gx - col as f32, gy - row as f32);
+
+                let h00 = self.height(col, row);
+                let h10 = self.height(col + 1, row);
+                let h01 = self.height(col, row + 1);
+                let h11 = self.height(col + 1, row + 1);
+                let z = if fx >= fy {
+                    h00 + fx * (h10 - h00) + fy * (h11 - h10)
+                } else {
+                    h00 + fy * (h01 - h00) + fx * (h11 - h01)
+                };
+
+                let normal = self
+                    .normal(col, row)
+                    .scale((1.0 - fx) * (1.0 - fy))
+                    .add(&self.normal(col + 1, row).scale(fx * (1.0 - fy)))
+                    .add(&self.normal(col, row + 1).scale((1.0 - fx) * fy))
+                    .add(&self.normal(col + 1, row + 1).scale(fx * fy))
+                    .normalize();
+
+                samples.push(Some(Sample {
+                    position: Vector3 {
+                        x: self.origin.x + gx * self.grid_size,
+                        y: self.origin.y - gy * self.grid_size,
+                        z,
+                    },
+                    normal,
+                }));
+            }
+        }
+
+        Target { size, samples }
+    }
+}
+
+/// Translation * rotation * non-uniform scale
+fn object_transform(
+    position: &Vector3<f32>,
+    rotation: &Quaternion,
+    scale: &Vector3<f32>,
+) -> Matrix4 {
+    let mut scale_matrix = Matrix4::identity();
+    scale_matrix.m[0][0] = scale.x;
+    scale_matrix.m[1][1] = scale.y;
+    scale_matrix.m[2][2] = scale.z;
+    Matrix4::from_transform(position, rotation, 1.0).multiply(&scale_matrix)
+}
+
+/// Transform a normal by the inverse transpose (cofactors) of a matrix
+fn transform_normal(matrix: &Matrix4, n: &Vector3<f32>) -> Vector3<f32> {
+    let m = &matrix.m;
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let c = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(1, 2, 0, 2),
+            cofactor(1, 2, 0, 1),
+        ],
+        [
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 2, 0, 1),
+        ],
+        [
+            cofactor(0, 1, 1, 2),
+            -cofactor(0, 1, 0, 2),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    let det = m[0][0] * c[0][0] + m[0][1] * c[0][1] + m[0][2] * c[0][2];
+    let sign = if det < 0.0 { -1.0 } else { 1.0 };
+
+    Vector3 {
+        x: (c[0][0] * n.x + c[0][1] * n.y + c[0][2] * n.z) * sign,
+        y: (c[1][0] * n.x + c[1][1] * n.y + c[1][2] * n.z) * sign,
+        z: (c[2][0] * n.x + c[2][1] * n.y + c[2][2] * n.z) * sign,
+    }
+    .normalize()
+}
+
+/// Add the triangles of every part of an IFO object to the occluders and
+/// return the parts that use a lightmap
+fn place_object(
+    object: &ObjectData,
+    scene: &BakeScene,
+    triangles: &mut Vec<Triangle>,
+) -> Result<Vec<BakePart>, Error> {
+    let object_idx = usize::try_from(object.object_id).ok();
+    let scene_object = match object_idx.and_then(|i| scene.scene.objects.get(i)) {
+        Some(o) => o,
+        None => bail!(
+            "Object {} ({}) is not in the ZSC",
+            object.object_id,
+            object.name
+        ),
+    };
+
+    let position = Vector3 {
+        x: object.position.x + OBJECT_OFFSET,
+        y: object.position.y + OBJECT_OFFSET,
+        z: object.position.z,
+    };
+    let root = object_transform(&position, &object.rotation, &object.scale);
+
+    let mut transforms: Vec<Matrix4> = Vec::new();
+    let mut parts = Vec::new();
+    for (part_idx, part) in scene_object.parts.iter().enumerate() {
+        // Parents are 1-based indices of earlier parts
+        let parent = match part.parent as usize {
+            p if p > 0 && p <= transforms.len() => transforms[p - 1],
+            _ => root,
+        };
+        let transform = parent.multiply(&object_transform(
+            &part.position,
+            &part.rotation,
+            &part.scale,
+        ));
+        transforms.push(transform);
+
+        let mesh = match scene.meshes.get(part.mesh_id as usize) {
+            Some(Some(mesh)) => mesh,
+            _ => continue,
+        };
+
+        let vertices: Vec<SurfaceVertex> = mesh
+            .vertices
+            .iter()
+            .map(|v| SurfaceVertex {
+                position: transform.transform_point(&v.position),
+                normal: transform_normal(&transform, &v.normal),
+                uv: v.uv2,
+            })
+            .collect();
+
+        let mut part_triangles = Vec::new();
+        for face in mesh.indices.iter() {
+            let indices = [face.x as usize, face.y as usize, face.z as usize];
+            if indices.iter().any(|i| *i >= vertices.len()) {
+                bail!("Invalid triangle in mesh {}", part.mesh_id);
+            }
+            let tri = [
+                vertices[indices[0]],
+                vertices[indices[1]],
+                vertices[indices[2]],
+            ];
+            triangles.push([tri[0].position, tri[1].position, tri[2].position]);
+            part_triangles.push(tri);
+        }
+
+        if part.use_lightmap && mesh.uv2_enabled() {
+            parts.push(BakePart {
+                part_idx,
+                triangles: part_triangles,
+                ..BakePart::default()
+            });
+        }
+    }
+
+    Ok(parts)
+}
+
+/// Power of two lightmap size of a part for the texel density
+fn part_size(triangles: &[[SurfaceVertex; 3]], options: &BakeOptions) -> u32 {
+    let area: f32 = triangles
+        .iter()
+        .map(|t| {
+            let a = t[1].position.sub(&t[0].position);
+            let b = t[2].position.sub(&t[0].position);
+            a.cross(&b).length() * 0.5
+        })
+        .sum();
+
+    // World units are centimeters
+    let texels = (area / 10000.0).sqrt() * options.texel_density;
+    (texels.ceil().max(1.0) as u32)
+        .next_power_of_two()
+        .max(options.min_part_size.next_power_of_two())
+        .min(options.max_part_size.next_power_of_two())
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct BvhNode {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+
+    /// First triangle of a leaf or first child of an inner node
+    index: usize,
+
+    /// Triangle count, 0 for inner nodes
+    count: usize,
+}
+
+/// Bounding volume hierarchy for shadow rays
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<Triangle>,
+}
+
+impl Bvh {
+    fn new(triangles: Vec<Triangle>) -> Bvh {
+        let centroids: Vec<Vector3<f32>> = triangles
+            .iter()
+            .map(|t| t[0].add(&t[1]).add(&t[2]).scale(1.0 / 3.0))
+            .collect();
+
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        let mut nodes = vec![BvhNode::default()];
+        if !triangles.is_empty() {
+            Bvh::split(&mut nodes, 0, &triangles, &centroids, &mut order, 0);
+        }
+
+        let triangles = order.iter().map(|i| triangles[*i]).collect();
+        Bvh { nodes, triangles }
+    }
+
+    fn split(
+        nodes: &mut Vec<BvhNode>,
+        node: usize,
+        triangles: &[Triangle],
+        centroids: &[Vector3<f32>],
+        order: &mut [usize],
+        start: usize,
+    ) {
+        let (mut min, mut max) = (vector(f32::MAX), vector(f32::MIN));
+        let (mut centroid_min, mut centroid_max) = (vector(f32::MAX), vector(f32::MIN));
+        for i in order.iter() {
+            for v in triangles[*i].iter() {
+                min = component_min(&min, v);
+                max = component_max(&max, v);
+            }
+            centroid_min = component_min(&centroid_min, &centroids[*i]);
+            centroid_max = component_max(&centroid_max, &centroids[*i]);
+        }
+        nodes[node].min = min;
+        nodes[node].max = max;
+
+        let extent = centroid_max.sub(&centroid_min);
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        if order.len() <= LEAF_SIZE || component(&extent, axis) <= 0.0 {
+            nodes[node].index = start;
+            nodes[node].count = order.len();
+            return;
+        }
+
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |a, b| {
+            component(&centroids[*a], axis)
+                .partial_cmp(&component(&centroids[*b], axis))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let left = nodes.len();
+        nodes.push(BvhNode::default());
+        nodes.push(BvhNode::default());
+        nodes[node].index = left;
+
+        let (left_order, right_order) = order.split_at_mut(mid);
+        Bvh::split(nodes, left, triangles, centroids, left_order, start);
+        Bvh::split(
+            nodes,
+            left + 1,
+            triangles,
+            centroids,
+            right_order,
+            start + mid,
+        );
+    }
+
+    /// Whether anything is hit within `max_distance` along the ray
+    fn occluded(&self, origin: &Vector3<f32>, direction: &Vector3<f32>, max_distance: f32) -> bool {
+        if self.triangles.is_empty() {
+            return false;
+        }
+
+        let inverse = Vector3 {
+            x: 1.0 / direction.x,
+            y: 1.0 / direction.y,
+            z: 1.0 / direction.z,
+        };
+
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !hits_box(&node.min, &node.max, origin, &inverse, max_distance) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.index);
+                stack.push(node.index + 1);
+                continue;
+            }
+
+            for tri in self.triangles[node.index..node.index + node.count].iter() {
+                if hits_triangle(tri, origin, direction, max_distance) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+fn vector(v: f32) -> Vector3<f32> {
+    Vector3 { x: v, y: v, z: v }
+}
+
+fn component(v: &Vector3<f32>, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn component_min(a: &Vector3<f32>, b: &Vector3<f32>) -> Vector3<f32> {
+    Vector3 {
+        x: a.x.min(b.x),
+        y: a.y.min(b.y),
+        z: a.z.min(b.z),
+    }
+}
+
+fn component_max(a: &Vector3<f32>, b: &Vector3<f32>) -> Vector3<f32> {
+    Vector3 {
+        x: a.x.max(b.x),
+        y: a.y.max(b.y),
+        z: a.z.max(b.z),
+    }
+}
+
+fn hits_box(
+    min: &Vector3<f32>,
+    max: &Vector3<f32>,
+    origin: &Vector3<f32>,
+    inverse: &Vector3<f32>,
+    max_distance: f32,
+) -> bool {
+    let (mut near, mut far) = (0.0f32, max_distance);
+    for axis in 0..3 {
+        let o = component(origin, axis);
+        let inv = component(inverse, axis);
+        let t0 = (component(min, axis) - o) * inv;
+        let t1 = (component(max, axis) - o) * inv;
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    near <= far
+}
+
+/// Möller–Trumbore ray/triangle intersection
+fn hits_triangle(
+    tri: &Triangle,
+    origin: &Vector3<f32>,
+    direction: &Vector3<f32>,
+    max_distance: f32,
+) -> bool {
+    let e1 = tri[1].sub(&tri[0]);
+    let e2 = tri[2].sub(&tri[0]);
+    let p = direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < 1e-9 {
+        return false;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = origin.sub(&tri[0]);
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+
+    let q = s.cross(&e1);
+    let v = direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    let t = e2.dot(&q) * inv_det;
+    t > 0.0 && t < max_distance
+}
+
+/// Cheap integer hash to decorrelate the AO rays of neighbouring texels
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+fn shade_sample(bvh: &Bvh, sample: &Sample, index: usize, options: &BakeOptions) -> Color3 {
+    let normal = sample.normal;
+    let origin = sample.position.add(&normal.scale(RAY_BIAS));
+
+    let to_sun = options.sun_direction.normalize().scale(-1.0);
+    let mut direct = normal.dot(&to_sun).max(0.0);
+    if direct > 0.0 && bvh.occluded(&origin, &to_sun, f32::INFINITY) {
+        direct = 0.0;
+    }
+
+    let mut ambient = 1.0;
+    if options.ao_samples > 0 {
+        let helper = if normal.z.abs() < 0.9 {
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            }
+        } else {
+            Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            }
+        };
+        let tangent = helper.cross(&normal).normalize();
+        let bitangent = normal.cross(&tangent);
+
+        // Hammersley points, randomly shifted per texel
+        let seed = hash(index as u32);
+        let shift_u = seed as f32 / u32::MAX as f32;
+        let shift_v = hash(seed) as f32 / u32::MAX as f32;
+
+        let mut open = 0;
+        for i in 0..options.ao_samples {
+            let u = ((i as f32 + 0.5) / options.ao_samples as f32 + shift_u).fract();
+            let v = (i.reverse_bits() as f32 / u32::MAX as f32 + shift_v).fract();
+
+            // Cosine weighted hemisphere direction
+            let r = u.sqrt();
+            let phi = 2.0 * PI * v;
+            let direction = tangent
+                .scale(r * phi.cos())
+                .add(&bitangent.scale(r * phi.sin()))
+                .add(&normal.scale((1.0 - u).max(0.0).sqrt()));
+
+            if !bvh.occluded(&origin, &direction, options.ao_distance) {
+                open += 1;
+            }
+        }
+        ambient = open as f32 / options.ao_samples as f32;
+    }
+
+    let sun = &options.sun_color;
+    let sky = &options.ambient_color;
+    Color3::rgb(
+        sun.r * direct + sky.r * ambient,
+        sun.g * direct + sky.g * ambient,
+        sun.b * direct + sky.b * ambient,
+    )
+}
+
+/// Light the surface points of every target and turn them into images
+fn shade_targets(bvh: &Bvh, targets: &[Target], options: &BakeOptions) -> Vec<RgbaImage> {
+    let samples: Vec<Sample> = targets
+        .iter()
+        .flat_map(|t| t.samples.iter().flatten().copied())
+        .collect();
+
+    let mut colors = vec![Color3::new(); samples.len()];
+    let chunk_size = (samples.len() / options.threads.max(1) + 1).max(64);
+    thread::scope(|scope| {
+        for (chunk_idx, (colors, samples)) in colors
+            .chunks_mut(chunk_size)
+            .zip(samples.chunks(chunk_size))
+            .enumerate()
+        {
+            scope.spawn(move || {
+                for (i, (color, sample)) in colors.iter_mut().zip(samples).enumerate() {
+                    *color = shade_sample(bvh, sample, chunk_idx * chunk_size + i, options);
+                }
+            });
+        }
+    });
+
+    let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut colors = colors.into_iter();
+    let mut images = Vec::new();
+    for target in targets.iter() {
+        let mut image = RgbaImage::from_pixel(target.size, target.size, Rgba([0, 0, 0, 255]));
+        let mut covered = vec![false; target.samples.len()];
+        for (i, sample) in target.samples.iter().enumerate() {
+            if sample.is_none() {
+                continue;
+            }
+            let c = colors.next().unwrap_or_default();
+            let (x, y) = (i as u32 % target.size, i as u32 / target.size);
+            image.put_pixel(x, y, Rgba([to_u8(c.r), to_u8(c.g), to_u8(c.b), 255]));
+            covered[i] = true;
+        }
+        dilate(&mut image, &mut covered);
+        images.push(image);
+    }
+    images
+}
+
+/// Grow covered texels into their uncovered neighbours
+fn dilate(image: &mut RgbaImage, covered: &mut [bool]) {
+    let (width, height) = image.dimensions();
+    for _ in 0..DILATE_PASSES {
+        let source = image.clone();
+        let previous = covered.to_vec();
+
+        for y in 0..height {
+            for x in 0..width {
+                if previous[(y * width + x) as usize] {
+                    continue;
+                }
+
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if previous[(ny * width + nx) as usize] {
+                            let pixel = source.get_pixel(nx, ny);
+                            for (s, p) in sum.iter_mut().zip(pixel.0.iter()) {
+                                *s += *p as u32;
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+
+                if let (Some(r), Some(g), Some(b)) = (
+                    sum[0].checked_div(count),
+                    sum[1].checked_div(count),
+                    sum[2].checked_div(count),
+                ) {
+                    image.put_pixel(x, y, Rgba([r as u8, g as u8, b as u8, 255]));
+                    covered[(y * width + x) as usize] = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use roselib::files::zms::{Vertex, VertexFormat};
+    use roselib::files::zsc::{SceneObject, SceneObjectPart};
+
+    fn flat_block(x: i32, y: i32, height: f32) -> BakeBlock {
+        let mut heightmap = HIM::new();
+        heightmap.width = 5;
+        heightmap.length = 5;
+        heightmap.heights = vec![height; 25];
+        BakeBlock {
+            x,
+            y,
+            heightmap,
+            objects: None,
+        }
+    }
+
+    /// Axis aligned box of the given half size resting on its origin
+    fn box_mesh(half: f32) -> ZMS {
+        let mut mesh = ZMS::new();
+        mesh.format =
+            VertexFormat::Position as i32 | VertexFormat::Normal as i32 | VertexFormat::UV2 as i32;
+
+        let corners = [
+            [-half, -half, 0.0],
+            [half, -half, 0.0],
+            [half, half, 0.0],
+            [-half, half, 0.0],
+            [-half, -half, half * 2.0],
+            [half, -half, half * 2.0],
+            [half, half, half * 2.0],
+            [-half, half, half * 2.0],
+        ];
+        for (i, c) in corners.iter().enumerate() {
+            let mut vertex = Vertex::new();
+            vertex.position = Vector3 {
+                x: c[0],
+                y: c[1],
+                z: c[2],
+            };
+            vertex.normal = vertex.position.normalize();
+            vertex.uv2 = Vector2 {
+                x: (i % 4) as f32 / 3.0,
+                y: (i / 4) as f32,
+            };
+            mesh.vertices.push(vertex);
+        }
+
+        for face in [
+            [0, 2, 1],
+            [0, 3, 2],
+            [4, 5, 6],
+            [4, 6, 7],
+            [0, 1, 5],
+            [0, 5, 4],
+            [1, 2, 6],
+            [1, 6, 5],
+            [2, 3, 7],
+            [2, 7, 6],
+            [3, 0, 4],
+            [3, 4, 7],
+        ]
+        .iter()
+        {
+            mesh.indices.push(Vector3 {
+                x: face[0],
+                y: face[1],
+                z: face[2],
+            });
+        }
+        mesh
+    }
+
+    fn options() -> BakeOptions {
+        BakeOptions {
+            sun_direction: Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+            sun_color: Color3::rgb(1.0, 1.0, 1.0),
+            ambient_color: Color3::rgb(0.0, 0.0, 0.0),
+            ao_samples: 0,
+            grid_size: 100.0,
+            terrain_size: 16,
+            threads: 2,
+            ..BakeOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_bake_terrain() {
+        let blocks = vec![flat_block(32, 32, 0.0)];
+        let scene = BakeScene::default();
+        let baked = bake_map(&blocks, &scene, &scene, &options()).unwrap();
+
+        assert_eq!(baked.len(), 1);
+        let terrain = &baked[0].terrain;
+        assert_eq!(terrain.dimensions(), (16, 16));
+        assert!(terrain.pixels().all(|p| *p == Rgba([255, 255, 255, 255])));
+        assert!(baked[0].objects.objects.is_empty());
+        assert_eq!(
+            baked[0].terrain_path(),
+            Path::new("32_32").join("32_32_PLANELIGHTINGMAP.DDS")
+        );
+
+        // A block right above the ground blocks the sun and the sky
+        let mut options = options();
+        options.ambient_color = Color3::rgb(1.0, 1.0, 1.0);
+        options.ao_samples = 8;
+        let blocks = vec![flat_block(32, 32, 0.0), flat_block(32, 32, 100.0)];
+        let baked = bake_map(&blocks, &scene, &scene, &options).unwrap();
+        let center = baked[0].terrain.get_pixel(8, 8).0[0];
+        assert!(center < 100, "{}", center);
+    }
+
+    #[test]
+    fn test_bake_objects() {
+        // 400x400 block with a 100 unit box in its center
+        let mut block = flat_block(0, BLOCK_ROWS, 0.0);
+        let mut ifo = IFO::new();
+        ifo.objects.push(ObjectData {
+            position: Vector3 {
+                x: 200.0 - OBJECT_OFFSET,
+                y: -200.0 - OBJECT_OFFSET,
+                z: 0.0,
+            },
+            rotation: Quaternion::identity(),
+            scale: vector(1.0),
+            ..ObjectData::default()
+        });
+        block.objects = Some(ifo);
+
+        let mut scene = BakeScene::default();
+        scene.meshes.push(Some(box_mesh(50.0)));
+        scene.scene.objects.push(SceneObject {
+            parts: vec![SceneObjectPart {
+                rotation: Quaternion::identity(),
+                scale: vector(1.0),
+                use_lightmap: true,
+                ..SceneObjectPart::default()
+            }],
+            ..SceneObject::default()
+        });
+
+        let mut options = options();
+        options.sun_direction = Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: -1.0,
+        };
+        let baked = bake_map(&[block], &scene, &BakeScene::default(), &options).unwrap();
+        let block = &baked[0];
+
+        // The box shadows the terrain east of it
+        let terrain = &block.terrain;
+        let lit = terrain.get_pixel(1, 8).0[0];
+        let shadowed = terrain.get_pixel(10, 8).0[0];
+        assert!(lit > 150, "{}", lit);
+        assert_eq!(shadowed, 0);
+
+        assert_eq!(block.objects.objects.len(), 1);
+        assert_eq!(block.objects.objects[0].id, 1);
+        let lit_part = &block.objects.objects[0].parts[0];
+        assert_eq!(lit_part.id, 0);
+        assert_eq!(block.object_atlases.len(), 1);
+        assert_eq!(block.object_atlases[0].0, block.objects.filenames[0]);
+        assert!(block.buildings.objects.is_empty());
+
+        // Object without a ZSC entry
+        let mut blocks = vec![flat_block(0, 0, 0.0)];
+        let mut ifo = IFO::new();
+        ifo.objects.push(ObjectData {
+            object_id: 5,
+            ..ObjectData::default()
+        });
+        blocks[0].objects = Some(ifo);
+        assert!(bake_map(&blocks, &scene, &scene, &options).is_err());
+    }
+}
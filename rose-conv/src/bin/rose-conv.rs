@@ -9,22 +9,47 @@ use std::process::exit;
 use clap::{crate_authors, crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
 use failure::{bail, Error};
 use image::io::Reader as ImageReader;
-use image::{GrayImage, ImageBuffer, RgbaImage};
+use image::RgbaImage;
 use serde::{Deserialize, Serialize};
 
+use roselib::files::dds::DdsFormat;
 use roselib::files::zon::ZoneTileRotation;
 use roselib::files::*;
-use roselib::io::{RoseFile, RoseReader};
-
+use roselib::io::{PathRoseExt, RoseFile, RoseReader};
+use roselib::utils::{Color3, Vector3};
+
+use rose_conv::bake::{bake_map, load_map, BakeOptions, BakeScene};
+use rose_conv::camera::camera_to_glb;
+use rose_conv::heightmap::{
+    heights_to_image, image_to_heights, raw_to_heights, split_heightmap, HeightmapInfo,
+};
+use rose_conv::lightmap::{
+    atlas_prefix, extract_lightmap, extract_lightmaps, lightmap_part_name, load_atlases,
+    repack_lightmaps,
+};
+use rose_conv::motion::{motion_from_bvh, motion_from_gltf, MotionImportOptions};
+use rose_conv::particles::{render_particles, simulate_effect, ParticleRenderOptions};
+use rose_conv::sprites::{
+    apply_color_key, cut_sprite, load_sprites, pack_sprites, resolve_path, sprite_file_name,
+    SpritePackOptions,
+};
+use rose_conv::texture::{image_to_dds, load_texture};
 use rose_conv::{FromCsv, ToCsv};
 use rose_conv::{FromJson, ToJson};
 
-const SERIALIZE_VALUES: [&'static str; 14] = [
-    "him", "idx", "ifo", "lit", "stb", "stl", "wstb", "til", "tsi", "zmd", "zmo", "zms", "zon",
-    "zsc",
+const TEXTURE_FORMATS: [&str; 10] = [
+    "dxt1", "dxt3", "dxt5", "a8r8g8b8", "x8r8g8b8", "r8g8b8", "a1r5g5b5", "x1r5g5b5", "a4r4g4b4",
+    "r5g6b5",
+];
+
+const SERIALIZE_VALUES: [&'static str; 22] = [
+    "aip", "chr", "con", "eft", "him", "idx", "ifo", "lit", "ltb", "ptl", "qsd", "stb", "stl",
+    "wstb", "til", "tsi", "zca", "zmd", "zmo", "zms", "zon", "zsc",
 ];
 
-const DESERIALIZE_VALUES: [&'static str; 5] = ["idx", "lit", "stb", "stl", "zsc"];
+const DESERIALIZE_VALUES: [&'static str; 12] = [
+    "aip", "chr", "eft", "idx", "lit", "ltb", "ptl", "qsd", "stb", "stl", "zca", "zsc",
+];
 
 #[derive(Debug, Deserialize, Serialize)]
 struct TilemapTile {
@@ -66,6 +91,51 @@ fn main() {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("heightmap")
+                .about("Split a heightmap image or RAW file into HIM files")
+                .arg(
+                    Arg::with_name("input")
+                        .help("16-bit grayscale image or RAW file of little endian floats")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("info")
+                        .help("Heightmap JSON written by the map command (Default: <input>_heightmap.json if it exists)")
+                        .long("info")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .help("Width of a RAW heightmap (Default: square)")
+                        .long("width")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("height_scale")
+                        .help("Height of a white pixel above the offset, or the multiplier of RAW values")
+                        .long("height-scale")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("height_offset")
+                        .help("Height of a black pixel, or the offset of RAW values")
+                        .long("height-offset")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("x")
+                        .help("Block X coordinate of the top left heightmap")
+                        .short("x")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .help("Block Y coordinate of the top left heightmap")
+                        .short("y")
+                        .takes_value(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("iconsheet")
                 .about("Convert ROSE iconsheet to icon files")
@@ -76,10 +146,341 @@ fn main() {
                         .multiple(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("sprites")
+                .about("Extract the sprites of a sprite information (TSI) file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to TSI file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("data_dir")
+                        .help("Directory sprite sheet paths are relative to (Default: TSI directory)")
+                        .long("data-dir")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pack-sprites")
+                .about("Pack a directory of sprite images into sheets and a TSI file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Directory of PNG/DDS sprites, named after the sprite")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("tsi")
+                        .help("Existing TSI to update, its sprites keep their indices")
+                        .long("tsi")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .help("Name of the new TSI file (Default: existing TSI or directory name)")
+                        .long("name")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("sheet_prefix")
+                        .help("Path prefix of new sheets in the TSI, e.g. 3DDATA/CONTROL/RES/UI")
+                        .long("sheet-prefix")
+                        .takes_value(true)
+                        .default_value("SPRITES"),
+                )
+                .arg(
+                    Arg::with_name("max_size")
+                        .help("Maximum width and height of a sheet")
+                        .long("max-size")
+                        .takes_value(true)
+                        .default_value("1024"),
+                )
+                .arg(
+                    Arg::with_name("padding")
+                        .help("Empty pixels between sprites")
+                        .long("padding")
+                        .takes_value(true)
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lightmaps")
+                .about("Extract the object lightmaps of a LIT file or repack edited ones")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to LIT file, atlases are loaded from the same directory")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("repack")
+                        .help("Directory of edited lightmaps to pack into new atlases (Default: extract the lightmaps)")
+                        .long("repack")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("atlas_size")
+                        .help("Width and height of repacked atlases")
+                        .long("atlas-size")
+                        .takes_value(true)
+                        .default_value("512"),
+                )
+                .arg(
+                    Arg::with_name("prefix")
+                        .help("File name prefix of repacked atlases, e.g. Object or Building (Default: prefix of the current atlases)")
+                        .long("prefix")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("DDS format of repacked atlases")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .case_insensitive(true)
+                        .possible_values(&TEXTURE_FORMATS)
+                        .default_value("dxt1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bake")
+                .about("Bake terrain and object lightmaps of a map")
+                .arg(
+                    Arg::with_name("map_dir")
+                        .help("Map directory with X_Y.HIM and X_Y.IFO files")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("decorations")
+                        .help("ZSC of the map decorations (IFO objects)")
+                        .long("deco")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("constructions")
+                        .help("ZSC of the map buildings (IFO buildings)")
+                        .long("cnst")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("data_dir")
+                        .help("Directory the mesh paths of the ZSC files are relative to")
+                        .long("data-dir")
+                        .takes_value(true)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("sun")
+                        .help("Direction the sunlight travels in as x,y,z")
+                        .long("sun")
+                        .takes_value(true)
+                        .default_value("-0.5,0.5,-1"),
+                )
+                .arg(
+                    Arg::with_name("sun_color")
+                        .help("Sunlight color as r,g,b from 0 to 1")
+                        .long("sun-color")
+                        .takes_value(true)
+                        .default_value("0.8,0.8,0.75"),
+                )
+                .arg(
+                    Arg::with_name("ambient")
+                        .help("Ambient color as r,g,b from 0 to 1")
+                        .long("ambient")
+                        .takes_value(true)
+                        .default_value("0.35,0.35,0.4"),
+                )
+                .arg(
+                    Arg::with_name("ao_samples")
+                        .help("Ambient occlusion rays per texel, 0 to disable")
+                        .long("ao-samples")
+                        .takes_value(true)
+                        .default_value("16"),
+                )
+                .arg(
+                    Arg::with_name("ao_distance")
+                        .help("Range of ambient occlusion in world units")
+                        .long("ao-distance")
+                        .takes_value(true)
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::with_name("terrain_size")
+                        .help("Width and height of each terrain block lightmap")
+                        .long("terrain-size")
+                        .takes_value(true)
+                        .default_value("256"),
+                )
+                .arg(
+                    Arg::with_name("atlas_size")
+                        .help("Width and height of object lightmap atlases")
+                        .long("atlas-size")
+                        .takes_value(true)
+                        .default_value("512"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("DDS format of the lightmaps")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .case_insensitive(true)
+                        .possible_values(&TEXTURE_FORMATS)
+                        .default_value("dxt1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("motion")
+                .about("Import a glTF/BVH animation as a ROSE motion (ZMO)")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to glTF, GLB or BVH file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("skeleton")
+                        .help("Path to the target skeleton (ZMD)")
+                        .short("s")
+                        .long("skeleton")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("fps")
+                        .help("Frames per second of the generated motion")
+                        .long("fps")
+                        .takes_value(true)
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::with_name("animation")
+                        .help("Name of the glTF animation to import (Default: first)")
+                        .long("animation")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("con")
+                .about("Extract the script of a conversation (CON) or rebuild it with a new one")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to CON file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("script")
+                        .help("Script to replace the embedded one with (Default: extract the script)")
+                        .short("s")
+                        .long("script")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("camera")
+                .about("Export a ROSE camera (ZCA) and camera motion (ZMO) to glTF")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to ZCA file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("motion")
+                        .help("Path to the camera motion (ZMO)")
+                        .short("m")
+                        .long("motion")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("scale")
+                        .help("Scale applied to camera positions")
+                        .long("scale")
+                        .takes_value(true)
+                        .default_value("1.0"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("particles")
+                .about("Simulate a particle system (PTL) or effect (EFT) and render PNG frames")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to PTL or EFT file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("data_dir")
+                        .help("Directory EFT particle paths are relative to")
+                        .long("data-dir")
+                        .takes_value(true)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("fps")
+                        .help("Frames per second of the simulation")
+                        .long("fps")
+                        .takes_value(true)
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .help("Number of frames to simulate")
+                        .long("frames")
+                        .takes_value(true)
+                        .default_value("60"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .help("Random seed, the same seed gives the same particles")
+                        .long("seed")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help("Size of the rendered frames in pixels")
+                        .long("size")
+                        .takes_value(true)
+                        .default_value("256"),
+                )
+                .arg(
+                    Arg::with_name("scale")
+                        .help("Pixels per world unit")
+                        .long("scale")
+                        .takes_value(true)
+                        .default_value("1.0"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .help("Also write the particle states of every frame as JSON")
+                        .long("json"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("texture")
+                .about("Convert DDS textures to PNG, or images to DDS")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Path to DDS or image file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("DDS format when converting an image to DDS")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .case_insensitive(true)
+                        .possible_values(&TEXTURE_FORMATS)
+                        .default_value("dxt5"),
+                )
+                .arg(
+                    Arg::with_name("no-mipmaps")
+                        .help("Do not generate mipmaps when converting an image to DDS")
+                        .long("no-mipmaps"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("serialize")
                 .visible_alias("se")
-                .about("Serialize a ROSE File into JSON (CSV for STB/STL).")
+                .about("Serialize a ROSE File into JSON (CSV for STB/STL/LTB).")
                 .arg(
                     Arg::with_name("input")
                         .help("Path to ROSE file")
@@ -105,7 +506,7 @@ fn main() {
         .subcommand(
             SubCommand::with_name("deserialize")
                 .visible_alias("de")
-                .about("Deserialize a ROSE file from JSON (CSV for STB/STL).")
+                .about("Deserialize a ROSE file from JSON (CSV for STB/STL/LTB).")
                 .arg(
                     Arg::with_name("type")
                         .help("ROSE file type")
@@ -135,9 +536,19 @@ priority over the output directory flag (`-o`)."
     // Run subcommands
     let res = match matches.subcommand() {
         ("map", Some(matches)) => convert_map(matches),
+        ("heightmap", Some(matches)) => import_heightmap(matches),
         ("serialize", Some(matches)) => serialize(matches),
         ("deserialize", Some(matches)) => deserialize(matches),
         ("iconsheet", Some(matches)) => convert_iconsheets(matches),
+        ("sprites", Some(matches)) => extract_sprites(matches),
+        ("pack-sprites", Some(matches)) => pack_sprite_sheets(matches),
+        ("lightmaps", Some(matches)) => convert_lightmaps(matches),
+        ("bake", Some(matches)) => bake_lightmaps(matches),
+        ("motion", Some(matches)) => import_motion(matches),
+        ("camera", Some(matches)) => export_camera(matches),
+        ("con", Some(matches)) => convert_conversation(matches),
+        ("particles", Some(matches)) => render_effect(matches),
+        ("texture", Some(matches)) => convert_texture(matches),
         _ => {
             eprintln!("ROSE Online Converter. Run with `--help` for more info.");
             exit(1);
@@ -149,6 +560,16 @@ priority over the output directory flag (`-o`)."
         let filename = match matches.subcommand() {
             ("serialize", Some(matches)) => matches.value_of("input"),
             ("deserialize", Some(matches)) => matches.value_of("input"),
+            ("motion", Some(matches)) => matches.value_of("input"),
+            ("camera", Some(matches)) => matches.value_of("input"),
+            ("con", Some(matches)) => matches.value_of("input"),
+            ("particles", Some(matches)) => matches.value_of("input"),
+            ("texture", Some(matches)) => matches.value_of("input"),
+            ("sprites", Some(matches)) => matches.value_of("input"),
+            ("pack-sprites", Some(matches)) => matches.value_of("input"),
+            ("lightmaps", Some(matches)) => matches.value_of("input"),
+            ("bake", Some(matches)) => matches.value_of("map_dir"),
+            ("heightmap", Some(matches)) => matches.value_of("input"),
             _ => None,
         };
 
@@ -198,13 +619,21 @@ fn serialize(matches: &ArgMatches) -> Result<(), Error> {
         // CSV
         "stb" => STB::from_path(&input)?.to_csv()?,
         "stl" => STL::from_path(&input)?.to_csv()?,
+        "ltb" => LTB::from_path(input)?.to_csv()?,
         // JSON
+        "aip" => AIP::from_path(input)?.to_json()?,
+        "chr" => CHR::from_path(input)?.to_json()?,
+        "con" => CON::from_path(input)?.to_json()?,
+        "eft" => EFT::from_path(input)?.to_json()?,
         "him" => HIM::from_path(&input)?.to_json()?,
         "idx" => IDX::from_path(&input)?.to_json()?,
         "ifo" => IFO::from_path(&input)?.to_json()?,
         "lit" => LIT::from_path(&input)?.to_json()?,
+        "ptl" => PTL::from_path(input)?.to_json()?,
+        "qsd" => QSD::from_path(input)?.to_json()?,
         "til" => TIL::from_path(&input)?.to_json()?,
         "tsi" => TSI::from_path(&input)?.to_json()?,
+        "zca" => ZCA::from_path(input)?.to_json()?,
         "zmd" => ZMD::from_path(&input)?.to_json()?,
         "zmo" => ZMO::from_path(&input)?.to_json()?,
         "zms" => ZMS::from_path(&input)?.to_json()?,
@@ -221,7 +650,7 @@ fn serialize(matches: &ArgMatches) -> Result<(), Error> {
         _ => bail!("Unsupported file type: {}", rose_type.as_str()),
     };
 
-    let new_extension = if rose_type == "stb" || rose_type == "stl" {
+    let new_extension = if rose_type == "stb" || rose_type == "stl" || rose_type == "ltb" {
         "csv"
     } else {
         "json"
@@ -279,8 +708,16 @@ fn deserialize(matches: &ArgMatches) -> Result<(), Error> {
     match filetype {
         "stb" => STB::from_csv(&data)?.write_to_path(&out)?,
         "stl" => STL::from_csv(&data)?.write_to_path(&out)?,
+        "ltb" => LTB::from_csv(&data)?.write_to_path(&out)?,
+        "aip" => AIP::from_json(&data)?.write_to_path(&out)?,
+        "chr" => CHR::from_json(&data)?.write_to_path(&out)?,
+        "con" => CON::from_json(&data)?.write_to_path(&out)?,
+        "eft" => EFT::from_json(&data)?.write_to_path(&out)?,
         "idx" => IDX::from_json(&data)?.write_to_path(&out)?,
         "lit" => IDX::from_json(&data)?.write_to_path(&out)?,
+        "ptl" => PTL::from_json(&data)?.write_to_path(&out)?,
+        "qsd" => QSD::from_json(&data)?.write_to_path(&out)?,
+        "zca" => ZCA::from_json(&data)?.write_to_path(&out)?,
         "zsc" => IDX::from_json(&data)?.write_to_path(&out)?,
         _ => bail!("Unsupported file type: {}", filetype),
     }
@@ -288,11 +725,201 @@ fn deserialize(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+fn import_motion(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+    let skeleton_path = Path::new(matches.value_of("skeleton").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let options = MotionImportOptions {
+        fps: matches.value_of("fps").unwrap_or_default().parse()?,
+        animation: matches.value_of("animation").map(String::from),
+    };
+
+    let skeleton = ZMD::from_path(skeleton_path)?;
+
+    let extension = input
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let mut zmo = match extension.as_str() {
+        "gltf" | "glb" => motion_from_gltf(input, &skeleton, &options)?,
+        "bvh" => motion_from_bvh(input, &skeleton, &options)?,
+        _ => bail!("Unsupported animation format: {}", extension),
+    };
+
+    create_output_dir(out_dir)?;
+
+    let out = out_dir
+        .join(input.file_name().unwrap_or_default())
+        .with_extension("zmo");
+
+    zmo.write_to_path(&out)?;
+
+    Ok(())
+}
+
+/// Extract the embedded script of a conversation to a lua file, or write a
+/// copy of the conversation with its script replaced
+fn convert_conversation(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let mut con = CON::from_path(input)?;
+
+    create_output_dir(out_dir)?;
+
+    let out = out_dir.join(input.file_name().unwrap_or_default());
+
+    match matches.value_of("script") {
+        Some(script) => {
+            con.script = fs::read(script)?;
+            con.write_to_path(&out)?;
+        }
+        None => {
+            let mut f = File::create(out.with_extension("lua"))?;
+            f.write_all(&con.script)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn export_camera(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let camera = ZCA::from_path(input)?;
+    let motion = match matches.value_of("motion") {
+        Some(path) => Some(ZMO::from_path(Path::new(path))?),
+        None => None,
+    };
+    let scale: f32 = matches.value_of("scale").unwrap_or_default().parse()?;
+
+    let name = input
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default();
+    let data = camera_to_glb(name, &camera, motion.as_ref(), scale)?;
+
+    create_output_dir(out_dir)?;
+
+    let out = out_dir
+        .join(input.file_name().unwrap_or_default())
+        .with_extension("glb");
+
+    let mut f = File::create(&out)?;
+    f.write_all(&data)?;
+
+    Ok(())
+}
+
+fn render_effect(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+    let data_dir = Path::new(matches.value_of("data_dir").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let fps: f32 = matches.value_of("fps").unwrap_or_default().parse()?;
+    let frame_count: u32 = matches.value_of("frames").unwrap_or_default().parse()?;
+    let seed: u32 = matches.value_of("seed").unwrap_or_default().parse()?;
+    let size: u32 = matches.value_of("size").unwrap_or_default().parse()?;
+
+    let options = ParticleRenderOptions {
+        width: size,
+        height: size,
+        scale: matches.value_of("scale").unwrap_or_default().parse()?,
+        ..Default::default()
+    };
+
+    let extension = input
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let frames = match extension.as_str() {
+        "ptl" => PTL::from_path(input)?.simulate(fps, frame_count, seed)?,
+        "eft" => simulate_effect(&EFT::from_path(input)?, data_dir, fps, frame_count, seed)?,
+        _ => bail!("Unsupported particle format: {}", extension),
+    };
+
+    let name = input
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default();
+    let out_dir = out_dir.join(name);
+    create_output_dir(&out_dir)?;
+
+    for (i, particles) in frames.iter().enumerate() {
+        let image = render_particles(particles, &options);
+        image.save(out_dir.join(format!("{}_{:04}.png", name, i)))?;
+    }
+
+    if matches.is_present("json") {
+        let mut f = File::create(out_dir.join(name).with_extension("json"))?;
+        f.write_all(serde_json::to_string_pretty(&frames)?.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn convert_texture(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let extension = input
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    create_output_dir(out_dir)?;
+
+    let image = load_texture(input)?;
+    let out = out_dir.join(input.file_name().unwrap_or_default());
+
+    if extension == "dds" {
+        image.save(out.with_extension("png"))?;
+    } else {
+        let format: DdsFormat = matches.value_of("format").unwrap_or_default().parse()?;
+        let mipmaps = !matches.is_present("no-mipmaps");
+        image_to_dds(&image, format, mipmaps)?.write_to_path(&out.with_extension("dds"))?;
+    }
+
+    Ok(())
+}
+
 /// Convert map files:
 /// - ZON: JSON
 /// - TIL: Combined into 1 JSON file
 /// - IFO: Combined into 1 JSON file
-/// - HIM: Combined into 1 greyscale png
+/// - HIM: Combined into 1 16-bit greyscale png with its height range in JSON
 fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     let map_dir = Path::new(matches.value_of("map_dir").unwrap());
     if !map_dir.is_dir() {
@@ -304,6 +931,7 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     // Collect coordinates from file names (using HIM as reference)
     let mut x_coords: Vec<u32> = Vec::new();
     let mut y_coords: Vec<u32> = Vec::new();
+    let mut blocks: Vec<(u32, u32)> = Vec::new();
 
     for f in fs::read_dir(map_dir)? {
         let f = f?;
@@ -315,10 +943,14 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
         if fpath.extension().unwrap().to_str().unwrap().to_lowercase() == "him" {
             let fname = fpath.file_stem().unwrap().to_str().unwrap();
             let parts: Vec<&str> = fname.split('_').collect();
-            x_coords.push(parts[0].parse()?);
-            y_coords.push(parts[1].parse()?);
+            let x = parts[0].parse()?;
+            let y = parts[1].parse()?;
+            x_coords.push(x);
+            y_coords.push(y);
+            blocks.push((x, y));
         }
     }
+    blocks.sort();
 
     x_coords.sort();
     y_coords.sort();
@@ -328,18 +960,19 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     let y_min = *y_coords.iter().min().unwrap();
     let y_max = *y_coords.iter().max().unwrap();
 
-    let map_width = (x_max - x_min + 1) * 65;
-    let map_height = (y_max - y_min + 1) * 65;
+    // Neighbouring heightmaps share their edge vertices, which also keeps the
+    // map dimensions divisible by 4 for tiling
+    let new_map_width = (x_max - x_min + 1) * 64 + 1;
+    let new_map_height = (y_max - y_min + 1) * 64 + 1;
 
     let mut max_height = f32::NAN;
     let mut min_height = f32::NAN;
-
-    // Ensure map dimensions are divisible by 4 for tiling
-    let new_map_width = (map_width as f32 / 4.0).ceil() * 4.0;
-    let new_map_height = (map_height as f32 / 4.0).ceil() * 4.0;
-
-    let new_map_width = new_map_width as u32 + 1;
-    let new_map_height = new_map_height as u32 + 1;
+    let mut heightmap_info = HeightmapInfo {
+        x: x_min as i32,
+        y: y_min as i32,
+        blocks: blocks.iter().map(|&(x, y)| (x as i32, y as i32)).collect(),
+        ..HeightmapInfo::default()
+    };
 
     let mut heights: Vec<Vec<f32>> = Vec::new();
     heights.resize(
@@ -359,6 +992,11 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
 
     for y in y_min..=y_max {
         for x in x_min..=x_max {
+            // Maps may have holes, these are left at 0 and skipped on import
+            if blocks.binary_search(&(x, y)).is_err() {
+                continue;
+            }
+
             //-- Load HIMs
             let him_name = format!("{}_{}.HIM", x, y);
             let him_path = map_dir.join(&him_name);
@@ -373,9 +1011,12 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
                 );
             }
 
+            heightmap_info.grid_count = him.grid_count;
+            heightmap_info.scale = him.scale;
+
             for h in 0..him.length {
                 for w in 0..him.width {
-                    let height = him.height(h as usize, w as usize);
+                    let height = him.height(w as usize, h as usize);
 
                     if (height > max_height) || (max_height.is_nan()) {
                         max_height = height;
@@ -384,8 +1025,8 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
                         min_height = height;
                     }
 
-                    let new_x = ((x - x_min) * 65) + w as u32;
-                    let new_y = ((y - y_min) * 65) + h as u32;
+                    let new_x = ((x - x_min) * 64) + w as u32;
+                    let new_y = ((y - y_min) * 64) + h as u32;
 
                     heights[new_y as usize][new_x as usize] = height;
                 }
@@ -426,20 +1067,14 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     create_output_dir(out_dir)?;
 
     // -- Heightmap image
-    let delta_height = max_height - min_height;
-
-    let mut height_image: GrayImage = ImageBuffer::new(new_map_width, new_map_height);
-
-    for y in 0..new_map_height {
-        for x in 0..new_map_width {
-            let height = heights[y as usize][x as usize];
-
-            let norm_height = |h| (255.0 * ((h - min_height) / delta_height)) as u8;
-
-            let pixel = image::Luma([norm_height(height)]);
-            height_image.put_pixel(x, y, pixel);
-        }
-    }
+    let heights: Vec<f32> = heights.into_iter().flatten().collect();
+    let height_image = heights_to_image(
+        new_map_width,
+        new_map_height,
+        &heights,
+        min_height,
+        max_height,
+    )?;
 
     // Save heightmap image
     let mut height_file = PathBuf::from(out_dir);
@@ -447,7 +1082,16 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     height_file.set_extension("png");
 
     println!("Saving heightmap to: {}", &height_file.to_str().unwrap());
-    height_image.save(height_file)?;
+    height_image.save(&height_file)?;
+
+    // Save the real height range to import the image again
+    heightmap_info.min_height = min_height;
+    heightmap_info.max_height = max_height;
+
+    let info_file = heightmap_info_path(&height_file);
+    println!("Saving heightmap info to: {}", &info_file.to_str().unwrap());
+    let f = File::create(info_file)?;
+    serde_json::to_writer_pretty(f, &heightmap_info)?;
 
     // Dump ZON as JSON
     let zon = ZON::from_path(&map_dir.join(format!("{}.ZON", map_name)))?;
@@ -488,6 +1132,87 @@ fn convert_map(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+/// Sidecar JSON of a heightmap image, `<name>_heightmap.json`
+fn heightmap_info_path(image_path: &Path) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_heightmap.json", stem))
+}
+
+/// Split a heightmap image or RAW file into `X_Y.HIM` files
+fn import_heightmap(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let info_path = match matches.value_of("info") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(heightmap_info_path(input)).filter(|p| p.exists()),
+    };
+    let mut info = match info_path {
+        Some(path) => serde_json::from_reader(File::open(path)?)?,
+        None => HeightmapInfo::default(),
+    };
+    if let Some(x) = matches.value_of("x") {
+        info.x = x.parse()?;
+    }
+    if let Some(y) = matches.value_of("y") {
+        info.y = y.parse()?;
+    }
+
+    let is_raw = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("raw"))
+        .unwrap_or(false);
+
+    // RAW values are used as is unless scaled
+    let (default_scale, default_offset) = if is_raw {
+        (1.0, 0.0)
+    } else {
+        (info.max_height - info.min_height, info.min_height)
+    };
+    let scale = match matches.value_of("height_scale") {
+        Some(scale) => scale.parse()?,
+        None => default_scale,
+    };
+    let offset = match matches.value_of("height_offset") {
+        Some(offset) => offset.parse()?,
+        None => default_offset,
+    };
+
+    let (width, length, heights) = if is_raw {
+        let heights = raw_to_heights(&fs::read(input)?, scale, offset)?;
+        let width = match matches.value_of("width") {
+            Some(width) => width.parse()?,
+            None => (heights.len() as f64).sqrt() as usize,
+        };
+        if width == 0 || heights.len() % width != 0 {
+            bail!(
+                "RAW heightmap of {} heights is not {} wide",
+                heights.len(),
+                width
+            );
+        }
+        (width, heights.len() / width, heights)
+    } else {
+        let image = ImageReader::open(input)?.decode()?.into_luma16();
+        let (width, length) = image.dimensions();
+        let heights = image_to_heights(&image, scale, offset);
+        (width as usize, length as usize, heights)
+    };
+
+    create_output_dir(out_dir)?;
+
+    for (x, y, mut him) in split_heightmap(width, length, &heights, &info)? {
+        him.write_to_path(&out_dir.join(format!("{}_{}.HIM", x, y)))?;
+    }
+
+    Ok(())
+}
+
 fn convert_iconsheets(matches: &ArgMatches) -> Result<(), Error> {
     let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
     let iconsheet_paths: Vec<PathBuf> = matches
@@ -553,6 +1278,272 @@ fn convert_iconsheets(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+fn extract_sprites(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let tsi_dir = input.parent().unwrap_or_else(|| Path::new(""));
+    let mut search_dirs = vec![tsi_dir];
+    if let Some(data_dir) = matches.value_of("data_dir") {
+        search_dirs.insert(0, Path::new(data_dir));
+    }
+
+    let tsi = TSI::from_path(input)?;
+
+    create_output_dir(out_dir)?;
+
+    let mut all_succeeded = true;
+    for sheet in tsi.sprite_sheets.iter() {
+        let sheet_path = match resolve_path(&sheet.path, &search_dirs) {
+            Some(p) => p,
+            None => {
+                all_succeeded = false;
+                eprintln!("Sprite sheet not found: {}", sheet.path.display());
+                continue;
+            }
+        };
+
+        let mut image = match load_texture(&sheet_path) {
+            Ok(image) => image,
+            Err(e) => {
+                all_succeeded = false;
+                eprintln!("{}: {}", sheet_path.display(), e);
+                continue;
+            }
+        };
+        apply_color_key(&mut image, sheet.color_key);
+
+        for (sprite_idx, sprite) in sheet.sprites.iter().enumerate() {
+            match cut_sprite(&image, sprite) {
+                Ok(sprite_image) => {
+                    sprite_image.save(out_dir.join(sprite_file_name(sheet, sprite_idx, sprite)))?
+                }
+                Err(e) => {
+                    all_succeeded = false;
+                    eprintln!("{}", e);
+                }
+            }
+        }
+    }
+
+    if !all_succeeded {
+        bail!("Failed to extract all sprites");
+    }
+
+    Ok(())
+}
+
+fn pack_sprite_sheets(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.is_dir() {
+        bail!("Sprite path is not a directory: {}", input.display());
+    }
+
+    let existing = match matches.value_of("tsi") {
+        Some(path) => Some(TSI::from_path(Path::new(path))?),
+        None => None,
+    };
+
+    let name = match (matches.value_of("name"), matches.value_of("tsi")) {
+        (Some(name), _) => PathBuf::from(name).with_extension("tsi"),
+        (None, Some(path)) => PathBuf::from(Path::new(path).file_name().unwrap_or_default()),
+        (None, None) => PathBuf::from(input.file_name().unwrap_or_default()).with_extension("tsi"),
+    };
+
+    let options = SpritePackOptions {
+        max_size: matches.value_of("max_size").unwrap_or_default().parse()?,
+        padding: matches.value_of("padding").unwrap_or_default().parse()?,
+        sheet_prefix: String::from(matches.value_of("sheet_prefix").unwrap_or_default()),
+    };
+
+    let sprites = load_sprites(input)?;
+    let (mut tsi, images) = pack_sprites(&sprites, existing.as_ref(), &options)?;
+
+    create_output_dir(out_dir)?;
+
+    for (sheet, image) in tsi.sprite_sheets.iter().zip(images.iter()) {
+        let sheet_path = PathBuf::from_rose_path(&sheet.path.to_string_lossy());
+        let out = out_dir.join(sheet_path.file_name().unwrap_or_default());
+
+        let is_dds = out
+            .extension()
+            .map(|e| e.to_string_lossy().eq_ignore_ascii_case("dds"))
+            .unwrap_or(false);
+        if is_dds {
+            image_to_dds(image, DdsFormat::Dxt5, false)?.write_to_path(&out)?;
+        } else {
+            image.save(&out)?;
+        }
+    }
+
+    tsi.write_to_path(&out_dir.join(name))?;
+
+    Ok(())
+}
+
+fn convert_lightmaps(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let input = Path::new(matches.value_of("input").unwrap_or_default());
+
+    if !input.exists() {
+        bail!("File does not exist: {}", input.display());
+    }
+
+    let mut lit = LIT::from_path(input)?;
+    let lit_dir = input.parent().unwrap_or_else(|| Path::new(""));
+    let atlases = load_atlases(&lit, lit_dir)?;
+
+    create_output_dir(out_dir)?;
+
+    let repack_dir = match matches.value_of("repack") {
+        Some(dir) => Path::new(dir),
+        None => {
+            for (name, lightmap) in extract_lightmaps(&lit, &atlases)? {
+                lightmap.save(out_dir.join(name).with_extension("png"))?;
+            }
+            return Ok(());
+        }
+    };
+
+    // Use the edited lightmap of a part if there is one, the original otherwise
+    let mut lightmaps = Vec::new();
+    for (object_idx, object) in lit.objects.iter().enumerate() {
+        for (part_idx, part) in object.parts.iter().enumerate() {
+            let name = lightmap_part_name(object_idx, part_idx, part);
+            let edited = repack_dir.join(name).with_extension("png");
+            if edited.exists() {
+                lightmaps.push(load_texture(&edited)?);
+            } else {
+                lightmaps.push(extract_lightmap(&lit, part, &atlases)?);
+            }
+        }
+    }
+
+    let atlas_size: u32 = matches.value_of("atlas_size").unwrap_or_default().parse()?;
+    let format: DdsFormat = matches.value_of("format").unwrap_or_default().parse()?;
+
+    let prefix = match matches.value_of("prefix") {
+        Some(prefix) => prefix.to_string(),
+        None => atlas_prefix(&lit),
+    };
+
+    for (filename, atlas) in repack_lightmaps(&mut lit, &lightmaps, atlas_size, &prefix)? {
+        image_to_dds(&atlas, format, true)?.write_to_path(&out_dir.join(filename))?;
+    }
+    lit.write_to_path(&out_dir.join(input.file_name().unwrap_or_default()))?;
+
+    Ok(())
+}
+
+/// Parse comma separated `x,y,z` values
+fn parse_vector3(value: &str) -> Result<Vector3<f32>, Error> {
+    let values: Vec<&str> = value.split(',').map(|v| v.trim()).collect();
+    match values.as_slice() {
+        [x, y, z] => Ok(Vector3 {
+            x: x.parse()?,
+            y: y.parse()?,
+            z: z.parse()?,
+        }),
+        _ => bail!("Expected 3 comma separated values: {}", value),
+    }
+}
+
+fn bake_lightmaps(matches: &ArgMatches) -> Result<(), Error> {
+    let out_dir = Path::new(matches.value_of("out_dir").unwrap_or_default());
+    let map_dir = Path::new(matches.value_of("map_dir").unwrap_or_default());
+    if !map_dir.is_dir() {
+        bail!("Map path is not a directory: {}", map_dir.display());
+    }
+
+    let data_dir = Path::new(matches.value_of("data_dir").unwrap_or_default());
+    // Maps without objects only need their terrain baked
+    let load_scene = |name| match matches.value_of(name) {
+        Some(path) => BakeScene::load(Path::new(path), data_dir),
+        None => Ok(BakeScene::default()),
+    };
+    let decorations = load_scene("decorations")?;
+    let constructions = load_scene("constructions")?;
+
+    let color = |name| -> Result<Color3, Error> {
+        let c = parse_vector3(matches.value_of(name).unwrap_or_default())?;
+        Ok(Color3::rgb(c.x, c.y, c.z))
+    };
+
+    let mut options = BakeOptions {
+        sun_direction: parse_vector3(matches.value_of("sun").unwrap_or_default())?,
+        sun_color: color("sun_color")?,
+        ambient_color: color("ambient")?,
+        ao_samples: matches.value_of("ao_samples").unwrap_or_default().parse()?,
+        ao_distance: matches
+            .value_of("ao_distance")
+            .unwrap_or_default()
+            .parse()?,
+        terrain_size: matches
+            .value_of("terrain_size")
+            .unwrap_or_default()
+            .parse()?,
+        atlas_size: matches.value_of("atlas_size").unwrap_or_default().parse()?,
+        ..BakeOptions::default()
+    };
+
+    // Heightmap spacing comes from the zone
+    let map_name = map_dir.file_name().unwrap_or_default().to_string_lossy();
+    let zon_name = PathBuf::from(format!("{}.ZON", map_name));
+    if let Some(zon_path) = resolve_path(&zon_name, &[map_dir]) {
+        options.grid_size = ZON::from_path(&zon_path)?.grid_size;
+    }
+
+    let format: DdsFormat = matches.value_of("format").unwrap_or_default().parse()?;
+
+    let blocks = load_map(map_dir)?;
+    if blocks.is_empty() {
+        bail!("No heightmaps found in {}", map_dir.display());
+    }
+
+    println!(
+        "Baking {} map blocks from: {}",
+        blocks.len(),
+        map_dir.display()
+    );
+    for block in bake_map(&blocks, &decorations, &constructions, &options)? {
+        let terrain_path = out_dir.join(block.terrain_path());
+        create_output_dir(terrain_path.parent().unwrap_or(out_dir))?;
+        image_to_dds(&block.terrain, format, true)?.write_to_path(&terrain_path)?;
+
+        let lightmap_dir = out_dir.join(block.lightmap_dir());
+        let lits = [
+            (
+                "ObjectLightmapData.lit",
+                block.objects,
+                block.object_atlases,
+            ),
+            (
+                "BuildingLightmapData.lit",
+                block.buildings,
+                block.building_atlases,
+            ),
+        ];
+        for (lit_name, mut lit, atlases) in lits {
+            if lit.objects.is_empty() {
+                continue;
+            }
+            create_output_dir(&lightmap_dir)?;
+            for (filename, atlas) in atlases {
+                image_to_dds(&atlas, format, true)?.write_to_path(&lightmap_dir.join(filename))?;
+            }
+            lit.write_to_path(&lightmap_dir.join(lit_name))?;
+        }
+    }
+
+    Ok(())
+}
+
 /*
 fn zms_to_obj(input: File, output: File) -> Result<(), Error> {
     let mut writer = BufWriter::new(output);
@@ -0,0 +1,331 @@
+//! Export ZCA cameras and camera motions to glTF
+use failure::Error;
+use serde_json::json;
+
+use roselib::files::zca::{CameraFrame, CameraProjection};
+use roselib::files::{ZCA, ZMO};
+use roselib::utils::{Quaternion, Vector3};
+
+/// Convert a ROSE position (Z up) to glTF (Y up)
+fn gltf_position(v: &Vector3<f32>, scale: f32) -> [f32; 3] {
+    [v.x * scale, v.z * scale, -v.y * scale]
+}
+
+fn from_array(v: [f32; 3]) -> Vector3<f32> {
+    Vector3 {
+        x: v[0],
+        y: v[1],
+        z: v[2],
+    }
+}
+
+/// glTF rotation (x, y, z, w) of a camera looking from `eye` to `target`.
+/// glTF cameras look down their local -Z axis with +Y up.
+fn gltf_rotation(frame: &CameraFrame) -> [f32; 4] {
+    let eye = from_array(gltf_position(&frame.eye, 1.0));
+    let target = from_array(gltf_position(&frame.target, 1.0));
+    let mut up = from_array(gltf_position(&frame.up, 1.0));
+
+    let z = eye.sub(&target).normalize();
+    if z.length() == 0.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+
+    // Pick another up vector when looking straight up or down
+    if up.cross(&z).length() < 1e-6 {
+        up = if z.y.abs() < 0.9 {
+            from_array([0.0, 1.0, 0.0])
+        } else {
+            from_array([0.0, 0.0, 1.0])
+        };
+    }
+    let x = up.cross(&z).normalize();
+    let y = z.cross(&x);
+
+    // Rotation matrix with x, y and z as columns to quaternion
+    let trace = x.x + y.y + z.z;
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        Quaternion {
+            w: 0.25 * s,
+            x: (y.z - z.y) / s,
+            y: (z.x - x.z) / s,
+            z: (x.y - y.x) / s,
+        }
+    } else if x.x > y.y && x.x > z.z {
+        let s = (1.0 + x.x - y.y - z.z).sqrt() * 2.0;
+        Quaternion {
+            w: (y.z - z.y) / s,
+            x: 0.25 * s,
+            y: (y.x + x.y) / s,
+            z: (z.x + x.z) / s,
+        }
+    } else if y.y > z.z {
+        let s = (1.0 + y.y - x.x - z.z).sqrt() * 2.0;
+        Quaternion {
+            w: (z.x - x.z) / s,
+            x: (y.x + x.y) / s,
+            y: 0.25 * s,
+            z: (z.y + y.z) / s,
+        }
+    } else {
+        let s = (1.0 + z.z - x.x - y.y).sqrt() * 2.0;
+        Quaternion {
+            w: (x.y - y.x) / s,
+            x: (z.x + x.z) / s,
+            y: (z.y + y.z) / s,
+            z: 0.25 * s,
+        }
+    }
+    .normalize();
+
+    [q.x, q.y, q.z, q.w]
+}
+
+/// Export a camera as a binary glTF (GLB) with a camera node.
+///
+/// When a camera motion is given the node is animated along the camera
+/// path. glTF can't animate the field of view so the per-frame values are
+/// stored in the animation's `extras`. Positions are multiplied by `scale`.
+pub fn camera_to_glb(
+    name: &str,
+    camera: &ZCA,
+    motion: Option<&ZMO>,
+    scale: f32,
+) -> Result<Vec<u8>, Error> {
+    let frames = match motion {
+        Some(motion) if motion.frames > 0 => camera.frames(motion),
+        _ => vec![CameraFrame {
+            eye: camera.eye,
+            target: camera.target,
+            up: camera.up,
+            fov: camera.fov,
+            near: camera.near,
+            far: camera.far,
+        }],
+    };
+    let first = &frames[0];
+
+    let near = if first.near > 0.0 { first.near } else { 1.0 } * scale;
+    let far = if first.far > first.near {
+        first.far * scale
+    } else {
+        near * 1000.0
+    };
+
+    let gltf_camera = match camera.projection {
+        CameraProjection::Perspective => {
+            let mut perspective = json!({
+                "yfov": first.fov.to_radians(),
+                "znear": near,
+                "zfar": far,
+            });
+            if camera.aspect_ratio > 0.0 {
+                perspective["aspectRatio"] = json!(camera.aspect_ratio);
+            }
+            json!({ "type": "perspective", "perspective": perspective })
+        }
+        CameraProjection::Orthographic => {
+            let m = &camera.projection_matrix.m;
+            let magnification = |v: f32| if v != 0.0 { 1.0 / v.abs() } else { 1.0 };
+            json!({
+                "type": "orthographic",
+                "orthographic": {
+                    "xmag": magnification(m[0][0]),
+                    "ymag": magnification(m[1][1]),
+                    "znear": near,
+                    "zfar": far,
+                },
+            })
+        }
+    };
+
+    let mut root = json!({
+        "asset": { "version": "2.0", "generator": "rose-conv" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{
+            "name": name,
+            "camera": 0,
+            "translation": gltf_position(&first.eye, scale),
+            "rotation": gltf_rotation(first),
+        }],
+        "cameras": [gltf_camera],
+    });
+
+    let mut buffer: Vec<u8> = Vec::new();
+    if let Some(motion) = motion.filter(|_| frames.len() > 1) {
+        let fps = motion.fps.max(1) as f32;
+        let times: Vec<f32> = (0..frames.len()).map(|i| i as f32 / fps).collect();
+
+        for t in times.iter() {
+            buffer.extend_from_slice(&t.to_le_bytes());
+        }
+        let translation_offset = buffer.len();
+        for frame in frames.iter() {
+            for v in gltf_position(&frame.eye, scale).iter() {
+                buffer.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        let rotation_offset = buffer.len();
+        for frame in frames.iter() {
+            for v in gltf_rotation(frame).iter() {
+                buffer.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        let count = frames.len();
+        root["buffers"] = json!([{ "byteLength": buffer.len() }]);
+        root["bufferViews"] = json!([
+            { "buffer": 0, "byteOffset": 0, "byteLength": translation_offset },
+            {
+                "buffer": 0,
+                "byteOffset": translation_offset,
+                "byteLength": rotation_offset - translation_offset,
+            },
+            {
+                "buffer": 0,
+                "byteOffset": rotation_offset,
+                "byteLength": buffer.len() - rotation_offset,
+            },
+        ]);
+        root["accessors"] = json!([
+            {
+                "bufferView": 0,
+                "componentType": 5126,
+                "count": count,
+                "type": "SCALAR",
+                "min": [times[0]],
+                "max": [times[count - 1]],
+            },
+            { "bufferView": 1, "componentType": 5126, "count": count, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5126, "count": count, "type": "VEC4" },
+        ]);
+        root["animations"] = json!([{
+            "name": name,
+            "samplers": [
+                { "input": 0, "output": 1, "interpolation": "LINEAR" },
+                { "input": 0, "output": 2, "interpolation": "LINEAR" },
+            ],
+            "channels": [
+                { "sampler": 0, "target": { "node": 0, "path": "translation" } },
+                { "sampler": 1, "target": { "node": 0, "path": "rotation" } },
+            ],
+            "extras": {
+                "fov": frames.iter().map(|f| f.fov).collect::<Vec<f32>>(),
+            },
+        }]);
+    }
+
+    Ok(glb(&serde_json::to_vec(&root)?, &buffer))
+}
+
+/// Pack a glTF JSON document and its binary buffer into a GLB
+fn glb(json: &[u8], bin: &[u8]) -> Vec<u8> {
+    let pad = |len: usize| (4 - len % 4) % 4;
+    let json_len = json.len() + pad(json.len());
+    let bin_len = bin.len() + pad(bin.len());
+
+    let mut total = 12 + 8 + json_len;
+    if !bin.is_empty() {
+        total += 8 + bin_len;
+    }
+
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(b"glTF");
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&(total as u32).to_le_bytes());
+
+    out.extend_from_slice(&(json_len as u32).to_le_bytes());
+    out.extend_from_slice(b"JSON");
+    out.extend_from_slice(json);
+    out.resize(out.len() + pad(json.len()), b' ');
+
+    if !bin.is_empty() {
+        out.extend_from_slice(&(bin_len as u32).to_le_bytes());
+        out.extend_from_slice(b"BIN\0");
+        out.extend_from_slice(bin);
+        out.resize(out.len() + pad(bin.len()), 0);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use roselib::files::zca::{CAMERA_EYE_CHANNEL, CAMERA_TARGET_CHANNEL, CAMERA_UP_CHANNEL};
+    use roselib::files::zmo::{Channel, ChannelData, ChannelType};
+    use roselib::io::RoseFile;
+
+    fn vector(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3 { x, y, z }
+    }
+
+    fn position_channel(index: u32, frames: Vec<Vector3<f32>>) -> Channel {
+        Channel {
+            typ: ChannelType::Position,
+            index,
+            frames: ChannelData::Position(frames),
+        }
+    }
+
+    #[test]
+    fn test_camera_glb() {
+        let mut camera = ZCA::new();
+        camera.fov = 45.0;
+        camera.aspect_ratio = 1.5;
+        camera.near = 10.0;
+        camera.far = 10000.0;
+
+        let mut motion = ZMO::new();
+        motion.fps = 10;
+        motion.frames = 3;
+        motion.channels.push(position_channel(
+            CAMERA_EYE_CHANNEL,
+            vec![vector(0.0, -100.0, 0.0); 3],
+        ));
+        motion.channels.push(position_channel(
+            CAMERA_TARGET_CHANNEL,
+            vec![
+                vector(0.0, 0.0, 0.0),
+                vector(100.0, -100.0, 0.0),
+                vector(0.0, -100.0, -100.0),
+            ],
+        ));
+        motion.channels.push(position_channel(
+            CAMERA_UP_CHANNEL,
+            vec![vector(0.0, 0.0, 1.0); 3],
+        ));
+
+        let frames = camera.frames(&motion);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].target, vector(100.0, -100.0, 0.0));
+        assert_eq!(frames[1].fov, 45.0);
+
+        let data = camera_to_glb("intro", &camera, Some(&motion), 0.01).unwrap();
+        let gltf = gltf::Gltf::from_slice(&data).unwrap();
+        assert_eq!(gltf.cameras().count(), 1);
+
+        let node = gltf.nodes().next().unwrap();
+        assert!(node.camera().is_some());
+        let (translation, rotation, _) = node.transform().decomposed();
+        assert_eq!(translation, [0.0, 0.0, 1.0]);
+
+        // Looking along ROSE +Y is looking along glTF -Z
+        for (a, b) in rotation.iter().zip([0.0, 0.0, 0.0, 1.0].iter()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        let animation = gltf.animations().next().unwrap();
+        assert_eq!(animation.channels().count(), 2);
+        assert_eq!(animation.samplers().next().unwrap().input().count(), 3);
+
+        // Without a motion only the camera is exported
+        let data = camera_to_glb("still", &camera, None, 1.0).unwrap();
+        let gltf = gltf::Gltf::from_slice(&data).unwrap();
+        assert_eq!(gltf.animations().count(), 0);
+        assert_eq!(gltf.cameras().count(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+//! Convert map heightmaps to and from 16-bit images and float RAW files
+use std::convert::TryInto;
+
+use failure::{bail, Error};
+use image::{ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+
+use roselib::files::HIM;
+use roselib::io::RoseFile;
+
+/// 16-bit grayscale heightmap image
+pub type HeightImage = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// Width and length of a heightmap block, neighbouring blocks share their
+/// edge vertices
+pub const BLOCK_SIZE: usize = 65;
+
+/// Sidecar of an exported heightmap image with what is needed to import it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HeightmapInfo {
+    /// Block coordinates of the top left heightmap
+    pub x: i32,
+    pub y: i32,
+
+    /// Heights of the darkest and brightest pixel
+    pub min_height: f32,
+    pub max_height: f32,
+
+    /// Grid count and scale of the heightmap blocks
+    pub grid_count: i32,
+    pub scale: f32,
+
+    /// Coordinates of the blocks the map has, all blocks when empty
+    #[serde(default)]
+    pub blocks: Vec<(i32, i32)>,
+}
+
+impl Default for HeightmapInfo {
+    fn default() -> HeightmapInfo {
+        HeightmapInfo {
+            x: 0,
+            y: 0,
+            min_height: 0.0,
+            max_height: 1.0,
+            grid_count: 4,
+            scale: 250.0,
+            blocks: Vec::new(),
+        }
+    }
+}
+
+/// Normalize heights to the full 16-bit range between `min_height` and `max_height`
+pub fn heights_to_image(
+    width: u32,
+    length: u32,
+    heights: &[f32],
+    min_height: f32,
+    max_height: f32,
+) -> Result<HeightImage, Error> {
+    if heights.len() != (width * length) as usize {
+        bail!(
+            "Expected {}x{} heights, found {}",
+            width,
+            length,
+            heights.len()
+        );
+    }
+
+    let range = max_height - min_height;
+    Ok(HeightImage::from_fn(width, length, |x, y| {
+        let height = heights[(y * width + x) as usize];
+        let value = if range > 0.0 {
+            (height - min_height) / range
+        } else {
+            0.0
+        };
+        Luma([(value.clamp(0.0, 1.0) * 65535.0).round() as u16])
+    }))
+}
+
+/// Heights of an image, mapping 0 to `offset` and 65535 to `offset + scale`
+pub fn image_to_heights(image: &HeightImage, scale: f32, offset: f32) -> Vec<f32> {
+    image
+        .pixels()
+        .map(|p| p.0[0] as f32 / 65535.0 * scale + offset)
+        .collect()
+}
+
+/// Heights of a RAW file of little endian floats, scaled and offset
+pub fn raw_to_heights(data: &[u8], scale: f32, offset: f32) -> Result<Vec<f32>, Error> {
+    if !data.len().is_multiple_of(4) {
+        bail!("RAW heightmap size is not a multiple of 4 bytes");
+    }
+
+    Ok(data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap_or_default()) * scale + offset)
+        .collect())
+}
+
+/// Split heights into `X_Y` heightmap blocks starting at the block of `info`.
+///
+/// Blocks overlap by one row and column, so the heights must be
+/// `64 * n + 1` wide and long. Patches of every block are recomputed.
+/// Blocks missing from `info.blocks` are skipped unless it is empty.
+pub fn split_heightmap(
+    width: usize,
+    length: usize,
+    heights: &[f32],
+    info: &HeightmapInfo,
+) -> Result<Vec<(i32, i32, HIM)>, Error> {
+    let step = BLOCK_SIZE - 1;
+    if heights.len() != width * length {
+        bail!(
+            "Expected {}x{} heights, found {}",
+            width,
+            length,
+            heights.len()
+        );
+    }
+    if width < BLOCK_SIZE
+        || length < BLOCK_SIZE
+        || !(width - 1).is_multiple_of(step)
+        || !(length - 1).is_multiple_of(step)
+    {
+        bail!(
+            "Heightmap of {}x{} can not be split into {}x{} blocks, expected {} * n + 1",
+            width,
+            length,
+            BLOCK_SIZE,
+            BLOCK_SIZE,
+            step
+        );
+    }
+
+    let mut blocks = Vec::new();
+    for block_y in 0..(length - 1) / step {
+        for block_x in 0..(width - 1) / step {
+            let (x, y) = (info.x + block_x as i32, info.y + block_y as i32);
+            if !info.blocks.is_empty() && !info.blocks.contains(&(x, y)) {
+                continue;
+            }
+
+            let mut him = HIM::new();
+            him.width = BLOCK_SIZE as i32;
+            him.length = BLOCK_SIZE as i32;
+            him.grid_count = info.grid_count;
+            him.scale = info.scale;
+
+            for y in 0..BLOCK_SIZE {
+                let start = (block_y * step + y) * width + block_x * step;
+                him.heights
+                    .extend_from_slice(&heights[start..start + BLOCK_SIZE]);
+            }
+            him.update_patches()?;
+
+            blocks.push((x, y, him));
+        }
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heightmap_roundtrip() {
+        // Two blocks side by side sharing the middle column
+        let (width, length) = (129, 65);
+        let heights: Vec<f32> = (0..width * length)
+            .map(|i| ((i % width) * 10) as f32 - 300.0)
+            .collect();
+
+        let image = heights_to_image(width as u32, length as u32, &heights, -300.0, 980.0).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0[0], 0);
+        assert_eq!(image.get_pixel(128, 64).0[0], 65535);
+
+        let imported = image_to_heights(&image, 1280.0, -300.0);
+        for (a, b) in heights.iter().zip(imported.iter()) {
+            assert!((a - b).abs() < 0.01, "{} {}", a, b);
+        }
+
+        let info = HeightmapInfo {
+            x: 30,
+            y: 31,
+            ..HeightmapInfo::default()
+        };
+        let blocks = split_heightmap(width, length, &heights, &info).unwrap();
+        assert_eq!(blocks.len(), 2);
+
+        let (x, y, left) = &blocks[0];
+        let (_, _, right) = &blocks[1];
+        assert_eq!((*x, *y), (30, 31));
+        assert_eq!(blocks[1].0, 31);
+        assert_eq!(left.heights[64], right.heights[0]);
+        assert_eq!(left.max_height, 340.0);
+        assert_eq!(right.min_height, 340.0);
+        assert_eq!(left.patches.len(), 256);
+
+        assert!(split_heightmap(width - 1, length, &heights[length..], &info).is_err());
+
+        // Only the blocks the map has are written
+        let info = HeightmapInfo {
+            blocks: vec![(31, 31)],
+            ..info
+        };
+        let blocks = split_heightmap(width, length, &heights, &info).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].0, blocks[0].1), (31, 31));
+        assert_eq!(blocks[0].2.heights[0], left.heights[64]);
+    }
+
+    #[test]
+    fn test_raw_heights() {
+        let data: Vec<u8> = [1.5f32, -2.0]
+            .iter()
+            .flat_map(|h| h.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(raw_to_heights(&data, 2.0, 1.0).unwrap(), vec![4.0, -3.0]);
+        assert!(raw_to_heights(&data[1..], 1.0, 0.0).is_err());
+    }
+}
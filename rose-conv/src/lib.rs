@@ -9,6 +9,15 @@ use roselib::files::stl::*;
 use roselib::files::*;
 use roselib::io::RoseFile;
 
+pub mod bake;
+pub mod camera;
+pub mod heightmap;
+pub mod lightmap;
+pub mod motion;
+pub mod particles;
+pub mod sprites;
+pub mod texture;
+
 pub trait ToCsv {
     fn to_csv(&self) -> Result<String, Error>;
 }
@@ -96,6 +105,13 @@ impl ToCsv for STL {
     }
 }
 
+/// LTB files share the STL CSV layout as a normal string table
+impl ToCsv for LTB {
+    fn to_csv(&self) -> Result<String, Error> {
+        self.to_string_table(StringTableType::Normal)?.to_csv()
+    }
+}
+
 pub trait FromCsv {
     fn from_csv(s: &str) -> Result<Self, Error>
     where
@@ -238,6 +254,16 @@ impl FromCsv for STL {
     }
 }
 
+impl FromCsv for LTB {
+    fn from_csv(s: &str) -> Result<Self, Error>
+    where
+        Self: std::marker::Sized,
+    {
+        let stl = STL::from_csv(s)?;
+        Ok(LTB::from(&stl))
+    }
+}
+
 pub trait ToJson {
     fn to_json(&self) -> Result<String, Error>
     where
@@ -278,6 +304,11 @@ mod tests {
 
     use std::path::PathBuf;
 
+    use roselib::files::aip::{AiAction, AiCondition, AiEvent, AiPattern};
+    use roselib::files::chr::Character;
+    use roselib::files::ptl::{ParticleEvent, ParticleEventData, ParticleRange, ParticleSequence};
+    use roselib::files::qsd::{QuestAction, QuestCondition, QuestGroup, QuestTrigger};
+
     macro_rules! test_csv {
         ($filetype: ident, $path: expr) => {{
             let orig_file = $filetype::from_path(&$path).unwrap();
@@ -323,4 +354,98 @@ mod tests {
         test_json!(ZSC, root.join("list_weapon.zsc"));
         test_json!(ZSC, root.join("part_npc.zsc"));
     }
+
+    #[test]
+    fn test_aip_json() {
+        let mut aip = AIP::new();
+        aip.events.push(AiEvent {
+            name: String::from("idle"),
+            patterns: vec![AiPattern {
+                name: String::from("wander"),
+                conditions: vec![AiCondition::RandomPercent { percent: 50 }],
+                actions: vec![AiAction::MoveRandom {
+                    distance: 100,
+                    speed: 0,
+                }],
+            }],
+        });
+
+        let json_string = aip.to_json().unwrap();
+        assert_eq!(AIP::from_json(&json_string).unwrap(), aip);
+    }
+
+    #[test]
+    fn test_chr_json() {
+        let mut chr = CHR::new();
+        chr.skeletons.push(PathBuf::from("3DData\\NPC\\jelly.zmd"));
+        chr.characters.push(None);
+        chr.characters.push(Some(Character {
+            name: String::from("Jelly Bean"),
+            objects: vec![1, 2],
+            ..Default::default()
+        }));
+
+        let json_string = chr.to_json().unwrap();
+        assert_eq!(CHR::from_json(&json_string).unwrap(), chr);
+    }
+
+    #[test]
+    fn test_ptl_json() {
+        let mut sequence = ParticleSequence::new();
+        sequence.events.push(ParticleEvent {
+            start_time: ParticleRange { min: 0.0, max: 1.0 },
+            fade: true,
+            data: ParticleEventData::Alpha(ParticleRange { min: 1.0, max: 0.0 }),
+        });
+
+        let mut ptl = PTL::new();
+        ptl.sequences.push(sequence);
+
+        let json_string = ptl.to_json().unwrap();
+        assert_eq!(PTL::from_json(&json_string).unwrap(), ptl);
+    }
+
+    #[test]
+    fn test_qsd_json() {
+        let mut qsd = QSD::new();
+        qsd.name = String::from("quest");
+        qsd.groups.push(QuestGroup {
+            name: String::from("group"),
+            triggers: vec![QuestTrigger {
+                name: String::from("trigger"),
+                check_next: false,
+                conditions: vec![QuestCondition::SelectNpc { npc: 1001 }],
+                actions: vec![
+                    QuestAction::ClearAllSwitches,
+                    QuestAction::Unknown {
+                        id: 40,
+                        data: vec![1, 2, 3, 4],
+                    },
+                ],
+            }],
+        });
+
+        let json_string = qsd.to_json().unwrap();
+        assert_eq!(QSD::from_json(&json_string).unwrap(), qsd);
+    }
+
+    #[test]
+    fn test_ltb_csv() {
+        let mut ltb = LTB::new();
+        ltb.column_count = 3;
+        ltb.rows.push(vec![
+            String::from("UI_OK"),
+            String::from("확인"),
+            String::from("OK, \"fine\""),
+        ]);
+        ltb.rows.push(vec![
+            String::from("UI_CANCEL"),
+            String::from("취소"),
+            String::from("Cancel"),
+        ]);
+
+        let csv_string = ltb.to_csv().unwrap();
+        assert!(csv_string.starts_with("NRST01,,,Korean,English"));
+        assert_eq!(LTB::from_csv(&csv_string).unwrap(), ltb);
+    }
 }
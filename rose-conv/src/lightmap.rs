@@ -0,0 +1,315 @@
+//! Extract and repack object lightmaps described by LIT files
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use failure::{bail, Error};
+use image::{imageops, RgbaImage};
+
+use roselib::files::lit::LightmapPart;
+use roselib::files::LIT;
+use roselib::io::PathRoseExt;
+
+use crate::sprites::resolve_path;
+use crate::texture::load_texture;
+
+/// File name of an extracted part lightmap, without extension.
+///
+/// Parts are named after their original lightmap (e.g.
+/// `fountain_Object_1_0_32_32_LightingMap`), or `<object>_<part>` by index.
+pub fn lightmap_part_name(object_idx: usize, part_idx: usize, part: &LightmapPart) -> String {
+    let name = PathBuf::from_rose_path(&part.name);
+    match name.file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => format!("{}_{}", object_idx, part_idx),
+    }
+}
+
+/// Load every atlas of a lightmap that exists next to the LIT file
+pub fn load_atlases(lit: &LIT, lit_dir: &Path) -> Result<HashMap<String, RgbaImage>, Error> {
+    let mut atlases = HashMap::new();
+    for object in lit.objects.iter() {
+        for part in object.parts.iter() {
+            let filename = match lit.atlas_filename(part) {
+                Some(filename) => filename,
+                None => continue,
+            };
+            if atlases.contains_key(filename) {
+                continue;
+            }
+            if let Some(path) = resolve_path(Path::new(filename), &[lit_dir]) {
+                atlases.insert(filename.to_string(), load_texture(&path)?);
+            }
+        }
+    }
+    Ok(atlases)
+}
+
+/// Cut the lightmap of a part out of its atlas
+pub fn extract_lightmap(
+    lit: &LIT,
+    part: &LightmapPart,
+    atlases: &HashMap<String, RgbaImage>,
+) -> Result<RgbaImage, Error> {
+    let rect = match part.rect() {
+        Some(rect) => rect,
+        None => bail!("Invalid lightmap position for part {}", part.name),
+    };
+
+    let filename = lit.atlas_filename(part).unwrap_or_default();
+    let atlas = match atlases.get(filename) {
+        Some(atlas) => atlas,
+        None => bail!("Lightmap atlas not found: {}", filename),
+    };
+
+    if rect.x + rect.size > atlas.width() || rect.y + rect.size > atlas.height() {
+        bail!(
+            "Lightmap part {} is outside of the {}x{} atlas {}",
+            part.name,
+            atlas.width(),
+            atlas.height(),
+            filename
+        );
+    }
+
+    Ok(imageops::crop_imm(atlas, rect.x, rect.y, rect.size, rect.size).to_image())
+}
+
+/// Extract the lightmap of every part, in object and part order
+pub fn extract_lightmaps(
+    lit: &LIT,
+    atlases: &HashMap<String, RgbaImage>,
+) -> Result<Vec<(String, RgbaImage)>, Error> {
+    let mut lightmaps = Vec::new();
+    for (object_idx, object) in lit.objects.iter().enumerate() {
+        for (part_idx, part) in object.parts.iter().enumerate() {
+            lightmaps.push((
+                lightmap_part_name(object_idx, part_idx, part),
+                extract_lightmap(lit, part, atlases)?,
+            ));
+        }
+    }
+    Ok(lightmaps)
+}
+
+/// Atlas name prefix of a LIT from its current atlas file names, e.g.
+/// `Building` for `Building_32_0.dds`. Defaults to `Object`.
+pub fn atlas_prefix(lit: &LIT) -> String {
+    lit.filenames
+        .iter()
+        .filter_map(|f| f.rsplit(['/', '\\']).next())
+        .filter_map(|f| f.split('_').next())
+        .find(|prefix| !prefix.is_empty())
+        .unwrap_or("Object")
+        .to_string()
+}
+
+/// Pack part lightmaps into new atlases and update the LIT to match.
+///
+/// `lightmaps` has one square image per part in object and part order, their
+/// size must divide `atlas_size` so the client's part UVs line up. Parts of
+/// the same size share `atlas_size` atlases named `<prefix>_<size>_<n>.dds`
+/// like the original client files (`Object` or `Building`).
+///
+/// Returns the file name and image of each atlas.
+pub fn repack_lightmaps(
+    lit: &mut LIT,
+    lightmaps: &[RgbaImage],
+    atlas_size: u32,
+    prefix: &str,
+) -> Result<Vec<(String, RgbaImage)>, Error> {
+    let part_count: usize = lit.objects.iter().map(|o| o.parts.len()).sum();
+    if lightmaps.len() != part_count {
+        bail!(
+            "Expected {} lightmaps for the LIT parts, found {}",
+            part_count,
+            lightmaps.len()
+        );
+    }
+
+    // Group parts by size, keeping their order
+    let mut groups: BTreeMap<u32, Vec<(usize, usize, usize)>> = BTreeMap::new();
+    let mut lightmap_idx = 0;
+    for (object_idx, object) in lit.objects.iter().enumerate() {
+        for (part_idx, part) in object.parts.iter().enumerate() {
+            let (width, height) = lightmaps[lightmap_idx].dimensions();
+            if width != height || width == 0 || width > atlas_size {
+                bail!(
+                    "Lightmap of part {} must be square and at most {} pixels, found {}x{}",
+                    part.name,
+                    atlas_size,
+                    width,
+                    height
+                );
+            }
+            if !atlas_size.is_multiple_of(width) {
+                bail!(
+                    "Lightmap of part {} is {} pixels which does not divide the {} pixel atlas",
+                    part.name,
+                    width,
+                    atlas_size
+                );
+            }
+            groups
+                .entry(width)
+                .or_default()
+                .push((object_idx, part_idx, lightmap_idx));
+            lightmap_idx += 1;
+        }
+    }
+
+    let mut atlases: Vec<(String, RgbaImage)> = Vec::new();
+    for (size, parts) in groups {
+        let parts_per_width = atlas_size / size;
+        let parts_per_atlas = (parts_per_width * parts_per_width) as usize;
+
+        for (atlas_number, chunk) in parts.chunks(parts_per_atlas).enumerate() {
+            let filename = format!("{}_{}_{}.dds", prefix, size, atlas_number);
+            let mut atlas = RgbaImage::new(atlas_size, atlas_size);
+
+            for (position, (object_idx, part_idx, lightmap_idx)) in chunk.iter().enumerate() {
+                let position = position as u32;
+                let x = position % parts_per_width * size;
+                let y = position / parts_per_width * size;
+                imageops::replace(&mut atlas, &lightmaps[*lightmap_idx], x, y);
+
+                let part = &mut lit.objects[*object_idx].parts[*part_idx];
+                part.filename = filename.clone();
+                part.lightmap_index = atlases.len() as i32;
+                part.pixels_per_part = size as i32;
+                part.parts_per_width = parts_per_width as i32;
+                part.part_position = position as i32;
+            }
+
+            atlases.push((filename, atlas));
+        }
+    }
+
+    lit.filenames = atlases.iter().map(|(name, _)| name.clone()).collect();
+
+    Ok(atlases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::Rgba;
+    use roselib::files::lit::LightmapObject;
+    use roselib::io::RoseFile;
+
+    fn part(name: &str, size: i32, parts_per_width: i32, position: i32) -> LightmapPart {
+        LightmapPart {
+            name: String::from(name),
+            id: 0,
+            filename: format!("Object_{}_0.dds", size),
+            lightmap_index: if size == 32 { 0 } else { 1 },
+            pixels_per_part: size,
+            parts_per_width,
+            part_position: position,
+        }
+    }
+
+    fn lightmap() -> LIT {
+        let mut lit = LIT::new();
+        lit.objects.push(LightmapObject {
+            id: 1,
+            parts: vec![
+                part("wall_Object_1_0_32_32_LightingMap.tga", 32, 2, 3),
+                part("wall_Object_1_1_16_16_LightingMap.tga", 16, 4, 5),
+            ],
+        });
+        lit.objects.push(LightmapObject {
+            id: 2,
+            parts: vec![part("", 32, 2, 0)],
+        });
+        lit.filenames = vec![
+            String::from("Object_32_0.dds"),
+            String::from("Object_16_0.dds"),
+        ];
+        lit
+    }
+
+    fn atlas(size: u32, cell: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let cell = (y / cell * (size / cell) + x / cell) as u8;
+            Rgba([cell, cell, cell, 255])
+        })
+    }
+
+    #[test]
+    fn test_extract_lightmaps() {
+        let lit = lightmap();
+        let mut atlases = HashMap::new();
+        atlases.insert(String::from("Object_32_0.dds"), atlas(64, 32));
+        atlases.insert(String::from("Object_16_0.dds"), atlas(64, 16));
+
+        let lightmaps = extract_lightmaps(&lit, &atlases).unwrap();
+        let names: Vec<&str> = lightmaps.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "wall_Object_1_0_32_32_LightingMap",
+                "wall_Object_1_1_16_16_LightingMap",
+                "1_0"
+            ]
+        );
+        assert_eq!(
+            lightmaps[0].1,
+            RgbaImage::from_pixel(32, 32, Rgba([3, 3, 3, 255]))
+        );
+        assert_eq!(
+            lightmaps[1].1,
+            RgbaImage::from_pixel(16, 16, Rgba([5, 5, 5, 255]))
+        );
+
+        atlases.remove("Object_16_0.dds");
+        assert!(extract_lightmaps(&lit, &atlases).is_err());
+    }
+
+    #[test]
+    fn test_atlas_prefix() {
+        let mut lit = LIT::new();
+        assert_eq!(atlas_prefix(&lit), "Object");
+
+        lit.filenames
+            .push(String::from("3DDATA\\JUNON\\Building_32_0.dds"));
+        assert_eq!(atlas_prefix(&lit), "Building");
+    }
+
+    #[test]
+    fn test_repack_lightmaps() {
+        let mut lit = lightmap();
+        let lightmaps = vec![
+            RgbaImage::from_pixel(32, 32, Rgba([1, 1, 1, 255])),
+            // Edited at a higher resolution
+            RgbaImage::from_pixel(32, 32, Rgba([2, 2, 2, 255])),
+            RgbaImage::from_pixel(16, 16, Rgba([3, 3, 3, 255])),
+        ];
+
+        let atlases = repack_lightmaps(&mut lit, &lightmaps, 32, "Object").unwrap();
+        let names: Vec<&str> = atlases.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Object_16_0.dds", "Object_32_0.dds", "Object_32_1.dds"]
+        );
+        assert_eq!(lit.filenames.len(), 3);
+
+        let atlases: HashMap<String, RgbaImage> = atlases.into_iter().collect();
+        let extracted = extract_lightmaps(&lit, &atlases).unwrap();
+        for ((_, extracted), lightmap) in extracted.iter().zip(lightmaps.iter()) {
+            assert_eq!(extracted, lightmap);
+        }
+
+        let part = &lit.objects[1].parts[0];
+        assert_eq!(part.lightmap_index, 0);
+        assert_eq!(part.parts_per_width, 2);
+
+        assert!(repack_lightmaps(&mut lit, &lightmaps[..2], 32, "Object").is_err());
+        assert!(repack_lightmaps(&mut lit, &lightmaps, 16, "Object").is_err());
+
+        // A 24 pixel part would leave a gap in a 64 pixel atlas
+        let mut lightmaps = lightmaps;
+        lightmaps[2] = RgbaImage::new(24, 24);
+        assert!(repack_lightmaps(&mut lit, &lightmaps, 64, "Object").is_err());
+    }
+}
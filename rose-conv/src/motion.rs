@@ -0,0 +1,810 @@
+//! Import glTF and BVH animations as ROSE motions
+//!
+//! Clips are sampled at a fixed frame rate against a target skeleton. Every
+//! bone in the skeleton gets a `Position` and a `Rotation` channel whose
+//! `index` is the bone's index in the ZMD. The clip is expected to be
+//! authored in the same space and units as the skeleton, no axis or unit
+//! conversion is applied.
+use std::path::Path;
+
+use failure::{bail, Error};
+
+use roselib::files::zmo::{Channel, ChannelType};
+use roselib::files::{ZMD, ZMO};
+use roselib::io::RoseFile;
+use roselib::utils::{Quaternion, Vector3};
+
+const ZMO_IDENTIFIER: &str = "ZMO0002";
+
+/// A source of joint transforms that can be sampled over time
+trait Clip {
+    /// Length of the clip in seconds
+    fn duration(&self) -> f32;
+
+    /// Look up a joint by name
+    fn joint(&self, name: &str) -> Option<usize>;
+
+    /// Local position and rotation of a joint at `time` seconds
+    fn sample(&self, joint: usize, time: f32) -> (Vector3<f32>, Quaternion);
+}
+
+/// Options used when importing animations
+#[derive(Debug)]
+pub struct MotionImportOptions {
+    /// Frames per second of the generated motion
+    pub fps: u32,
+
+    /// Name of the glTF animation to import, the first one is used if `None`
+    pub animation: Option<String>,
+}
+
+impl Default for MotionImportOptions {
+    fn default() -> MotionImportOptions {
+        MotionImportOptions {
+            fps: 30,
+            animation: None,
+        }
+    }
+}
+
+/// Import a motion from a glTF (`.gltf`/`.glb`) file
+pub fn motion_from_gltf(
+    path: &Path,
+    skeleton: &ZMD,
+    options: &MotionImportOptions,
+) -> Result<ZMO, Error> {
+    let (document, buffers, _) = gltf::import(path)?;
+    let clip = GltfClip::new(&document, &buffers, options.animation.as_deref())?;
+    build_motion(&clip, skeleton, options.fps)
+}
+
+/// Import a motion from an in-memory glTF or GLB file
+pub fn motion_from_gltf_slice(
+    data: &[u8],
+    skeleton: &ZMD,
+    options: &MotionImportOptions,
+) -> Result<ZMO, Error> {
+    let (document, buffers, _) = gltf::import_slice(data)?;
+    let clip = GltfClip::new(&document, &buffers, options.animation.as_deref())?;
+    build_motion(&clip, skeleton, options.fps)
+}
+
+/// Import a motion from a BVH file
+pub fn motion_from_bvh(
+    path: &Path,
+    skeleton: &ZMD,
+    options: &MotionImportOptions,
+) -> Result<ZMO, Error> {
+    let s = std::fs::read_to_string(path)?;
+    motion_from_bvh_str(&s, skeleton, options)
+}
+
+/// Import a motion from the contents of a BVH file
+pub fn motion_from_bvh_str(
+    s: &str,
+    skeleton: &ZMD,
+    options: &MotionImportOptions,
+) -> Result<ZMO, Error> {
+    let clip = BvhClip::parse(s)?;
+    build_motion(&clip, skeleton, options.fps)
+}
+
+fn build_motion<C: Clip>(clip: &C, skeleton: &ZMD, fps: u32) -> Result<ZMO, Error> {
+    if fps == 0 {
+        bail!("Motion fps must be greater than 0");
+    }
+
+    if skeleton.bones.is_empty() {
+        bail!("Skeleton has no bones");
+    }
+
+    let mut joints = Vec::with_capacity(skeleton.bones.len());
+    let mut missing = Vec::new();
+    for bone in &skeleton.bones {
+        match clip.joint(&bone.name) {
+            Some(joint) => joints.push(joint),
+            None => missing.push(bone.name.as_str()),
+        }
+    }
+
+    if !missing.is_empty() {
+        bail!(
+            "Skeleton bones not found in animation: {}",
+            missing.join(", ")
+        );
+    }
+
+    let frame_count = (clip.duration() * fps as f32).round() as u32 + 1;
+
+    let mut motion = ZMO::new();
+    motion.identifier = String::from(ZMO_IDENTIFIER);
+    motion.fps = fps;
+    motion.frames = frame_count;
+
+    for (bone_idx, joint) in joints.iter().enumerate() {
+        let mut position = Channel::from(ChannelType::Position);
+        position.index = bone_idx as u32;
+
+        let mut rotation = Channel::from(ChannelType::Rotation);
+        rotation.index = bone_idx as u32;
+
+        for frame in 0..frame_count {
+            let time = frame as f32 / fps as f32;
+            let (p, r) = clip.sample(*joint, time);
+            position.position_frames().unwrap().push(p);
+            rotation.rotation_frames().unwrap().push(r);
+        }
+
+        motion.channels.push(position);
+        motion.channels.push(rotation);
+    }
+
+    Ok(motion)
+}
+
+fn find_joint(names: &[String], name: &str) -> Option<usize> {
+    names
+        .iter()
+        .position(|n| n == name)
+        .or_else(|| names.iter().position(|n| n.eq_ignore_ascii_case(name)))
+}
+
+/// Keyframe track of a glTF animation channel
+struct GltfTrack<T> {
+    interpolation: gltf::animation::Interpolation,
+    times: Vec<f32>,
+    values: Vec<T>,
+}
+
+impl<T: Copy> GltfTrack<T> {
+    /// Sample the track, `lerp` blends two values and `hermite` evaluates
+    /// a cubic spline segment from (value, out tangent, in tangent, value)
+    fn sample<L, H>(&self, time: f32, lerp: L, hermite: H) -> T
+    where
+        L: Fn(&T, &T, f32) -> T,
+        H: Fn(&T, &T, &T, &T, f32, f32) -> T,
+    {
+        use gltf::animation::Interpolation;
+
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let value = |i: usize| {
+            if cubic {
+                self.values[i * 3 + 1]
+            } else {
+                self.values[i]
+            }
+        };
+
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return value(0);
+        }
+        if time >= self.times[last] {
+            return value(last);
+        }
+
+        let next = self.times.iter().position(|&t| t > time).unwrap_or(last);
+        let prev = next - 1;
+        let delta = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / delta;
+
+        match self.interpolation {
+            Interpolation::Step => value(prev),
+            Interpolation::Linear => lerp(&value(prev), &value(next), t),
+            Interpolation::CubicSpline => hermite(
+                &value(prev),
+                &self.values[prev * 3 + 2],
+                &self.values[next * 3],
+                &value(next),
+                t,
+                delta,
+            ),
+        }
+    }
+}
+
+struct GltfJoint {
+    position: Vector3<f32>,
+    rotation: Quaternion,
+    translations: Option<GltfTrack<Vector3<f32>>>,
+    rotations: Option<GltfTrack<Quaternion>>,
+}
+
+struct GltfClip {
+    names: Vec<String>,
+    joints: Vec<GltfJoint>,
+    duration: f32,
+}
+
+impl GltfClip {
+    fn new(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        animation_name: Option<&str>,
+    ) -> Result<GltfClip, Error> {
+        use gltf::animation::util::ReadOutputs;
+        use gltf::animation::Property;
+
+        let animation = match animation_name {
+            Some(name) => document.animations().find(|a| a.name() == Some(name)),
+            None => document.animations().next(),
+        };
+
+        let animation = match animation {
+            Some(a) => a,
+            None => match animation_name {
+                Some(name) => bail!("Animation not found in glTF file: {}", name),
+                None => bail!("No animations found in glTF file"),
+            },
+        };
+
+        let mut names = Vec::new();
+        let mut joints = Vec::new();
+        for node in document.nodes() {
+            let (t, r, _) = node.transform().decomposed();
+            names.push(String::from(node.name().unwrap_or_default()));
+            joints.push(GltfJoint {
+                position: Vector3 {
+                    x: t[0],
+                    y: t[1],
+                    z: t[2],
+                },
+                rotation: Quaternion {
+                    w: r[3],
+                    x: r[0],
+                    y: r[1],
+                    z: r[2],
+                },
+                translations: None,
+                rotations: None,
+            });
+        }
+
+        let mut start = f32::MAX;
+        let mut end = f32::MIN;
+
+        for channel in animation.channels() {
+            let node = channel.target().node().index();
+            let interpolation = channel.sampler().interpolation();
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let times: Vec<f32> = match reader.read_inputs() {
+                Some(inputs) => inputs.collect(),
+                None => continue,
+            };
+            if times.is_empty() {
+                continue;
+            }
+
+            start = start.min(times[0]);
+            end = end.max(times[times.len() - 1]);
+
+            match (channel.target().property(), reader.read_outputs()) {
+                (Property::Translation, Some(ReadOutputs::Translations(values))) => {
+                    let values = values
+                        .map(|v| Vector3 {
+                            x: v[0],
+                            y: v[1],
+                            z: v[2],
+                        })
+                        .collect();
+                    joints[node].translations = Some(GltfTrack {
+                        interpolation,
+                        times,
+                        values,
+                    });
+                }
+                (Property::Rotation, Some(ReadOutputs::Rotations(values))) => {
+                    let values = values
+                        .into_f32()
+                        .map(|q| Quaternion {
+                            w: q[3],
+                            x: q[0],
+                            y: q[1],
+                            z: q[2],
+                        })
+                        .collect();
+                    joints[node].rotations = Some(GltfTrack {
+                        interpolation,
+                        times,
+                        values,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if start > end {
+            bail!("glTF animation has no keyframes");
+        }
+
+        // Shift all tracks so the clip starts at 0
+        for joint in joints.iter_mut() {
+            if let Some(track) = joint.translations.as_mut() {
+                track.times.iter_mut().for_each(|t| *t -= start);
+            }
+            if let Some(track) = joint.rotations.as_mut() {
+                track.times.iter_mut().for_each(|t| *t -= start);
+            }
+        }
+
+        Ok(GltfClip {
+            names,
+            joints,
+            duration: end - start,
+        })
+    }
+}
+
+impl Clip for GltfClip {
+    fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    fn joint(&self, name: &str) -> Option<usize> {
+        find_joint(&self.names, name)
+    }
+
+    fn sample(&self, joint: usize, time: f32) -> (Vector3<f32>, Quaternion) {
+        let joint = &self.joints[joint];
+
+        let position = match &joint.translations {
+            Some(track) => track.sample(
+                time,
+                |a, b, t| a.lerp(b, t),
+                |p0, m0, m1, p1, t, dt| Vector3 {
+                    x: hermite(p0.x, m0.x, m1.x, p1.x, t, dt),
+                    y: hermite(p0.y, m0.y, m1.y, p1.y, t, dt),
+                    z: hermite(p0.z, m0.z, m1.z, p1.z, t, dt),
+                },
+            ),
+            None => joint.position,
+        };
+
+        let rotation = match &joint.rotations {
+            Some(track) => track.sample(
+                time,
+                |a, b, t| a.slerp(b, t),
+                |p0, m0, m1, p1, t, dt| {
+                    Quaternion {
+                        w: hermite(p0.w, m0.w, m1.w, p1.w, t, dt),
+                        x: hermite(p0.x, m0.x, m1.x, p1.x, t, dt),
+                        y: hermite(p0.y, m0.y, m1.y, p1.y, t, dt),
+                        z: hermite(p0.z, m0.z, m1.z, p1.z, t, dt),
+                    }
+                    .normalize()
+                },
+            ),
+            None => joint.rotation,
+        };
+
+        (position, rotation)
+    }
+}
+
+/// Cubic hermite spline as defined by the glTF spec
+fn hermite(p0: f32, m0: f32, m1: f32, p1: f32, t: f32, dt: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p0
+        + (t3 - 2.0 * t2 + t) * m0 * dt
+        + (-2.0 * t3 + 3.0 * t2) * p1
+        + (t3 - t2) * m1 * dt
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BvhChannel {
+    PositionX,
+    PositionY,
+    PositionZ,
+    RotationX,
+    RotationY,
+    RotationZ,
+}
+
+struct BvhJoint {
+    offset: Vector3<f32>,
+    channels: Vec<BvhChannel>,
+    // Index of this joint's first channel in a frame
+    channel_offset: usize,
+}
+
+struct BvhClip {
+    names: Vec<String>,
+    joints: Vec<BvhJoint>,
+    frame_time: f32,
+    frames: Vec<Vec<f32>>,
+}
+
+impl BvhClip {
+    fn parse(s: &str) -> Result<BvhClip, Error> {
+        let mut tokens = s.split_whitespace();
+
+        let mut next = |what: &str| -> Result<&str, Error> {
+            match tokens.next() {
+                Some(t) => Ok(t),
+                None => bail!("Unexpected end of BVH file, expected {}", what),
+            }
+        };
+
+        fn number(token: &str) -> Result<f32, Error> {
+            match token.parse::<f32>() {
+                Ok(n) => Ok(n),
+                Err(_) => bail!("Invalid number in BVH file: {}", token),
+            }
+        }
+
+        if next("HIERARCHY")? != "HIERARCHY" {
+            bail!("Invalid BVH file, missing HIERARCHY");
+        }
+
+        let mut names = Vec::new();
+        let mut joints: Vec<BvhJoint> = Vec::new();
+        let mut channel_count = 0;
+
+        // Stack of open blocks, `true` for joints and `false` for end sites
+        let mut stack: Vec<bool> = Vec::new();
+        loop {
+            let token = next("joint")?;
+            match token {
+                "ROOT" | "JOINT" => {
+                    names.push(String::from(next("joint name")?));
+                    if next("{")? != "{" {
+                        bail!("Expected {{ after BVH joint {}", names[names.len() - 1]);
+                    }
+                    joints.push(BvhJoint {
+                        offset: Vector3::<f32>::new(),
+                        channels: Vec::new(),
+                        channel_offset: channel_count,
+                    });
+                    stack.push(true);
+                }
+                "End" => {
+                    next("Site")?;
+                    if next("{")? != "{" {
+                        bail!("Expected {{ after BVH End Site");
+                    }
+                    stack.push(false);
+                }
+                "OFFSET" => {
+                    let offset = Vector3 {
+                        x: number(next("offset")?)?,
+                        y: number(next("offset")?)?,
+                        z: number(next("offset")?)?,
+                    };
+                    if stack.last() == Some(&true) {
+                        joints.last_mut().unwrap().offset = offset;
+                    }
+                }
+                "CHANNELS" => {
+                    let count = number(next("channel count")?)? as usize;
+                    let joint = match joints.last_mut() {
+                        Some(j) => j,
+                        None => bail!("BVH CHANNELS found outside of a joint"),
+                    };
+                    for _ in 0..count {
+                        let channel = match next("channel")? {
+                            "Xposition" => BvhChannel::PositionX,
+                            "Yposition" => BvhChannel::PositionY,
+                            "Zposition" => BvhChannel::PositionZ,
+                            "Xrotation" => BvhChannel::RotationX,
+                            "Yrotation" => BvhChannel::RotationY,
+                            "Zrotation" => BvhChannel::RotationZ,
+                            c => bail!("Unknown BVH channel: {}", c),
+                        };
+                        joint.channels.push(channel);
+                    }
+                    channel_count += count;
+                }
+                "}" => {
+                    if stack.pop().is_none() {
+                        bail!("Unbalanced braces in BVH file");
+                    }
+                }
+                "MOTION" => {
+                    if !stack.is_empty() {
+                        bail!("Unbalanced braces in BVH file");
+                    }
+                    break;
+                }
+                t => bail!("Unexpected token in BVH hierarchy: {}", t),
+            }
+        }
+
+        if next("Frames:")? != "Frames:" {
+            bail!("Invalid BVH file, missing frame count");
+        }
+        let frame_count = number(next("frame count")?)? as usize;
+
+        if next("Frame")? != "Frame" || next("Time:")? != "Time:" {
+            bail!("Invalid BVH file, missing frame time");
+        }
+        let frame_time = number(next("frame time")?)?;
+        if frame_time <= 0.0 {
+            bail!("Invalid BVH frame time: {}", frame_time);
+        }
+
+        // The header count isn't trusted for allocation, missing frames
+        // are an unexpected end of file
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let mut frame = Vec::with_capacity(channel_count);
+            for _ in 0..channel_count {
+                frame.push(number(next("frame data")?)?);
+            }
+            frames.push(frame);
+        }
+
+        if frames.is_empty() {
+            bail!("BVH file has no frames");
+        }
+
+        Ok(BvhClip {
+            names,
+            joints,
+            frame_time,
+            frames,
+        })
+    }
+
+    fn sample_frame(&self, joint: usize, frame: usize) -> (Vector3<f32>, Quaternion) {
+        let joint = &self.joints[joint];
+        let values = &self.frames[frame][joint.channel_offset..];
+
+        let mut position = joint.offset;
+        let mut rotation = Quaternion::identity();
+
+        for (channel, value) in joint.channels.iter().zip(values.iter()) {
+            let axis = match channel {
+                BvhChannel::PositionX => {
+                    position.x = *value;
+                    continue;
+                }
+                BvhChannel::PositionY => {
+                    position.y = *value;
+                    continue;
+                }
+                BvhChannel::PositionZ => {
+                    position.z = *value;
+                    continue;
+                }
+                BvhChannel::RotationX => Vector3 {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                BvhChannel::RotationY => Vector3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+                BvhChannel::RotationZ => Vector3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                },
+            };
+
+            // BVH rotations are applied in channel order
+            let r = Quaternion::from_axis_angle(axis, value.to_radians());
+            rotation = rotation.multiply(&r);
+        }
+
+        (position, rotation.normalize())
+    }
+}
+
+impl Clip for BvhClip {
+    fn duration(&self) -> f32 {
+        (self.frames.len() - 1) as f32 * self.frame_time
+    }
+
+    fn joint(&self, name: &str) -> Option<usize> {
+        find_joint(&self.names, name)
+    }
+
+    fn sample(&self, joint: usize, time: f32) -> (Vector3<f32>, Quaternion) {
+        let frame = (time / self.frame_time).max(0.0);
+        let last = self.frames.len() - 1;
+
+        let prev = (frame.floor() as usize).min(last);
+        let next = (prev + 1).min(last);
+        let t = frame - prev as f32;
+
+        let (p0, r0) = self.sample_frame(joint, prev);
+        if next == prev {
+            return (p0, r0);
+        }
+
+        let (p1, r1) = self.sample_frame(joint, next);
+        (p0.lerp(&p1, t), r0.slerp(&r1, t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use roselib::files::zmd::Bone;
+
+    fn skeleton(names: &[&str]) -> ZMD {
+        let mut zmd = ZMD::new();
+        for (idx, name) in names.iter().enumerate() {
+            let mut bone = Bone::new();
+            bone.name = String::from(*name);
+            bone.parent = idx as i32 - 1;
+            bone.rotation = Quaternion::identity();
+            zmd.bones.push(bone);
+        }
+        zmd
+    }
+
+    const BVH: &str = "HIERARCHY
+ROOT b1_pelvis
+{
+    OFFSET 0 0 0
+    CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+    JOINT b1_spine
+    {
+        OFFSET 0 10 0
+        CHANNELS 3 Zrotation Xrotation Yrotation
+        End Site
+        {
+            OFFSET 0 5 0
+        }
+    }
+}
+MOTION
+Frames: 3
+Frame Time: 0.5
+0 0 0 0 0 0 0 0 0
+2 0 0 90 0 0 0 0 0
+4 0 0 90 0 0 0 0 90
+";
+
+    #[test]
+    fn test_bvh() {
+        let zmd = skeleton(&["b1_pelvis", "b1_spine"]);
+        let options = MotionImportOptions {
+            fps: 4,
+            ..Default::default()
+        };
+
+        let mut zmo = motion_from_bvh_str(BVH, &zmd, &options).unwrap();
+        assert_eq!(zmo.identifier, ZMO_IDENTIFIER);
+        assert_eq!(zmo.fps, 4);
+        assert_eq!(zmo.frames, 5);
+        assert_eq!(zmo.channels.len(), 4);
+
+        assert_eq!(zmo.channels[0].typ, ChannelType::Position);
+        assert_eq!(zmo.channels[0].index, 0);
+        assert_eq!(zmo.channels[1].typ, ChannelType::Rotation);
+        assert_eq!(zmo.channels[1].index, 0);
+        assert_eq!(zmo.channels[2].index, 1);
+        assert_eq!(zmo.channels[3].index, 1);
+
+        // Root position is interpolated between BVH frames
+        let positions = zmo.channels[0].position_frames().unwrap().clone();
+        assert_eq!(positions.len(), 5);
+        assert!((positions[1].x - 1.0).abs() < 1e-5);
+        assert!((positions[4].x - 4.0).abs() < 1e-5);
+
+        // Child joints keep their offset
+        let positions = zmo.channels[2].position_frames().unwrap().clone();
+        assert!((positions[0].y - 10.0).abs() < 1e-5);
+
+        // 90 degrees around z on the last frame
+        let rotations = zmo.channels[1].rotation_frames().unwrap().clone();
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((rotations[4].w - half).abs() < 1e-5);
+        assert!((rotations[4].z - half).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_bvh_missing_bones() {
+        let zmd = skeleton(&["b1_pelvis", "b1_head", "b1_tail"]);
+        let options = MotionImportOptions::default();
+
+        let err = motion_from_bvh_str(BVH, &zmd, &options).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("b1_head"));
+        assert!(msg.contains("b1_tail"));
+    }
+
+    #[test]
+    fn test_bvh_frame_count() {
+        let zmd = skeleton(&["b1_pelvis", "b1_spine"]);
+        let options = MotionImportOptions::default();
+
+        let bvh = BVH.replace("Frames: 3", "Frames: 1e30");
+        assert!(motion_from_bvh_str(&bvh, &zmd, &options).is_err());
+
+        let bvh = BVH.replace("Frames: 3", "Frames: 4");
+        assert!(motion_from_bvh_str(&bvh, &zmd, &options).is_err());
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut bin = bin.to_vec();
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        let mut data = Vec::new();
+        data.extend_from_slice(b"glTF");
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&(total as u32).to_le_bytes());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"JSON");
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"BIN\0");
+        data.extend_from_slice(&bin);
+        data
+    }
+
+    #[test]
+    fn test_gltf() {
+        let mut bin = Vec::new();
+        for t in &[0.0f32, 1.0] {
+            bin.extend_from_slice(&t.to_le_bytes());
+        }
+        for v in &[0.0f32, 0.0, 0.0, 10.0, 0.0, 0.0] {
+            bin.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let json = format!(
+            r#"{{
+            "asset": {{"version": "2.0"}},
+            "nodes": [
+                {{"name": "b1_pelvis", "children": [1]}},
+                {{"name": "b1_spine", "translation": [0, 5, 0]}}
+            ],
+            "buffers": [{{"byteLength": {}}}],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 8}},
+                {{"buffer": 0, "byteOffset": 8, "byteLength": 24}}
+            ],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3"}}
+            ],
+            "animations": [{{
+                "name": "walk",
+                "samplers": [{{"input": 0, "output": 1, "interpolation": "LINEAR"}}],
+                "channels": [{{"sampler": 0, "target": {{"node": 0, "path": "translation"}}}}]
+            }}]
+        }}"#,
+            bin.len()
+        );
+
+        let data = glb(&json, &bin);
+        let zmd = skeleton(&["b1_pelvis", "b1_spine"]);
+        let options = MotionImportOptions {
+            fps: 10,
+            ..Default::default()
+        };
+
+        let mut zmo = motion_from_gltf_slice(&data, &zmd, &options).unwrap();
+        assert_eq!(zmo.frames, 11);
+        assert_eq!(zmo.channels.len(), 4);
+
+        let positions = zmo.channels[0].position_frames().unwrap().clone();
+        assert!((positions[5].x - 5.0).abs() < 1e-4);
+        assert!((positions[10].x - 10.0).abs() < 1e-4);
+
+        // Unanimated nodes use their rest transform
+        let positions = zmo.channels[2].position_frames().unwrap().clone();
+        assert!((positions[3].y - 5.0).abs() < 1e-5);
+
+        let options = MotionImportOptions {
+            animation: Some(String::from("run")),
+            ..options
+        };
+        assert!(motion_from_gltf_slice(&data, &zmd, &options).is_err());
+    }
+}
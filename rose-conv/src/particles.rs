@@ -0,0 +1,224 @@
+//! Preview PTL particle systems and EFT effects
+use std::path::{Path, PathBuf};
+
+use failure::{bail, Error};
+use image::{Rgba, RgbaImage};
+
+use roselib::files::ptl::Particle;
+use roselib::files::{EFT, PTL};
+use roselib::io::RoseFile;
+use roselib::utils::Matrix4;
+
+pub struct ParticleRenderOptions {
+    pub width: u32,
+    pub height: u32,
+
+    /// Pixels per world unit
+    pub scale: f32,
+    pub background: [u8; 4],
+}
+
+impl Default for ParticleRenderOptions {
+    fn default() -> ParticleRenderOptions {
+        ParticleRenderOptions {
+            width: 256,
+            height: 256,
+            scale: 1.0,
+            background: [0, 0, 0, 255],
+        }
+    }
+}
+
+/// Simulate every particle system of an effect.
+///
+/// Particle paths are resolved against `data_dir`. Particles are moved by
+/// their link transform and start after their start delay.
+pub fn simulate_effect(
+    eft: &EFT,
+    data_dir: &Path,
+    fps: f32,
+    frame_count: u32,
+    seed: u32,
+) -> Result<Vec<Vec<Particle>>, Error> {
+    if !(fps > 0.0 && fps.is_finite()) {
+        bail!("Invalid particle simulation fps: {}", fps);
+    }
+
+    let mut frames = vec![Vec::new(); frame_count as usize];
+
+    for (i, effect_particle) in eft.particles.iter().enumerate() {
+        let path = effect_particle
+            .particle_path
+            .to_string_lossy()
+            .replace('\\', "/");
+        let ptl = PTL::from_path(&data_dir.join(PathBuf::from(path)))?;
+
+        let link = &effect_particle.link;
+        let transform = Matrix4::from_transform(&link.position, &link.rotation, 1.0);
+        let delay = (link.start_delay as f32 / 1000.0 * fps).round() as usize;
+        if delay >= frames.len() {
+            continue;
+        }
+
+        let particle_frames = ptl.simulate(
+            fps,
+            frame_count - delay as u32,
+            seed.wrapping_add(i as u32 * 1000),
+        )?;
+        for (frame, particles) in frames[delay..].iter_mut().zip(particle_frames) {
+            frame.extend(particles.into_iter().map(|mut p| {
+                p.position = transform.transform_point(&p.position);
+                p
+            }));
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Draw particles as soft ellipses looking down the Y axis with Z up, the
+/// origin is in the center of the image
+pub fn render_particles(particles: &[Particle], options: &ParticleRenderOptions) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(options.width, options.height, Rgba(options.background));
+
+    // Draw back to front
+    let mut sorted: Vec<&Particle> = particles.iter().collect();
+    sorted.sort_by(|a, b| {
+        b.position
+            .y
+            .partial_cmp(&a.position.y)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let center_x = options.width as f32 / 2.0;
+    let center_y = options.height as f32 / 2.0;
+
+    for particle in sorted {
+        let x = center_x + particle.position.x * options.scale;
+        let y = center_y - particle.position.z * options.scale;
+        let radius_x = (particle.size.x * options.scale / 2.0).max(0.5);
+        let radius_y = (particle.size.y * options.scale / 2.0).max(0.5);
+
+        let min_x = (x - radius_x).floor().max(0.0) as u32;
+        let max_x = (x + radius_x).ceil().min(options.width as f32) as u32;
+        let min_y = (y - radius_y).floor().max(0.0) as u32;
+        let max_y = (y + radius_y).ceil().min(options.height as f32) as u32;
+
+        let color = &particle.color;
+        for py in min_y..max_y {
+            for px in min_x..max_x {
+                let dx = (px as f32 + 0.5 - x) / radius_x;
+                let dy = (py as f32 + 0.5 - y) / radius_y;
+                let distance = dx * dx + dy * dy;
+                if distance >= 1.0 {
+                    continue;
+                }
+
+                let alpha = (color.a * (1.0 - distance)).clamp(0.0, 1.0);
+                let pixel = image.get_pixel_mut(px, py);
+                for (channel, value) in [color.r, color.g, color.b].iter().enumerate() {
+                    let src = value.clamp(0.0, 1.0) * 255.0;
+                    let dst = pixel[channel] as f32;
+                    pixel[channel] = (src * alpha + dst * (1.0 - alpha)).round() as u8;
+                }
+                let dst = pixel[3] as f32 / 255.0;
+                pixel[3] = ((alpha + dst * (1.0 - alpha)) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use roselib::files::ptl::{ParticleEvent, ParticleEventData, ParticleRange, ParticleSequence};
+    use roselib::utils::{Color4, Vector2, Vector3};
+
+    fn fountain() -> PTL {
+        let mut sequence = ParticleSequence::new();
+        sequence.lifetime = ParticleRange { min: 1.0, max: 1.0 };
+        sequence.emit_rate = ParticleRange {
+            min: 10.0,
+            max: 10.0,
+        };
+        sequence.particle_count = 100;
+        sequence.spawn_direction = ParticleRange {
+            min: Vector3 {
+                x: -10.0,
+                y: 0.0,
+                z: 50.0,
+            },
+            max: Vector3 {
+                x: 10.0,
+                y: 0.0,
+                z: 50.0,
+            },
+        };
+        sequence.gravity = ParticleRange {
+            min: Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -50.0,
+            },
+            max: Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -50.0,
+            },
+        };
+        sequence.events.push(ParticleEvent {
+            start_time: ParticleRange::default(),
+            fade: false,
+            data: ParticleEventData::Size(ParticleRange {
+                min: Vector2 { x: 8.0, y: 8.0 },
+                max: Vector2 { x: 8.0, y: 8.0 },
+            }),
+        });
+        sequence.events.push(ParticleEvent {
+            start_time: ParticleRange::default(),
+            fade: false,
+            data: ParticleEventData::Color(ParticleRange {
+                min: Color4 {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+                max: Color4 {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+            }),
+        });
+
+        let mut ptl = PTL::new();
+        ptl.sequences.push(sequence);
+        ptl
+    }
+
+    #[test]
+    fn render() {
+        let ptl = fountain();
+        let frames = ptl.simulate(10.0, 6, 1).unwrap();
+        assert!(frames[0].is_empty());
+        assert!(!frames[5].is_empty());
+
+        let options = ParticleRenderOptions {
+            width: 64,
+            height: 64,
+            ..Default::default()
+        };
+        let blank = render_particles(&[], &options);
+        let image = render_particles(&frames[5], &options);
+        assert_ne!(blank, image);
+        assert!(image.pixels().any(|p| p[0] > 128 && p[1] == 0));
+
+        assert!(ptl.simulate(0.0, 6, 1).is_err());
+        assert!(simulate_effect(&EFT::new(), Path::new("."), 0.0, 6, 1).is_err());
+    }
+}
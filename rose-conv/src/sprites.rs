@@ -0,0 +1,546 @@
+//! Extract and pack UI sprites described by sprite information (TSI) files
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use failure::{bail, Error};
+use image::{imageops, RgbaImage};
+
+use roselib::files::tsi::{Sprite, SpriteSheet};
+use roselib::files::TSI;
+use roselib::io::{PathRoseExt, RoseFile};
+use roselib::utils::Vector2;
+
+use crate::texture::load_texture;
+
+/// Sprite names are stored in 32 bytes including the null terminator
+const MAX_SPRITE_NAME: usize = 31;
+
+pub struct SpritePackOptions {
+    /// Maximum width and height of a sheet, sheets are powers of two
+    pub max_size: u32,
+
+    /// Empty pixels between sprites
+    pub padding: u32,
+
+    /// Path prefix of new sheets, e.g. `3DDATA/CONTROL/RES/UI` for
+    /// `3DDATA/CONTROL/RES/UI_<n>.DDS`
+    pub sheet_prefix: String,
+}
+
+impl Default for SpritePackOptions {
+    fn default() -> SpritePackOptions {
+        SpritePackOptions {
+            max_size: 1024,
+            padding: 1,
+            sheet_prefix: String::from("SPRITES"),
+        }
+    }
+}
+
+/// Find a file from a ROSE path in one of the search directories.
+///
+/// ROSE paths use `\` separators and are case-insensitive, so each path
+/// component is matched ignoring case if the exact path does not exist.
+pub fn resolve_path(path: &Path, search_dirs: &[&Path]) -> Option<PathBuf> {
+    let path = PathBuf::from_rose_path(&path.to_string_lossy());
+
+    // Also try only the file name, sheets are often stored next to the TSI
+    let mut candidates = vec![path.clone()];
+    if let Some(file_name) = path.file_name() {
+        candidates.push(PathBuf::from(file_name));
+    }
+
+    for candidate in candidates.iter() {
+        for dir in search_dirs {
+            if let Some(p) = find_ignore_case(dir, candidate) {
+                return Some(p);
+            }
+        }
+    }
+
+    None
+}
+
+fn find_ignore_case(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let exact = dir.join(path);
+    if exact.exists() {
+        return Some(exact);
+    }
+
+    let mut current = dir.to_path_buf();
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy().to_string(),
+            Component::CurDir => continue,
+            _ => return None,
+        };
+
+        let entry = fs::read_dir(&current)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case(&name))?;
+        current = entry.path();
+    }
+
+    Some(current)
+}
+
+/// Make pixels matching the color key transparent.
+///
+/// The color key is a `0xAARRGGBB` color where only the RGB part is compared.
+/// A color key of 0 disables color keying.
+pub fn apply_color_key(image: &mut RgbaImage, color_key: u32) {
+    if color_key == 0 {
+        return;
+    }
+
+    let key = [
+        ((color_key >> 16) & 0xFF) as u8,
+        ((color_key >> 8) & 0xFF) as u8,
+        (color_key & 0xFF) as u8,
+    ];
+    for pixel in image.pixels_mut() {
+        if pixel.0[..3] == key {
+            pixel.0 = [0, 0, 0, 0];
+        }
+    }
+}
+
+/// Cut a sprite out of its sheet.
+///
+/// The end point is exclusive, rectangles are clamped to the sheet.
+pub fn cut_sprite(sheet: &RgbaImage, sprite: &Sprite) -> Result<RgbaImage, Error> {
+    let x = sprite.start_point.x.min(sheet.width());
+    let y = sprite.start_point.y.min(sheet.height());
+    let width = sprite.end_point.x.min(sheet.width()).saturating_sub(x);
+    let height = sprite.end_point.y.min(sheet.height()).saturating_sub(y);
+
+    if width == 0 || height == 0 {
+        bail!(
+            "Sprite {} ({},{} - {},{}) is outside of the {}x{} sheet",
+            sprite.name.trim_end_matches('\0'),
+            sprite.start_point.x,
+            sprite.start_point.y,
+            sprite.end_point.x,
+            sprite.end_point.y,
+            sheet.width(),
+            sheet.height()
+        );
+    }
+
+    Ok(imageops::crop_imm(sheet, x, y, width, height).to_image())
+}
+
+/// Output file name of a sprite: `<sheet>_<index>_<name>.png`
+pub fn sprite_file_name(sheet: &SpriteSheet, index: usize, sprite: &Sprite) -> String {
+    let sheet_path = PathBuf::from_rose_path(&sheet.path.to_string_lossy());
+    let sheet_name = sheet_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+
+    // Names are padded with nulls to their fixed size
+    let sprite_name: String = sprite
+        .name
+        .trim_end_matches('\0')
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sprite_name.is_empty() {
+        format!("{}_{}.png", sheet_name, index)
+    } else {
+        format!("{}_{}_{}.png", sheet_name, index, sprite_name)
+    }
+}
+
+/// Load every image of a directory as a sprite named after the file.
+///
+/// Sprites are sorted by name so packing is deterministic.
+pub fn load_sprites(dir: &Path) -> Result<Vec<(String, RgbaImage)>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| ["png", "dds"].contains(&e.to_lowercase().as_str()))
+            .unwrap_or(false);
+        if is_image {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut sprites = Vec::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        sprites.push((name, load_texture(&path)?));
+    }
+
+    Ok(sprites)
+}
+
+/// Pack sprites into power of two sheets.
+///
+/// When updating an `existing` TSI every sprite keeps its sheet and index,
+/// only the rectangles change. Existing sprites are matched by their name or
+/// by the file name written by `sprite_file_name`, every one of them must be
+/// provided. New sprites are added to the first sheet with enough space or
+/// to new sheets.
+///
+/// Returns the sprite information and an image for each of its sheets.
+pub fn pack_sprites(
+    sprites: &[(String, RgbaImage)],
+    existing: Option<&TSI>,
+    options: &SpritePackOptions,
+) -> Result<(TSI, Vec<RgbaImage>), Error> {
+    let mut images: HashMap<&str, &RgbaImage> = HashMap::new();
+    for (name, image) in sprites.iter() {
+        if image.width() > options.max_size || image.height() > options.max_size {
+            bail!(
+                "Sprite {} ({}x{}) does not fit in a {}x{} sheet",
+                name,
+                image.width(),
+                image.height(),
+                options.max_size,
+                options.max_size
+            );
+        }
+        images.insert(name.as_str(), image);
+    }
+
+    // Sheets as lists of (sprite, image)
+    let mut tsi = TSI::new();
+    let mut sheets: Vec<Vec<(Sprite, &RgbaImage)>> = Vec::new();
+
+    if let Some(existing) = existing {
+        for existing_sheet in existing.sprite_sheets.iter() {
+            let mut sheet = Vec::new();
+            for (sprite_idx, existing_sprite) in existing_sheet.sprites.iter().enumerate() {
+                let name = existing_sprite.name.trim_end_matches('\0');
+                let file_name = sprite_file_name(existing_sheet, sprite_idx, existing_sprite);
+                let file_stem = file_name.trim_end_matches(".png");
+
+                let image = match images.remove(name).or_else(|| images.remove(file_stem)) {
+                    Some(image) => image,
+                    None => bail!("Missing image for existing sprite {}", name),
+                };
+
+                let sprite = Sprite {
+                    name: name.to_string(),
+                    color: existing_sprite.color,
+                    ..Default::default()
+                };
+                sheet.push((sprite, image));
+            }
+
+            tsi.sprite_sheets.push(SpriteSheet {
+                path: existing_sheet.path.clone(),
+                color_key: existing_sheet.color_key,
+                sprites: Vec::new(),
+            });
+            sheets.push(sheet);
+        }
+    }
+
+    for (name, _) in sprites.iter() {
+        let image = match images.remove(name.as_str()) {
+            Some(image) => image,
+            None => continue,
+        };
+
+        if name.len() > MAX_SPRITE_NAME {
+            bail!(
+                "Sprite name {} is longer than {} characters",
+                name,
+                MAX_SPRITE_NAME
+            );
+        }
+
+        let sprite = Sprite {
+            name: name.clone(),
+            ..Default::default()
+        };
+
+        let free_sheet = sheets.iter().position(|sheet| {
+            let mut sizes = sheet_sizes(sheet);
+            sizes.push(image.dimensions());
+            pack_sheet(&sizes, options).is_some()
+        });
+
+        match free_sheet {
+            Some(sheet_idx) => sheets[sheet_idx].push((sprite, image)),
+            None => {
+                tsi.sprite_sheets.push(SpriteSheet {
+                    path: PathBuf::from_rose_path(&format!(
+                        "{}_{}.DDS",
+                        options.sheet_prefix,
+                        sheets.len()
+                    )),
+                    ..Default::default()
+                });
+                sheets.push(vec![(sprite, image)]);
+            }
+        }
+    }
+
+    let mut sheet_images = Vec::new();
+    for (sheet_idx, sheet) in sheets.into_iter().enumerate() {
+        let (size, positions) = match pack_sheet(&sheet_sizes(&sheet), options) {
+            Some(packed) => packed,
+            None => bail!(
+                "Sprites of sheet {} do not fit in a {}x{} sheet",
+                tsi.sprite_sheets[sheet_idx].path.display(),
+                options.max_size,
+                options.max_size
+            ),
+        };
+
+        let mut sheet_image = RgbaImage::new(size.0, size.1);
+        for ((mut sprite, image), (x, y)) in sheet.into_iter().zip(positions) {
+            imageops::replace(&mut sheet_image, image, x, y);
+            sprite.start_point = Vector2 { x, y };
+            sprite.end_point = Vector2 {
+                x: x + image.width(),
+                y: y + image.height(),
+            };
+            tsi.sprite_sheets[sheet_idx].sprites.push(sprite);
+        }
+        sheet_images.push(sheet_image);
+    }
+
+    Ok((tsi, sheet_images))
+}
+
+/// Sheet size and the position of each sprite
+type PackedSheet = ((u32, u32), Vec<(u32, u32)>);
+
+fn sheet_sizes(sheet: &[(Sprite, &RgbaImage)]) -> Vec<(u32, u32)> {
+    sheet.iter().map(|(_, image)| image.dimensions()).collect()
+}
+
+/// Find the smallest power of two sheet that fits all sprites
+fn pack_sheet(sizes: &[(u32, u32)], options: &SpritePackOptions) -> Option<PackedSheet> {
+    let area: u64 = sizes
+        .iter()
+        .map(|(w, h)| u64::from(*w) * u64::from(*h))
+        .sum();
+
+    let mut candidates = Vec::new();
+    let mut width = 1;
+    while width <= options.max_size {
+        let mut height = 1;
+        while height <= options.max_size {
+            if u64::from(width) * u64::from(height) >= area {
+                candidates.push((width, height));
+            }
+            height *= 2;
+        }
+        width *= 2;
+    }
+    candidates.sort_by_key(|(w, h)| (u64::from(*w) * u64::from(*h), (*w).max(*h)));
+
+    for (width, height) in candidates {
+        if let Some(positions) = shelf_pack(sizes, width, height, options.padding) {
+            return Some(((width, height), positions));
+        }
+    }
+
+    None
+}
+
+/// Place sprites on rows from the tallest to the shortest
+fn shelf_pack(
+    sizes: &[(u32, u32)],
+    width: u32,
+    height: u32,
+    padding: u32,
+) -> Option<Vec<(u32, u32)>> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(sizes[i].1), std::cmp::Reverse(sizes[i].0)));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if x + w > width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if x + w > width || y + h > height {
+            return None;
+        }
+
+        positions[i] = (x, y);
+        x += w + padding;
+        shelf_height = shelf_height.max(h + padding);
+    }
+
+    Some(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::Rgba;
+
+    fn sprite(name: &str, start: (u32, u32), end: (u32, u32)) -> Sprite {
+        Sprite {
+            name: String::from(name),
+            start_point: Vector2 {
+                x: start.0,
+                y: start.1,
+            },
+            end_point: Vector2 { x: end.0, y: end.1 },
+            color: 0,
+        }
+    }
+
+    #[test]
+    fn test_cut_sprite() {
+        let mut sheet = RgbaImage::from_pixel(64, 32, Rgba([255, 0, 255, 255]));
+        sheet.put_pixel(10, 5, Rgba([1, 2, 3, 255]));
+        apply_color_key(&mut sheet, 0xFFFF_00FF);
+
+        let button = cut_sprite(&sheet, &sprite("UI_BUTTON", (10, 5), (30, 13))).unwrap();
+        assert_eq!(button.dimensions(), (20, 8));
+        assert_eq!(button.get_pixel(0, 0), &Rgba([1, 2, 3, 255]));
+        assert_eq!(button.get_pixel(1, 0), &Rgba([0, 0, 0, 0]));
+
+        let clamped = cut_sprite(&sheet, &sprite("EDGE", (60, 30), (70, 40))).unwrap();
+        assert_eq!(clamped.dimensions(), (4, 2));
+
+        assert!(cut_sprite(&sheet, &sprite("OUT", (64, 0), (80, 10))).is_err());
+    }
+
+    #[test]
+    fn test_sprite_file_name() {
+        let sheet = SpriteSheet {
+            path: PathBuf::from("3DDATA\\CONTROL\\RES\\UI1.DDS"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sprite_file_name(&sheet, 3, &sprite("ID_BTN OK\0\0", (0, 0), (1, 1))),
+            "ui1_3_ID_BTN_OK.png"
+        );
+        assert_eq!(
+            sprite_file_name(&sheet, 4, &sprite("", (0, 0), (1, 1))),
+            "ui1_4.png"
+        );
+    }
+
+    fn solid(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]))
+    }
+
+    fn check_sheet(sheet: &SpriteSheet, image: &RgbaImage) {
+        assert!(image.width().is_power_of_two() && image.height().is_power_of_two());
+        for (i, a) in sheet.sprites.iter().enumerate() {
+            assert!(a.end_point.x <= image.width() && a.end_point.y <= image.height());
+            for b in sheet.sprites.iter().skip(i + 1) {
+                let overlaps = a.start_point.x < b.end_point.x
+                    && b.start_point.x < a.end_point.x
+                    && a.start_point.y < b.end_point.y
+                    && b.start_point.y < a.end_point.y;
+                assert!(!overlaps, "{} overlaps {}", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_sprites() {
+        let sprites = vec![
+            (String::from("A"), solid(40, 40, 10)),
+            (String::from("B"), solid(20, 60, 20)),
+            (String::from("C"), solid(100, 8, 30)),
+            (String::from("D"), solid(60, 60, 40)),
+        ];
+        let options = SpritePackOptions {
+            max_size: 128,
+            ..Default::default()
+        };
+
+        let (tsi, images) = pack_sprites(&sprites, None, &options).unwrap();
+        assert_eq!(tsi.sprite_sheets.len(), 1);
+        assert_eq!(images.len(), 1);
+        assert_eq!(tsi.sprite_sheets[0].path, PathBuf::from("SPRITES_0.DDS"));
+        check_sheet(&tsi.sprite_sheets[0], &images[0]);
+
+        let names: Vec<&str> = tsi.sprite_sheets[0]
+            .sprites
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["A", "B", "C", "D"]);
+
+        let d = &tsi.sprite_sheets[0].sprites[3];
+        let cut = cut_sprite(&images[0], d).unwrap();
+        assert_eq!(cut, sprites[3].1);
+
+        // Sprites that do not fit together go to new sheets
+        let options = SpritePackOptions {
+            max_size: 64,
+            ..Default::default()
+        };
+        let (tsi, images) =
+            pack_sprites(&[sprites[0].clone(), sprites[3].clone()], None, &options).unwrap();
+        assert_eq!(tsi.sprite_sheets.len(), 2);
+        check_sheet(&tsi.sprite_sheets[1], &images[1]);
+
+        assert!(pack_sprites(&sprites, None, &options).is_err());
+    }
+
+    #[test]
+    fn test_pack_sprites_update() {
+        let options = SpritePackOptions {
+            max_size: 64,
+            ..Default::default()
+        };
+        let sprites = vec![
+            (String::from("OLD_1"), solid(30, 30, 10)),
+            (String::from("OLD_2"), solid(30, 30, 20)),
+        ];
+        let (mut existing, _) = pack_sprites(&sprites, None, &options).unwrap();
+        existing.sprite_sheets[0].color_key = 0xFFFF_00FF;
+
+        // Existing sprites may come from extracted files, in any order
+        let sprites = vec![
+            (String::from("NEW_1"), solid(60, 60, 30)),
+            (String::from("NEW_2"), solid(30, 30, 40)),
+            (String::from("OLD_2"), solid(30, 30, 20)),
+            (String::from("sprites_0_0_OLD_1"), solid(30, 30, 10)),
+        ];
+        let (tsi, images) = pack_sprites(&sprites, Some(&existing), &options).unwrap();
+
+        assert_eq!(tsi.sprite_sheets.len(), 2);
+        assert_eq!(tsi.sprite_sheets[0].color_key, 0xFFFF_00FF);
+        let names: Vec<Vec<&str>> = tsi
+            .sprite_sheets
+            .iter()
+            .map(|sheet| sheet.sprites.iter().map(|s| s.name.as_str()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["OLD_1", "OLD_2", "NEW_2"], vec!["NEW_1"]]);
+        for (sheet, image) in tsi.sprite_sheets.iter().zip(images.iter()) {
+            check_sheet(sheet, image);
+        }
+
+        // Every existing sprite is required
+        assert!(pack_sprites(&sprites[..3], Some(&existing), &options).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Convert DDS textures to and from common image formats
+use std::path::Path;
+
+use failure::{bail, Error};
+use image::io::Reader as ImageReader;
+use image::RgbaImage;
+
+use roselib::files::dds::DdsFormat;
+use roselib::files::DDS;
+use roselib::io::RoseFile;
+
+/// Decode the full size image of a DDS texture
+pub fn dds_to_image(dds: &DDS) -> Result<RgbaImage, Error> {
+    let data = dds.decode(0)?;
+    match RgbaImage::from_raw(dds.width, dds.height, data) {
+        Some(image) => Ok(image),
+        None => bail!("Failed to decode texture"),
+    }
+}
+
+/// Encode an image as a DDS texture, optionally with the full mipmap chain
+pub fn image_to_dds(image: &RgbaImage, format: DdsFormat, mipmaps: bool) -> Result<DDS, Error> {
+    DDS::encode(
+        image.width(),
+        image.height(),
+        image.as_raw(),
+        format,
+        mipmaps,
+    )
+}
+
+/// Load an image from a DDS texture or any format supported by `image`
+pub fn load_texture(path: &Path) -> Result<RgbaImage, Error> {
+    let is_dds = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("dds"))
+        .unwrap_or(false);
+
+    if is_dds {
+        dds_to_image(&DDS::from_path(path)?)
+    } else {
+        Ok(ImageReader::open(path)?.decode()?.into_rgba8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::Rgba;
+
+    #[test]
+    fn test_dds_image() {
+        let mut image = RgbaImage::new(8, 4);
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            *pixel = if x < 4 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            };
+        }
+
+        let dds = image_to_dds(&image, DdsFormat::Dxt1, true).unwrap();
+        assert_eq!(dds.mipmap_count(), 4);
+        assert_eq!(dds_to_image(&dds).unwrap(), image);
+    }
+}
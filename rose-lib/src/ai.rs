@@ -0,0 +1,631 @@
+//! Monster AI interpretation
+//!
+//! Steps an AIP script over a simulated world: events pick the first
+//! pattern whose conditions pass, its actions update the world and are
+//! recorded as commands. Distances in the script are compared in world
+//! units.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::files::aip::{AiAction, AiCondition, AI_EVENT_DAMAGED, AI_EVENT_IDLE};
+use crate::files::AIP;
+use crate::utils::{compare, in_range, in_time, Random, Vector3};
+
+/// Character or monster in the simulated world
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AiEntity {
+    pub position: Vector3<f32>,
+    pub level: i32,
+    pub health: i32,
+    pub max_health: i32,
+
+    /// Ability values by the ability codes used in the script
+    pub abilities: BTreeMap<u8, i32>,
+
+    /// Whether the entity is on the monster's side
+    pub allied: bool,
+}
+
+impl AiEntity {
+    fn ability(&self, ability: u8) -> i32 {
+        self.abilities.get(&ability).cloned().unwrap_or(0)
+    }
+
+    fn distance(&self, position: &Vector3<f32>) -> f32 {
+        distance(&self.position, position)
+    }
+}
+
+fn distance(a: &Vector3<f32>, b: &Vector3<f32>) -> f32 {
+    let dx = a.x - b.x;
+    let dy = a.y - b.y;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// State the monster AI runs in
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AiWorld {
+    pub monster: AiEntity,
+    pub spawn: Vector3<f32>,
+
+    /// Other characters and monsters
+    pub entities: Vec<AiEntity>,
+
+    /// Indices into `entities`
+    pub target: Option<usize>,
+    pub attacker: Option<usize>,
+    pub owner: Option<usize>,
+
+    pub damage_received: i32,
+    pub damage_given: i32,
+
+    pub monster_variables: BTreeMap<i16, i32>,
+    pub npc_variables: BTreeMap<i16, i32>,
+    pub world_variables: BTreeMap<i16, i32>,
+    pub economy_variables: BTreeMap<i16, i32>,
+
+    pub is_night: bool,
+    pub world_time: u32,
+    pub zone_time: u32,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub channel: u16,
+}
+
+/// Result of checking a condition
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiCheck {
+    Passed,
+    Failed,
+
+    /// The simulated world has no state for this condition, counts as failed
+    Unsupported,
+}
+
+/// Condition checked during a step
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AiConditionResult {
+    pub pattern: usize,
+    pub condition: usize,
+    pub result: AiCheck,
+}
+
+/// What the monster did
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiCommand {
+    Stop,
+    Emote(u8),
+    Say(i32),
+    Move {
+        position: Vector3<f32>,
+        speed: u8,
+    },
+    Attack {
+        target: usize,
+    },
+    SpecialAttack,
+    Transform(u16),
+    Spawn {
+        monster: u16,
+        position: Vector3<f32>,
+    },
+    CallForHelp {
+        distance: i32,
+        count: i32,
+    },
+    Drop {
+        item: i16,
+        to_owner: bool,
+    },
+
+    /// Action the simulated world can't represent
+    Unsupported(AiAction),
+}
+
+/// Record of one event
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AiStep {
+    pub event: usize,
+
+    /// Pattern whose actions ran
+    pub pattern: Option<usize>,
+    pub conditions: Vec<AiConditionResult>,
+    pub commands: Vec<AiCommand>,
+}
+
+/// Compare with 0 for greater or equal and 1 for less or equal
+fn more_less(more_less: u8, value: i64, target: i64) -> bool {
+    if more_less == 0 {
+        value >= target
+    } else {
+        value <= target
+    }
+}
+
+/// Steps a monster AI over a world
+pub struct AiInterpreter<'a> {
+    pub script: &'a AIP,
+    pub world: AiWorld,
+
+    /// Entity found by the last search condition
+    pub found: Option<usize>,
+    random: Random,
+    idle_time: f32,
+}
+
+impl<'a> AiInterpreter<'a> {
+    /// Random rolls and movement are repeatable for the same `seed`
+    pub fn new(script: &'a AIP, world: AiWorld, seed: u32) -> AiInterpreter<'a> {
+        AiInterpreter {
+            script,
+            world,
+            found: None,
+            random: Random::new(seed),
+            idle_time: 0.0,
+        }
+    }
+
+    fn random_percent(&mut self) -> u32 {
+        self.random.next_u32() % 100
+    }
+
+    fn random_offset(&mut self, origin: &Vector3<f32>, distance: i32) -> Vector3<f32> {
+        let angle = (self.random.next_u32() % 3600) as f32 / 3600.0 * std::f32::consts::PI * 2.0;
+        let length = (self.random.next_u32() % 1000) as f32 / 1000.0 * distance as f32;
+        Vector3 {
+            x: origin.x + angle.cos() * length,
+            y: origin.y + angle.sin() * length,
+            z: origin.z,
+        }
+    }
+
+    /// Position `distance` away from `from`, on the far side of the monster
+    fn away_from(&self, from: &Vector3<f32>, distance: i32) -> Vector3<f32> {
+        let monster = &self.world.monster.position;
+        let length = self.world.monster.distance(from);
+        let (dx, dy) = if length > 0.0 {
+            ((monster.x - from.x) / length, (monster.y - from.y) / length)
+        } else {
+            (1.0, 0.0)
+        };
+        Vector3 {
+            x: from.x + dx * distance as f32,
+            y: from.y + dy * distance as f32,
+            z: monster.z,
+        }
+    }
+
+    fn entity(&self, index: Option<usize>) -> Option<&AiEntity> {
+        index.and_then(|i| self.world.entities.get(i))
+    }
+
+    /// Entities within `distance` matching the alliance and level difference
+    fn search(&self, distance: i32, is_allied: bool, level_min: i16, level_max: i16) -> Vec<usize> {
+        let monster = &self.world.monster;
+        let mut found: Vec<usize> = (0..self.world.entities.len())
+            .filter(|i| {
+                let entity = &self.world.entities[*i];
+                entity.allied == is_allied
+                    && entity.health > 0
+                    && entity.distance(&monster.position) <= distance as f32
+                    && in_range(
+                        entity.level - monster.level,
+                        i32::from(level_min),
+                        i32::from(level_max),
+                    )
+            })
+            .collect();
+        found.sort_by(|a, b| {
+            let a = self.world.entities[*a].distance(&monster.position);
+            let b = self.world.entities[*b].distance(&monster.position);
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        found
+    }
+
+    /// Check a condition, search conditions set `found`
+    pub fn check(&mut self, condition: &AiCondition) -> AiCheck {
+        let world = &self.world;
+        let passed = match condition {
+            AiCondition::FightOrDelay { not_fighting } => *not_fighting == world.target.is_none(),
+            AiCondition::Damage {
+                damage,
+                receive_or_give,
+            } => {
+                let value = if *receive_or_give == 0 {
+                    world.damage_received
+                } else {
+                    world.damage_given
+                };
+                value >= *damage
+            }
+            AiCondition::FindCharacters {
+                distance,
+                is_allied,
+                level_min,
+                level_max,
+                count,
+            } => {
+                let found = self.search(*distance, *is_allied, *level_min, *level_max);
+                let passed = found.len() >= usize::from(*count) && !found.is_empty();
+                if passed {
+                    self.found = found.first().cloned();
+                }
+                passed
+            }
+            AiCondition::DistanceFromSpawn { distance } => {
+                world.monster.distance(&world.spawn) >= *distance as f32
+            }
+            AiCondition::DistanceToTarget {
+                distance,
+                more_less: op,
+            } => match self.entity(world.target) {
+                Some(target) => more_less(
+                    *op,
+                    target.distance(&world.monster.position) as i64,
+                    i64::from(*distance),
+                ),
+                None => false,
+            },
+            AiCondition::TargetAbility {
+                ability,
+                value,
+                more_less: op,
+            } => match self.entity(world.target) {
+                Some(target) => {
+                    more_less(*op, i64::from(target.ability(*ability)), i64::from(*value))
+                }
+                None => false,
+            },
+            AiCondition::HealthPercent {
+                percent,
+                more_less: op,
+            } => {
+                let health = if world.monster.max_health > 0 {
+                    i64::from(world.monster.health) * 100 / i64::from(world.monster.max_health)
+                } else {
+                    0
+                };
+                more_less(*op, health, i64::from(*percent))
+            }
+            AiCondition::RandomPercent { percent } => self.random_percent() < u32::from(*percent),
+            AiCondition::FindNearest {
+                distance,
+                level_min,
+                level_max,
+                is_allied,
+            } => {
+                let found = self.search(*distance, *is_allied, *level_min, *level_max);
+                self.found = found.first().cloned();
+                self.found.is_some()
+            }
+            AiCondition::CompareAttackerAndTarget {
+                ability,
+                more_less: op,
+            } => match (self.entity(world.attacker), self.entity(world.target)) {
+                (Some(attacker), Some(target)) => more_less(
+                    *op,
+                    i64::from(attacker.ability(*ability)),
+                    i64::from(target.ability(*ability)),
+                ),
+                _ => false,
+            },
+            AiCondition::AttackerAbility {
+                ability,
+                value,
+                more_less: op,
+            } => match self.entity(world.attacker) {
+                Some(attacker) => more_less(
+                    *op,
+                    i64::from(attacker.ability(*ability)),
+                    i64::from(*value),
+                ),
+                None => false,
+            },
+            AiCondition::DayTime { when } => (*when == 1) == world.is_night,
+            AiCondition::NpcVariable {
+                variable,
+                value,
+                op,
+            } => compare(
+                *op,
+                i64::from(world.npc_variables.get(variable).cloned().unwrap_or(0)),
+                i64::from(*value),
+            ),
+            AiCondition::WorldVariable {
+                variable,
+                value,
+                op,
+            } => compare(
+                *op,
+                i64::from(world.world_variables.get(variable).cloned().unwrap_or(0)),
+                i64::from(*value),
+            ),
+            AiCondition::EconomyVariable {
+                variable,
+                value,
+                op,
+            } => compare(
+                *op,
+                i64::from(world.economy_variables.get(variable).cloned().unwrap_or(0)),
+                i64::from(*value),
+            ),
+            AiCondition::MonsterVariable {
+                variable,
+                value,
+                op,
+            } => compare(
+                *op,
+                i64::from(world.monster_variables.get(variable).cloned().unwrap_or(0)),
+                i64::from(*value),
+            ),
+            AiCondition::SelectNpc { .. } => true,
+            AiCondition::OwnerDistance { distance, op } => match self.entity(world.owner) {
+                Some(owner) => compare(
+                    *op,
+                    owner.distance(&world.monster.position) as i64,
+                    i64::from(*distance),
+                ),
+                None => false,
+            },
+            AiCondition::ZoneTime { start, end } => in_range(world.zone_time, *start, *end),
+            AiCondition::SelfAbility { ability, value, op } => compare(
+                *op,
+                i64::from(world.monster.ability(*ability)),
+                i64::from(*value),
+            ),
+            AiCondition::HasOwner => world.owner.is_some(),
+            AiCondition::WorldTime { start, end } => in_range(world.world_time, *start, *end),
+            AiCondition::MonthDayTime {
+                day,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                (*day == 0 || *day == world.day)
+                    && in_time(
+                        (world.hour, world.minute),
+                        (*start_hour, *start_minute),
+                        (*end_hour, *end_minute),
+                    )
+            }
+            AiCondition::WeekDayTime {
+                weekday,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                *weekday == world.weekday
+                    && in_time(
+                        (world.hour, world.minute),
+                        (*start_hour, *start_minute),
+                        (*end_hour, *end_minute),
+                    )
+            }
+            AiCondition::ChannelNumber { min, max } => in_range(world.channel, *min, *max),
+            AiCondition::CountCharacters {
+                distance,
+                is_allied,
+                level_min,
+                level_max,
+                count,
+                op,
+            } => {
+                let found = self.search(*distance, *is_allied, *level_min, *level_max);
+                compare(*op, found.len() as i64, i64::from(*count))
+            }
+            _ => return AiCheck::Unsupported,
+        };
+
+        if passed {
+            AiCheck::Passed
+        } else {
+            AiCheck::Failed
+        }
+    }
+
+    /// Apply an action to the world
+    pub fn apply(&mut self, action: &AiAction) -> AiCommand {
+        match action {
+            AiAction::Stop => {
+                self.world.target = None;
+                AiCommand::Stop
+            }
+            AiAction::Emote { motion } => AiCommand::Emote(*motion),
+            AiAction::Say { string_id } => AiCommand::Say(*string_id),
+            AiAction::MoveRandom { distance, speed } => {
+                let origin = self.world.monster.position;
+                {
+                    let position = self.random_offset(&origin, *distance);
+                    self.move_to(position, *speed)
+                }
+            }
+            AiAction::MoveRandomFromSpawn { distance, speed } => {
+                let origin = self.world.spawn;
+                {
+                    let position = self.random_offset(&origin, *distance);
+                    self.move_to(position, *speed)
+                }
+            }
+            AiAction::MoveToFound { speed } => match self.entity(self.found) {
+                Some(found) => self.move_to(found.position, *speed),
+                None => AiCommand::Unsupported(action.clone()),
+            },
+            AiAction::AttackByAbility {
+                distance,
+                ability,
+                more_less,
+            } => {
+                let monster = self.world.monster.position;
+                let candidates = (0..self.world.entities.len()).filter(|i| {
+                    let entity = &self.world.entities[*i];
+                    !entity.allied
+                        && entity.health > 0
+                        && entity.distance(&monster) <= *distance as f32
+                });
+                let ability = |i: &usize| self.world.entities[*i].ability(*ability);
+                let target = if *more_less == 0 {
+                    candidates.max_by_key(ability)
+                } else {
+                    candidates.min_by_key(ability)
+                };
+                self.attack(target, action)
+            }
+            AiAction::SpecialAttack => AiCommand::SpecialAttack,
+            AiAction::MoveFromTarget { distance, speed } => match self.entity(self.world.target) {
+                Some(target) => {
+                    let position = self.away_from(&target.position, *distance);
+                    self.move_to(position, *speed)
+                }
+                None => AiCommand::Unsupported(action.clone()),
+            },
+            AiAction::Transform { monster } => AiCommand::Transform(*monster),
+            AiAction::SpawnMonster { monster } => AiCommand::Spawn {
+                monster: *monster,
+                position: self.world.monster.position,
+            },
+            AiAction::CallAlliesForHelp { distance, count } => AiCommand::CallForHelp {
+                distance: *distance,
+                count: *count,
+            },
+            AiAction::CallFamilyForHelp { distance } => AiCommand::CallForHelp {
+                distance: *distance,
+                count: 0,
+            },
+            AiAction::AttackNearest | AiAction::AttackFound => self.attack(self.found, action),
+            AiAction::AttackAttacker => self.attack(self.world.attacker, action),
+            AiAction::RunAway { distance } => {
+                let from = self
+                    .entity(self.world.attacker.or(self.world.target))
+                    .map(|e| e.position)
+                    .unwrap_or(self.world.spawn);
+                let position = self.away_from(&from, *distance);
+                self.world.target = None;
+                self.move_to(position, 1)
+            }
+            AiAction::DropItem { items, to_owner } => {
+                let items: Vec<i16> = items.iter().cloned().filter(|i| *i != 0).collect();
+                if items.is_empty() {
+                    return AiCommand::Unsupported(action.clone());
+                }
+                let item = items[self.random.next_u32() as usize % items.len()];
+                AiCommand::Drop {
+                    item,
+                    to_owner: *to_owner,
+                }
+            }
+            AiAction::SpawnMonsterAt {
+                monster,
+                position,
+                distance,
+            } => {
+                let origin = match position {
+                    0 => Some(self.world.spawn),
+                    1 => Some(self.world.monster.position),
+                    _ => self.entity(self.world.target).map(|e| e.position),
+                };
+                match origin {
+                    Some(origin) => AiCommand::Spawn {
+                        monster: *monster,
+                        position: self.random_offset(&origin, *distance),
+                    },
+                    None => AiCommand::Unsupported(action.clone()),
+                }
+            }
+            AiAction::Unknown { .. } => AiCommand::Unsupported(action.clone()),
+        }
+    }
+
+    fn move_to(&mut self, position: Vector3<f32>, speed: u8) -> AiCommand {
+        self.world.monster.position = position;
+        AiCommand::Move { position, speed }
+    }
+
+    fn attack(&mut self, target: Option<usize>, action: &AiAction) -> AiCommand {
+        match target {
+            Some(target) => {
+                self.world.target = Some(target);
+                AiCommand::Attack { target }
+            }
+            None => AiCommand::Unsupported(action.clone()),
+        }
+    }
+
+    /// Run an event, see the `AI_EVENT_*` constants
+    pub fn step(&mut self, event: usize) -> AiStep {
+        let mut step = AiStep {
+            event,
+            ..Default::default()
+        };
+        let script = self.script;
+        let patterns = match script.event(event) {
+            Some(event) => &event.patterns,
+            None => return step,
+        };
+
+        self.found = None;
+        for (p, pattern) in patterns.iter().enumerate() {
+            let mut passed = true;
+            for (c, condition) in pattern.conditions.iter().enumerate() {
+                let result = self.check(condition);
+                step.conditions.push(AiConditionResult {
+                    pattern: p,
+                    condition: c,
+                    result,
+                });
+                if result != AiCheck::Passed {
+                    passed = false;
+                    break;
+                }
+            }
+
+            if passed {
+                step.pattern = Some(p);
+                for action in pattern.actions.iter() {
+                    let command = self.apply(action);
+                    step.commands.push(command);
+                }
+                break;
+            }
+        }
+
+        step
+    }
+
+    /// Advance time, running the idle event every `idle_interval` seconds
+    /// while the monster has no target
+    pub fn advance(&mut self, seconds: f32) -> Vec<AiStep> {
+        let interval = self.script.idle_interval.max(1) as f32;
+        let mut steps = Vec::new();
+
+        self.idle_time += seconds;
+        while self.idle_time >= interval {
+            self.idle_time -= interval;
+            if self.world.target.is_none() {
+                steps.push(self.step(AI_EVENT_IDLE));
+            }
+        }
+        steps
+    }
+
+    /// Damage the monster, the damaged event runs with a `damage_rate`
+    /// percent chance
+    pub fn damage(&mut self, attacker: usize, damage: i32) -> Option<AiStep> {
+        self.world.attacker = Some(attacker);
+        self.world.damage_received = damage;
+        self.world.monster.health = (self.world.monster.health - damage).max(0);
+
+        if self.random_percent() < self.script.damage_rate {
+            Some(self.step(AI_EVENT_DAMAGED))
+        } else {
+            None
+        }
+    }
+}
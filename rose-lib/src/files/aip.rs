@@ -0,0 +1,921 @@
+//! ROSE Online AI Patterns
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::record::{decode_record, encode_record, pad, read_record, skip, write_record};
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// AI pattern file
+pub type AIP = AiScript;
+
+/// Length of the fixed-size event and pattern names
+const NAME_LENGTH: usize = 32;
+
+/// Type id of the first condition record
+const CONDITION_TYPE_BASE: u32 = 0x0400_0001;
+
+/// Type id of the first action record
+const ACTION_TYPE_BASE: u32 = 0x0B00_0001;
+
+/// Event run when the monster spawns
+pub const AI_EVENT_SPAWN: usize = 0;
+
+/// Event run every `idle_interval` seconds while idle
+pub const AI_EVENT_IDLE: usize = 1;
+
+/// Event run while moving to attack
+pub const AI_EVENT_ATTACK_MOVE: usize = 2;
+
+/// Event run when the monster is damaged
+pub const AI_EVENT_DAMAGED: usize = 3;
+
+/// Event run when the monster kills its target
+pub const AI_EVENT_KILL: usize = 4;
+
+/// Event run when the monster dies
+pub const AI_EVENT_DEAD: usize = 5;
+
+/// Monster AI
+///
+/// Events are indexed by the `AI_EVENT_*` constants. When an event fires the
+/// first pattern whose conditions all pass runs its actions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AiScript {
+    /// Seconds between idle events
+    pub idle_interval: u32,
+
+    /// Percent chance of running the damaged event when hit
+    pub damage_rate: u32,
+    pub name: String,
+    pub events: Vec<AiEvent>,
+}
+
+impl AiScript {
+    pub fn event(&self, event: usize) -> Option<&AiEvent> {
+        self.events.get(event)
+    }
+}
+
+impl RoseFile for AiScript {
+    fn new() -> AiScript {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let event_count = reader.read_u32()?;
+        self.idle_interval = reader.read_u32()?;
+        self.damage_rate = reader.read_u32()?;
+        self.name = reader.read_string_u32()?;
+
+        for _ in 0..event_count {
+            let mut event = AiEvent {
+                name: read_name(reader)?,
+                patterns: Vec::new(),
+            };
+
+            let pattern_count = reader.read_u32()?;
+            for _ in 0..pattern_count {
+                let mut pattern = AiPattern {
+                    name: read_name(reader)?,
+                    ..Default::default()
+                };
+
+                let condition_count = reader.read_u32()?;
+                for _ in 0..condition_count {
+                    let (id, data) = read_record(reader)?;
+                    let id = id.wrapping_sub(CONDITION_TYPE_BASE);
+                    pattern.conditions.push(AiCondition::decode(id, &data));
+                }
+
+                let action_count = reader.read_u32()?;
+                for _ in 0..action_count {
+                    let (id, data) = read_record(reader)?;
+                    let id = id.wrapping_sub(ACTION_TYPE_BASE);
+                    pattern.actions.push(AiAction::decode(id, &data));
+                }
+
+                event.patterns.push(pattern);
+            }
+
+            self.events.push(event);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.events.len() as u32)?;
+        writer.write_u32(self.idle_interval)?;
+        writer.write_u32(self.damage_rate)?;
+        writer.write_string_u32(&self.name)?;
+
+        for event in self.events.iter() {
+            write_name(writer, &event.name)?;
+            writer.write_u32(event.patterns.len() as u32)?;
+
+            for pattern in event.patterns.iter() {
+                write_name(writer, &pattern.name)?;
+
+                writer.write_u32(pattern.conditions.len() as u32)?;
+                for condition in pattern.conditions.iter() {
+                    let id = condition.id().wrapping_add(CONDITION_TYPE_BASE);
+                    write_record(writer, id, &condition.encode()?)?;
+                }
+
+                writer.write_u32(pattern.actions.len() as u32)?;
+                for action in pattern.actions.iter() {
+                    let id = action.id().wrapping_add(ACTION_TYPE_BASE);
+                    write_record(writer, id, &action.encode()?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_name<R: ReadRoseExt>(reader: &mut R) -> Result<String, Error> {
+    let name = reader.read_string(NAME_LENGTH as u64)?;
+    Ok(name.trim_end_matches('\0').to_string())
+}
+
+fn write_name<W: WriteRoseExt>(writer: &mut W, name: &str) -> Result<(), Error> {
+    if name.len() >= NAME_LENGTH {
+        bail!(
+            "AI names must be shorter than {} bytes: {}",
+            NAME_LENGTH,
+            name
+        );
+    }
+    writer.write_string(name, NAME_LENGTH as i32)?;
+    Ok(())
+}
+
+/// Patterns run for an event
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AiEvent {
+    pub name: String,
+    pub patterns: Vec<AiPattern>,
+}
+
+/// Actions run when every condition passes
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AiPattern {
+    pub name: String,
+    pub conditions: Vec<AiCondition>,
+    pub actions: Vec<AiAction>,
+}
+
+/// Condition record
+///
+/// `more_less` fields are 0 for greater or equal and 1 for less or equal.
+/// `op` fields are 0 equal, 1 greater, 2 greater or equal, 3 less, 4 less
+/// or equal and 10 not equal. Records with an unknown type or layout are
+/// kept as `Unknown`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiCondition {
+    FightOrDelay {
+        not_fighting: bool,
+    },
+    /// Damage received (0) or given (1) is at least `damage`
+    Damage {
+        damage: i32,
+        receive_or_give: u8,
+    },
+    /// Find `count` characters within `distance` whose level difference is
+    /// in range, the found character is used by later actions
+    FindCharacters {
+        distance: i32,
+        is_allied: bool,
+        level_min: i16,
+        level_max: i16,
+        count: u16,
+    },
+    DistanceFromSpawn {
+        distance: i32,
+    },
+    DistanceToTarget {
+        distance: i32,
+        more_less: u8,
+    },
+    TargetAbility {
+        ability: u8,
+        value: i32,
+        more_less: u8,
+    },
+    HealthPercent {
+        percent: u32,
+        more_less: u8,
+    },
+    RandomPercent {
+        percent: u8,
+    },
+    /// Find the nearest character within `distance` whose level difference
+    /// is in range
+    FindNearest {
+        distance: i32,
+        level_min: i16,
+        level_max: i16,
+        is_allied: bool,
+    },
+    TargetChanged,
+    CompareAttackerAndTarget {
+        ability: u8,
+        more_less: u8,
+    },
+    AttackerAbility {
+        ability: u8,
+        value: i32,
+        more_less: u8,
+    },
+    /// 0 during the day, 1 at night
+    DayTime {
+        when: u8,
+    },
+    StatusEffect {
+        target: u8,
+        status_type: u8,
+        have: bool,
+    },
+    NpcVariable {
+        variable: i16,
+        value: i32,
+        op: u8,
+    },
+    WorldVariable {
+        variable: i16,
+        value: i32,
+        op: u8,
+    },
+    EconomyVariable {
+        variable: i16,
+        value: i32,
+        op: u8,
+    },
+    SelectNpc {
+        npc: i32,
+    },
+    OwnerDistance {
+        distance: i32,
+        op: u8,
+    },
+    ZoneTime {
+        start: u32,
+        end: u32,
+    },
+    SelfAbility {
+        ability: u8,
+        value: i32,
+        op: u8,
+    },
+    HasOwner,
+    OwnerHasTarget,
+    WorldTime {
+        start: u32,
+        end: u32,
+    },
+    MonthDayTime {
+        day: u8,
+        start_hour: u8,
+        start_minute: u8,
+        end_hour: u8,
+        end_minute: u8,
+    },
+    WeekDayTime {
+        weekday: u8,
+        start_hour: u8,
+        start_minute: u8,
+        end_hour: u8,
+        end_minute: u8,
+    },
+    ChannelNumber {
+        min: u16,
+        max: u16,
+    },
+    /// Compare the number of characters within `distance` whose level
+    /// difference is in range
+    CountCharacters {
+        distance: i32,
+        is_allied: bool,
+        level_min: i16,
+        level_max: i16,
+        count: u16,
+        op: u8,
+    },
+    MonsterVariable {
+        variable: i16,
+        value: i32,
+        op: u8,
+    },
+    Unknown {
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl AiCondition {
+    /// Record type id, relative to the first condition type
+    pub fn id(&self) -> u32 {
+        match self {
+            AiCondition::FightOrDelay { .. } => 0,
+            AiCondition::Damage { .. } => 1,
+            AiCondition::FindCharacters { .. } => 2,
+            AiCondition::DistanceFromSpawn { .. } => 3,
+            AiCondition::DistanceToTarget { .. } => 4,
+            AiCondition::TargetAbility { .. } => 5,
+            AiCondition::HealthPercent { .. } => 6,
+            AiCondition::RandomPercent { .. } => 7,
+            AiCondition::FindNearest { .. } => 8,
+            AiCondition::TargetChanged => 9,
+            AiCondition::CompareAttackerAndTarget { .. } => 10,
+            AiCondition::AttackerAbility { .. } => 11,
+            AiCondition::DayTime { .. } => 12,
+            AiCondition::StatusEffect { .. } => 13,
+            AiCondition::NpcVariable { .. } => 14,
+            AiCondition::WorldVariable { .. } => 15,
+            AiCondition::EconomyVariable { .. } => 16,
+            AiCondition::SelectNpc { .. } => 17,
+            AiCondition::OwnerDistance { .. } => 18,
+            AiCondition::ZoneTime { .. } => 19,
+            AiCondition::SelfAbility { .. } => 20,
+            AiCondition::HasOwner => 21,
+            AiCondition::OwnerHasTarget => 22,
+            AiCondition::WorldTime { .. } => 23,
+            AiCondition::MonthDayTime { .. } => 24,
+            AiCondition::WeekDayTime { .. } => 25,
+            AiCondition::ChannelNumber { .. } => 26,
+            AiCondition::CountCharacters { .. } => 27,
+            AiCondition::MonsterVariable { .. } => 28,
+            AiCondition::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Decode a record payload, unknown records are kept as-is
+    pub fn decode(id: u32, data: &[u8]) -> AiCondition {
+        decode_record(data, |r| AiCondition::read(id, r)).unwrap_or_else(|| AiCondition::Unknown {
+            id,
+            data: data.to_vec(),
+        })
+    }
+
+    fn read<R: ReadRoseExt>(id: u32, r: &mut R) -> Result<AiCondition, Error> {
+        Ok(match id {
+            0 => AiCondition::FightOrDelay {
+                not_fighting: r.read_u8()? != 0,
+            },
+            1 => AiCondition::Damage {
+                damage: r.read_i32()?,
+                receive_or_give: r.read_u8()?,
+            },
+            2 => {
+                let distance = r.read_i32()?;
+                let is_allied = r.read_u8()? != 0;
+                skip(r, 1)?;
+                AiCondition::FindCharacters {
+                    distance,
+                    is_allied,
+                    level_min: r.read_i16()?,
+                    level_max: r.read_i16()?,
+                    count: r.read_u16()?,
+                }
+            }
+            3 => AiCondition::DistanceFromSpawn {
+                distance: r.read_i32()?,
+            },
+            4 => AiCondition::DistanceToTarget {
+                distance: r.read_i32()?,
+                more_less: r.read_u8()?,
+            },
+            5 => {
+                let ability = r.read_u8()?;
+                skip(r, 3)?;
+                AiCondition::TargetAbility {
+                    ability,
+                    value: r.read_i32()?,
+                    more_less: r.read_u8()?,
+                }
+            }
+            6 => AiCondition::HealthPercent {
+                percent: r.read_u32()?,
+                more_less: r.read_u8()?,
+            },
+            7 => AiCondition::RandomPercent {
+                percent: r.read_u8()?,
+            },
+            8 => AiCondition::FindNearest {
+                distance: r.read_i32()?,
+                level_min: r.read_i16()?,
+                level_max: r.read_i16()?,
+                is_allied: r.read_u8()? != 0,
+            },
+            9 => AiCondition::TargetChanged,
+            10 => AiCondition::CompareAttackerAndTarget {
+                ability: r.read_u8()?,
+                more_less: r.read_u8()?,
+            },
+            11 | 20 => {
+                let ability = r.read_u8()?;
+                skip(r, 3)?;
+                let value = r.read_i32()?;
+                let op = r.read_u8()?;
+                if id == 11 {
+                    AiCondition::AttackerAbility {
+                        ability,
+                        value,
+                        more_less: op,
+                    }
+                } else {
+                    AiCondition::SelfAbility { ability, value, op }
+                }
+            }
+            12 => AiCondition::DayTime { when: r.read_u8()? },
+            13 => AiCondition::StatusEffect {
+                target: r.read_u8()?,
+                status_type: r.read_u8()?,
+                have: r.read_u8()? != 0,
+            },
+            14 | 15 | 16 | 28 => {
+                let variable = r.read_i16()?;
+                skip(r, 2)?;
+                let value = r.read_i32()?;
+                let op = r.read_u8()?;
+                match id {
+                    14 => AiCondition::NpcVariable {
+                        variable,
+                        value,
+                        op,
+                    },
+                    15 => AiCondition::WorldVariable {
+                        variable,
+                        value,
+                        op,
+                    },
+                    16 => AiCondition::EconomyVariable {
+                        variable,
+                        value,
+                        op,
+                    },
+                    _ => AiCondition::MonsterVariable {
+                        variable,
+                        value,
+                        op,
+                    },
+                }
+            }
+            17 => AiCondition::SelectNpc { npc: r.read_i32()? },
+            18 => AiCondition::OwnerDistance {
+                distance: r.read_i32()?,
+                op: r.read_u8()?,
+            },
+            19 => AiCondition::ZoneTime {
+                start: r.read_u32()?,
+                end: r.read_u32()?,
+            },
+            21 => AiCondition::HasOwner,
+            22 => AiCondition::OwnerHasTarget,
+            23 => AiCondition::WorldTime {
+                start: r.read_u32()?,
+                end: r.read_u32()?,
+            },
+            24 => AiCondition::MonthDayTime {
+                day: r.read_u8()?,
+                start_hour: r.read_u8()?,
+                start_minute: r.read_u8()?,
+                end_hour: r.read_u8()?,
+                end_minute: r.read_u8()?,
+            },
+            25 => AiCondition::WeekDayTime {
+                weekday: r.read_u8()?,
+                start_hour: r.read_u8()?,
+                start_minute: r.read_u8()?,
+                end_hour: r.read_u8()?,
+                end_minute: r.read_u8()?,
+            },
+            26 => AiCondition::ChannelNumber {
+                min: r.read_u16()?,
+                max: r.read_u16()?,
+            },
+            27 => {
+                let distance = r.read_i32()?;
+                let is_allied = r.read_u8()? != 0;
+                skip(r, 1)?;
+                AiCondition::CountCharacters {
+                    distance,
+                    is_allied,
+                    level_min: r.read_i16()?,
+                    level_max: r.read_i16()?,
+                    count: r.read_u16()?,
+                    op: r.read_u8()?,
+                }
+            }
+            _ => bail!("Unknown AI condition: {}", id),
+        })
+    }
+
+    /// Record payload
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        if let AiCondition::Unknown { data, .. } = self {
+            return Ok(data.clone());
+        }
+        encode_record(|w| self.write(w))
+    }
+
+    fn write<W: WriteRoseExt>(&self, w: &mut W) -> Result<(), Error> {
+        match self {
+            AiCondition::FightOrDelay { not_fighting } => w.write_u8(*not_fighting as u8)?,
+            AiCondition::Damage {
+                damage,
+                receive_or_give,
+            } => {
+                w.write_i32(*damage)?;
+                w.write_u8(*receive_or_give)?;
+            }
+            AiCondition::FindCharacters {
+                distance,
+                is_allied,
+                level_min,
+                level_max,
+                count,
+            } => {
+                w.write_i32(*distance)?;
+                w.write_u8(*is_allied as u8)?;
+                pad(w, 1)?;
+                w.write_i16(*level_min)?;
+                w.write_i16(*level_max)?;
+                w.write_u16(*count)?;
+            }
+            AiCondition::DistanceFromSpawn { distance } => w.write_i32(*distance)?,
+            AiCondition::DistanceToTarget {
+                distance,
+                more_less: op,
+            }
+            | AiCondition::OwnerDistance { distance, op } => {
+                w.write_i32(*distance)?;
+                w.write_u8(*op)?;
+            }
+            AiCondition::TargetAbility {
+                ability,
+                value,
+                more_less: op,
+            }
+            | AiCondition::AttackerAbility {
+                ability,
+                value,
+                more_less: op,
+            }
+            | AiCondition::SelfAbility { ability, value, op } => {
+                w.write_u8(*ability)?;
+                pad(w, 3)?;
+                w.write_i32(*value)?;
+                w.write_u8(*op)?;
+            }
+            AiCondition::HealthPercent { percent, more_less } => {
+                w.write_u32(*percent)?;
+                w.write_u8(*more_less)?;
+            }
+            AiCondition::RandomPercent { percent } => w.write_u8(*percent)?,
+            AiCondition::FindNearest {
+                distance,
+                level_min,
+                level_max,
+                is_allied,
+            } => {
+                w.write_i32(*distance)?;
+                w.write_i16(*level_min)?;
+                w.write_i16(*level_max)?;
+                w.write_u8(*is_allied as u8)?;
+            }
+            AiCondition::TargetChanged | AiCondition::HasOwner | AiCondition::OwnerHasTarget => {}
+            AiCondition::CompareAttackerAndTarget { ability, more_less } => {
+                w.write_u8(*ability)?;
+                w.write_u8(*more_less)?;
+            }
+            AiCondition::DayTime { when } => w.write_u8(*when)?,
+            AiCondition::StatusEffect {
+                target,
+                status_type,
+                have,
+            } => {
+                w.write_u8(*target)?;
+                w.write_u8(*status_type)?;
+                w.write_u8(*have as u8)?;
+            }
+            AiCondition::NpcVariable {
+                variable,
+                value,
+                op,
+            }
+            | AiCondition::WorldVariable {
+                variable,
+                value,
+                op,
+            }
+            | AiCondition::EconomyVariable {
+                variable,
+                value,
+                op,
+            }
+            | AiCondition::MonsterVariable {
+                variable,
+                value,
+                op,
+            } => {
+                w.write_i16(*variable)?;
+                pad(w, 2)?;
+                w.write_i32(*value)?;
+                w.write_u8(*op)?;
+            }
+            AiCondition::SelectNpc { npc } => w.write_i32(*npc)?,
+            AiCondition::ZoneTime { start, end } | AiCondition::WorldTime { start, end } => {
+                w.write_u32(*start)?;
+                w.write_u32(*end)?;
+            }
+            AiCondition::MonthDayTime {
+                day: first,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            }
+            | AiCondition::WeekDayTime {
+                weekday: first,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                w.write_u8(*first)?;
+                w.write_u8(*start_hour)?;
+                w.write_u8(*start_minute)?;
+                w.write_u8(*end_hour)?;
+                w.write_u8(*end_minute)?;
+            }
+            AiCondition::ChannelNumber { min, max } => {
+                w.write_u16(*min)?;
+                w.write_u16(*max)?;
+            }
+            AiCondition::CountCharacters {
+                distance,
+                is_allied,
+                level_min,
+                level_max,
+                count,
+                op,
+            } => {
+                w.write_i32(*distance)?;
+                w.write_u8(*is_allied as u8)?;
+                pad(w, 1)?;
+                w.write_i16(*level_min)?;
+                w.write_i16(*level_max)?;
+                w.write_u16(*count)?;
+                w.write_u8(*op)?;
+            }
+            AiCondition::Unknown { data, .. } => w.write_all(data)?,
+        }
+        Ok(())
+    }
+}
+
+/// Action record
+///
+/// Records with an unknown type or layout are kept as `Unknown`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiAction {
+    Stop,
+    Emote {
+        motion: u8,
+    },
+    Say {
+        string_id: i32,
+    },
+    MoveRandom {
+        distance: i32,
+        speed: u8,
+    },
+    MoveRandomFromSpawn {
+        distance: i32,
+        speed: u8,
+    },
+    /// Move to the character found by a condition
+    MoveToFound {
+        speed: u8,
+    },
+    /// Attack the character within `distance` with the highest (0) or
+    /// lowest (1) ability value
+    AttackByAbility {
+        distance: i32,
+        ability: u8,
+        more_less: u8,
+    },
+    SpecialAttack,
+    MoveFromTarget {
+        distance: i32,
+        speed: u8,
+    },
+    Transform {
+        monster: u16,
+    },
+    SpawnMonster {
+        monster: u16,
+    },
+    /// Call up to `count` allies within `distance` to attack the target
+    CallAlliesForHelp {
+        distance: i32,
+        count: i32,
+    },
+    AttackNearest,
+    AttackFound,
+    CallFamilyForHelp {
+        distance: i32,
+    },
+    AttackAttacker,
+    RunAway {
+        distance: i32,
+    },
+    /// Drop one of the items at random
+    DropItem {
+        items: [i16; 5],
+        to_owner: bool,
+    },
+    /// Spawn a monster near the spawn point (0), the monster (1) or the
+    /// target (2)
+    SpawnMonsterAt {
+        monster: u16,
+        position: u8,
+        distance: i32,
+    },
+    Unknown {
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl AiAction {
+    /// Record type id, relative to the first action type
+    pub fn id(&self) -> u32 {
+        match self {
+            AiAction::Stop => 0,
+            AiAction::Emote { .. } => 1,
+            AiAction::Say { .. } => 2,
+            AiAction::MoveRandom { .. } => 3,
+            AiAction::MoveRandomFromSpawn { .. } => 4,
+            AiAction::MoveToFound { .. } => 5,
+            AiAction::AttackByAbility { .. } => 6,
+            AiAction::SpecialAttack => 7,
+            AiAction::MoveFromTarget { .. } => 8,
+            AiAction::Transform { .. } => 9,
+            AiAction::SpawnMonster { .. } => 10,
+            AiAction::CallAlliesForHelp { .. } => 11,
+            AiAction::AttackNearest => 12,
+            AiAction::AttackFound => 13,
+            AiAction::CallFamilyForHelp { .. } => 14,
+            AiAction::AttackAttacker => 15,
+            AiAction::RunAway { .. } => 16,
+            AiAction::DropItem { .. } => 17,
+            AiAction::SpawnMonsterAt { .. } => 20,
+            AiAction::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Decode a record payload, unknown records are kept as-is
+    pub fn decode(id: u32, data: &[u8]) -> AiAction {
+        decode_record(data, |r| AiAction::read(id, r)).unwrap_or_else(|| AiAction::Unknown {
+            id,
+            data: data.to_vec(),
+        })
+    }
+
+    fn read<R: ReadRoseExt>(id: u32, r: &mut R) -> Result<AiAction, Error> {
+        Ok(match id {
+            0 => AiAction::Stop,
+            1 => AiAction::Emote {
+                motion: r.read_u8()?,
+            },
+            2 => AiAction::Say {
+                string_id: r.read_i32()?,
+            },
+            3 | 4 | 8 => {
+                let distance = r.read_i32()?;
+                let speed = r.read_u8()?;
+                match id {
+                    3 => AiAction::MoveRandom { distance, speed },
+                    4 => AiAction::MoveRandomFromSpawn { distance, speed },
+                    _ => AiAction::MoveFromTarget { distance, speed },
+                }
+            }
+            5 => AiAction::MoveToFound {
+                speed: r.read_u8()?,
+            },
+            6 => AiAction::AttackByAbility {
+                distance: r.read_i32()?,
+                ability: r.read_u8()?,
+                more_less: r.read_u8()?,
+            },
+            7 => AiAction::SpecialAttack,
+            9 => AiAction::Transform {
+                monster: r.read_u16()?,
+            },
+            10 => AiAction::SpawnMonster {
+                monster: r.read_u16()?,
+            },
+            11 => AiAction::CallAlliesForHelp {
+                distance: r.read_i32()?,
+                count: r.read_i32()?,
+            },
+            12 => AiAction::AttackNearest,
+            13 => AiAction::AttackFound,
+            14 => AiAction::CallFamilyForHelp {
+                distance: r.read_i32()?,
+            },
+            15 => AiAction::AttackAttacker,
+            16 => AiAction::RunAway {
+                distance: r.read_i32()?,
+            },
+            17 => {
+                let mut items = [0i16; 5];
+                for item in items.iter_mut() {
+                    *item = r.read_i16()?;
+                }
+                AiAction::DropItem {
+                    items,
+                    to_owner: r.read_u8()? != 0,
+                }
+            }
+            20 => {
+                let monster = r.read_u16()?;
+                let position = r.read_u8()?;
+                skip(r, 1)?;
+                AiAction::SpawnMonsterAt {
+                    monster,
+                    position,
+                    distance: r.read_i32()?,
+                }
+            }
+            _ => bail!("Unknown AI action: {}", id),
+        })
+    }
+
+    /// Record payload
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        if let AiAction::Unknown { data, .. } = self {
+            return Ok(data.clone());
+        }
+        encode_record(|w| self.write(w))
+    }
+
+    fn write<W: WriteRoseExt>(&self, w: &mut W) -> Result<(), Error> {
+        match self {
+            AiAction::Stop
+            | AiAction::SpecialAttack
+            | AiAction::AttackNearest
+            | AiAction::AttackFound
+            | AiAction::AttackAttacker => {}
+            AiAction::Emote { motion } => w.write_u8(*motion)?,
+            AiAction::Say { string_id } => w.write_i32(*string_id)?,
+            AiAction::MoveRandom { distance, speed }
+            | AiAction::MoveRandomFromSpawn { distance, speed }
+            | AiAction::MoveFromTarget { distance, speed } => {
+                w.write_i32(*distance)?;
+                w.write_u8(*speed)?;
+            }
+            AiAction::MoveToFound { speed } => w.write_u8(*speed)?,
+            AiAction::AttackByAbility {
+                distance,
+                ability,
+                more_less,
+            } => {
+                w.write_i32(*distance)?;
+                w.write_u8(*ability)?;
+                w.write_u8(*more_less)?;
+            }
+            AiAction::Transform { monster } | AiAction::SpawnMonster { monster } => {
+                w.write_u16(*monster)?
+            }
+            AiAction::CallAlliesForHelp { distance, count } => {
+                w.write_i32(*distance)?;
+                w.write_i32(*count)?;
+            }
+            AiAction::CallFamilyForHelp { distance } | AiAction::RunAway { distance } => {
+                w.write_i32(*distance)?
+            }
+            AiAction::DropItem { items, to_owner } => {
+                for item in items.iter() {
+                    w.write_i16(*item)?;
+                }
+                w.write_u8(*to_owner as u8)?;
+            }
+            AiAction::SpawnMonsterAt {
+                monster,
+                position,
+                distance,
+            } => {
+                w.write_u16(*monster)?;
+                w.write_u8(*position)?;
+                pad(w, 1)?;
+                w.write_i32(*distance)?;
+            }
+            AiAction::Unknown { data, .. } => w.write_all(data)?,
+        }
+        Ok(())
+    }
+}
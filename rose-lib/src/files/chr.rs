@@ -0,0 +1,187 @@
+//! ROSE Online Character Definitions
+use std::path::PathBuf;
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// Character file
+pub type CHR = CharacterList;
+
+/// Character list
+///
+/// Characters are indexed by NPC/monster id. Ids without a character are
+/// `None` and are kept so the remaining ids don't shift.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CharacterList {
+    pub skeletons: Vec<PathBuf>,
+    pub motions: Vec<PathBuf>,
+    pub effects: Vec<PathBuf>,
+    pub characters: Vec<Option<Character>>,
+}
+
+impl CharacterList {
+    pub fn character(&self, id: usize) -> Option<&Character> {
+        self.characters.get(id).and_then(|c| c.as_ref())
+    }
+
+    /// Skeleton path of a character
+    pub fn skeleton(&self, id: usize) -> Option<&PathBuf> {
+        let character = self.character(id)?;
+        self.skeletons.get(character.skeleton_id as usize)
+    }
+
+    /// Motion path a character uses for `action_id`
+    pub fn motion(&self, id: usize, action_id: u16) -> Option<&PathBuf> {
+        let motion = self
+            .character(id)?
+            .motions
+            .iter()
+            .find(|m| m.action_id == action_id)?;
+        self.motions.get(motion.motion_id as usize)
+    }
+
+    /// Effect paths of a character with the bone they are attached to
+    pub fn effects(&self, id: usize) -> Vec<(u16, &PathBuf)> {
+        self.character(id)
+            .map(|c| {
+                c.effects
+                    .iter()
+                    .filter_map(|e| {
+                        self.effects
+                            .get(e.effect_id as usize)
+                            .map(|p| (e.bone_index, p))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl RoseFile for CharacterList {
+    fn new() -> CharacterList {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        for paths in [&mut self.skeletons, &mut self.motions, &mut self.effects].iter_mut() {
+            let count = reader.read_u16()?;
+            for _ in 0..count {
+                paths.push(PathBuf::from(reader.read_cstring()?));
+            }
+        }
+
+        let character_count = reader.read_u16()?;
+        for _ in 0..character_count {
+            if !reader.read_bool()? {
+                self.characters.push(None);
+                continue;
+            }
+
+            let mut character = Character::new();
+            character.skeleton_id = reader.read_u16()?;
+            character.name = reader.read_cstring()?;
+
+            let object_count = reader.read_u16()?;
+            for _ in 0..object_count {
+                character.objects.push(reader.read_u16()?);
+            }
+
+            let motion_count = reader.read_u16()?;
+            for _ in 0..motion_count {
+                character.motions.push(CharacterMotion {
+                    action_id: reader.read_u16()?,
+                    motion_id: reader.read_u16()?,
+                });
+            }
+
+            let effect_count = reader.read_u16()?;
+            for _ in 0..effect_count {
+                character.effects.push(CharacterEffect {
+                    bone_index: reader.read_u16()?,
+                    effect_id: reader.read_u16()?,
+                });
+            }
+
+            self.characters.push(Some(character));
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        for paths in [&self.skeletons, &self.motions, &self.effects].iter() {
+            writer.write_u16(paths.len() as u16)?;
+            for path in paths.iter() {
+                writer.write_cstring(path.to_str().unwrap())?;
+            }
+        }
+
+        writer.write_u16(self.characters.len() as u16)?;
+        for character in self.characters.iter() {
+            let character = match character {
+                Some(c) => c,
+                None => {
+                    writer.write_bool(false)?;
+                    continue;
+                }
+            };
+
+            writer.write_bool(true)?;
+            writer.write_u16(character.skeleton_id)?;
+            writer.write_cstring(&character.name)?;
+
+            writer.write_u16(character.objects.len() as u16)?;
+            for object in character.objects.iter() {
+                writer.write_u16(*object)?;
+            }
+
+            writer.write_u16(character.motions.len() as u16)?;
+            for motion in character.motions.iter() {
+                writer.write_u16(motion.action_id)?;
+                writer.write_u16(motion.motion_id)?;
+            }
+
+            writer.write_u16(character.effects.len() as u16)?;
+            for effect in character.effects.iter() {
+                writer.write_u16(effect.bone_index)?;
+                writer.write_u16(effect.effect_id)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Character
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Character {
+    pub skeleton_id: u16,
+    pub name: String,
+
+    /// ZSC object ids of the character's model parts
+    pub objects: Vec<u16>,
+    pub motions: Vec<CharacterMotion>,
+    pub effects: Vec<CharacterEffect>,
+}
+
+impl Character {
+    pub fn new() -> Character {
+        Self::default()
+    }
+}
+
+/// Motion used by a character for an action
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CharacterMotion {
+    pub action_id: u16,
+    pub motion_id: u16,
+}
+
+/// Effect attached to a character bone
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CharacterEffect {
+    pub bone_index: u16,
+    pub effect_id: u16,
+}
@@ -0,0 +1,224 @@
+//! ROSE Online Conversations
+use std::io::{Read, SeekFrom};
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// Conversation file
+pub type CON = Conversation;
+
+/// Length of the fixed-size function names
+const FUNCTION_NAME_LENGTH: usize = 32;
+
+/// Size of a message entry
+const MESSAGE_SIZE: u32 = 4 * 4 + FUNCTION_NAME_LENGTH as u32 * 2;
+
+/// Message type that closes the dialog
+pub const MESSAGE_CLOSE: u32 = 0;
+
+/// Message type shown as an option for the player
+pub const MESSAGE_PLAYER_SELECT: u32 = 1;
+
+/// Message type that jumps to the menu in `value`
+pub const MESSAGE_JUMP_SELECT: u32 = 2;
+
+/// NPC conversation
+///
+/// The dialog starts with `messages`, options then lead into `menus`.
+/// Conditions and actions are functions in the embedded script.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Conversation {
+    pub mask: u32,
+    pub messages: Vec<ConversationMessage>,
+    pub menus: Vec<ConversationMenu>,
+
+    /// Embedded Lua script, usually compiled
+    pub script: Vec<u8>,
+}
+
+impl Conversation {
+    /// Menu a jump message leads to
+    pub fn jump_menu(&self, message: &ConversationMessage) -> Option<&ConversationMenu> {
+        if message.message_type != MESSAGE_JUMP_SELECT {
+            return None;
+        }
+        self.menus.get(message.value as usize)
+    }
+
+    /// Whether the script is compiled Lua bytecode rather than source
+    pub fn script_compiled(&self) -> bool {
+        self.script.starts_with(b"\x1bLua")
+    }
+}
+
+impl RoseFile for Conversation {
+    fn new() -> Conversation {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.mask = reader.read_u32()?;
+        let menus_offset = reader.read_u32()?;
+        let script_offset = reader.read_u32()?;
+
+        self.messages = read_messages(reader)?;
+
+        reader.seek(SeekFrom::Start(u64::from(menus_offset)))?;
+        let menu_count = reader.read_u32()?;
+        let mut menu_offsets = Vec::new();
+        for _ in 0..menu_count {
+            menu_offsets.push(reader.read_u32()?);
+        }
+        for offset in menu_offsets {
+            reader.seek(SeekFrom::Start(u64::from(menus_offset) + u64::from(offset)))?;
+            self.menus.push(ConversationMenu {
+                messages: read_messages(reader)?,
+            });
+        }
+
+        reader.seek(SeekFrom::Start(u64::from(script_offset)))?;
+        let script_size = reader.read_u32()?;
+        self.script = Vec::new();
+        reader
+            .by_ref()
+            .take(u64::from(script_size))
+            .read_to_end(&mut self.script)?;
+        if self.script.len() != script_size as usize {
+            bail!(
+                "Conversation script is {} bytes, expected {}",
+                self.script.len(),
+                script_size
+            );
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        let messages_size = table_size(self.messages.len());
+        let menus_offset = 12 + messages_size;
+        let menus_size: u32 = 4
+            + 4 * self.menus.len() as u32
+            + self
+                .menus
+                .iter()
+                .map(|m| table_size(m.messages.len()))
+                .sum::<u32>();
+        let script_offset = menus_offset + menus_size;
+
+        writer.write_u32(self.mask)?;
+        writer.write_u32(menus_offset)?;
+        writer.write_u32(script_offset)?;
+
+        write_messages(writer, &self.messages)?;
+
+        writer.write_u32(self.menus.len() as u32)?;
+        let mut offset = 4 + 4 * self.menus.len() as u32;
+        for menu in self.menus.iter() {
+            writer.write_u32(offset)?;
+            offset += table_size(menu.messages.len());
+        }
+        for menu in self.menus.iter() {
+            write_messages(writer, &menu.messages)?;
+        }
+
+        writer.write_u32(self.script.len() as u32)?;
+        writer.write_all(&self.script)?;
+
+        Ok(())
+    }
+}
+
+/// Size of a message table, offsets are relative to the start of the table
+fn table_size(count: usize) -> u32 {
+    4 + (4 + MESSAGE_SIZE) * count as u32
+}
+
+fn read_function_name<R: ReadRoseExt>(reader: &mut R) -> Result<String, Error> {
+    // Bytes after the terminator are left over from the editor
+    let name = reader.read_string(FUNCTION_NAME_LENGTH as u64)?;
+    Ok(name.split('\0').next().unwrap_or_default().to_string())
+}
+
+fn read_messages<R: ReadRoseExt>(reader: &mut R) -> Result<Vec<ConversationMessage>, Error> {
+    let table_offset = reader.position()?;
+    let count = reader.read_u32()?;
+    let mut offsets = Vec::new();
+    for _ in 0..count {
+        offsets.push(reader.read_u32()?);
+    }
+
+    let mut messages = Vec::new();
+    for offset in offsets {
+        reader.seek(SeekFrom::Start(table_offset + u64::from(offset)))?;
+        messages.push(ConversationMessage {
+            id: reader.read_u32()?,
+            message_type: reader.read_u32()?,
+            value: reader.read_u32()?,
+            condition: read_function_name(reader)?,
+            action: read_function_name(reader)?,
+            string_id: reader.read_u32()?,
+        });
+    }
+    Ok(messages)
+}
+
+fn write_messages<W: WriteRoseExt>(
+    writer: &mut W,
+    messages: &[ConversationMessage],
+) -> Result<(), Error> {
+    writer.write_u32(messages.len() as u32)?;
+    for i in 0..messages.len() as u32 {
+        writer.write_u32(4 + 4 * messages.len() as u32 + MESSAGE_SIZE * i)?;
+    }
+
+    for message in messages.iter() {
+        if message.condition.len() >= FUNCTION_NAME_LENGTH
+            || message.action.len() >= FUNCTION_NAME_LENGTH
+        {
+            bail!(
+                "Function names must be shorter than {} bytes: {}",
+                FUNCTION_NAME_LENGTH,
+                message.id
+            );
+        }
+
+        writer.write_u32(message.id)?;
+        writer.write_u32(message.message_type)?;
+        writer.write_u32(message.value)?;
+        writer.write_string(&message.condition, FUNCTION_NAME_LENGTH as i32)?;
+        writer.write_string(&message.action, FUNCTION_NAME_LENGTH as i32)?;
+        writer.write_u32(message.string_id)?;
+    }
+    Ok(())
+}
+
+/// Options shown together
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConversationMenu {
+    pub messages: Vec<ConversationMessage>,
+}
+
+/// Dialog message or player option
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConversationMessage {
+    pub id: u32,
+
+    /// One of the `MESSAGE_*` types
+    pub message_type: u32,
+
+    /// Menu index for jump messages
+    pub value: u32,
+
+    /// Script function deciding whether the message is shown, empty if
+    /// always shown
+    pub condition: String,
+
+    /// Script function run when the message is selected
+    pub action: String,
+
+    /// Row of the message text in the conversation string table
+    pub string_id: u32,
+}
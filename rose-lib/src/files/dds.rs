@@ -0,0 +1,753 @@
+//! DirectDraw Surface
+//!
+//! Texture container used by all ROSE Online models, terrain and UI. Supports
+//! DXT1/DXT3/DXT5 block compression and uncompressed RGB/ARGB pixel formats
+//! with a full mipmap chain. Cube maps and volume textures are not supported.
+use std::fmt;
+use std::str;
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// DirectDraw Surface file
+pub type DDS = DirectDrawSurface;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: u32 = 124;
+const DDS_PIXEL_FORMAT_SIZE: u32 = 32;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+/// Pixel format of a DDS texture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DdsFormat {
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    #[default]
+    A8R8G8B8,
+    X8R8G8B8,
+    R8G8B8,
+    A1R5G5B5,
+    X1R5G5B5,
+    A4R4G4B4,
+    R5G6B5,
+}
+
+impl fmt::Display for DdsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DdsFormat::Dxt1 => write!(f, "dxt1"),
+            DdsFormat::Dxt3 => write!(f, "dxt3"),
+            DdsFormat::Dxt5 => write!(f, "dxt5"),
+            DdsFormat::A8R8G8B8 => write!(f, "a8r8g8b8"),
+            DdsFormat::X8R8G8B8 => write!(f, "x8r8g8b8"),
+            DdsFormat::R8G8B8 => write!(f, "r8g8b8"),
+            DdsFormat::A1R5G5B5 => write!(f, "a1r5g5b5"),
+            DdsFormat::X1R5G5B5 => write!(f, "x1r5g5b5"),
+            DdsFormat::A4R4G4B4 => write!(f, "a4r4g4b4"),
+            DdsFormat::R5G6B5 => write!(f, "r5g6b5"),
+        }
+    }
+}
+
+impl str::FromStr for DdsFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<DdsFormat, Self::Err> {
+        for format in DdsFormat::all() {
+            if format.to_string() == s.to_lowercase() {
+                return Ok(*format);
+            }
+        }
+        bail!("Invalid DDS format: {}", s)
+    }
+}
+
+impl DdsFormat {
+    /// All supported formats
+    pub fn all() -> &'static [DdsFormat] {
+        &[
+            DdsFormat::Dxt1,
+            DdsFormat::Dxt3,
+            DdsFormat::Dxt5,
+            DdsFormat::A8R8G8B8,
+            DdsFormat::X8R8G8B8,
+            DdsFormat::R8G8B8,
+            DdsFormat::A1R5G5B5,
+            DdsFormat::X1R5G5B5,
+            DdsFormat::A4R4G4B4,
+            DdsFormat::R5G6B5,
+        ]
+    }
+
+    pub fn is_compressed(self) -> bool {
+        matches!(self, DdsFormat::Dxt1 | DdsFormat::Dxt3 | DdsFormat::Dxt5)
+    }
+
+    /// Bytes per 4x4 block for compressed formats, bytes per pixel otherwise
+    fn block_size(self) -> usize {
+        match self {
+            DdsFormat::Dxt1 => 8,
+            DdsFormat::Dxt3 | DdsFormat::Dxt5 => 16,
+            _ => self.masks().0 as usize / 8,
+        }
+    }
+
+    /// Four character code of compressed formats
+    fn fourcc(self) -> Option<&'static [u8; 4]> {
+        match self {
+            DdsFormat::Dxt1 => Some(b"DXT1"),
+            DdsFormat::Dxt3 => Some(b"DXT3"),
+            DdsFormat::Dxt5 => Some(b"DXT5"),
+            _ => None,
+        }
+    }
+
+    /// Bit count and red, green, blue and alpha masks of uncompressed formats
+    fn masks(self) -> (u32, [u32; 4]) {
+        match self {
+            DdsFormat::A8R8G8B8 => (32, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]),
+            DdsFormat::X8R8G8B8 => (32, [0xFF_0000, 0xFF00, 0xFF, 0]),
+            DdsFormat::R8G8B8 => (24, [0xFF_0000, 0xFF00, 0xFF, 0]),
+            DdsFormat::A1R5G5B5 => (16, [0x7C00, 0x03E0, 0x1F, 0x8000]),
+            DdsFormat::X1R5G5B5 => (16, [0x7C00, 0x03E0, 0x1F, 0]),
+            DdsFormat::A4R4G4B4 => (16, [0x0F00, 0x00F0, 0x0F, 0xF000]),
+            DdsFormat::R5G6B5 => (16, [0xF800, 0x07E0, 0x1F, 0]),
+            DdsFormat::Dxt1 | DdsFormat::Dxt3 | DdsFormat::Dxt5 => (0, [0; 4]),
+        }
+    }
+
+    /// Size in bytes of an image with the given dimensions
+    pub fn level_size(self, width: u32, height: u32) -> usize {
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        if self.is_compressed() {
+            width.div_ceil(4) * height.div_ceil(4) * self.block_size()
+        } else {
+            width * height * self.block_size()
+        }
+    }
+}
+
+/// DirectDraw Surface
+///
+/// Each mipmap holds the raw (compressed) pixel data of one level, starting
+/// with the full size image.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirectDrawSurface {
+    pub width: u32,
+    pub height: u32,
+    pub format: DdsFormat,
+    pub mipmaps: Vec<Vec<u8>>,
+}
+
+impl DirectDrawSurface {
+    /// Encode an RGBA8 image, optionally generating the full mipmap chain
+    pub fn encode(
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        format: DdsFormat,
+        mipmaps: bool,
+    ) -> Result<DirectDrawSurface, Error> {
+        if width == 0 || height == 0 {
+            bail!("Invalid texture size: {}x{}", width, height);
+        }
+        if rgba.len() != (width * height * 4) as usize {
+            bail!(
+                "Expected {} bytes of RGBA data for a {}x{} texture, found {}",
+                width * height * 4,
+                width,
+                height,
+                rgba.len()
+            );
+        }
+
+        let mut dds = DirectDrawSurface {
+            width,
+            height,
+            format,
+            mipmaps: Vec::new(),
+        };
+
+        let (mut level_width, mut level_height) = (width, height);
+        let mut level = rgba.to_vec();
+        loop {
+            dds.mipmaps
+                .push(encode_level(level_width, level_height, &level, format));
+
+            if !mipmaps || (level_width == 1 && level_height == 1) {
+                break;
+            }
+
+            level = downsample(level_width, level_height, &level);
+            level_width = (level_width / 2).max(1);
+            level_height = (level_height / 2).max(1);
+        }
+
+        Ok(dds)
+    }
+
+    /// Decode a mipmap level to RGBA8
+    pub fn decode(&self, level: usize) -> Result<Vec<u8>, Error> {
+        let data = match self.mipmaps.get(level) {
+            Some(data) => data,
+            None => bail!("Mipmap level {} does not exist", level),
+        };
+
+        let (width, height) = self.level_dimensions(level);
+        if data.len() < self.format.level_size(width, height) {
+            bail!("Not enough data for mipmap level {}", level);
+        }
+
+        Ok(decode_level(width, height, data, self.format))
+    }
+
+    pub fn mipmap_count(&self) -> usize {
+        self.mipmaps.len()
+    }
+
+    /// Width and height of a mipmap level
+    pub fn level_dimensions(&self, level: usize) -> (u32, u32) {
+        let width = self.width.checked_shr(level as u32).unwrap_or(0);
+        let height = self.height.checked_shr(level as u32).unwrap_or(0);
+        (width.max(1), height.max(1))
+    }
+}
+
+impl RoseFile for DirectDrawSurface {
+    fn new() -> DirectDrawSurface {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != DDS_MAGIC {
+            bail!("Invalid DDS magic: {:?}", magic);
+        }
+
+        let header_size = reader.read_u32()?;
+        if header_size != DDS_HEADER_SIZE {
+            bail!("Invalid DDS header size: {}", header_size);
+        }
+
+        let flags = reader.read_u32()?;
+        self.height = reader.read_u32()?;
+        self.width = reader.read_u32()?;
+        let _pitch_or_linear_size = reader.read_u32()?;
+        let _depth = reader.read_u32()?;
+        let mipmap_count = reader.read_u32()?;
+        for _ in 0..11 {
+            let _reserved = reader.read_u32()?;
+        }
+
+        let _pixel_format_size = reader.read_u32()?;
+        let pixel_flags = reader.read_u32()?;
+        let mut fourcc = [0u8; 4];
+        reader.read_exact(&mut fourcc)?;
+        let bit_count = reader.read_u32()?;
+        let mut masks = [0u32; 4];
+        for mask in masks.iter_mut() {
+            *mask = reader.read_u32()?;
+        }
+
+        let _caps = reader.read_u32()?;
+        let caps2 = reader.read_u32()?;
+        let _caps3 = reader.read_u32()?;
+        let _caps4 = reader.read_u32()?;
+        let _reserved2 = reader.read_u32()?;
+
+        if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+            bail!("Cube map and volume textures are not supported");
+        }
+        if self.width == 0 || self.height == 0 {
+            bail!("Invalid texture size: {}x{}", self.width, self.height);
+        }
+
+        self.format = if pixel_flags & DDPF_FOURCC != 0 {
+            match DdsFormat::all()
+                .iter()
+                .find(|f| f.fourcc() == Some(&fourcc))
+            {
+                Some(format) => *format,
+                None => bail!(
+                    "Unsupported DDS compression: {}",
+                    String::from_utf8_lossy(&fourcc)
+                ),
+            }
+        } else if pixel_flags & DDPF_RGB != 0 {
+            if pixel_flags & DDPF_ALPHAPIXELS == 0 {
+                masks[3] = 0;
+            }
+            match DdsFormat::all()
+                .iter()
+                .find(|f| !f.is_compressed() && f.masks() == (bit_count, masks))
+            {
+                Some(format) => *format,
+                None => bail!(
+                    "Unsupported DDS pixel format: {} bits, masks {:08X?}",
+                    bit_count,
+                    masks
+                ),
+            }
+        } else {
+            bail!("Unsupported DDS pixel format flags: {:#X}", pixel_flags);
+        };
+
+        // Some exporters leave the count at 0 for textures without mipmaps
+        let max_levels = 32 - self.width.max(self.height).leading_zeros();
+        let mipmap_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+            mipmap_count.clamp(1, max_levels)
+        } else {
+            1
+        };
+
+        for level in 0..mipmap_count as usize {
+            let (width, height) = self.level_dimensions(level);
+            let mut data = vec![0u8; self.format.level_size(width, height)];
+            reader.read_exact(&mut data)?;
+            self.mipmaps.push(data);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        if self.mipmaps.is_empty() {
+            bail!("DDS texture has no image data");
+        }
+        for level in 0..self.mipmaps.len() {
+            let (width, height) = self.level_dimensions(level);
+            if self.mipmaps[level].len() != self.format.level_size(width, height) {
+                bail!(
+                    "Mipmap level {} has {} bytes, expected {}",
+                    level,
+                    self.mipmaps[level].len(),
+                    self.format.level_size(width, height)
+                );
+            }
+        }
+
+        let has_mipmaps = self.mipmaps.len() > 1;
+
+        let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+        if has_mipmaps {
+            flags |= DDSD_MIPMAPCOUNT;
+        }
+        let pitch_or_linear_size = if self.format.is_compressed() {
+            flags |= DDSD_LINEARSIZE;
+            self.mipmaps[0].len() as u32
+        } else {
+            flags |= DDSD_PITCH;
+            self.width * self.format.block_size() as u32
+        };
+
+        writer.write_all(DDS_MAGIC)?;
+        writer.write_u32(DDS_HEADER_SIZE)?;
+        writer.write_u32(flags)?;
+        writer.write_u32(self.height)?;
+        writer.write_u32(self.width)?;
+        writer.write_u32(pitch_or_linear_size)?;
+        writer.write_u32(0)?;
+        writer.write_u32(self.mipmaps.len() as u32)?;
+        for _ in 0..11 {
+            writer.write_u32(0)?;
+        }
+
+        let (bit_count, masks) = self.format.masks();
+        writer.write_u32(DDS_PIXEL_FORMAT_SIZE)?;
+        match self.format.fourcc() {
+            Some(fourcc) => {
+                writer.write_u32(DDPF_FOURCC)?;
+                writer.write_all(fourcc)?;
+            }
+            None => {
+                let alpha_flag = if masks[3] != 0 { DDPF_ALPHAPIXELS } else { 0 };
+                writer.write_u32(DDPF_RGB | alpha_flag)?;
+                writer.write_u32(0)?;
+            }
+        }
+        writer.write_u32(bit_count)?;
+        for mask in masks.iter() {
+            writer.write_u32(*mask)?;
+        }
+
+        let mut caps = DDSCAPS_TEXTURE;
+        if has_mipmaps {
+            caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+        }
+        writer.write_u32(caps)?;
+        for _ in 0..4 {
+            writer.write_u32(0)?;
+        }
+
+        for data in self.mipmaps.iter() {
+            writer.write_all(data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Halve an RGBA8 image by averaging 2x2 pixel blocks
+fn downsample(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let (new_width, new_height) = ((width / 2).max(1), (height / 2).max(1));
+    let pixel = |x: u32, y: u32| (((y.min(height - 1) * width) + x.min(width - 1)) * 4) as usize;
+
+    let mut data = Vec::with_capacity((new_width * new_height * 4) as usize);
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let samples = [
+                pixel(x * 2, y * 2),
+                pixel(x * 2 + 1, y * 2),
+                pixel(x * 2, y * 2 + 1),
+                pixel(x * 2 + 1, y * 2 + 1),
+            ];
+            for channel in 0..4 {
+                let sum: u32 = samples.iter().map(|s| rgba[s + channel] as u32).sum();
+                data.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    data
+}
+
+fn decode_level(width: u32, height: u32, data: &[u8], format: DdsFormat) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut rgba = vec![0u8; width * height * 4];
+
+    if !format.is_compressed() {
+        let (bit_count, masks) = format.masks();
+        let bytes = bit_count as usize / 8;
+        for (pixel, chunk) in data.chunks(bytes).take(width * height).enumerate() {
+            let value = chunk
+                .iter()
+                .rev()
+                .fold(0u32, |value, b| (value << 8) | *b as u32);
+            for channel in 0..4 {
+                rgba[pixel * 4 + channel] = match masks[channel] {
+                    0 => 255,
+                    mask => unpack_channel(value, mask),
+                };
+            }
+        }
+        return rgba;
+    }
+
+    let block_size = format.block_size();
+    let blocks_wide = width.div_ceil(4);
+    for (block_idx, block) in data.chunks(block_size).enumerate() {
+        let (block_x, block_y) = (block_idx % blocks_wide * 4, block_idx / blocks_wide * 4);
+        if block_y >= height {
+            break;
+        }
+
+        let pixels = match format {
+            DdsFormat::Dxt1 => decode_color_block(block, true),
+            DdsFormat::Dxt3 => {
+                let mut pixels = decode_color_block(&block[8..], false);
+                let alpha = u64::from_le_bytes(to_array(&block[..8]));
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    pixel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+                }
+                pixels
+            }
+            _ => {
+                let mut pixels = decode_color_block(&block[8..], false);
+                let palette = alpha_palette(block[0], block[1]);
+                let mut indices = [0u8; 8];
+                indices[..6].copy_from_slice(&block[2..8]);
+                let indices = u64::from_le_bytes(indices);
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    pixel[3] = palette[((indices >> (i * 3)) & 0x7) as usize];
+                }
+                pixels
+            }
+        };
+
+        for (i, pixel) in pixels.iter().enumerate() {
+            let (x, y) = (block_x + i % 4, block_y + i / 4);
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                rgba[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+
+    rgba
+}
+
+fn encode_level(width: u32, height: u32, rgba: &[u8], format: DdsFormat) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut data = Vec::with_capacity(format.level_size(width as u32, height as u32));
+
+    if !format.is_compressed() {
+        let (bit_count, masks) = format.masks();
+        for pixel in rgba.chunks(4) {
+            let mut value = 0;
+            for channel in 0..4 {
+                value |= pack_channel(pixel[channel], masks[channel]);
+            }
+            data.extend_from_slice(&value.to_le_bytes()[..bit_count as usize / 8]);
+        }
+        return data;
+    }
+
+    for block_y in (0..height).step_by(4) {
+        for block_x in (0..width).step_by(4) {
+            // Pad partial blocks by repeating the edge pixels
+            let mut pixels = [[0u8; 4]; 16];
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let x = (block_x + i % 4).min(width - 1);
+                let y = (block_y + i / 4).min(height - 1);
+                let offset = (y * width + x) * 4;
+                pixel.copy_from_slice(&rgba[offset..offset + 4]);
+            }
+
+            match format {
+                DdsFormat::Dxt1 => data.extend_from_slice(&encode_color_block(&pixels, true)),
+                DdsFormat::Dxt3 => {
+                    let mut alpha = 0u64;
+                    for (i, pixel) in pixels.iter().enumerate() {
+                        let value = (pixel[3] as u64 * 15 + 127) / 255;
+                        alpha |= value << (i * 4);
+                    }
+                    data.extend_from_slice(&alpha.to_le_bytes());
+                    data.extend_from_slice(&encode_color_block(&pixels, false));
+                }
+                _ => {
+                    data.extend_from_slice(&encode_alpha_block(&pixels));
+                    data.extend_from_slice(&encode_color_block(&pixels, false));
+                }
+            }
+        }
+    }
+
+    data
+}
+
+fn to_array(bytes: &[u8]) -> [u8; 8] {
+    let mut array = [0u8; 8];
+    array.copy_from_slice(&bytes[..8]);
+    array
+}
+
+fn unpack_channel(value: u32, mask: u32) -> u8 {
+    let max = mask >> mask.trailing_zeros();
+    let channel = (value & mask) >> mask.trailing_zeros();
+    ((channel * 255 + max / 2) / max) as u8
+}
+
+fn pack_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = mask >> mask.trailing_zeros();
+    ((value as u32 * max + 127) / 255) << mask.trailing_zeros()
+}
+
+fn unpack_565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 0x1F) as u8;
+    let g = ((color >> 5) & 0x3F) as u8;
+    let b = (color & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+fn pack_565(color: [f32; 3]) -> u16 {
+    let quantize = |c: f32, max: f32| (c.clamp(0.0, 255.0) * max / 255.0).round() as u16;
+    (quantize(color[0], 31.0) << 11) | (quantize(color[1], 63.0) << 5) | quantize(color[2], 31.0)
+}
+
+/// Palette of a color block, DXT1 blocks with `color0 <= color1` have three
+/// colors and transparent black
+fn color_palette(color0: u16, color1: u16, allow_alpha: bool) -> [[u8; 4]; 4] {
+    let c0 = unpack_565(color0);
+    let c1 = unpack_565(color1);
+    let mix = |w0: u32, w1: u32| {
+        let mut color = [255u8; 4];
+        for channel in 0..3 {
+            color[channel] =
+                ((c0[channel] as u32 * w0 + c1[channel] as u32 * w1) / (w0 + w1)) as u8;
+        }
+        color
+    };
+
+    if color0 > color1 || !allow_alpha {
+        [c0, c1, mix(2, 1), mix(1, 2)]
+    } else {
+        [c0, c1, mix(1, 1), [0, 0, 0, 0]]
+    }
+}
+
+fn decode_color_block(block: &[u8], allow_alpha: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let palette = color_palette(color0, color1, allow_alpha);
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+    pixels
+}
+
+fn alpha_palette(alpha0: u8, alpha1: u8) -> [u8; 8] {
+    let (a0, a1) = (alpha0 as u32, alpha1 as u32);
+    let mut palette = [alpha0, alpha1, 0, 0, 0, 0, 0, 255];
+    if alpha0 > alpha1 {
+        for (i, alpha) in palette.iter_mut().enumerate().skip(2) {
+            let i = i as u32;
+            *alpha = (((8 - i) * a0 + (i - 1) * a1) / 7) as u8;
+        }
+    } else {
+        for (i, alpha) in palette.iter_mut().enumerate().take(6).skip(2) {
+            let i = i as u32;
+            *alpha = (((6 - i) * a0 + (i - 1) * a1) / 5) as u8;
+        }
+    }
+    palette
+}
+
+fn color_distance(a: &[u8; 4], b: &[u8; 4]) -> u32 {
+    (0..3)
+        .map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Encode a block by fitting a line through the colors along their principal
+/// axis and quantizing the extremes to the endpoints
+fn encode_color_block(pixels: &[[u8; 4]; 16], allow_alpha: bool) -> [u8; 8] {
+    let transparent = |p: &[u8; 4]| allow_alpha && p[3] < 128;
+    let opaque: Vec<[f32; 3]> = pixels
+        .iter()
+        .filter(|p| !transparent(p))
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+    let has_alpha = opaque.len() < pixels.len();
+
+    let mut block = [0u8; 8];
+    if opaque.is_empty() {
+        block[4..].copy_from_slice(&[0xFF; 4]);
+        return block;
+    }
+
+    let count = opaque.len() as f32;
+    let mut mean = [0f32; 3];
+    for color in opaque.iter() {
+        for c in 0..3 {
+            mean[c] += color[c] / count;
+        }
+    }
+
+    let mut covariance = [[0f32; 3]; 3];
+    for color in opaque.iter() {
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += (color[i] - mean[i]) * (color[j] - mean[j]);
+            }
+        }
+    }
+
+    // Power iteration for the principal axis
+    let mut axis = [1f32, 1.0, 1.0];
+    for _ in 0..8 {
+        let mut next = [0f32; 3];
+        for i in 0..3 {
+            next[i] = (0..3).map(|j| covariance[i][j] * axis[j]).sum();
+        }
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < f32::EPSILON {
+            break;
+        }
+        axis = [next[0] / length, next[1] / length, next[2] / length];
+    }
+
+    let project = |color: &[f32; 3]| (0..3).map(|c| (color[c] - mean[c]) * axis[c]).sum();
+    let (mut min, mut max) = (0f32, 0f32);
+    for color in opaque.iter() {
+        let t: f32 = project(color);
+        min = min.min(t);
+        max = max.max(t);
+    }
+    let endpoint = |t: f32| {
+        pack_565([
+            mean[0] + axis[0] * t,
+            mean[1] + axis[1] * t,
+            mean[2] + axis[2] * t,
+        ])
+    };
+
+    let (mut color0, mut color1) = (endpoint(max), endpoint(min));
+    if has_alpha == (color0 > color1) {
+        std::mem::swap(&mut color0, &mut color1);
+    }
+    let palette = color_palette(color0, color1, allow_alpha);
+    let colors = if allow_alpha && color0 <= color1 {
+        3
+    } else {
+        4
+    };
+
+    let mut indices = 0u32;
+    for (i, pixel) in pixels.iter().enumerate() {
+        let index = if transparent(pixel) {
+            3
+        } else {
+            (0..colors)
+                .min_by_key(|&c| color_distance(&palette[c], pixel))
+                .unwrap_or(0)
+        };
+        indices |= (index as u32) << (i * 2);
+    }
+
+    block[..2].copy_from_slice(&color0.to_le_bytes());
+    block[2..4].copy_from_slice(&color1.to_le_bytes());
+    block[4..].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+fn encode_alpha_block(pixels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let alpha0 = pixels.iter().map(|p| p[3]).max().unwrap_or(255);
+    let alpha1 = pixels.iter().map(|p| p[3]).min().unwrap_or(255);
+    let palette = alpha_palette(alpha0, alpha1);
+
+    let mut indices = 0u64;
+    for (i, pixel) in pixels.iter().enumerate() {
+        let index = (0..8)
+            .min_by_key(|&a| (palette[a] as i32 - pixel[3] as i32).abs())
+            .unwrap_or(0);
+        indices |= (index as u64) << (i * 3);
+    }
+
+    let mut block = [0u8; 8];
+    block[0] = alpha0;
+    block[1] = alpha1;
+    block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
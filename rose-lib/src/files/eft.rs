@@ -0,0 +1,225 @@
+//! ROSE Online Effects
+use std::path::PathBuf;
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+use crate::utils::{Quaternion, Vector3};
+
+/// Effect file
+pub type EFT = Effect;
+
+/// Effect
+///
+/// An effect combines particle systems and animated meshes with an optional
+/// sound.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Effect {
+    pub name: String,
+    pub sound_enabled: bool,
+    pub sound_path: PathBuf,
+    pub sound_repeat_count: u32,
+    pub particles: Vec<EffectParticle>,
+    pub meshes: Vec<EffectMesh>,
+}
+
+impl RoseFile for Effect {
+    fn new() -> Effect {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.name = reader.read_string_u32()?;
+        self.sound_enabled = reader.read_u32()? != 0;
+        self.sound_path = PathBuf::from(reader.read_string_u32()?);
+        self.sound_repeat_count = reader.read_u32()?;
+
+        let particle_count = reader.read_u32()?;
+        for _ in 0..particle_count {
+            let mut particle = EffectParticle::new();
+            particle.name = reader.read_string_u32()?;
+            particle.unique_name = reader.read_string_u32()?;
+            particle.particle_index = reader.read_u32()?;
+            particle.particle_path = PathBuf::from(reader.read_string_u32()?);
+            particle.animation = EffectAnimation::read(reader)?;
+            particle.link = EffectLink::read(reader)?;
+            self.particles.push(particle);
+        }
+
+        let mesh_count = reader.read_u32()?;
+        for _ in 0..mesh_count {
+            let mut mesh = EffectMesh::new();
+            mesh.name = reader.read_string_u32()?;
+            mesh.unique_name = reader.read_string_u32()?;
+            mesh.mesh_index = reader.read_u32()?;
+            mesh.mesh_path = PathBuf::from(reader.read_string_u32()?);
+            mesh.mesh_animation_path = PathBuf::from(reader.read_string_u32()?);
+            mesh.texture_path = PathBuf::from(reader.read_string_u32()?);
+            mesh.alpha_enabled = reader.read_u32()? != 0;
+            mesh.two_sided = reader.read_u32()? != 0;
+            mesh.alpha_test_enabled = reader.read_u32()? != 0;
+            mesh.z_test_enabled = reader.read_u32()? != 0;
+            mesh.z_write_enabled = reader.read_u32()? != 0;
+            mesh.src_blend = reader.read_u32()?;
+            mesh.dst_blend = reader.read_u32()?;
+            mesh.blend_op = reader.read_u32()?;
+            mesh.animation = EffectAnimation::read(reader)?;
+            mesh.link = EffectLink::read(reader)?;
+            mesh.repeat_count = reader.read_u32()?;
+            self.meshes.push(mesh);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_string_u32(&self.name)?;
+        writer.write_u32(self.sound_enabled as u32)?;
+        writer.write_string_u32(self.sound_path.to_str().unwrap())?;
+        writer.write_u32(self.sound_repeat_count)?;
+
+        writer.write_u32(self.particles.len() as u32)?;
+        for particle in self.particles.iter() {
+            writer.write_string_u32(&particle.name)?;
+            writer.write_string_u32(&particle.unique_name)?;
+            writer.write_u32(particle.particle_index)?;
+            writer.write_string_u32(particle.particle_path.to_str().unwrap())?;
+            particle.animation.write(writer)?;
+            particle.link.write(writer)?;
+        }
+
+        writer.write_u32(self.meshes.len() as u32)?;
+        for mesh in self.meshes.iter() {
+            writer.write_string_u32(&mesh.name)?;
+            writer.write_string_u32(&mesh.unique_name)?;
+            writer.write_u32(mesh.mesh_index)?;
+            writer.write_string_u32(mesh.mesh_path.to_str().unwrap())?;
+            writer.write_string_u32(mesh.mesh_animation_path.to_str().unwrap())?;
+            writer.write_string_u32(mesh.texture_path.to_str().unwrap())?;
+            writer.write_u32(mesh.alpha_enabled as u32)?;
+            writer.write_u32(mesh.two_sided as u32)?;
+            writer.write_u32(mesh.alpha_test_enabled as u32)?;
+            writer.write_u32(mesh.z_test_enabled as u32)?;
+            writer.write_u32(mesh.z_write_enabled as u32)?;
+            writer.write_u32(mesh.src_blend)?;
+            writer.write_u32(mesh.dst_blend)?;
+            writer.write_u32(mesh.blend_op)?;
+            mesh.animation.write(writer)?;
+            mesh.link.write(writer)?;
+            writer.write_u32(mesh.repeat_count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Particle system (PTL) spawned by an effect
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EffectParticle {
+    pub name: String,
+    pub unique_name: String,
+    pub particle_index: u32,
+    pub particle_path: PathBuf,
+    pub animation: EffectAnimation,
+    pub link: EffectLink,
+}
+
+impl EffectParticle {
+    pub fn new() -> EffectParticle {
+        Self::default()
+    }
+}
+
+/// Mesh spawned by an effect
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EffectMesh {
+    pub name: String,
+    pub unique_name: String,
+    pub mesh_index: u32,
+    pub mesh_path: PathBuf,
+
+    /// Vertex animation (ZMO) of the mesh
+    pub mesh_animation_path: PathBuf,
+    pub texture_path: PathBuf,
+    pub alpha_enabled: bool,
+    pub two_sided: bool,
+    pub alpha_test_enabled: bool,
+    pub z_test_enabled: bool,
+    pub z_write_enabled: bool,
+
+    /// Direct3D blend values
+    pub src_blend: u32,
+    pub dst_blend: u32,
+    pub blend_op: u32,
+
+    pub animation: EffectAnimation,
+    pub link: EffectLink,
+    pub repeat_count: u32,
+}
+
+impl EffectMesh {
+    pub fn new() -> EffectMesh {
+        Self::default()
+    }
+}
+
+/// Motion (ZMO) moving a sub-effect
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EffectAnimation {
+    pub enabled: bool,
+    pub path: PathBuf,
+    pub repeat_count: u32,
+}
+
+impl EffectAnimation {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<EffectAnimation, Error> {
+        Ok(EffectAnimation {
+            enabled: reader.read_u32()? != 0,
+            path: PathBuf::from(reader.read_string_u32()?),
+            repeat_count: reader.read_u32()?,
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.enabled as u32)?;
+        writer.write_string_u32(self.path.to_str().unwrap())?;
+        writer.write_u32(self.repeat_count)?;
+        Ok(())
+    }
+}
+
+/// Where a sub-effect is placed relative to the effect
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EffectLink {
+    pub point_index: u32,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion,
+
+    /// Delay in milliseconds before the sub-effect starts
+    pub start_delay: u32,
+
+    /// Linked sub-effects follow the effect after being spawned
+    pub linked: bool,
+}
+
+impl EffectLink {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<EffectLink, Error> {
+        Ok(EffectLink {
+            point_index: reader.read_u32()?,
+            position: reader.read_vector3_f32()?,
+            rotation: reader.read_quaternion()?,
+            start_delay: reader.read_u32()?,
+            linked: reader.read_u32()? != 0,
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.point_index)?;
+        writer.write_vector3_f32(&self.position)?;
+        writer.write_quaternion(&self.rotation)?;
+        writer.write_u32(self.start_delay)?;
+        writer.write_u32(self.linked as u32)?;
+        Ok(())
+    }
+}
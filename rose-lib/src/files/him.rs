@@ -21,17 +21,101 @@ pub struct Heightmap {
 
     pub min_height: f32,
     pub max_height: f32,
+
+    /// Height range of each `grid_count` sized patch, row by row
+    pub patches: Vec<HeightmapPatch>,
+
+    /// Height range of the quadtree over the patches, level by level from
+    /// the whole heightmap down to the level above the patches
+    pub quad_patches: Vec<HeightmapPatch>,
+}
+
+/// Height range of an area of a heightmap
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct HeightmapPatch {
+    pub max_height: f32,
+    pub min_height: f32,
 }
 
 impl Heightmap {
+    /// Height of the vertex in column `x` and row `y`, 0 outside the heightmap
     pub fn height(&self, x: usize, y: usize) -> f32 {
-        let width = self.width as usize;
-        let length = self.length as usize;
-        let index = (y * length) + x;
-        if x > width || y > length || index > self.heights.len() {
+        let width = self.width.max(0) as usize;
+        let length = self.length.max(0) as usize;
+        if x >= width || y >= length {
             return 0.0;
         }
-        return self.heights[index];
+        self.heights.get(y * width + x).copied().unwrap_or(0.0)
+    }
+
+    /// Height range of the vertices from `(x0, y0)` to `(x1, y1)` inclusive
+    fn range(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> HeightmapPatch {
+        let width = self.width as usize;
+        let mut patch = HeightmapPatch {
+            max_height: f32::MIN,
+            min_height: f32::MAX,
+        };
+        for y in y0..=y1 {
+            for height in &self.heights[y * width + x0..=y * width + x1] {
+                patch.max_height = patch.max_height.max(*height);
+                patch.min_height = patch.min_height.min(*height);
+            }
+        }
+        patch
+    }
+
+    /// Recompute the height range, patches and quadtree from the heights
+    pub fn update_patches(&mut self) -> Result<(), Error> {
+        let width = self.width.max(0) as usize;
+        let length = self.length.max(0) as usize;
+        let grid_count = self.grid_count.max(0) as usize;
+
+        if width < 2 || length < 2 || self.heights.len() != width * length {
+            bail!(
+                "Expected {}x{} heights, found {}",
+                self.width,
+                self.length,
+                self.heights.len()
+            );
+        }
+        if grid_count == 0 || !(width - 1).is_multiple_of(grid_count) || width != length {
+            bail!(
+                "Heightmap of {}x{} can not be split into patches of {} grids",
+                self.width,
+                self.length,
+                self.grid_count
+            );
+        }
+
+        let whole = self.range(0, 0, width - 1, length - 1);
+        self.min_height = whole.min_height;
+        self.max_height = whole.max_height;
+
+        // Patches share their edge vertices
+        let patch_count = (width - 1) / grid_count;
+        self.patches.clear();
+        for py in 0..patch_count {
+            for px in 0..patch_count {
+                let (x, y) = (px * grid_count, py * grid_count);
+                self.patches
+                    .push(self.range(x, y, x + grid_count, y + grid_count));
+            }
+        }
+
+        self.quad_patches.clear();
+        let mut level_count = 1;
+        while level_count < patch_count {
+            let size = (width - 1) / level_count;
+            for qy in 0..level_count {
+                for qx in 0..level_count {
+                    let (x, y) = (qx * size, qy * size);
+                    self.quad_patches.push(self.range(x, y, x + size, y + size));
+                }
+            }
+            level_count *= 2;
+        }
+
+        Ok(())
     }
 }
 
@@ -46,7 +130,8 @@ impl RoseFile for Heightmap {
         self.grid_count = reader.read_i32()?;
         self.scale = reader.read_f32()?;
 
-        self.heights = Vec::with_capacity((self.width * self.length) as usize);
+        // Counts come from the file, so nothing is reserved up front
+        self.heights = Vec::new();
         for _ in 0..self.length {
             for _ in 0..self.width {
                 let height = reader.read_f32()?;
@@ -63,13 +148,69 @@ impl RoseFile for Heightmap {
             }
         }
 
-        // TODO: File contains more data
+        let _name = reader.read_string_u8()?;
+
+        let patch_count = reader.read_i32()?;
+        self.patches = Vec::new();
+        for _ in 0..patch_count {
+            self.patches.push(HeightmapPatch::read(reader)?);
+        }
+
+        let quad_patch_count = reader.read_i32()?;
+        self.quad_patches = Vec::new();
+        for _ in 0..quad_patch_count {
+            self.quad_patches.push(HeightmapPatch::read(reader)?);
+        }
 
         Ok(())
     }
 
-    fn write<W: WriteRoseExt>(&mut self, _writer: &mut W) -> Result<(), Error> {
-        // TODO: Implement writer
-        unimplemented!();
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        if self.heights.len() != (self.width.max(0) * self.length.max(0)) as usize {
+            bail!(
+                "Expected {}x{} heights, found {}",
+                self.width,
+                self.length,
+                self.heights.len()
+            );
+        }
+
+        writer.write_i32(self.width)?;
+        writer.write_i32(self.length)?;
+        writer.write_i32(self.grid_count)?;
+        writer.write_f32(self.scale)?;
+
+        for height in &self.heights {
+            writer.write_f32(*height)?;
+        }
+
+        writer.write_string_u8("quad")?;
+
+        writer.write_i32(self.patches.len() as i32)?;
+        for patch in &self.patches {
+            patch.write(writer)?;
+        }
+
+        writer.write_i32(self.quad_patches.len() as i32)?;
+        for patch in &self.quad_patches {
+            patch.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl HeightmapPatch {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<HeightmapPatch, Error> {
+        Ok(HeightmapPatch {
+            max_height: reader.read_f32()?,
+            min_height: reader.read_f32()?,
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_f32(self.max_height)?;
+        writer.write_f32(self.min_height)?;
+        Ok(())
     }
 }
@@ -3,10 +3,13 @@
 //! ROSE Online uses pre-baked lights that get rendered to a lightmap texture
 //! for blending with terrain/object textures.
 //!
+use std::convert::TryFrom;
+
 use failure::Error;
 use serde::{Deserialize, Serialize};
 
 use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+use crate::utils::Vector2;
 
 /// Lightmap file
 pub type LIT = Lightmap;
@@ -17,6 +20,19 @@ pub struct Lightmap {
     pub filenames: Vec<String>,
 }
 
+impl Lightmap {
+    /// Atlas file name of a part, relative to the LIT file
+    pub fn atlas_filename<'a>(&'a self, part: &'a LightmapPart) -> Option<&'a str> {
+        if !part.filename.is_empty() {
+            return Some(&part.filename);
+        }
+        usize::try_from(part.lightmap_index)
+            .ok()
+            .and_then(|idx| self.filenames.get(idx))
+            .map(|s| s.as_str())
+    }
+}
+
 impl RoseFile for Lightmap {
     fn new() -> Lightmap {
         Self::default()
@@ -120,6 +136,73 @@ impl LightmapPart {
     pub fn new() -> LightmapPart {
         Self::default()
     }
+
+    /// Pixel rectangle of this part within its lightmap atlas.
+    ///
+    /// Atlases are grids of `parts_per_width` x `parts_per_width` square parts
+    /// of `pixels_per_part` pixels, numbered row by row by `part_position`.
+    /// There is no rectangle when the atlas size doesn't fit in a `u32`.
+    pub fn rect(&self) -> Option<LightmapRect> {
+        if self.pixels_per_part <= 0 || self.parts_per_width <= 0 || self.part_position < 0 {
+            return None;
+        }
+
+        let size = self.pixels_per_part as u32;
+        let parts_per_width = self.parts_per_width as u32;
+        let position = self.part_position as u32;
+        if u64::from(position) >= u64::from(parts_per_width) * u64::from(parts_per_width) {
+            return None;
+        }
+
+        // Parts are inside the atlas so their offsets fit when its size does
+        let atlas_size = parts_per_width.checked_mul(size)?;
+        Some(LightmapRect {
+            x: position % parts_per_width * size,
+            y: position / parts_per_width * size,
+            size,
+            atlas_size,
+        })
+    }
+
+    /// Minimum and maximum texture coordinates of this part within its atlas
+    pub fn uv_rect(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        self.rect().map(|rect| (rect.uv_min(), rect.uv_max()))
+    }
+}
+
+/// Location of a lightmap part within its atlas
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LightmapRect {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    pub atlas_size: u32,
+}
+
+impl LightmapRect {
+    pub fn uv_min(&self) -> Vector2<f32> {
+        Vector2 {
+            x: self.x as f32 / self.atlas_size as f32,
+            y: self.y as f32 / self.atlas_size as f32,
+        }
+    }
+
+    pub fn uv_max(&self) -> Vector2<f32> {
+        Vector2 {
+            x: (self.x + self.size) as f32 / self.atlas_size as f32,
+            y: (self.y + self.size) as f32 / self.atlas_size as f32,
+        }
+    }
+
+    /// Map a texture coordinate of the part's own lightmap into the atlas
+    pub fn to_atlas_uv(&self, uv: &Vector2<f32>) -> Vector2<f32> {
+        let min = self.uv_min();
+        let max = self.uv_max();
+        Vector2 {
+            x: min.x + (max.x - min.x) * uv.x,
+            y: min.y + (max.y - min.y) * uv.y,
+        }
+    }
 }
 
 impl Default for LightmapPart {
@@ -0,0 +1,211 @@
+//! ROSE Online Language Table
+use std::io::SeekFrom;
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::files::stl::{
+    ItemRowData, NormalRowData, QuestRowData, StringTable, StringTableKey, StringTableLanguage,
+    StringTableLanguageTable, StringTableRow, StringTableType,
+};
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// Language table file
+pub type LTB = LanguageTable;
+
+/// Size of a cell entry: u32 offset and u16 length
+const CELL_SIZE: u64 = 6;
+
+/// Number of text columns per language in a string table format
+fn field_count(format: &StringTableType) -> usize {
+    match format {
+        StringTableType::Normal => 1,
+        StringTableType::Item => 2,
+        StringTableType::Quest => 4,
+    }
+}
+
+/// Language table
+///
+/// A grid of UTF-16 strings. The first column holds the row key and the
+/// remaining columns hold one string per language.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LanguageTable {
+    pub column_count: usize,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl LanguageTable {
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Cell text, empty if out of range
+    pub fn get(&self, row: usize, column: usize) -> &str {
+        self.rows
+            .get(row)
+            .and_then(|r| r.get(column))
+            .map(|s| s.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Convert to a string table of the given format.
+    ///
+    /// Columns after the key are split into one language per text field of
+    /// the format (1 for normal, 2 for item and 4 for quest tables). Key IDs
+    /// are the row indices.
+    pub fn to_string_table(&self, format: StringTableType) -> Result<StringTable, Error> {
+        let fields = field_count(&format);
+
+        let data_columns = self.column_count.saturating_sub(1);
+        if !data_columns.is_multiple_of(fields) {
+            bail!(
+                "Language table has {} text columns, expected a multiple of {}",
+                data_columns,
+                fields
+            );
+        }
+
+        let mut stl = StringTable {
+            format,
+            ..Default::default()
+        };
+
+        for language_idx in 0..data_columns / fields {
+            stl.language_tables.push(StringTableLanguageTable {
+                language: StringTableLanguage::from(language_idx as u32),
+                rows: Vec::new(),
+            });
+        }
+
+        for (row_idx, _) in self.rows.iter().enumerate() {
+            stl.keys.push(StringTableKey {
+                id: row_idx as u32,
+                name: self.get(row_idx, 0).to_string(),
+            });
+
+            for (language_idx, table) in stl.language_tables.iter_mut().enumerate() {
+                let column = 1 + language_idx * fields;
+                let text = |i: usize| self.get(row_idx, column + i).to_string();
+                let row = match stl.format {
+                    StringTableType::Normal => {
+                        StringTableRow::NormalRow(NormalRowData { text: text(0) })
+                    }
+                    StringTableType::Item => StringTableRow::ItemRow(ItemRowData {
+                        text: text(0),
+                        description: text(1),
+                    }),
+                    StringTableType::Quest => StringTableRow::QuestRow(QuestRowData {
+                        text: text(0),
+                        description: text(1),
+                        start_message: text(2),
+                        end_message: text(3),
+                    }),
+                };
+                table.rows.push(row);
+            }
+        }
+
+        Ok(stl)
+    }
+}
+
+impl From<&StringTable> for LanguageTable {
+    /// Flatten a string table into key and text columns, languages follow
+    /// each other with all of their text fields
+    fn from(stl: &StringTable) -> LanguageTable {
+        let fields = field_count(&stl.format);
+
+        let mut ltb = LanguageTable {
+            column_count: 1 + stl.language_count() * fields,
+            rows: Vec::new(),
+        };
+
+        for (row_idx, key) in stl.keys.iter().enumerate() {
+            let mut row = vec![key.name.clone()];
+            for table in stl.language_tables.iter() {
+                match table.rows.get(row_idx) {
+                    Some(StringTableRow::NormalRow(data)) => row.push(data.text.clone()),
+                    Some(StringTableRow::ItemRow(data)) => {
+                        row.push(data.text.clone());
+                        row.push(data.description.clone());
+                    }
+                    Some(StringTableRow::QuestRow(data)) => {
+                        row.push(data.text.clone());
+                        row.push(data.description.clone());
+                        row.push(data.start_message.clone());
+                        row.push(data.end_message.clone());
+                    }
+                    None => {}
+                }
+            }
+            row.resize(ltb.column_count, String::new());
+            ltb.rows.push(row);
+        }
+
+        ltb
+    }
+}
+
+impl RoseFile for LanguageTable {
+    fn new() -> LanguageTable {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.column_count = reader.read_u32()? as usize;
+        let row_count = reader.read_u32()? as usize;
+
+        let mut cells = Vec::new();
+        for _ in 0..row_count * self.column_count {
+            let offset = reader.read_u32()?;
+            let length = reader.read_u16()?;
+            cells.push((offset, length));
+        }
+
+        for row in cells.chunks(self.column_count.max(1)) {
+            let mut strings = Vec::new();
+            for (offset, length) in row.iter() {
+                reader.seek(SeekFrom::Start(u64::from(*offset)))?;
+                let mut text = Vec::new();
+                for _ in 0..*length {
+                    text.push(reader.read_u16()?);
+                }
+                let text = String::from_utf16_lossy(&text);
+                strings.push(text.trim_end_matches('\0').to_string());
+            }
+            self.rows.push(strings);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.column_count as u32)?;
+        writer.write_u32(self.rows.len() as u32)?;
+
+        let mut offset = 8 + CELL_SIZE * (self.rows.len() * self.column_count) as u64;
+        let mut strings = Vec::new();
+        for row_idx in 0..self.rows.len() {
+            for column in 0..self.column_count {
+                let text: Vec<u16> = self.get(row_idx, column).encode_utf16().collect();
+                if text.len() > usize::from(u16::MAX) {
+                    bail!("Language table string is too long: row {}", row_idx);
+                }
+
+                writer.write_u32(offset as u32)?;
+                writer.write_u16(text.len() as u16)?;
+                offset += 2 * text.len() as u64;
+                strings.push(text);
+            }
+        }
+
+        for text in strings {
+            for c in text {
+                writer.write_u16(c)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,26 +1,44 @@
 // pub mod idx;
+pub mod aip;
+pub mod chr;
+pub mod con;
+pub mod dds;
+pub mod eft;
 pub mod him;
 pub mod idx;
 pub mod ifo;
 pub mod lit;
+pub mod ltb;
+pub mod ptl;
+pub mod qsd;
 pub mod stb;
 pub mod stl;
 pub mod til;
 pub mod tsi;
+pub mod zca;
 pub mod zmd;
 pub mod zmo;
 pub mod zms;
 pub mod zon;
 pub mod zsc;
 
+pub use self::aip::AIP;
+pub use self::chr::CHR;
+pub use self::con::CON;
+pub use self::dds::DDS;
+pub use self::eft::EFT;
 pub use self::him::HIM;
 pub use self::idx::IDX;
 pub use self::ifo::IFO;
 pub use self::lit::LIT;
+pub use self::ltb::LTB;
+pub use self::ptl::PTL;
+pub use self::qsd::QSD;
 pub use self::stb::STB;
 pub use self::stl::STL;
 pub use self::til::TIL;
 pub use self::tsi::TSI;
+pub use self::zca::ZCA;
 pub use self::zmd::ZMD;
 pub use self::zmo::ZMO;
 pub use self::zms::ZMS;
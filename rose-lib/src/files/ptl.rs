@@ -0,0 +1,621 @@
+//! ROSE Online Particle Systems
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+use crate::utils::{Color4, Random, Vector2, Vector3};
+
+/// Particle file
+pub type PTL = ParticleSystem;
+
+/// Particle system
+///
+/// Each sequence is an emitter with its own texture and events.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ParticleSystem {
+    pub sequences: Vec<ParticleSequence>,
+}
+
+impl RoseFile for ParticleSystem {
+    fn new() -> ParticleSystem {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let sequence_count = reader.read_u32()?;
+        for _ in 0..sequence_count {
+            let mut sequence = ParticleSequence::new();
+            sequence.name = reader.read_string_u32()?;
+            sequence.lifetime = ParticleRange::read_f32(reader)?;
+            sequence.emit_rate = ParticleRange::read_f32(reader)?;
+            sequence.loop_count = reader.read_i32()?;
+            sequence.spawn_direction = ParticleRange::read_vector3(reader)?;
+            sequence.emit_radius = ParticleRange::read_vector3(reader)?;
+            sequence.gravity = ParticleRange::read_vector3(reader)?;
+            sequence.texture_path = PathBuf::from(reader.read_string_u32()?);
+            sequence.particle_count = reader.read_u32()?;
+            sequence.align = reader.read_u32()?;
+            sequence.update_coords = reader.read_u32()?;
+            sequence.texture_columns = reader.read_u32()?;
+            sequence.texture_rows = reader.read_u32()?;
+            sequence.dst_blend = reader.read_u32()?;
+            sequence.src_blend = reader.read_u32()?;
+            sequence.blend_op = reader.read_u32()?;
+
+            let event_count = reader.read_u32()?;
+            for _ in 0..event_count {
+                let event_type = reader.read_u32()?;
+                let start_time = ParticleRange::read_f32(reader)?;
+                let fade = reader.read_u32()? != 0;
+
+                let data = match event_type {
+                    1 => ParticleEventData::Size(ParticleRange {
+                        min: reader.read_vector2_f32()?,
+                        max: reader.read_vector2_f32()?,
+                    }),
+                    2 => ParticleEventData::Timer(ParticleRange::read_f32(reader)?),
+                    3 => ParticleEventData::Red(ParticleRange::read_f32(reader)?),
+                    4 => ParticleEventData::Green(ParticleRange::read_f32(reader)?),
+                    5 => ParticleEventData::Blue(ParticleRange::read_f32(reader)?),
+                    6 => ParticleEventData::Alpha(ParticleRange::read_f32(reader)?),
+                    7 => ParticleEventData::Color(ParticleRange {
+                        min: reader.read_color4()?,
+                        max: reader.read_color4()?,
+                    }),
+                    8 => ParticleEventData::VelocityX(ParticleRange::read_f32(reader)?),
+                    9 => ParticleEventData::VelocityY(ParticleRange::read_f32(reader)?),
+                    10 => ParticleEventData::VelocityZ(ParticleRange::read_f32(reader)?),
+                    11 => ParticleEventData::Velocity(ParticleRange::read_vector3(reader)?),
+                    12 => ParticleEventData::Texture(ParticleRange::read_f32(reader)?),
+                    13 => ParticleEventData::Rotation(ParticleRange::read_f32(reader)?),
+                    _ => bail!("Invalid particle event type: {}", event_type),
+                };
+
+                sequence.events.push(ParticleEvent {
+                    start_time,
+                    fade,
+                    data,
+                });
+            }
+
+            self.sequences.push(sequence);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.sequences.len() as u32)?;
+        for sequence in self.sequences.iter() {
+            writer.write_string_u32(&sequence.name)?;
+            sequence.lifetime.write_f32(writer)?;
+            sequence.emit_rate.write_f32(writer)?;
+            writer.write_i32(sequence.loop_count)?;
+            sequence.spawn_direction.write_vector3(writer)?;
+            sequence.emit_radius.write_vector3(writer)?;
+            sequence.gravity.write_vector3(writer)?;
+            writer.write_string_u32(sequence.texture_path.to_str().unwrap())?;
+            writer.write_u32(sequence.particle_count)?;
+            writer.write_u32(sequence.align)?;
+            writer.write_u32(sequence.update_coords)?;
+            writer.write_u32(sequence.texture_columns)?;
+            writer.write_u32(sequence.texture_rows)?;
+            writer.write_u32(sequence.dst_blend)?;
+            writer.write_u32(sequence.src_blend)?;
+            writer.write_u32(sequence.blend_op)?;
+
+            writer.write_u32(sequence.events.len() as u32)?;
+            for event in sequence.events.iter() {
+                writer.write_u32(event.data.event_type())?;
+                event.start_time.write_f32(writer)?;
+                writer.write_u32(event.fade as u32)?;
+
+                match &event.data {
+                    ParticleEventData::Size(r) => {
+                        writer.write_vector2_f32(&r.min)?;
+                        writer.write_vector2_f32(&r.max)?;
+                    }
+                    ParticleEventData::Color(r) => {
+                        writer.write_color4(&r.min)?;
+                        writer.write_color4(&r.max)?;
+                    }
+                    ParticleEventData::Velocity(r) => r.write_vector3(writer)?,
+                    ParticleEventData::Timer(r)
+                    | ParticleEventData::Red(r)
+                    | ParticleEventData::Green(r)
+                    | ParticleEventData::Blue(r)
+                    | ParticleEventData::Alpha(r)
+                    | ParticleEventData::VelocityX(r)
+                    | ParticleEventData::VelocityY(r)
+                    | ParticleEventData::VelocityZ(r)
+                    | ParticleEventData::Texture(r)
+                    | ParticleEventData::Rotation(r) => r.write_f32(writer)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Particle emitter
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ParticleSequence {
+    pub name: String,
+
+    /// Particle lifetime in seconds
+    pub lifetime: ParticleRange<f32>,
+
+    /// Particles emitted per second
+    pub emit_rate: ParticleRange<f32>,
+
+    /// Number of times the sequence is played, 0 loops forever
+    pub loop_count: i32,
+    pub spawn_direction: ParticleRange<Vector3<f32>>,
+    pub emit_radius: ParticleRange<Vector3<f32>>,
+    pub gravity: ParticleRange<Vector3<f32>>,
+    pub texture_path: PathBuf,
+
+    /// Maximum number of live particles
+    pub particle_count: u32,
+
+    /// Quad orientation: 0 billboard, 1 world, 2 axis aligned billboard
+    pub align: u32,
+
+    /// Simulation space: 0 world, 1 local to the emitter, 2 emitted locally
+    /// then left in world space
+    pub update_coords: u32,
+
+    /// Texture atlas layout used by texture events
+    pub texture_columns: u32,
+    pub texture_rows: u32,
+
+    /// Direct3D blend values
+    pub dst_blend: u32,
+    pub src_blend: u32,
+    pub blend_op: u32,
+
+    pub events: Vec<ParticleEvent>,
+}
+
+impl ParticleSequence {
+    pub fn new() -> ParticleSequence {
+        Self::default()
+    }
+}
+
+/// Value randomly chosen between `min` and `max`
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ParticleRange<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl ParticleRange<f32> {
+    /// Value at `t` between `min` (0.0) and `max` (1.0)
+    pub fn lerp(&self, t: f32) -> f32 {
+        self.min + (self.max - self.min) * t
+    }
+
+    fn read_f32<R: ReadRoseExt>(reader: &mut R) -> Result<ParticleRange<f32>, Error> {
+        Ok(ParticleRange {
+            min: reader.read_f32()?,
+            max: reader.read_f32()?,
+        })
+    }
+
+    fn write_f32<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_f32(self.min)?;
+        writer.write_f32(self.max)?;
+        Ok(())
+    }
+}
+
+impl ParticleRange<Vector3<f32>> {
+    /// Value at `t` between `min` (0.0) and `max` (1.0)
+    pub fn lerp(&self, t: f32) -> Vector3<f32> {
+        self.min.lerp(&self.max, t)
+    }
+
+    fn read_vector3<R: ReadRoseExt>(reader: &mut R) -> Result<ParticleRange<Vector3<f32>>, Error> {
+        Ok(ParticleRange {
+            min: reader.read_vector3_f32()?,
+            max: reader.read_vector3_f32()?,
+        })
+    }
+
+    fn write_vector3<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_vector3_f32(&self.min)?;
+        writer.write_vector3_f32(&self.max)?;
+        Ok(())
+    }
+}
+
+/// Change applied to a particle once it reaches `start_time`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ParticleEvent {
+    /// Particle age in seconds
+    pub start_time: ParticleRange<f32>,
+
+    /// Interpolate from the previous event of the same type instead of
+    /// setting the value immediately
+    pub fade: bool,
+    pub data: ParticleEventData,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ParticleEventData {
+    Size(ParticleRange<Vector2<f32>>),
+    Timer(ParticleRange<f32>),
+    Red(ParticleRange<f32>),
+    Green(ParticleRange<f32>),
+    Blue(ParticleRange<f32>),
+    Alpha(ParticleRange<f32>),
+    Color(ParticleRange<Color4>),
+    VelocityX(ParticleRange<f32>),
+    VelocityY(ParticleRange<f32>),
+    VelocityZ(ParticleRange<f32>),
+    Velocity(ParticleRange<Vector3<f32>>),
+    Texture(ParticleRange<f32>),
+    Rotation(ParticleRange<f32>),
+}
+
+impl ParticleEventData {
+    /// Event type id used in PTL files
+    pub fn event_type(&self) -> u32 {
+        match self {
+            ParticleEventData::Size(_) => 1,
+            ParticleEventData::Timer(_) => 2,
+            ParticleEventData::Red(_) => 3,
+            ParticleEventData::Green(_) => 4,
+            ParticleEventData::Blue(_) => 5,
+            ParticleEventData::Alpha(_) => 6,
+            ParticleEventData::Color(_) => 7,
+            ParticleEventData::VelocityX(_) => 8,
+            ParticleEventData::VelocityY(_) => 9,
+            ParticleEventData::VelocityZ(_) => 10,
+            ParticleEventData::Velocity(_) => 11,
+            ParticleEventData::Texture(_) => 12,
+            ParticleEventData::Rotation(_) => 13,
+        }
+    }
+}
+
+impl ParticleSystem {
+    /// Emitters for every sequence, `seed` makes the simulation repeatable
+    pub fn emitters(&self, seed: u32) -> Vec<ParticleEmitter<'_>> {
+        self.sequences
+            .iter()
+            .enumerate()
+            .map(|(i, sequence)| ParticleEmitter::new(sequence, i, seed.wrapping_add(i as u32)))
+            .collect()
+    }
+
+    /// Live particles of all sequences at each frame, frame `i` is the state
+    /// at `i / fps` seconds
+    pub fn simulate(
+        &self,
+        fps: f32,
+        frame_count: u32,
+        seed: u32,
+    ) -> Result<Vec<Vec<Particle>>, Error> {
+        if !(fps > 0.0 && fps.is_finite()) {
+            bail!("Invalid particle simulation fps: {}", fps);
+        }
+
+        let mut emitters = self.emitters(seed);
+        let mut frames = Vec::with_capacity(frame_count as usize);
+
+        for frame in 0..frame_count {
+            if frame > 0 {
+                for emitter in emitters.iter_mut() {
+                    emitter.step(1.0 / fps);
+                }
+            }
+            frames.push(
+                emitters
+                    .iter()
+                    .flat_map(|e| e.particles().iter().cloned())
+                    .collect(),
+            );
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Particle simulated by a `ParticleEmitter`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Particle {
+    /// Index of the sequence that emitted the particle
+    pub sequence: usize,
+
+    /// Seconds since the particle was emitted
+    pub age: f32,
+    pub lifetime: f32,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub size: Vector2<f32>,
+    pub color: Color4,
+    pub rotation: f32,
+
+    /// Texture atlas cell, row-major
+    pub texture: u32,
+
+    /// Time events are evaluated at, timer events move it
+    #[serde(skip)]
+    event_time: f32,
+
+    #[serde(skip)]
+    spawn_velocity: Vector3<f32>,
+
+    #[serde(skip)]
+    gravity: Vector3<f32>,
+
+    #[serde(skip)]
+    gravity_velocity: Vector3<f32>,
+
+    #[serde(skip)]
+    keys: Vec<ParticleKey>,
+}
+
+/// Scalar value set by an event, sampled when the particle is emitted
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ParticleKey {
+    channel: ParticleChannel,
+    time: f32,
+    value: f32,
+    fade: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ParticleChannel {
+    Timer,
+    SizeX,
+    SizeY,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    VelocityX,
+    VelocityY,
+    VelocityZ,
+    Texture,
+    Rotation,
+}
+
+impl Particle {
+    /// Value of `channel` at `time`. Keys hold their value until the next key
+    /// of the channel, which is faded into when it has `fade` set.
+    fn channel(&self, channel: ParticleChannel, time: f32) -> Option<f32> {
+        let mut previous: Option<&ParticleKey> = None;
+        for key in self.keys.iter().filter(|k| k.channel == channel) {
+            if key.time <= time {
+                previous = Some(key);
+                continue;
+            }
+
+            if let (true, Some(p)) = (key.fade, previous) {
+                let span = key.time - p.time;
+                let t = if span > 0.0 {
+                    (time - p.time) / span
+                } else {
+                    1.0
+                };
+                return Some(p.value + (key.value - p.value) * t);
+            }
+            break;
+        }
+        previous.map(|k| k.value)
+    }
+
+    fn update(&mut self, sequence: &ParticleSequence) {
+        let t = self.event_time;
+
+        self.size.x = self.channel(ParticleChannel::SizeX, t).unwrap_or(1.0);
+        self.size.y = self.channel(ParticleChannel::SizeY, t).unwrap_or(1.0);
+        self.color.r = self.channel(ParticleChannel::Red, t).unwrap_or(1.0);
+        self.color.g = self.channel(ParticleChannel::Green, t).unwrap_or(1.0);
+        self.color.b = self.channel(ParticleChannel::Blue, t).unwrap_or(1.0);
+        self.color.a = self.channel(ParticleChannel::Alpha, t).unwrap_or(1.0);
+        self.rotation = self.channel(ParticleChannel::Rotation, t).unwrap_or(0.0);
+
+        let cells = (sequence.texture_columns * sequence.texture_rows).max(1);
+        let texture = self.channel(ParticleChannel::Texture, t).unwrap_or(0.0);
+        self.texture = (texture.max(0.0) as u32).min(cells - 1);
+
+        let base = Vector3 {
+            x: self
+                .channel(ParticleChannel::VelocityX, t)
+                .unwrap_or(self.spawn_velocity.x),
+            y: self
+                .channel(ParticleChannel::VelocityY, t)
+                .unwrap_or(self.spawn_velocity.y),
+            z: self
+                .channel(ParticleChannel::VelocityZ, t)
+                .unwrap_or(self.spawn_velocity.z),
+        };
+        self.velocity = base.add(&self.gravity_velocity);
+    }
+}
+
+/// Random values for particles
+#[derive(Clone, Debug)]
+struct ParticleRandom(Random);
+
+impl ParticleRandom {
+    fn new(seed: u32) -> ParticleRandom {
+        ParticleRandom(Random::new(seed))
+    }
+
+    /// Value in `[0, 1)`
+    fn next(&mut self) -> f32 {
+        (self.0.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn range(&mut self, range: &ParticleRange<f32>) -> f32 {
+        range.lerp(self.next())
+    }
+
+    fn range3(&mut self, range: &ParticleRange<Vector3<f32>>) -> Vector3<f32> {
+        Vector3 {
+            x: range.min.x + (range.max.x - range.min.x) * self.next(),
+            y: range.min.y + (range.max.y - range.min.y) * self.next(),
+            z: range.min.z + (range.max.z - range.min.z) * self.next(),
+        }
+    }
+}
+
+/// Steps the particles of a sequence over time
+///
+/// Each loop emits `particle_count` particles at the sequence emit rate, with
+/// at most `particle_count` particles alive at once.
+#[derive(Clone, Debug)]
+pub struct ParticleEmitter<'a> {
+    sequence: &'a ParticleSequence,
+    index: usize,
+    random: ParticleRandom,
+    emit_rate: f32,
+    pending: f32,
+    emitted: u64,
+    particles: Vec<Particle>,
+}
+
+impl<'a> ParticleEmitter<'a> {
+    pub fn new(sequence: &'a ParticleSequence, index: usize, seed: u32) -> ParticleEmitter<'a> {
+        let mut random = ParticleRandom::new(seed);
+        let emit_rate = random.range(&sequence.emit_rate).max(0.0);
+        ParticleEmitter {
+            sequence,
+            index,
+            random,
+            emit_rate,
+            pending: 0.0,
+            emitted: 0,
+            particles: Vec::new(),
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Whether all loops have been emitted and every particle has died
+    pub fn finished(&self) -> bool {
+        !self.emitting() && self.particles.is_empty()
+    }
+
+    fn emitting(&self) -> bool {
+        self.sequence.loop_count <= 0
+            || self.emitted
+                < self.sequence.loop_count as u64 * u64::from(self.sequence.particle_count)
+    }
+
+    /// Advance the simulation by `dt` seconds
+    pub fn step(&mut self, dt: f32) {
+        let sequence = self.sequence;
+
+        for particle in self.particles.iter_mut() {
+            particle.age += dt;
+
+            let previous = particle.event_time;
+            particle.event_time += dt;
+            let timer = particle.keys.iter().find(|k| {
+                k.channel == ParticleChannel::Timer
+                    && k.time > previous
+                    && k.time <= particle.event_time
+            });
+            if let Some(key) = timer {
+                particle.event_time = key.value;
+            }
+
+            particle.gravity_velocity = particle.gravity_velocity.add(&particle.gravity.scale(dt));
+            particle.update(sequence);
+            particle.position = particle.position.add(&particle.velocity.scale(dt));
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if !self.emitting() {
+            return;
+        }
+
+        self.pending += self.emit_rate * dt;
+        while self.pending >= 1.0
+            && self.emitting()
+            && self.particles.len() < sequence.particle_count as usize
+        {
+            let particle = self.spawn();
+            self.particles.push(particle);
+            self.emitted += 1;
+            self.pending -= 1.0;
+        }
+
+        // Don't build up a burst while the emitter is full
+        self.pending = self.pending.min(1.0);
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let sequence = self.sequence;
+        let random = &mut self.random;
+
+        let mut keys = Vec::new();
+        for event in sequence.events.iter() {
+            let time = random.range(&event.start_time);
+            let mut key = |channel, value| {
+                keys.push(ParticleKey {
+                    channel,
+                    time,
+                    value,
+                    fade: event.fade,
+                })
+            };
+
+            match &event.data {
+                ParticleEventData::Size(r) => {
+                    let t = random.next();
+                    key(ParticleChannel::SizeX, r.min.x + (r.max.x - r.min.x) * t);
+                    key(ParticleChannel::SizeY, r.min.y + (r.max.y - r.min.y) * t);
+                }
+                ParticleEventData::Timer(r) => key(ParticleChannel::Timer, random.range(r)),
+                ParticleEventData::Red(r) => key(ParticleChannel::Red, random.range(r)),
+                ParticleEventData::Green(r) => key(ParticleChannel::Green, random.range(r)),
+                ParticleEventData::Blue(r) => key(ParticleChannel::Blue, random.range(r)),
+                ParticleEventData::Alpha(r) => key(ParticleChannel::Alpha, random.range(r)),
+                ParticleEventData::Color(r) => {
+                    let t = random.next();
+                    key(ParticleChannel::Red, r.min.r + (r.max.r - r.min.r) * t);
+                    key(ParticleChannel::Green, r.min.g + (r.max.g - r.min.g) * t);
+                    key(ParticleChannel::Blue, r.min.b + (r.max.b - r.min.b) * t);
+                    key(ParticleChannel::Alpha, r.min.a + (r.max.a - r.min.a) * t);
+                }
+                ParticleEventData::VelocityX(r) => key(ParticleChannel::VelocityX, random.range(r)),
+                ParticleEventData::VelocityY(r) => key(ParticleChannel::VelocityY, random.range(r)),
+                ParticleEventData::VelocityZ(r) => key(ParticleChannel::VelocityZ, random.range(r)),
+                ParticleEventData::Velocity(r) => {
+                    let v = random.range3(r);
+                    key(ParticleChannel::VelocityX, v.x);
+                    key(ParticleChannel::VelocityY, v.y);
+                    key(ParticleChannel::VelocityZ, v.z);
+                }
+                ParticleEventData::Texture(r) => key(ParticleChannel::Texture, random.range(r)),
+                ParticleEventData::Rotation(r) => key(ParticleChannel::Rotation, random.range(r)),
+            }
+        }
+        keys.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+        let mut particle = Particle {
+            sequence: self.index,
+            lifetime: random.range(&sequence.lifetime),
+            position: random.range3(&sequence.emit_radius),
+            spawn_velocity: random.range3(&sequence.spawn_direction),
+            gravity: random.range3(&sequence.gravity),
+            keys,
+            ..Default::default()
+        };
+        particle.update(sequence);
+        particle
+    }
+}
@@ -0,0 +1,1363 @@
+//! ROSE Online Quest Scripts
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::record::{decode_record, encode_record, pad, read_record, skip, write_record};
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+
+/// Quest script file
+pub type QSD = QuestScript;
+
+/// Flag set on the type id of every action record
+const ACTION_TYPE_FLAG: u32 = 0x0100_0000;
+
+/// Quest script
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestScript {
+    pub version: u32,
+    pub name: String,
+    pub groups: Vec<QuestGroup>,
+}
+
+impl QuestScript {
+    /// Find a trigger by name in any group
+    pub fn trigger(&self, name: &str) -> Option<&QuestTrigger> {
+        self.groups
+            .iter()
+            .flat_map(|g| g.triggers.iter())
+            .find(|t| t.name == name)
+    }
+}
+
+impl RoseFile for QuestScript {
+    fn new() -> QuestScript {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.version = reader.read_u32()?;
+        let group_count = reader.read_u32()?;
+        self.name = reader.read_string_u16()?;
+
+        for _ in 0..group_count {
+            let trigger_count = reader.read_u32()?;
+            let mut group = QuestGroup {
+                name: reader.read_string_u16()?,
+                triggers: Vec::new(),
+            };
+
+            for _ in 0..trigger_count {
+                let check_next = reader.read_u8()? != 0;
+                let condition_count = reader.read_u32()?;
+                let action_count = reader.read_u32()?;
+
+                let mut trigger = QuestTrigger {
+                    name: reader.read_string_u16()?,
+                    check_next,
+                    conditions: Vec::new(),
+                    actions: Vec::new(),
+                };
+
+                for _ in 0..condition_count {
+                    let (id, data) = read_record(reader)?;
+                    trigger.conditions.push(QuestCondition::decode(id, &data));
+                }
+
+                for _ in 0..action_count {
+                    let (id, data) = read_record(reader)?;
+                    trigger
+                        .actions
+                        .push(QuestAction::decode(id & !ACTION_TYPE_FLAG, &data));
+                }
+
+                group.triggers.push(trigger);
+            }
+
+            self.groups.push(group);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u32(self.version)?;
+        writer.write_u32(self.groups.len() as u32)?;
+        writer.write_string_u16(&self.name)?;
+
+        for group in self.groups.iter() {
+            writer.write_u32(group.triggers.len() as u32)?;
+            writer.write_string_u16(&group.name)?;
+
+            for trigger in group.triggers.iter() {
+                writer.write_u8(trigger.check_next as u8)?;
+                writer.write_u32(trigger.conditions.len() as u32)?;
+                writer.write_u32(trigger.actions.len() as u32)?;
+                writer.write_string_u16(&trigger.name)?;
+
+                for condition in trigger.conditions.iter() {
+                    write_record(writer, condition.id(), &condition.encode()?)?;
+                }
+
+                for action in trigger.actions.iter() {
+                    write_record(writer, action.id() | ACTION_TYPE_FLAG, &action.encode()?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Strings in records have a u16 length that includes a null terminator
+fn read_record_string<R: ReadRoseExt>(reader: &mut R) -> Result<String, Error> {
+    let length = reader.read_u16()?;
+    let s = reader.read_string(u64::from(length))?;
+    Ok(s.trim_end_matches('\0').to_string())
+}
+
+fn write_record_string<W: WriteRoseExt>(writer: &mut W, s: &str) -> Result<(), Error> {
+    writer.write_u16(s.len() as u16 + 1)?;
+    writer.write_cstring(s)?;
+    Ok(())
+}
+
+/// Group of triggers
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestGroup {
+    pub name: String,
+    pub triggers: Vec<QuestTrigger>,
+}
+
+/// Actions run when every condition passes
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestTrigger {
+    pub name: String,
+
+    /// Run the next trigger in the group when a condition fails
+    pub check_next: bool,
+    pub conditions: Vec<QuestCondition>,
+    pub actions: Vec<QuestAction>,
+}
+
+/// Quest variable compared or changed by a record
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestVariable {
+    pub index: u16,
+    pub variable_type: u16,
+    pub value: i16,
+    pub op: u8,
+}
+
+impl QuestVariable {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<QuestVariable, Error> {
+        let variable = QuestVariable {
+            index: reader.read_u16()?,
+            variable_type: reader.read_u16()?,
+            value: reader.read_i16()?,
+            op: reader.read_u8()?,
+        };
+        skip(reader, 1)?;
+        Ok(variable)
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u16(self.index)?;
+        writer.write_u16(self.variable_type)?;
+        writer.write_i16(self.value)?;
+        writer.write_u8(self.op)?;
+        pad(writer, 1)
+    }
+}
+
+/// Character ability compared or changed by a record
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestAbility {
+    pub ability_type: i32,
+    pub value: i32,
+    pub op: u8,
+}
+
+impl QuestAbility {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<QuestAbility, Error> {
+        let ability = QuestAbility {
+            ability_type: reader.read_i32()?,
+            value: reader.read_i32()?,
+            op: reader.read_u8()?,
+        };
+        skip(reader, 3)?;
+        Ok(ability)
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_i32(self.ability_type)?;
+        writer.write_i32(self.value)?;
+        writer.write_u8(self.op)?;
+        pad(writer, 3)
+    }
+}
+
+/// Item count check
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestItemCheck {
+    pub item: u32,
+    pub equipment_index: i32,
+    pub count: i32,
+    pub op: u8,
+}
+
+/// NPC variable compared by `QuestCondition::CompareNpcVariables`
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestNpcVariable {
+    pub npc: i32,
+    pub variable: i16,
+}
+
+/// Condition record
+///
+/// Comparison operators (`op`) are 0 equal, 1 greater, 2 greater or equal,
+/// 3 less, 4 less or equal and 10 not equal. Records with an unknown type
+/// or layout are kept as `Unknown`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum QuestCondition {
+    SelectQuest {
+        quest: i32,
+    },
+    QuestData(Vec<QuestVariable>),
+    QuestVariable(Vec<QuestVariable>),
+    AbilityValue(Vec<QuestAbility>),
+    QuestItems(Vec<QuestItemCheck>),
+    Party {
+        is_leader: bool,
+        level: i32,
+        reverse: bool,
+    },
+    Position {
+        zone: i32,
+        x: i32,
+        y: i32,
+        z: i32,
+        radius: i32,
+    },
+    WorldTime {
+        start: u32,
+        end: u32,
+    },
+    QuestTimeRemaining {
+        time: u32,
+        op: u8,
+    },
+    HasSkill {
+        start: i32,
+        end: i32,
+        op: u8,
+    },
+    RandomPercent {
+        min: u8,
+        max: u8,
+    },
+    ObjectVariable {
+        object: u8,
+        variable: u16,
+        value: i32,
+        op: u8,
+    },
+    SelectEventObject {
+        zone: i16,
+        x: i32,
+        y: i32,
+        event: u32,
+    },
+    SelectNpc {
+        npc: i32,
+    },
+    SwitchBit {
+        switch: i16,
+        value: i16,
+    },
+    PartyMemberCount {
+        min: i16,
+        max: i16,
+    },
+    ObjectZoneTime {
+        object: u8,
+        start: u32,
+        end: u32,
+    },
+    CompareNpcVariables {
+        first: QuestNpcVariable,
+        second: QuestNpcVariable,
+        op: u8,
+    },
+    MonthDayTime {
+        day: u8,
+        start_hour: u8,
+        start_minute: u8,
+        end_hour: u8,
+        end_minute: u8,
+    },
+    WeekDayTime {
+        weekday: u8,
+        start_hour: u8,
+        start_minute: u8,
+        end_hour: u8,
+        end_minute: u8,
+    },
+    TeamNumber {
+        min: i32,
+        max: i32,
+    },
+    ObjectDistance {
+        object: u8,
+        distance: i32,
+    },
+    ServerChannelNumber {
+        min: u16,
+        max: u16,
+    },
+    InClan {
+        in_clan: bool,
+    },
+    ClanPosition {
+        position: u16,
+        op: u8,
+    },
+    ClanPointContribution {
+        points: u16,
+        op: u8,
+    },
+    ClanLevel {
+        level: i16,
+        op: u8,
+    },
+    ClanPoints {
+        points: i16,
+        op: u8,
+    },
+    ClanMoney {
+        money: i32,
+        op: u8,
+    },
+    ClanMemberCount {
+        count: i16,
+        op: u8,
+    },
+    ClanSkill {
+        start: i16,
+        end: i16,
+        op: u8,
+    },
+    Unknown {
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl QuestCondition {
+    /// Record type id
+    pub fn id(&self) -> u32 {
+        match self {
+            QuestCondition::SelectQuest { .. } => 0,
+            QuestCondition::QuestData(_) => 1,
+            QuestCondition::QuestVariable(_) => 2,
+            QuestCondition::AbilityValue(_) => 3,
+            QuestCondition::QuestItems(_) => 4,
+            QuestCondition::Party { .. } => 5,
+            QuestCondition::Position { .. } => 6,
+            QuestCondition::WorldTime { .. } => 7,
+            QuestCondition::QuestTimeRemaining { .. } => 8,
+            QuestCondition::HasSkill { .. } => 9,
+            QuestCondition::RandomPercent { .. } => 10,
+            QuestCondition::ObjectVariable { .. } => 11,
+            QuestCondition::SelectEventObject { .. } => 12,
+            QuestCondition::SelectNpc { .. } => 13,
+            QuestCondition::SwitchBit { .. } => 14,
+            QuestCondition::PartyMemberCount { .. } => 15,
+            QuestCondition::ObjectZoneTime { .. } => 16,
+            QuestCondition::CompareNpcVariables { .. } => 17,
+            QuestCondition::MonthDayTime { .. } => 18,
+            QuestCondition::WeekDayTime { .. } => 19,
+            QuestCondition::TeamNumber { .. } => 20,
+            QuestCondition::ObjectDistance { .. } => 21,
+            QuestCondition::ServerChannelNumber { .. } => 22,
+            QuestCondition::InClan { .. } => 23,
+            QuestCondition::ClanPosition { .. } => 24,
+            QuestCondition::ClanPointContribution { .. } => 25,
+            QuestCondition::ClanLevel { .. } => 26,
+            QuestCondition::ClanPoints { .. } => 27,
+            QuestCondition::ClanMoney { .. } => 28,
+            QuestCondition::ClanMemberCount { .. } => 29,
+            QuestCondition::ClanSkill { .. } => 30,
+            QuestCondition::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Decode a record payload, unknown records are kept as-is
+    pub fn decode(id: u32, data: &[u8]) -> QuestCondition {
+        decode_record(data, |r| QuestCondition::read(id, r)).unwrap_or_else(|| {
+            QuestCondition::Unknown {
+                id,
+                data: data.to_vec(),
+            }
+        })
+    }
+
+    fn read<R: ReadRoseExt>(id: u32, r: &mut R) -> Result<QuestCondition, Error> {
+        Ok(match id {
+            0 => QuestCondition::SelectQuest {
+                quest: r.read_i32()?,
+            },
+            1 | 2 => {
+                let count = r.read_i32()?;
+                let mut variables = Vec::new();
+                for _ in 0..count {
+                    variables.push(QuestVariable::read(r)?);
+                }
+                if id == 1 {
+                    QuestCondition::QuestData(variables)
+                } else {
+                    QuestCondition::QuestVariable(variables)
+                }
+            }
+            3 => {
+                let count = r.read_i32()?;
+                let mut abilities = Vec::new();
+                for _ in 0..count {
+                    abilities.push(QuestAbility::read(r)?);
+                }
+                QuestCondition::AbilityValue(abilities)
+            }
+            4 => {
+                let count = r.read_i32()?;
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    items.push(QuestItemCheck {
+                        item: r.read_u32()?,
+                        equipment_index: r.read_i32()?,
+                        count: r.read_i32()?,
+                        op: r.read_u8()?,
+                    });
+                    skip(r, 3)?;
+                }
+                QuestCondition::QuestItems(items)
+            }
+            5 => {
+                let is_leader = r.read_u8()? != 0;
+                skip(r, 3)?;
+                let level = r.read_i32()?;
+                let reverse = r.read_u8()? != 0;
+                QuestCondition::Party {
+                    is_leader,
+                    level,
+                    reverse,
+                }
+            }
+            6 => QuestCondition::Position {
+                zone: r.read_i32()?,
+                x: r.read_i32()?,
+                y: r.read_i32()?,
+                z: r.read_i32()?,
+                radius: r.read_i32()?,
+            },
+            7 => QuestCondition::WorldTime {
+                start: r.read_u32()?,
+                end: r.read_u32()?,
+            },
+            8 => QuestCondition::QuestTimeRemaining {
+                time: r.read_u32()?,
+                op: r.read_u8()?,
+            },
+            9 => QuestCondition::HasSkill {
+                start: r.read_i32()?,
+                end: r.read_i32()?,
+                op: r.read_u8()?,
+            },
+            10 => QuestCondition::RandomPercent {
+                min: r.read_u8()?,
+                max: r.read_u8()?,
+            },
+            11 => {
+                let object = r.read_u8()?;
+                skip(r, 1)?;
+                QuestCondition::ObjectVariable {
+                    object,
+                    variable: r.read_u16()?,
+                    value: r.read_i32()?,
+                    op: r.read_u8()?,
+                }
+            }
+            12 => {
+                let zone = r.read_i16()?;
+                skip(r, 2)?;
+                QuestCondition::SelectEventObject {
+                    zone,
+                    x: r.read_i32()?,
+                    y: r.read_i32()?,
+                    event: r.read_u32()?,
+                }
+            }
+            13 => QuestCondition::SelectNpc { npc: r.read_i32()? },
+            14 => QuestCondition::SwitchBit {
+                switch: r.read_i16()?,
+                value: r.read_i16()?,
+            },
+            15 => QuestCondition::PartyMemberCount {
+                min: r.read_i16()?,
+                max: r.read_i16()?,
+            },
+            16 => {
+                let object = r.read_u8()?;
+                skip(r, 3)?;
+                QuestCondition::ObjectZoneTime {
+                    object,
+                    start: r.read_u32()?,
+                    end: r.read_u32()?,
+                }
+            }
+            17 => {
+                let npc_variable = |r: &mut R| -> Result<QuestNpcVariable, Error> {
+                    let variable = QuestNpcVariable {
+                        npc: r.read_i32()?,
+                        variable: r.read_i16()?,
+                    };
+                    skip(r, 2)?;
+                    Ok(variable)
+                };
+                QuestCondition::CompareNpcVariables {
+                    first: npc_variable(r)?,
+                    second: npc_variable(r)?,
+                    op: r.read_u8()?,
+                }
+            }
+            18 => QuestCondition::MonthDayTime {
+                day: r.read_u8()?,
+                start_hour: r.read_u8()?,
+                start_minute: r.read_u8()?,
+                end_hour: r.read_u8()?,
+                end_minute: r.read_u8()?,
+            },
+            19 => QuestCondition::WeekDayTime {
+                weekday: r.read_u8()?,
+                start_hour: r.read_u8()?,
+                start_minute: r.read_u8()?,
+                end_hour: r.read_u8()?,
+                end_minute: r.read_u8()?,
+            },
+            20 => QuestCondition::TeamNumber {
+                min: r.read_i32()?,
+                max: r.read_i32()?,
+            },
+            21 => {
+                let object = r.read_u8()?;
+                skip(r, 3)?;
+                QuestCondition::ObjectDistance {
+                    object,
+                    distance: r.read_i32()?,
+                }
+            }
+            22 => QuestCondition::ServerChannelNumber {
+                min: r.read_u16()?,
+                max: r.read_u16()?,
+            },
+            23 => QuestCondition::InClan {
+                in_clan: r.read_u8()? != 0,
+            },
+            24 => QuestCondition::ClanPosition {
+                position: r.read_u16()?,
+                op: r.read_u8()?,
+            },
+            25 => QuestCondition::ClanPointContribution {
+                points: r.read_u16()?,
+                op: r.read_u8()?,
+            },
+            26 => QuestCondition::ClanLevel {
+                level: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            27 => QuestCondition::ClanPoints {
+                points: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            28 => QuestCondition::ClanMoney {
+                money: r.read_i32()?,
+                op: r.read_u8()?,
+            },
+            29 => QuestCondition::ClanMemberCount {
+                count: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            30 => QuestCondition::ClanSkill {
+                start: r.read_i16()?,
+                end: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            _ => bail!("Unknown quest condition: {}", id),
+        })
+    }
+
+    /// Record payload
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        if let QuestCondition::Unknown { data, .. } = self {
+            return Ok(data.clone());
+        }
+        encode_record(|w| self.write(w))
+    }
+
+    fn write<W: WriteRoseExt>(&self, w: &mut W) -> Result<(), Error> {
+        match self {
+            QuestCondition::SelectQuest { quest } => w.write_i32(*quest)?,
+            QuestCondition::QuestData(variables) | QuestCondition::QuestVariable(variables) => {
+                w.write_i32(variables.len() as i32)?;
+                for variable in variables.iter() {
+                    variable.write(w)?;
+                }
+            }
+            QuestCondition::AbilityValue(abilities) => {
+                w.write_i32(abilities.len() as i32)?;
+                for ability in abilities.iter() {
+                    ability.write(w)?;
+                }
+            }
+            QuestCondition::QuestItems(items) => {
+                w.write_i32(items.len() as i32)?;
+                for item in items.iter() {
+                    w.write_u32(item.item)?;
+                    w.write_i32(item.equipment_index)?;
+                    w.write_i32(item.count)?;
+                    w.write_u8(item.op)?;
+                    pad(w, 3)?;
+                }
+            }
+            QuestCondition::Party {
+                is_leader,
+                level,
+                reverse,
+            } => {
+                w.write_u8(*is_leader as u8)?;
+                pad(w, 3)?;
+                w.write_i32(*level)?;
+                w.write_u8(*reverse as u8)?;
+            }
+            QuestCondition::Position {
+                zone,
+                x,
+                y,
+                z,
+                radius,
+            } => {
+                w.write_i32(*zone)?;
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+                w.write_i32(*z)?;
+                w.write_i32(*radius)?;
+            }
+            QuestCondition::WorldTime { start, end } => {
+                w.write_u32(*start)?;
+                w.write_u32(*end)?;
+            }
+            QuestCondition::QuestTimeRemaining { time, op } => {
+                w.write_u32(*time)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::HasSkill { start, end, op } => {
+                w.write_i32(*start)?;
+                w.write_i32(*end)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::RandomPercent { min, max } => {
+                w.write_u8(*min)?;
+                w.write_u8(*max)?;
+            }
+            QuestCondition::ObjectVariable {
+                object,
+                variable,
+                value,
+                op,
+            } => {
+                w.write_u8(*object)?;
+                pad(w, 1)?;
+                w.write_u16(*variable)?;
+                w.write_i32(*value)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::SelectEventObject { zone, x, y, event } => {
+                w.write_i16(*zone)?;
+                pad(w, 2)?;
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+                w.write_u32(*event)?;
+            }
+            QuestCondition::SelectNpc { npc } => w.write_i32(*npc)?,
+            QuestCondition::SwitchBit { switch, value } => {
+                w.write_i16(*switch)?;
+                w.write_i16(*value)?;
+            }
+            QuestCondition::PartyMemberCount { min, max } => {
+                w.write_i16(*min)?;
+                w.write_i16(*max)?;
+            }
+            QuestCondition::ObjectZoneTime { object, start, end } => {
+                w.write_u8(*object)?;
+                pad(w, 3)?;
+                w.write_u32(*start)?;
+                w.write_u32(*end)?;
+            }
+            QuestCondition::CompareNpcVariables { first, second, op } => {
+                for variable in [first, second].iter() {
+                    w.write_i32(variable.npc)?;
+                    w.write_i16(variable.variable)?;
+                    pad(w, 2)?;
+                }
+                w.write_u8(*op)?;
+            }
+            QuestCondition::MonthDayTime {
+                day: first,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            }
+            | QuestCondition::WeekDayTime {
+                weekday: first,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                w.write_u8(*first)?;
+                w.write_u8(*start_hour)?;
+                w.write_u8(*start_minute)?;
+                w.write_u8(*end_hour)?;
+                w.write_u8(*end_minute)?;
+            }
+            QuestCondition::TeamNumber { min, max } => {
+                w.write_i32(*min)?;
+                w.write_i32(*max)?;
+            }
+            QuestCondition::ObjectDistance { object, distance } => {
+                w.write_u8(*object)?;
+                pad(w, 3)?;
+                w.write_i32(*distance)?;
+            }
+            QuestCondition::ServerChannelNumber { min, max } => {
+                w.write_u16(*min)?;
+                w.write_u16(*max)?;
+            }
+            QuestCondition::InClan { in_clan } => w.write_u8(*in_clan as u8)?,
+            QuestCondition::ClanPosition {
+                position: value,
+                op,
+            }
+            | QuestCondition::ClanPointContribution { points: value, op } => {
+                w.write_u16(*value)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::ClanLevel { level: value, op }
+            | QuestCondition::ClanPoints { points: value, op }
+            | QuestCondition::ClanMemberCount { count: value, op } => {
+                w.write_i16(*value)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::ClanMoney { money, op } => {
+                w.write_i32(*money)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::ClanSkill { start, end, op } => {
+                w.write_i16(*start)?;
+                w.write_i16(*end)?;
+                w.write_u8(*op)?;
+            }
+            QuestCondition::Unknown { data, .. } => w.write_all(data)?,
+        }
+        Ok(())
+    }
+}
+
+/// Action record
+///
+/// Records with an unknown type or layout are kept as `Unknown`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum QuestAction {
+    /// Options: 0 remove, 1 start, 2 replace keeping variables, 3 replace
+    /// resetting variables, 4 select
+    SetQuest {
+        quest: u32,
+        option: u8,
+    },
+    UpdateItem {
+        item: u32,
+        op: u8,
+        count: u16,
+        party: u8,
+    },
+    SetQuestData(Vec<QuestVariable>),
+    AbilityValue {
+        abilities: Vec<QuestAbility>,
+        party: u8,
+    },
+    SetQuestVariable(Vec<QuestVariable>),
+    CalculatedReward {
+        target: u8,
+        equation: u8,
+        base: i32,
+        percent: i32,
+        item: u32,
+        party: u8,
+        item_option: u16,
+    },
+    RestoreHpMp {
+        hp_percent: i32,
+        mp_percent: i32,
+        party: u8,
+    },
+    Teleport {
+        target: u8,
+        zone: u32,
+        x: i32,
+        y: i32,
+        party: u8,
+    },
+    SpawnMonster {
+        monster: u32,
+        count: u32,
+        target: u8,
+        zone: i32,
+        x: i32,
+        y: i32,
+        range: i32,
+        team: i32,
+    },
+    NextTrigger {
+        trigger: String,
+    },
+    ResetBasicStats,
+    ObjectVariable {
+        object: u8,
+        variable: u16,
+        value: i32,
+        op: u8,
+    },
+    NpcMessage {
+        message_type: u8,
+        string_id: i32,
+    },
+    TriggerAfterDelay {
+        object: u8,
+        seconds: u32,
+        trigger: String,
+    },
+    Skill {
+        op: u8,
+        skill: i32,
+    },
+    SetSwitch {
+        switch: i16,
+        value: i16,
+    },
+    ClearSwitchGroup {
+        group: u16,
+    },
+    ClearAllSwitches,
+    /// Announcement with format arguments kept as-is
+    FormatAnnounceMessage {
+        string_id: i32,
+        arguments: Vec<u8>,
+    },
+    TriggerForZoneTeam {
+        zone: u16,
+        team: u16,
+        trigger: String,
+    },
+    SetTeamNumber {
+        source: u8,
+    },
+    SetRevivePosition {
+        x: i32,
+        y: i32,
+    },
+    SetMonsterSpawnState {
+        zone: u16,
+        op: u8,
+    },
+    ClanLevelIncrease,
+    ClanMoney {
+        money: i32,
+        op: u8,
+    },
+    ClanPoints {
+        points: i16,
+        op: u8,
+    },
+    ClanSkill {
+        skill: u16,
+        op: u8,
+    },
+    ClanPointContribution {
+        points: i16,
+        op: u8,
+    },
+    TeleportNearbyClanMembers {
+        range: i32,
+        zone: u16,
+        x: i32,
+        y: i32,
+    },
+    CallLuaFunction {
+        function: String,
+    },
+    ResetSkills,
+    Unknown {
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl QuestAction {
+    /// Record type id, without the action flag
+    pub fn id(&self) -> u32 {
+        match self {
+            QuestAction::SetQuest { .. } => 0,
+            QuestAction::UpdateItem { .. } => 1,
+            QuestAction::SetQuestData(_) => 2,
+            QuestAction::AbilityValue { .. } => 3,
+            QuestAction::SetQuestVariable(_) => 4,
+            QuestAction::CalculatedReward { .. } => 5,
+            QuestAction::RestoreHpMp { .. } => 6,
+            QuestAction::Teleport { .. } => 7,
+            QuestAction::SpawnMonster { .. } => 8,
+            QuestAction::NextTrigger { .. } => 9,
+            QuestAction::ResetBasicStats => 10,
+            QuestAction::ObjectVariable { .. } => 11,
+            QuestAction::NpcMessage { .. } => 12,
+            QuestAction::TriggerAfterDelay { .. } => 13,
+            QuestAction::Skill { .. } => 14,
+            QuestAction::SetSwitch { .. } => 15,
+            QuestAction::ClearSwitchGroup { .. } => 16,
+            QuestAction::ClearAllSwitches => 17,
+            QuestAction::FormatAnnounceMessage { .. } => 18,
+            QuestAction::TriggerForZoneTeam { .. } => 19,
+            QuestAction::SetTeamNumber { .. } => 20,
+            QuestAction::SetRevivePosition { .. } => 21,
+            QuestAction::SetMonsterSpawnState { .. } => 22,
+            QuestAction::ClanLevelIncrease => 23,
+            QuestAction::ClanMoney { .. } => 24,
+            QuestAction::ClanPoints { .. } => 25,
+            QuestAction::ClanSkill { .. } => 26,
+            QuestAction::ClanPointContribution { .. } => 27,
+            QuestAction::TeleportNearbyClanMembers { .. } => 28,
+            QuestAction::CallLuaFunction { .. } => 29,
+            QuestAction::ResetSkills => 30,
+            QuestAction::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Decode a record payload, unknown records are kept as-is
+    pub fn decode(id: u32, data: &[u8]) -> QuestAction {
+        decode_record(data, |r| QuestAction::read(id, data.len(), r)).unwrap_or_else(|| {
+            QuestAction::Unknown {
+                id,
+                data: data.to_vec(),
+            }
+        })
+    }
+
+    fn read<R: ReadRoseExt>(id: u32, size: usize, r: &mut R) -> Result<QuestAction, Error> {
+        Ok(match id {
+            0 => QuestAction::SetQuest {
+                quest: r.read_u32()?,
+                option: r.read_u8()?,
+            },
+            1 => {
+                let item = r.read_u32()?;
+                let op = r.read_u8()?;
+                skip(r, 1)?;
+                QuestAction::UpdateItem {
+                    item,
+                    op,
+                    count: r.read_u16()?,
+                    party: r.read_u8()?,
+                }
+            }
+            2 | 4 => {
+                let count = r.read_i32()?;
+                let mut variables = Vec::new();
+                for _ in 0..count {
+                    variables.push(QuestVariable::read(r)?);
+                }
+                if id == 2 {
+                    QuestAction::SetQuestData(variables)
+                } else {
+                    QuestAction::SetQuestVariable(variables)
+                }
+            }
+            3 => {
+                let count = r.read_i32()?;
+                let mut abilities = Vec::new();
+                for _ in 0..count {
+                    abilities.push(QuestAbility::read(r)?);
+                }
+                QuestAction::AbilityValue {
+                    abilities,
+                    party: r.read_u8()?,
+                }
+            }
+            5 => {
+                let target = r.read_u8()?;
+                let equation = r.read_u8()?;
+                skip(r, 2)?;
+                let base = r.read_i32()?;
+                let percent = r.read_i32()?;
+                let item = r.read_u32()?;
+                let party = r.read_u8()?;
+                skip(r, 1)?;
+                QuestAction::CalculatedReward {
+                    target,
+                    equation,
+                    base,
+                    percent,
+                    item,
+                    party,
+                    item_option: r.read_u16()?,
+                }
+            }
+            6 => QuestAction::RestoreHpMp {
+                hp_percent: r.read_i32()?,
+                mp_percent: r.read_i32()?,
+                party: r.read_u8()?,
+            },
+            7 => {
+                let target = r.read_u8()?;
+                skip(r, 3)?;
+                QuestAction::Teleport {
+                    target,
+                    zone: r.read_u32()?,
+                    x: r.read_i32()?,
+                    y: r.read_i32()?,
+                    party: r.read_u8()?,
+                }
+            }
+            8 => {
+                let monster = r.read_u32()?;
+                let count = r.read_u32()?;
+                let target = r.read_u8()?;
+                skip(r, 3)?;
+                QuestAction::SpawnMonster {
+                    monster,
+                    count,
+                    target,
+                    zone: r.read_i32()?,
+                    x: r.read_i32()?,
+                    y: r.read_i32()?,
+                    range: r.read_i32()?,
+                    team: r.read_i32()?,
+                }
+            }
+            9 => QuestAction::NextTrigger {
+                trigger: read_record_string(r)?,
+            },
+            10 => QuestAction::ResetBasicStats,
+            11 => {
+                let object = r.read_u8()?;
+                skip(r, 1)?;
+                QuestAction::ObjectVariable {
+                    object,
+                    variable: r.read_u16()?,
+                    value: r.read_i32()?,
+                    op: r.read_u8()?,
+                }
+            }
+            12 => {
+                let message_type = r.read_u8()?;
+                skip(r, 3)?;
+                QuestAction::NpcMessage {
+                    message_type,
+                    string_id: r.read_i32()?,
+                }
+            }
+            13 => {
+                let object = r.read_u8()?;
+                skip(r, 3)?;
+                QuestAction::TriggerAfterDelay {
+                    object,
+                    seconds: r.read_u32()?,
+                    trigger: read_record_string(r)?,
+                }
+            }
+            14 => {
+                let op = r.read_u8()?;
+                skip(r, 3)?;
+                QuestAction::Skill {
+                    op,
+                    skill: r.read_i32()?,
+                }
+            }
+            15 => QuestAction::SetSwitch {
+                switch: r.read_i16()?,
+                value: r.read_i16()?,
+            },
+            16 => QuestAction::ClearSwitchGroup {
+                group: r.read_u16()?,
+            },
+            17 => QuestAction::ClearAllSwitches,
+            18 => {
+                let string_id = r.read_i32()?;
+                let mut arguments = vec![0u8; size.saturating_sub(4)];
+                r.read_exact(&mut arguments)?;
+                QuestAction::FormatAnnounceMessage {
+                    string_id,
+                    arguments,
+                }
+            }
+            19 => QuestAction::TriggerForZoneTeam {
+                zone: r.read_u16()?,
+                team: r.read_u16()?,
+                trigger: read_record_string(r)?,
+            },
+            20 => QuestAction::SetTeamNumber {
+                source: r.read_u8()?,
+            },
+            21 => QuestAction::SetRevivePosition {
+                x: r.read_i32()?,
+                y: r.read_i32()?,
+            },
+            22 => QuestAction::SetMonsterSpawnState {
+                zone: r.read_u16()?,
+                op: r.read_u8()?,
+            },
+            23 => QuestAction::ClanLevelIncrease,
+            24 => QuestAction::ClanMoney {
+                money: r.read_i32()?,
+                op: r.read_u8()?,
+            },
+            25 => QuestAction::ClanPoints {
+                points: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            26 => QuestAction::ClanSkill {
+                skill: r.read_u16()?,
+                op: r.read_u8()?,
+            },
+            27 => QuestAction::ClanPointContribution {
+                points: r.read_i16()?,
+                op: r.read_u8()?,
+            },
+            28 => {
+                let range = r.read_i32()?;
+                let zone = r.read_u16()?;
+                skip(r, 2)?;
+                QuestAction::TeleportNearbyClanMembers {
+                    range,
+                    zone,
+                    x: r.read_i32()?,
+                    y: r.read_i32()?,
+                }
+            }
+            29 => QuestAction::CallLuaFunction {
+                function: read_record_string(r)?,
+            },
+            30 => QuestAction::ResetSkills,
+            _ => bail!("Unknown quest action: {}", id),
+        })
+    }
+
+    /// Record payload
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            QuestAction::Unknown { data, .. } => Ok(data.clone()),
+            QuestAction::FormatAnnounceMessage {
+                string_id,
+                arguments,
+            } => {
+                let mut data = string_id.to_le_bytes().to_vec();
+                data.extend_from_slice(arguments);
+                Ok(data)
+            }
+            _ => encode_record(|w| self.write(w)),
+        }
+    }
+
+    fn write<W: WriteRoseExt>(&self, w: &mut W) -> Result<(), Error> {
+        match self {
+            QuestAction::SetQuest { quest, option } => {
+                w.write_u32(*quest)?;
+                w.write_u8(*option)?;
+            }
+            QuestAction::UpdateItem {
+                item,
+                op,
+                count,
+                party,
+            } => {
+                w.write_u32(*item)?;
+                w.write_u8(*op)?;
+                pad(w, 1)?;
+                w.write_u16(*count)?;
+                w.write_u8(*party)?;
+            }
+            QuestAction::SetQuestData(variables) | QuestAction::SetQuestVariable(variables) => {
+                w.write_i32(variables.len() as i32)?;
+                for variable in variables.iter() {
+                    variable.write(w)?;
+                }
+            }
+            QuestAction::AbilityValue { abilities, party } => {
+                w.write_i32(abilities.len() as i32)?;
+                for ability in abilities.iter() {
+                    ability.write(w)?;
+                }
+                w.write_u8(*party)?;
+            }
+            QuestAction::CalculatedReward {
+                target,
+                equation,
+                base,
+                percent,
+                item,
+                party,
+                item_option,
+            } => {
+                w.write_u8(*target)?;
+                w.write_u8(*equation)?;
+                pad(w, 2)?;
+                w.write_i32(*base)?;
+                w.write_i32(*percent)?;
+                w.write_u32(*item)?;
+                w.write_u8(*party)?;
+                pad(w, 1)?;
+                w.write_u16(*item_option)?;
+            }
+            QuestAction::RestoreHpMp {
+                hp_percent,
+                mp_percent,
+                party,
+            } => {
+                w.write_i32(*hp_percent)?;
+                w.write_i32(*mp_percent)?;
+                w.write_u8(*party)?;
+            }
+            QuestAction::Teleport {
+                target,
+                zone,
+                x,
+                y,
+                party,
+            } => {
+                w.write_u8(*target)?;
+                pad(w, 3)?;
+                w.write_u32(*zone)?;
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+                w.write_u8(*party)?;
+            }
+            QuestAction::SpawnMonster {
+                monster,
+                count,
+                target,
+                zone,
+                x,
+                y,
+                range,
+                team,
+            } => {
+                w.write_u32(*monster)?;
+                w.write_u32(*count)?;
+                w.write_u8(*target)?;
+                pad(w, 3)?;
+                w.write_i32(*zone)?;
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+                w.write_i32(*range)?;
+                w.write_i32(*team)?;
+            }
+            QuestAction::NextTrigger { trigger } => write_record_string(w, trigger)?,
+            QuestAction::ObjectVariable {
+                object,
+                variable,
+                value,
+                op,
+            } => {
+                w.write_u8(*object)?;
+                pad(w, 1)?;
+                w.write_u16(*variable)?;
+                w.write_i32(*value)?;
+                w.write_u8(*op)?;
+            }
+            QuestAction::NpcMessage {
+                message_type,
+                string_id,
+            } => {
+                w.write_u8(*message_type)?;
+                pad(w, 3)?;
+                w.write_i32(*string_id)?;
+            }
+            QuestAction::TriggerAfterDelay {
+                object,
+                seconds,
+                trigger,
+            } => {
+                w.write_u8(*object)?;
+                pad(w, 3)?;
+                w.write_u32(*seconds)?;
+                write_record_string(w, trigger)?;
+            }
+            QuestAction::Skill { op, skill } => {
+                w.write_u8(*op)?;
+                pad(w, 3)?;
+                w.write_i32(*skill)?;
+            }
+            QuestAction::SetSwitch { switch, value } => {
+                w.write_i16(*switch)?;
+                w.write_i16(*value)?;
+            }
+            QuestAction::ClearSwitchGroup { group } => w.write_u16(*group)?,
+            QuestAction::TriggerForZoneTeam {
+                zone,
+                team,
+                trigger,
+            } => {
+                w.write_u16(*zone)?;
+                w.write_u16(*team)?;
+                write_record_string(w, trigger)?;
+            }
+            QuestAction::SetTeamNumber { source } => w.write_u8(*source)?,
+            QuestAction::SetRevivePosition { x, y } => {
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+            }
+            QuestAction::SetMonsterSpawnState { zone, op }
+            | QuestAction::ClanSkill { skill: zone, op } => {
+                w.write_u16(*zone)?;
+                w.write_u8(*op)?;
+            }
+            QuestAction::ClanMoney { money, op } => {
+                w.write_i32(*money)?;
+                w.write_u8(*op)?;
+            }
+            QuestAction::ClanPoints { points, op }
+            | QuestAction::ClanPointContribution { points, op } => {
+                w.write_i16(*points)?;
+                w.write_u8(*op)?;
+            }
+            QuestAction::TeleportNearbyClanMembers { range, zone, x, y } => {
+                w.write_i32(*range)?;
+                w.write_u16(*zone)?;
+                pad(w, 2)?;
+                w.write_i32(*x)?;
+                w.write_i32(*y)?;
+            }
+            QuestAction::CallLuaFunction { function } => write_record_string(w, function)?,
+            QuestAction::ResetBasicStats
+            | QuestAction::ClearAllSwitches
+            | QuestAction::ClanLevelIncrease
+            | QuestAction::ResetSkills => {}
+            QuestAction::FormatAnnounceMessage {
+                string_id,
+                arguments,
+            } => {
+                w.write_i32(*string_id)?;
+                w.write_all(arguments)?;
+            }
+            QuestAction::Unknown { data, .. } => w.write_all(data)?,
+        }
+        Ok(())
+    }
+}
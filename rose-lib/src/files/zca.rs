@@ -0,0 +1,160 @@
+//! ROSE Online Camera
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::files::zmo::{Motion, Pose};
+use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
+use crate::utils::{Matrix4, Vector3};
+
+/// Camera file
+pub type ZCA = Camera;
+
+const ZCA_IDENTIFIER: &str = "ZCA0001";
+
+/// Motion channel holding the camera eye position
+pub const CAMERA_EYE_CHANNEL: u32 = 0;
+
+/// Motion channel holding the position the camera looks at
+pub const CAMERA_TARGET_CHANNEL: u32 = 1;
+
+/// Motion channel holding the camera up vector
+pub const CAMERA_UP_CHANNEL: u32 = 2;
+
+/// Motion channel holding the field of view (x), near plane (y) and far
+/// plane (z) in a position channel
+pub const CAMERA_FOV_CHANNEL: u32 = 3;
+
+/// Camera
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Camera {
+    pub projection: CameraProjection,
+
+    /// Matrices as stored in the file
+    pub model_view: Matrix4,
+    pub projection_matrix: Matrix4,
+
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+
+    pub eye: Vector3<f32>,
+    pub target: Vector3<f32>,
+    pub up: Vector3<f32>,
+}
+
+impl Camera {
+    /// Camera state at `time` seconds of a camera motion.
+    ///
+    /// Camera motions use position channels instead of bones, see the
+    /// `CAMERA_*_CHANNEL` constants. Values missing from the motion are taken
+    /// from the camera.
+    pub fn sample(&self, motion: &Motion, time: f32) -> CameraFrame {
+        self.frame(&motion.sample(time))
+    }
+
+    /// Camera state at every frame of a camera motion
+    pub fn frames(&self, motion: &Motion) -> Vec<CameraFrame> {
+        (0..motion.frames)
+            .map(|frame| self.frame(&motion.sample_frame(frame)))
+            .collect()
+    }
+
+    fn frame(&self, pose: &Pose) -> CameraFrame {
+        let lens = pose.position(CAMERA_FOV_CHANNEL);
+        CameraFrame {
+            eye: pose.position(CAMERA_EYE_CHANNEL).unwrap_or(self.eye),
+            target: pose.position(CAMERA_TARGET_CHANNEL).unwrap_or(self.target),
+            up: pose.position(CAMERA_UP_CHANNEL).unwrap_or(self.up),
+            fov: lens.map(|v| v.x).unwrap_or(self.fov),
+            near: lens.map(|v| v.y).unwrap_or(self.near),
+            far: lens.map(|v| v.z).unwrap_or(self.far),
+        }
+    }
+}
+
+impl RoseFile for Camera {
+    fn new() -> Camera {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let identifier = reader.read_cstring()?;
+        if identifier != ZCA_IDENTIFIER {
+            bail!("Unsupported ZCA version: {}", identifier);
+        }
+
+        self.projection = match reader.read_i32()? {
+            0 => CameraProjection::Perspective,
+            1 => CameraProjection::Orthographic,
+            p => bail!("Invalid camera projection: {}", p),
+        };
+        self.model_view = read_matrix(reader)?;
+        self.projection_matrix = read_matrix(reader)?;
+        self.fov = reader.read_f32()?;
+        self.aspect_ratio = reader.read_f32()?;
+        self.near = reader.read_f32()?;
+        self.far = reader.read_f32()?;
+        self.eye = reader.read_vector3_f32()?;
+        self.target = reader.read_vector3_f32()?;
+        self.up = reader.read_vector3_f32()?;
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_cstring(ZCA_IDENTIFIER)?;
+        writer.write_i32(self.projection as i32)?;
+        write_matrix(writer, &self.model_view)?;
+        write_matrix(writer, &self.projection_matrix)?;
+        writer.write_f32(self.fov)?;
+        writer.write_f32(self.aspect_ratio)?;
+        writer.write_f32(self.near)?;
+        writer.write_f32(self.far)?;
+        writer.write_vector3_f32(&self.eye)?;
+        writer.write_vector3_f32(&self.target)?;
+        writer.write_vector3_f32(&self.up)?;
+
+        Ok(())
+    }
+}
+
+fn read_matrix<R: ReadRoseExt>(reader: &mut R) -> Result<Matrix4, Error> {
+    let mut matrix = Matrix4::default();
+    for row in matrix.m.iter_mut() {
+        for value in row.iter_mut() {
+            *value = reader.read_f32()?;
+        }
+    }
+    Ok(matrix)
+}
+
+fn write_matrix<W: WriteRoseExt>(writer: &mut W, matrix: &Matrix4) -> Result<(), Error> {
+    for row in matrix.m.iter() {
+        for value in row.iter() {
+            writer.write_f32(*value)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub enum CameraProjection {
+    #[default]
+    Perspective = 0,
+    Orthographic = 1,
+}
+
+/// Camera state at a point in time
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CameraFrame {
+    pub eye: Vector3<f32>,
+    pub target: Vector3<f32>,
+    pub up: Vector3<f32>,
+
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
@@ -2,8 +2,9 @@
 use failure::Error;
 use serde::{Deserialize, Serialize};
 
+use crate::files::zmo::Pose;
 use crate::io::{ReadRoseExt, RoseFile, WriteRoseExt};
-use crate::utils::{Quaternion, Vector3};
+use crate::utils::{Matrix4, Quaternion, Vector3};
 
 /// Skeleton file
 pub type ZMD = Skeleton;
@@ -18,6 +19,45 @@ pub struct Skeleton {
     pub dummy_bones: Vec<Bone>,
 }
 
+impl Skeleton {
+    /// World space matrices for every bone followed by every dummy bone.
+    ///
+    /// Channels in `pose` replace the bind transform of the bone with the same
+    /// index, dummy bones are indexed after the regular bones.
+    pub fn world_matrices(&self, pose: &Pose) -> Vec<Matrix4> {
+        let mut matrices: Vec<Matrix4> =
+            Vec::with_capacity(self.bones.len() + self.dummy_bones.len());
+
+        for (i, bone) in self.bones.iter().chain(&self.dummy_bones).enumerate() {
+            let index = i as u32;
+            let position = pose.position(index).unwrap_or(bone.position);
+            let rotation = pose.rotation(index).unwrap_or(bone.rotation);
+            let scale = pose.scale(index).unwrap_or(1.0);
+            let local = Matrix4::from_transform(&position, &rotation, scale);
+
+            // Bones must come after their parent, the root bone is its own parent
+            let parent = if i < self.bones.len() {
+                bone.parent >= 0 && (bone.parent as usize) < i
+            } else {
+                bone.parent >= 0 && (bone.parent as usize) < self.bones.len()
+            };
+
+            matrices.push(if parent {
+                matrices[bone.parent as usize].multiply(&local)
+            } else {
+                local
+            });
+        }
+
+        matrices
+    }
+
+    /// World space matrices of the skeleton in its bind pose
+    pub fn bind_matrices(&self) -> Vec<Matrix4> {
+        self.world_matrices(&Pose::default())
+    }
+}
+
 impl RoseFile for Skeleton {
     fn new() -> Skeleton {
         Self::default()
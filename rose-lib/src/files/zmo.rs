@@ -1,5 +1,7 @@
 //! ROSE Online Motion
 use std::convert::TryFrom;
+use std::io::SeekFrom;
+use std::ops::Range;
 
 use failure::Error;
 use serde::{Deserialize, Serialize};
@@ -10,14 +12,329 @@ use crate::utils::{Quaternion, Vector2, Vector3};
 /// Motion File
 pub type ZMO = Motion;
 
+const EVENT_TAG: &str = "EZMO";
+const EVENT_INTERPOLATION_TAG: &str = "3ZMO";
+
 /// Motion
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Motion {
     pub identifier: String,
     pub fps: u32,
     pub frames: u32,
 
     pub channels: Vec<Channel>,
+
+    /// Frame events from the optional footer, e.g. attack hits and sounds
+    #[serde(default)]
+    pub events: Vec<MotionEvent>,
+
+    /// Interpolation interval in milliseconds from a `3ZMO` footer
+    #[serde(default)]
+    pub interpolation_interval: Option<u32>,
+}
+
+/// Event triggered when a motion reaches a frame
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct MotionEvent {
+    pub frame: u32,
+    pub event_id: u16,
+}
+
+impl Motion {
+    /// Length of the motion in seconds
+    pub fn duration(&self) -> f32 {
+        if self.fps == 0 || self.frames < 2 {
+            return 0.0;
+        }
+        (self.frames - 1) as f32 / self.fps as f32
+    }
+
+    /// Sample every channel at `time` seconds, clamped to the motion's length.
+    ///
+    /// Rotations are interpolated with slerp, texture frames are stepped and
+    /// every other channel is interpolated linearly.
+    pub fn sample(&self, time: f32) -> Pose {
+        let mut pose = Pose::default();
+        for channel in &self.channels {
+            if let Some(value) = self.sample_channel(channel, time) {
+                pose.channels.push(PoseChannel {
+                    index: channel.index,
+                    value,
+                });
+            }
+        }
+        pose
+    }
+
+    /// Values of every channel at a single frame, clamped to the last frame
+    pub fn sample_frame(&self, frame: u32) -> Pose {
+        let mut pose = Pose::default();
+        if self.frames == 0 {
+            return pose;
+        }
+
+        let frame = frame.min(self.frames - 1) as usize;
+        for channel in &self.channels {
+            if let Some(value) = channel.frames.value(frame) {
+                pose.channels.push(PoseChannel {
+                    index: channel.index,
+                    value,
+                });
+            }
+        }
+        pose
+    }
+
+    /// Create a copy of the motion sampled at a different frame rate
+    pub fn resample(&self, fps: u32) -> Result<Motion, Error> {
+        if fps == 0 {
+            bail!("Motion fps must be greater than 0");
+        }
+
+        let frames = if self.frames == 0 {
+            0
+        } else {
+            (self.duration() * fps as f32).round() as u32 + 1
+        };
+
+        let mut motion = self.empty_copy();
+        motion.fps = fps;
+        motion.frames = frames;
+
+        // Events keep their time, moved to the nearest new frame. Only one
+        // event fits on a frame so later events move to the next free frame.
+        let scale = fps as f32 / self.fps.max(1) as f32;
+        let mut events: Vec<&MotionEvent> = self.events.iter().collect();
+        events.sort_by_key(|e| e.frame);
+
+        let mut next_free = 0;
+        for event in events {
+            let nearest =
+                ((event.frame as f32 * scale).round() as u32).min(frames.saturating_sub(1));
+            let frame = nearest.max(next_free);
+            if frame >= frames {
+                bail!(
+                    "Motion event {} on frame {} has no free frame at {} fps",
+                    event.event_id,
+                    event.frame,
+                    fps
+                );
+            }
+            motion.events.push(MotionEvent {
+                frame,
+                event_id: event.event_id,
+            });
+            next_free = frame + 1;
+        }
+
+        for (channel, source) in motion.channels.iter_mut().zip(&self.channels) {
+            for frame in 0..frames {
+                if let Some(value) = self.sample_channel(source, frame as f32 / fps as f32) {
+                    channel.frames.push(value)?;
+                }
+            }
+        }
+
+        Ok(motion)
+    }
+
+    /// Create a copy containing only `frames`
+    pub fn trim(&self, frames: Range<u32>) -> Result<Motion, Error> {
+        if frames.start >= frames.end || frames.end > self.frames {
+            bail!(
+                "Invalid frame range {}..{} for motion with {} frames",
+                frames.start,
+                frames.end,
+                self.frames
+            );
+        }
+
+        let mut motion = self.empty_copy();
+        motion.frames = frames.end - frames.start;
+        motion.events = self
+            .events
+            .iter()
+            .filter(|e| frames.contains(&e.frame))
+            .map(|e| MotionEvent {
+                frame: e.frame - frames.start,
+                event_id: e.event_id,
+            })
+            .collect();
+        for (channel, source) in motion.channels.iter_mut().zip(&self.channels) {
+            for frame in frames.clone() {
+                if let Some(value) = source.frames.value(frame as usize) {
+                    channel.frames.push(value)?;
+                }
+            }
+        }
+
+        Ok(motion)
+    }
+
+    /// Create a motion playing `self` followed by `other`. Both motions must
+    /// have the same frame rate and channel layout.
+    pub fn concat(&self, other: &Motion) -> Result<Motion, Error> {
+        if self.fps != other.fps {
+            bail!("Motion fps differs: {} and {}", self.fps, other.fps);
+        }
+
+        let same_layout = self.channels.len() == other.channels.len()
+            && self
+                .channels
+                .iter()
+                .zip(&other.channels)
+                .all(|(a, b)| a.typ == b.typ && a.index == b.index);
+        if !same_layout {
+            bail!("Motion channel layouts differ");
+        }
+
+        let mut motion = self.clone();
+        motion
+            .events
+            .extend(other.events.iter().map(|e| MotionEvent {
+                frame: e.frame + self.frames,
+                event_id: e.event_id,
+            }));
+        motion.frames += other.frames;
+        for (channel, source) in motion.channels.iter_mut().zip(&other.channels) {
+            for frame in 0..other.frames as usize {
+                if let Some(value) = source.frames.value(frame) {
+                    channel.frames.push(value)?;
+                }
+            }
+        }
+
+        Ok(motion)
+    }
+
+    fn sample_channel(&self, channel: &Channel, time: f32) -> Option<ChannelValue> {
+        if self.frames == 0 {
+            return None;
+        }
+
+        let last = (self.frames - 1) as f32;
+        let frame = (time * self.fps as f32).max(0.0).min(last);
+        let start = frame.floor() as usize;
+        let end = (start + 1).min(self.frames as usize - 1);
+        let t = frame - start as f32;
+
+        match (channel.frames.value(start), channel.frames.value(end)) {
+            (Some(a), Some(b)) if t > 0.0 => Some(a.interpolate(&b, t)),
+            (a, _) => a,
+        }
+    }
+
+    /// Read the event footer, if the file has one.
+    ///
+    /// The footer holds a u16 event id for each frame (0 for no event), an
+    /// interpolation interval for `3ZMO` footers, then the footer's offset
+    /// and tag as the last 8 bytes of the file.
+    fn read_events<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let data_end = reader.position()?;
+        let file_end = reader.seek(SeekFrom::End(0))?;
+        if file_end < data_end + 8 {
+            return Ok(());
+        }
+
+        reader.seek(SeekFrom::End(-4))?;
+        let tag = reader.read_string(4)?;
+        if tag != EVENT_TAG && tag != EVENT_INTERPOLATION_TAG {
+            reader.seek(SeekFrom::Start(data_end))?;
+            return Ok(());
+        }
+
+        reader.seek(SeekFrom::End(-8))?;
+        let offset = reader.read_u32()?;
+        reader.seek(SeekFrom::Start(offset as u64))?;
+
+        let frame_count = reader.read_u16()?;
+        for frame in 0..frame_count {
+            let event_id = reader.read_u16()?;
+            if event_id != 0 {
+                self.events.push(MotionEvent {
+                    frame: frame as u32,
+                    event_id,
+                });
+            }
+        }
+
+        if tag == EVENT_INTERPOLATION_TAG {
+            self.interpolation_interval = Some(reader.read_u32()?);
+        }
+
+        reader.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Write the event footer when there are events or an interpolation
+    /// interval, otherwise leave the file as plain frame data
+    fn write_events<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        if self.events.is_empty() && self.interpolation_interval.is_none() {
+            return Ok(());
+        }
+
+        let last_event = self.events.iter().map(|e| e.frame + 1).max().unwrap_or(0);
+        let frame_count = self.frames.max(last_event);
+        if frame_count > u16::MAX as u32 {
+            bail!("Motion events support at most {} frames", u16::MAX);
+        }
+
+        // The footer holds a single event per frame and 0 for none
+        let mut event_ids = vec![0u16; frame_count as usize];
+        for event in &self.events {
+            if event.event_id == 0 {
+                bail!("Motion event on frame {} has no event id", event.frame);
+            }
+            let event_id = &mut event_ids[event.frame as usize];
+            if *event_id != 0 {
+                bail!(
+                    "Motion events {} and {} are both on frame {}",
+                    event_id,
+                    event.event_id,
+                    event.frame
+                );
+            }
+            *event_id = event.event_id;
+        }
+
+        let offset = writer.position()?;
+        writer.write_u16(frame_count as u16)?;
+        for event_id in event_ids {
+            writer.write_u16(event_id)?;
+        }
+
+        let tag = match self.interpolation_interval {
+            Some(interval) => {
+                writer.write_u32(interval)?;
+                EVENT_INTERPOLATION_TAG
+            }
+            None => EVENT_TAG,
+        };
+
+        writer.write_u32(offset as u32)?;
+        writer.write_string(tag, 4)?;
+        Ok(())
+    }
+
+    /// Copy of the motion with the same channels but no frames
+    fn empty_copy(&self) -> Motion {
+        Motion {
+            identifier: self.identifier.clone(),
+            fps: self.fps,
+            frames: 0,
+            events: Vec::new(),
+            interpolation_interval: self.interpolation_interval,
+            channels: self
+                .channels
+                .iter()
+                .map(|c| {
+                    let mut channel = Channel::from(c.typ);
+                    channel.index = c.index;
+                    channel
+                })
+                .collect(),
+        }
+    }
 }
 
 impl RoseFile for Motion {
@@ -93,6 +410,8 @@ impl RoseFile for Motion {
             }
         }
 
+        self.read_events(reader)?;
+
         Ok(())
     }
 
@@ -156,11 +475,13 @@ impl RoseFile for Motion {
             }
         }
 
+        self.write_events(writer)?;
+
         Ok(())
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Channel {
     pub typ: ChannelType,
     pub index: u32,
@@ -272,7 +593,7 @@ impl From<ChannelType> for Channel {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ChannelData {
     None,
     Position(Vec<Vector3<f32>>),
@@ -287,12 +608,125 @@ pub enum ChannelData {
     Scale(Vec<f32>),
 }
 
+impl ChannelData {
+    /// Value at `frame`, if the channel has that many frames
+    pub fn value(&self, frame: usize) -> Option<ChannelValue> {
+        match self {
+            ChannelData::None => None,
+            ChannelData::Position(v) => v.get(frame).map(|v| ChannelValue::Position(*v)),
+            ChannelData::Rotation(v) => v.get(frame).map(|v| ChannelValue::Rotation(*v)),
+            ChannelData::Normal(v) => v.get(frame).map(|v| ChannelValue::Normal(*v)),
+            ChannelData::Alpha(v) => v.get(frame).map(|v| ChannelValue::Alpha(*v)),
+            ChannelData::UV1(v) => v.get(frame).map(|v| ChannelValue::UV1(*v)),
+            ChannelData::UV2(v) => v.get(frame).map(|v| ChannelValue::UV2(*v)),
+            ChannelData::UV3(v) => v.get(frame).map(|v| ChannelValue::UV3(*v)),
+            ChannelData::UV4(v) => v.get(frame).map(|v| ChannelValue::UV4(*v)),
+            ChannelData::Texture(v) => v.get(frame).map(|v| ChannelValue::Texture(*v)),
+            ChannelData::Scale(v) => v.get(frame).map(|v| ChannelValue::Scale(*v)),
+        }
+    }
+
+    /// Append a frame, the value must match the channel's type
+    pub fn push(&mut self, value: ChannelValue) -> Result<(), Error> {
+        match (self, value) {
+            (ChannelData::Position(v), ChannelValue::Position(x)) => v.push(x),
+            (ChannelData::Rotation(v), ChannelValue::Rotation(x)) => v.push(x),
+            (ChannelData::Normal(v), ChannelValue::Normal(x)) => v.push(x),
+            (ChannelData::Alpha(v), ChannelValue::Alpha(x)) => v.push(x),
+            (ChannelData::UV1(v), ChannelValue::UV1(x)) => v.push(x),
+            (ChannelData::UV2(v), ChannelValue::UV2(x)) => v.push(x),
+            (ChannelData::UV3(v), ChannelValue::UV3(x)) => v.push(x),
+            (ChannelData::UV4(v), ChannelValue::UV4(x)) => v.push(x),
+            (ChannelData::Texture(v), ChannelValue::Texture(x)) => v.push(x),
+            (ChannelData::Scale(v), ChannelValue::Scale(x)) => v.push(x),
+            (_, value) => bail!("Channel value does not match channel type: {:?}", value),
+        }
+        Ok(())
+    }
+}
+
 impl Default for ChannelData {
     fn default() -> ChannelData {
         ChannelData::None
     }
 }
 
+/// Single frame value of a channel
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ChannelValue {
+    Position(Vector3<f32>),
+    Rotation(Quaternion),
+    Normal(Vector3<f32>),
+    Alpha(f32),
+    UV1(Vector2<f32>),
+    UV2(Vector2<f32>),
+    UV3(Vector2<f32>),
+    UV4(Vector2<f32>),
+    Texture(f32),
+    Scale(f32),
+}
+
+impl ChannelValue {
+    /// Interpolate towards `other`, values of a different type return `self`
+    pub fn interpolate(&self, other: &ChannelValue, t: f32) -> ChannelValue {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        match (self, other) {
+            (ChannelValue::Position(a), ChannelValue::Position(b)) => {
+                ChannelValue::Position(a.lerp(b, t))
+            }
+            (ChannelValue::Rotation(a), ChannelValue::Rotation(b)) => {
+                ChannelValue::Rotation(a.slerp(b, t))
+            }
+            (ChannelValue::Normal(a), ChannelValue::Normal(b)) => {
+                ChannelValue::Normal(a.lerp(b, t).normalize())
+            }
+            (ChannelValue::Alpha(a), ChannelValue::Alpha(b)) => ChannelValue::Alpha(lerp(*a, *b)),
+            (ChannelValue::UV1(a), ChannelValue::UV1(b)) => ChannelValue::UV1(a.lerp(b, t)),
+            (ChannelValue::UV2(a), ChannelValue::UV2(b)) => ChannelValue::UV2(a.lerp(b, t)),
+            (ChannelValue::UV3(a), ChannelValue::UV3(b)) => ChannelValue::UV3(a.lerp(b, t)),
+            (ChannelValue::UV4(a), ChannelValue::UV4(b)) => ChannelValue::UV4(a.lerp(b, t)),
+            (ChannelValue::Scale(a), ChannelValue::Scale(b)) => ChannelValue::Scale(lerp(*a, *b)),
+            _ => *self,
+        }
+    }
+}
+
+/// Channel values of a motion at a point in time
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Pose {
+    pub channels: Vec<PoseChannel>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PoseChannel {
+    pub index: u32,
+    pub value: ChannelValue,
+}
+
+impl Pose {
+    pub fn position(&self, index: u32) -> Option<Vector3<f32>> {
+        self.channels.iter().find_map(|c| match c.value {
+            ChannelValue::Position(v) if c.index == index => Some(v),
+            _ => None,
+        })
+    }
+
+    pub fn rotation(&self, index: u32) -> Option<Quaternion> {
+        self.channels.iter().find_map(|c| match c.value {
+            ChannelValue::Rotation(q) if c.index == index => Some(q),
+            _ => None,
+        })
+    }
+
+    pub fn scale(&self, index: u32) -> Option<f32> {
+        self.channels.iter().find_map(|c| match c.value {
+            ChannelValue::Scale(s) if c.index == index => Some(s),
+            _ => None,
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ChannelType {
     None = 1,
@@ -1,4 +1,6 @@
 //! ROSE Online 3D Meshes
+use std::collections::HashMap;
+
 use failure::Error;
 use serde::{Deserialize, Serialize};
 
@@ -63,7 +65,17 @@ impl Mesh {
         (VertexFormat::UV4 as i32 & self.format) != 0
     }
 
+    pub fn read_version5<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.read_legacy(reader, 5)
+    }
+
     pub fn read_version6<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.read_legacy(reader, 6)
+    }
+
+    /// Versions 5 and 6 prefix every element with its index and store counts
+    /// and indices as 32-bit values. Version 5 has no material block.
+    fn read_legacy<R: ReadRoseExt>(&mut self, reader: &mut R, version: u32) -> Result<(), Error> {
         self.format = reader.read_i32()?;
         self.bounding_box.min = reader.read_vector3_f32()?;
         self.bounding_box.max = reader.read_vector3_f32()?;
@@ -160,127 +172,153 @@ impl Mesh {
             self.indices.push(indices_i16);
         }
 
-        let material_count = reader.read_u32()?;
-        for _ in 0..material_count {
-            let _index = reader.read_u32()?;
-            self.materials.push(reader.read_u32()? as i16);
+        if version >= 6 {
+            let material_count = reader.read_u32()?;
+            for _ in 0..material_count {
+                let _index = reader.read_u32()?;
+                self.materials.push(reader.read_u32()? as i16);
+            }
         }
 
         Ok(())
     }
-}
-
-impl RoseFile for Mesh {
-    fn new() -> Mesh {
-        Self::default()
-    }
-
-    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
-        self.identifier = reader.read_cstring()?;
-
-        let version = match self.identifier.as_str() {
-            "ZMS0006" => 6,
-            "ZMS0007" => 7,
-            "ZMS0008" => 8,
-            _ => bail!(format!("Unsupported Mesh version: {}", self.identifier)),
-        };
 
-        if version == 6 {
-            return self.read_version6(reader);
+    /// Write the mesh using the layout of a specific ZMS version (5-8).
+    ///
+    /// Data the target version can't store is dropped: strips and the pool
+    /// type before version 7 and 8 respectively, and materials in version 5.
+    pub fn write_version<W: WriteRoseExt>(
+        &mut self,
+        writer: &mut W,
+        version: u32,
+    ) -> Result<(), Error> {
+        match version {
+            5 | 6 => self.write_legacy(writer, version),
+            7 | 8 => self.write_modern(writer, version),
+            _ => bail!("Unsupported Mesh version: {}", version),
         }
+    }
 
-        self.format = reader.read_i32()?;
-        self.bounding_box.min = reader.read_vector3_f32()?;
-        self.bounding_box.max = reader.read_vector3_f32()?;
+    fn write_legacy<W: WriteRoseExt>(&mut self, writer: &mut W, version: u32) -> Result<(), Error> {
+        writer.write_cstring(&format!("ZMS{:04}", version))?;
+        writer.write_i32(self.format)?;
 
-        let bone_count = reader.read_i16()?;
-        for _ in 0..bone_count {
-            self.bones.push(reader.read_i16()?);
-        }
+        writer.write_vector3_f32(&self.bounding_box.min)?;
+        writer.write_vector3_f32(&self.bounding_box.max)?;
 
-        let vert_count = reader.read_i16()?;
-        for _ in 0..vert_count {
-            self.vertices.push(Vertex::new());
+        writer.write_u32(self.bones.len() as u32)?;
+        for (i, bone) in self.bones.iter().enumerate() {
+            writer.write_u32(i as u32)?;
+            writer.write_u32(*bone as u32)?;
         }
 
-        if self.positions_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].position = reader.read_vector3_f32()?;
-            }
+        writer.write_u32(self.vertices.len() as u32)?;
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            writer.write_u32(i as u32)?;
+            writer.write_vector3_f32(&vertex.position)?;
         }
 
         if self.normals_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].normal = reader.read_vector3_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector3_f32(&vertex.normal)?;
             }
         }
 
         if self.colors_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].color = reader.read_color4()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_color4(&vertex.color)?;
             }
         }
 
         if self.bones_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].bone_weights = reader.read_vector4_f32()?;
-                self.vertices[i].bone_indices = reader.read_vector4_i16()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector4_f32(&vertex.bone_weights)?;
+
+                let b = &vertex.bone_indices;
+                for index in &[b.w, b.x, b.y, b.z] {
+                    writer.write_u32(*index as u32)?;
+                }
             }
         }
 
         if self.tangents_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].tangent = reader.read_vector3_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector3_f32(&vertex.tangent)?;
             }
         }
 
         if self.uv1_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv1 = reader.read_vector2_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector2_f32(&vertex.uv1)?;
             }
         }
 
         if self.uv2_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv2 = reader.read_vector2_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector2_f32(&vertex.uv2)?;
             }
         }
 
         if self.uv3_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv3 = reader.read_vector2_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector2_f32(&vertex.uv3)?;
             }
         }
+
         if self.uv4_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv4 = reader.read_vector2_f32()?;
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_vector2_f32(&vertex.uv4)?;
             }
         }
 
-        let index_count = reader.read_i16()?;
-        for _ in 0..index_count {
-            self.indices.push(reader.read_vector3_i16()?);
-        }
-
-        let material_count = reader.read_i16()?;
-        for _ in 0..material_count {
-            self.materials.push(reader.read_i16()?);
-        }
-
-        let strip_count = reader.read_i16()?;
-        for _ in 0..strip_count {
-            self.strips.push(reader.read_i16()?);
+        writer.write_u32(self.indices.len() as u32)?;
+        for (i, face) in self.indices.iter().enumerate() {
+            writer.write_u32(i as u32)?;
+            writer.write_u32(face.x as u32)?;
+            writer.write_u32(face.y as u32)?;
+            writer.write_u32(face.z as u32)?;
         }
 
-        if version >= 8 {
-            self.pool = reader.read_i16()?;
+        if version >= 6 {
+            writer.write_u32(self.materials.len() as u32)?;
+            for (i, mat) in self.materials.iter().enumerate() {
+                writer.write_u32(i as u32)?;
+                writer.write_u32(*mat as u32)?;
+            }
         }
 
         Ok(())
     }
 
-    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
-        writer.write_cstring("ZMS0008")?;
+    fn write_modern<W: WriteRoseExt>(&mut self, writer: &mut W, version: u32) -> Result<(), Error> {
+        let counts = [
+            ("bones", self.bones.len()),
+            ("vertices", self.vertices.len()),
+            ("triangles", self.indices.len()),
+            ("materials", self.materials.len()),
+            ("strip indices", self.strips.len()),
+        ];
+        for &(name, count) in &counts {
+            if count > i16::MAX as usize {
+                bail!(
+                    "ZMS{:04} supports at most {} {}, the mesh has {}",
+                    version,
+                    i16::MAX,
+                    name,
+                    count
+                );
+            }
+        }
+
+        writer.write_cstring(&format!("ZMS{:04}", version))?;
         writer.write_i32(self.format)?;
 
         writer.write_vector3_f32(&self.bounding_box.min)?;
@@ -364,10 +402,748 @@ impl RoseFile for Mesh {
             writer.write_i16(*strip)?;
         }
 
-        writer.write_i16(self.pool)?;
+        if version >= 8 {
+            writer.write_i16(self.pool)?;
+        }
+
+        Ok(())
+    }
+
+    /// Recalculate the bounding box from the vertex positions
+    pub fn recompute_bounding_box(&mut self) {
+        let mut vertices = self.vertices.iter();
+        let first = match vertices.next() {
+            Some(v) => v.position,
+            None => {
+                self.bounding_box.min = Vector3::<f32>::new();
+                self.bounding_box.max = Vector3::<f32>::new();
+                return;
+            }
+        };
+
+        let (mut min, mut max) = (first, first);
+        for v in vertices {
+            min.x = min.x.min(v.position.x);
+            min.y = min.y.min(v.position.y);
+            min.z = min.z.min(v.position.z);
+            max.x = max.x.max(v.position.x);
+            max.y = max.y.max(v.position.y);
+            max.z = max.z.max(v.position.z);
+        }
+
+        self.bounding_box.min = min;
+        self.bounding_box.max = max;
+    }
+
+    /// Recalculate vertex normals by averaging the area-weighted normals of
+    /// every triangle sharing the vertex
+    pub fn recompute_smooth_normals(&mut self) {
+        let mut normals = vec![Vector3::<f32>::new(); self.vertices.len()];
+        for (i, face) in self.indices.iter().enumerate() {
+            let normal = self.face_normal(i);
+            for &index in &[face.x, face.y, face.z] {
+                if let Some(n) = normals.get_mut(index as usize) {
+                    *n = n.add(&normal);
+                }
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize();
+        }
+        self.format |= VertexFormat::Normal as i32;
+    }
+
+    /// Recalculate vertex normals so each triangle is shaded flat.
+    ///
+    /// Every triangle gets its own copy of its vertices, existing strips are
+    /// cleared as they no longer reference the right vertices. Fails if a
+    /// triangle references a missing vertex.
+    pub fn recompute_flat_normals(&mut self) -> Result<(), Error> {
+        self.check_indices()?;
+
+        let vertex_count = self.indices.len() * 3;
+        if vertex_count > i16::MAX as usize {
+            bail!(
+                "Flat shading needs {} vertices, the maximum is {}",
+                vertex_count,
+                i16::MAX
+            );
+        }
+
+        let mut vertices = Vec::with_capacity(vertex_count);
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (i, face) in self.indices.iter().enumerate() {
+            let normal = self.face_normal(i).normalize();
+            let start = vertices.len() as i16;
+            for &index in &[face.x, face.y, face.z] {
+                let mut vertex = self.vertices[index as usize].clone();
+                vertex.normal = normal;
+                vertices.push(vertex);
+            }
+            indices.push(Vector3 {
+                x: start,
+                y: start + 1,
+                z: start + 2,
+            });
+        }
+
+        self.vertices = vertices;
+        self.indices = indices;
+        self.strips.clear();
+        self.format |= VertexFormat::Normal as i32;
+        Ok(())
+    }
+
+    /// Calculate vertex tangents from the first UV channel. Tangents are made
+    /// orthogonal to the vertex normal so normals should be up to date first.
+    pub fn compute_tangents(&mut self) -> Result<(), Error> {
+        if !self.uv1_enabled() {
+            bail!("Tangents require the UV1 channel");
+        }
+
+        let mut tangents = vec![Vector3::<f32>::new(); self.vertices.len()];
+        for face in &self.indices {
+            let (i0, i1, i2) = (face.x as usize, face.y as usize, face.z as usize);
+            let (v0, v1, v2) = match (
+                self.vertices.get(i0),
+                self.vertices.get(i1),
+                self.vertices.get(i2),
+            ) {
+                (Some(v0), Some(v1), Some(v2)) => (v0, v1, v2),
+                _ => continue,
+            };
+
+            let e1 = v1.position.sub(&v0.position);
+            let e2 = v2.position.sub(&v0.position);
+            let (du1, dv1) = (v1.uv1.x - v0.uv1.x, v1.uv1.y - v0.uv1.y);
+            let (du2, dv2) = (v2.uv1.x - v0.uv1.x, v2.uv1.y - v0.uv1.y);
+
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+
+            let tangent = e1.scale(dv2).sub(&e2.scale(dv1)).scale(1.0 / det);
+            for &i in &[i0, i1, i2] {
+                tangents[i] = tangents[i].add(&tangent);
+            }
+        }
+
+        for (vertex, tangent) in self.vertices.iter_mut().zip(tangents) {
+            // Gram-Schmidt against the normal
+            let n = vertex.normal;
+            vertex.tangent = tangent.sub(&n.scale(n.dot(&tangent))).normalize();
+        }
+        self.format |= VertexFormat::Tangent as i32;
+        Ok(())
+    }
+
+    /// Merge vertices whose attributes are all within `epsilon` of each other.
+    ///
+    /// Returns the number of vertices removed.
+    pub fn weld_vertices(&mut self, epsilon: f32) -> usize {
+        let cell_size = if epsilon > 0.0 { epsilon } else { 1e-6 };
+        let cell = |p: &Vector3<f32>| {
+            (
+                (p.x / cell_size).floor() as i64,
+                (p.y / cell_size).floor() as i64,
+                (p.z / cell_size).floor() as i64,
+            )
+        };
+
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        let mut vertices: Vec<Vertex> = Vec::new();
+
+        for vertex in &self.vertices {
+            let (cx, cy, cz) = cell(&vertex.position);
+
+            let mut found = None;
+            'search: for x in cx - 1..=cx + 1 {
+                for y in cy - 1..=cy + 1 {
+                    for z in cz - 1..=cz + 1 {
+                        if let Some(candidates) = grid.get(&(x, y, z)) {
+                            for &c in candidates {
+                                if vertices[c].approx_eq(vertex, epsilon) {
+                                    found = Some(c);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            let index = match found {
+                Some(index) => index,
+                None => {
+                    vertices.push(vertex.clone());
+                    grid.entry((cx, cy, cz))
+                        .or_default()
+                        .push(vertices.len() - 1);
+                    vertices.len() - 1
+                }
+            };
+            remap.push(index as i16);
+        }
+
+        let removed = self.vertices.len() - vertices.len();
+        let lookup = |i: i16| remap.get(i as usize).cloned().unwrap_or(i);
+        for face in &mut self.indices {
+            face.x = lookup(face.x);
+            face.y = lookup(face.y);
+            face.z = lookup(face.z);
+        }
+        for strip in &mut self.strips {
+            *strip = lookup(*strip);
+        }
+
+        self.vertices = vertices;
+        removed
+    }
+
+    /// Remove triangles that reuse a vertex or have no area.
+    ///
+    /// The per-material face counts are updated to match when they cover all
+    /// triangles. Returns the number of triangles removed.
+    pub fn remove_degenerate_triangles(&mut self) -> usize {
+        let keep: Vec<bool> = (0..self.indices.len())
+            .map(|i| {
+                let face = &self.indices[i];
+                face.x != face.y
+                    && face.y != face.z
+                    && face.x != face.z
+                    && self.face_normal(i).length() > 0.0
+            })
+            .collect();
+
+        let material_faces: i32 = self.materials.iter().map(|&m| m as i32).sum();
+        if material_faces as usize == self.indices.len() {
+            let mut start = 0;
+            for count in &mut self.materials {
+                let end = start + *count as usize;
+                *count = keep[start..end].iter().filter(|&&k| k).count() as i16;
+                start = end;
+            }
+        }
+
+        let before = self.indices.len();
+        let mut keep = keep.into_iter();
+        self.indices.retain(|_| keep.next().unwrap_or(true));
+        before - self.indices.len()
+    }
+
+    /// Check the mesh for data the client can't handle
+    pub fn validate(&self) -> Result<(), Error> {
+        self.check_indices()?;
+        self.check_strip_indices()?;
+
+        if self.bones_enabled() {
+            let bone_count = self.bones.len();
+            for (i, vertex) in self.vertices.iter().enumerate() {
+                let b = &vertex.bone_indices;
+                for &index in &[b.w, b.x, b.y, b.z] {
+                    if index < 0 || index as usize >= bone_count {
+                        bail!(
+                            "Vertex {} references bone {} but the mesh has {} bones",
+                            i,
+                            index,
+                            bone_count
+                        );
+                    }
+                }
+
+                let w = &vertex.bone_weights;
+                let sum = w.w + w.x + w.y + w.z;
+                if (sum - 1.0).abs() > 1e-3 {
+                    bail!("Vertex {} bone weights sum to {}, expected 1.0", i, sum);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that every triangle references an existing vertex
+    fn check_indices(&self) -> Result<(), Error> {
+        let vertex_count = self.vertices.len();
+        for (i, face) in self.indices.iter().enumerate() {
+            for &index in &[face.x, face.y, face.z] {
+                if index < 0 || index as usize >= vertex_count {
+                    bail!(
+                        "Triangle {} references vertex {} but the mesh has {} vertices",
+                        i,
+                        index,
+                        vertex_count
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
 
+    /// Check that every strip index references an existing vertex
+    fn check_strip_indices(&self) -> Result<(), Error> {
+        let vertex_count = self.vertices.len();
+        for (i, &index) in self.strips.iter().enumerate() {
+            if index < 0 || index as usize >= vertex_count {
+                bail!(
+                    "Strip index {} references vertex {} but the mesh has {} vertices",
+                    i,
+                    index,
+                    vertex_count
+                );
+            }
+        }
         Ok(())
     }
+
+    /// Expand `strips` into a triangle list, degenerate triangles used to join
+    /// strips together are skipped
+    pub fn strip_triangles(&self) -> Vec<Vector3<i16>> {
+        let mut triangles = Vec::new();
+        for (k, window) in self.strips.windows(3).enumerate() {
+            let (a, b, c) = (window[0], window[1], window[2]);
+            if a == b || b == c || a == c {
+                continue;
+            }
+
+            // Every other triangle in a strip has reversed winding
+            if k % 2 == 0 {
+                triangles.push(Vector3 { x: a, y: b, z: c });
+            } else {
+                triangles.push(Vector3 { x: b, y: a, z: c });
+            }
+        }
+        triangles
+    }
+
+    /// Replace `indices` with the triangles from `strips`
+    pub fn load_strips(&mut self) {
+        self.indices = self.strip_triangles();
+    }
+
+    /// Build a single triangle strip from `indices` and store it in `strips`.
+    ///
+    /// Strips are grown greedily across shared edges and joined with
+    /// degenerate triangles.
+    pub fn generate_strips(&mut self) {
+        let faces: Vec<[i16; 3]> = self.indices.iter().map(|f| [f.x, f.y, f.z]).collect();
+
+        let mut edges: HashMap<(i16, i16), Vec<usize>> = HashMap::new();
+        for (i, f) in faces.iter().enumerate() {
+            for e in 0..3 {
+                edges.entry((f[e], f[(e + 1) % 3])).or_default().push(i);
+            }
+        }
+
+        let mut used = vec![false; faces.len()];
+        let mut strips: Vec<i16> = Vec::new();
+
+        for start in 0..faces.len() {
+            if used[start] {
+                continue;
+            }
+
+            // Try each rotation of the starting triangle and keep the longest strip
+            let mut best: Option<(Vec<i16>, Vec<usize>)> = None;
+            for rotation in 0..3 {
+                let candidate = grow_strip(&faces, &edges, &used, start, rotation);
+                if best.as_ref().is_none_or(|b| candidate.1.len() > b.1.len()) {
+                    best = Some(candidate);
+                }
+            }
+
+            let (strip, strip_faces) = best.unwrap_or_default();
+            for face in strip_faces {
+                used[face] = true;
+            }
+
+            if let (Some(&last), Some(&first)) = (strips.last(), strip.first()) {
+                let odd = strips.len() % 2 == 1;
+                strips.push(last);
+                strips.push(first);
+                if odd {
+                    strips.push(first);
+                }
+            }
+            strips.extend(strip);
+        }
+
+        self.strips = strips;
+    }
+
+    /// Reorder triangles and vertices for the post-transform vertex cache.
+    ///
+    /// Triangles are reordered using Tom Forsyth's linear-speed algorithm,
+    /// per material when the material face counts cover every triangle, then
+    /// vertices are sorted by first use. Fails if a triangle or strip
+    /// references a missing vertex.
+    pub fn optimize_vertex_cache(&mut self) -> Result<(), Error> {
+        const CACHE_SIZE: usize = 32;
+
+        self.check_indices()?;
+        self.check_strip_indices()?;
+
+        let material_faces: i32 = self.materials.iter().map(|&m| m as i32).sum();
+        let mut ranges = Vec::new();
+        if !self.materials.is_empty() && material_faces as usize == self.indices.len() {
+            let mut start = 0;
+            for &count in &self.materials {
+                ranges.push(start..start + count as usize);
+                start += count as usize;
+            }
+        } else {
+            ranges.push(0..self.indices.len());
+        }
+
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for range in ranges {
+            let faces = &self.indices[range];
+            for i in forsyth_order(faces, self.vertices.len(), CACHE_SIZE) {
+                indices.push(faces[i]);
+            }
+        }
+
+        // Sort vertices in the order they are first referenced
+        let mut remap = vec![-1i16; self.vertices.len()];
+        let mut order = Vec::with_capacity(self.vertices.len());
+        for face in &mut indices {
+            for index in [&mut face.x, &mut face.y, &mut face.z].iter_mut() {
+                let old = **index as usize;
+                if remap[old] < 0 {
+                    remap[old] = order.len() as i16;
+                    order.push(old);
+                }
+                **index = remap[old];
+            }
+        }
+        for (old, new) in remap.iter_mut().enumerate() {
+            if *new < 0 {
+                *new = order.len() as i16;
+                order.push(old);
+            }
+        }
+
+        let mut vertices: Vec<Option<Vertex>> = self.vertices.drain(..).map(Some).collect();
+        self.vertices = order.iter().filter_map(|&i| vertices[i].take()).collect();
+        self.indices = indices;
+        for strip in &mut self.strips {
+            *strip = remap[*strip as usize];
+        }
+
+        Ok(())
+    }
+
+    /// Average cache miss ratio, the number of vertices transformed per
+    /// triangle with a FIFO cache of `cache_size` entries
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        if self.indices.is_empty() {
+            return 0.0;
+        }
+
+        let mut cache = std::collections::VecDeque::with_capacity(cache_size);
+        let mut misses = 0;
+        for face in &self.indices {
+            for &index in &[face.x, face.y, face.z] {
+                if !cache.contains(&index) {
+                    misses += 1;
+                    if cache.len() == cache_size {
+                        cache.pop_front();
+                    }
+                    cache.push_back(index);
+                }
+            }
+        }
+        misses as f32 / self.indices.len() as f32
+    }
+
+    /// Non-normalized triangle normal, its length is twice the triangle area
+    fn face_normal(&self, face: usize) -> Vector3<f32> {
+        let face = &self.indices[face];
+        let position = |i: i16| {
+            self.vertices
+                .get(i as usize)
+                .map(|v| v.position)
+                .unwrap_or_default()
+        };
+
+        let p0 = position(face.x);
+        let e1 = position(face.y).sub(&p0);
+        let e2 = position(face.z).sub(&p0);
+        e1.cross(&e2)
+    }
+}
+
+/// Grow a strip from `start`, beginning with the triangle rotated by `rotation`.
+/// Returns the strip indices and the faces it covers.
+fn grow_strip(
+    faces: &[[i16; 3]],
+    edges: &HashMap<(i16, i16), Vec<usize>>,
+    used: &[bool],
+    start: usize,
+    rotation: usize,
+) -> (Vec<i16>, Vec<usize>) {
+    let f = faces[start];
+    let mut strip = vec![f[rotation], f[(rotation + 1) % 3], f[(rotation + 2) % 3]];
+    let mut strip_faces = vec![start];
+
+    loop {
+        let n = strip.len();
+        let (p, q) = (strip[n - 2], strip[n - 1]);
+
+        // The next triangle must share the last edge with matching winding
+        let edge = if (n - 2) % 2 == 0 { (p, q) } else { (q, p) };
+        let next = edges.get(&edge).and_then(|candidates| {
+            candidates
+                .iter()
+                .find(|&&c| !used[c] && !strip_faces.contains(&c))
+        });
+
+        let next = match next {
+            Some(&next) => next,
+            None => break,
+        };
+
+        let third = faces[next]
+            .iter()
+            .cloned()
+            .find(|&v| v != p && v != q)
+            .unwrap_or(q);
+        strip.push(third);
+        strip_faces.push(next);
+    }
+
+    (strip, strip_faces)
+}
+
+/// Triangle order for `faces` using Tom Forsyth's vertex cache optimization
+fn forsyth_order(faces: &[Vector3<i16>], vertex_count: usize, cache_size: usize) -> Vec<usize> {
+    let vertex_score = |cache_position: Option<usize>, valence: usize| -> f32 {
+        if valence == 0 {
+            return -1.0;
+        }
+
+        let mut score = match cache_position {
+            Some(p) if p < 3 => 0.75,
+            Some(p) => {
+                let scale = 1.0 / (cache_size - 3) as f32;
+                (1.0 - (p - 3) as f32 * scale).powf(1.5)
+            }
+            None => 0.0,
+        };
+        score += 2.0 * (valence as f32).powf(-0.5);
+        score
+    };
+
+    let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (i, f) in faces.iter().enumerate() {
+        for &v in &[f.x, f.y, f.z] {
+            if let Some(list) = vertex_faces.get_mut(v as usize) {
+                list.push(i);
+            }
+        }
+    }
+
+    let mut valence: Vec<usize> = vertex_faces.iter().map(|f| f.len()).collect();
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = (0..vertex_count)
+        .map(|v| vertex_score(None, valence[v]))
+        .collect();
+
+    let face_vertices = |f: usize| {
+        let f = &faces[f];
+        [f.x as usize, f.y as usize, f.z as usize]
+    };
+    let face_score = |scores: &[f32], f: usize| -> f32 {
+        face_vertices(f)
+            .iter()
+            .map(|&v| scores.get(v).cloned().unwrap_or(0.0))
+            .sum()
+    };
+
+    let mut emitted = vec![false; faces.len()];
+    let mut order = Vec::with_capacity(faces.len());
+    let mut cache: Vec<usize> = Vec::new();
+
+    while order.len() < faces.len() {
+        // Best face touching the cache, falling back to a full scan
+        let mut best = None;
+        let mut best_score = -1.0;
+        for &v in &cache {
+            for &f in &vertex_faces[v] {
+                if !emitted[f] {
+                    let score = face_score(&scores, f);
+                    if score > best_score {
+                        best = Some(f);
+                        best_score = score;
+                    }
+                }
+            }
+        }
+        if best.is_none() {
+            for (f, _) in emitted.iter().enumerate().filter(|(_, &e)| !e) {
+                let score = face_score(&scores, f);
+                if score > best_score {
+                    best = Some(f);
+                    best_score = score;
+                }
+            }
+        }
+
+        let face = match best {
+            Some(face) => face,
+            None => break,
+        };
+        emitted[face] = true;
+        order.push(face);
+
+        let vertices = face_vertices(face);
+        for &v in &vertices {
+            if v < vertex_count {
+                valence[v] = valence[v].saturating_sub(1);
+            }
+        }
+
+        // Move the face's vertices to the front of the cache
+        let mut new_cache: Vec<usize> = vertices
+            .iter()
+            .cloned()
+            .filter(|&v| v < vertex_count)
+            .collect();
+        new_cache.dedup();
+        new_cache.extend(cache.iter().cloned().filter(|v| !vertices.contains(v)));
+
+        for &v in &cache {
+            cache_position[v] = None;
+        }
+        for (i, &v) in new_cache.iter().enumerate() {
+            cache_position[v] = if i < cache_size { Some(i) } else { None };
+        }
+        for &v in &new_cache {
+            scores[v] = vertex_score(cache_position[v], valence[v]);
+        }
+        new_cache.truncate(cache_size);
+        cache = new_cache;
+    }
+
+    order
+}
+
+impl RoseFile for Mesh {
+    fn new() -> Mesh {
+        Self::default()
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.identifier = reader.read_cstring()?;
+
+        let version = match self.identifier.as_str() {
+            "ZMS0005" => 5,
+            "ZMS0006" => 6,
+            "ZMS0007" => 7,
+            "ZMS0008" => 8,
+            _ => bail!(format!("Unsupported Mesh version: {}", self.identifier)),
+        };
+
+        if version <= 6 {
+            return self.read_legacy(reader, version);
+        }
+
+        self.format = reader.read_i32()?;
+        self.bounding_box.min = reader.read_vector3_f32()?;
+        self.bounding_box.max = reader.read_vector3_f32()?;
+
+        let bone_count = reader.read_i16()?;
+        for _ in 0..bone_count {
+            self.bones.push(reader.read_i16()?);
+        }
+
+        let vert_count = reader.read_i16()?;
+        for _ in 0..vert_count {
+            self.vertices.push(Vertex::new());
+        }
+
+        if self.positions_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].position = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.normals_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].normal = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.colors_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].color = reader.read_color4()?;
+            }
+        }
+
+        if self.bones_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].bone_weights = reader.read_vector4_f32()?;
+                self.vertices[i].bone_indices = reader.read_vector4_i16()?;
+            }
+        }
+
+        if self.tangents_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].tangent = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.uv1_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].uv1 = reader.read_vector2_f32()?;
+            }
+        }
+
+        if self.uv2_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].uv2 = reader.read_vector2_f32()?;
+            }
+        }
+
+        if self.uv3_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].uv3 = reader.read_vector2_f32()?;
+            }
+        }
+        if self.uv4_enabled() {
+            for i in 0..vert_count as usize {
+                self.vertices[i].uv4 = reader.read_vector2_f32()?;
+            }
+        }
+
+        let index_count = reader.read_i16()?;
+        for _ in 0..index_count {
+            self.indices.push(reader.read_vector3_i16()?);
+        }
+
+        let material_count = reader.read_i16()?;
+        for _ in 0..material_count {
+            self.materials.push(reader.read_i16()?);
+        }
+
+        let strip_count = reader.read_i16()?;
+        for _ in 0..strip_count {
+            self.strips.push(reader.read_i16()?);
+        }
+
+        if version >= 8 {
+            self.pool = reader.read_i16()?;
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        self.write_version(writer, 8)
+    }
 }
 
 impl Default for Mesh {
@@ -390,7 +1166,7 @@ impl Default for Mesh {
 }
 
 /// Mesh Vertex
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Vertex {
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>,
@@ -408,6 +1184,30 @@ impl Vertex {
     pub fn new() -> Vertex {
         Self::default()
     }
+
+    /// Compare every attribute within `epsilon`
+    pub fn approx_eq(&self, other: &Vertex, epsilon: f32) -> bool {
+        let eq = |a: f32, b: f32| (a - b).abs() <= epsilon;
+        let eq2 = |a: &Vector2<f32>, b: &Vector2<f32>| eq(a.x, b.x) && eq(a.y, b.y);
+        let eq3 = |a: &Vector3<f32>, b: &Vector3<f32>| eq(a.x, b.x) && eq(a.y, b.y) && eq(a.z, b.z);
+
+        eq3(&self.position, &other.position)
+            && eq3(&self.normal, &other.normal)
+            && eq3(&self.tangent, &other.tangent)
+            && eq(self.color.r, other.color.r)
+            && eq(self.color.g, other.color.g)
+            && eq(self.color.b, other.color.b)
+            && eq(self.color.a, other.color.a)
+            && eq(self.bone_weights.w, other.bone_weights.w)
+            && eq(self.bone_weights.x, other.bone_weights.x)
+            && eq(self.bone_weights.y, other.bone_weights.y)
+            && eq(self.bone_weights.z, other.bone_weights.z)
+            && self.bone_indices == other.bone_indices
+            && eq2(&self.uv1, &other.uv1)
+            && eq2(&self.uv2, &other.uv2)
+            && eq2(&self.uv3, &other.uv3)
+            && eq2(&self.uv4, &other.uv4)
+    }
 }
 
 /// Mesh Vertex Flags
@@ -1,6 +1,5 @@
 //! ROSE Scene
 use std::convert::{Into, TryFrom, TryInto};
-use std::io::SeekFrom;
 use std::path::PathBuf;
 
 use failure::Error;
@@ -13,7 +12,7 @@ use crate::utils::{BoundingBox, BoundingCylinder, Color3, Quaternion, Vector3};
 pub type ZSC = Scene;
 
 /// Scene
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Scene {
     pub meshes: Vec<PathBuf>,
     pub materials: Vec<SceneMaterial>,
@@ -21,6 +20,236 @@ pub struct Scene {
     pub objects: Vec<SceneObject>,
 }
 
+impl Scene {
+    /// Add a mesh, returning its index. Paths already in the scene are reused.
+    pub fn add_mesh(&mut self, path: PathBuf) -> Result<u16, Error> {
+        if let Some(i) = self.meshes.iter().position(|m| same_path(m, &path)) {
+            return Ok(i as u16);
+        }
+        push_entry(&mut self.meshes, path, "meshes")
+    }
+
+    /// Add a material, returning its index. Identical materials are reused.
+    pub fn add_material(&mut self, material: SceneMaterial) -> Result<u16, Error> {
+        if let Some(i) = self
+            .materials
+            .iter()
+            .position(|m| same_material(m, &material))
+        {
+            return Ok(i as u16);
+        }
+        push_entry(&mut self.materials, material, "materials")
+    }
+
+    /// Add an effect, returning its index. Paths already in the scene are reused.
+    pub fn add_effect(&mut self, path: PathBuf) -> Result<u16, Error> {
+        if let Some(i) = self.effects.iter().position(|e| same_path(e, &path)) {
+            return Ok(i as u16);
+        }
+        push_entry(&mut self.effects, path, "effects")
+    }
+
+    /// Remove an unused mesh, later meshes are renumbered
+    pub fn remove_mesh(&mut self, index: u16) -> Result<PathBuf, Error> {
+        if index as usize >= self.meshes.len() {
+            bail!("Mesh {} does not exist", index);
+        }
+        if let Some(object) = self
+            .objects
+            .iter()
+            .position(|o| o.parts.iter().any(|p| p.mesh_id == index))
+        {
+            bail!("Mesh {} is used by object {}", index, object);
+        }
+
+        let path = self.meshes[index as usize].clone();
+        let mut keep = vec![true; self.meshes.len()];
+        keep[index as usize] = false;
+        self.retain_meshes(&keep);
+        Ok(path)
+    }
+
+    /// Remove an unused material, later materials are renumbered
+    pub fn remove_material(&mut self, index: u16) -> Result<SceneMaterial, Error> {
+        if index as usize >= self.materials.len() {
+            bail!("Material {} does not exist", index);
+        }
+        if let Some(object) = self
+            .objects
+            .iter()
+            .position(|o| o.parts.iter().any(|p| p.material_id == index))
+        {
+            bail!("Material {} is used by object {}", index, object);
+        }
+
+        let material = self.materials[index as usize].clone();
+        let mut keep = vec![true; self.materials.len()];
+        keep[index as usize] = false;
+        self.retain_materials(&keep);
+        Ok(material)
+    }
+
+    /// Remove an unused effect, later effects are renumbered
+    pub fn remove_effect(&mut self, index: u16) -> Result<PathBuf, Error> {
+        if index as usize >= self.effects.len() {
+            bail!("Effect {} does not exist", index);
+        }
+        if let Some(object) = self
+            .objects
+            .iter()
+            .position(|o| o.effects.iter().any(|e| e.effect_id == index))
+        {
+            bail!("Effect {} is used by object {}", index, object);
+        }
+
+        let path = self.effects[index as usize].clone();
+        let mut keep = vec![true; self.effects.len()];
+        keep[index as usize] = false;
+        self.retain_effects(&keep);
+        Ok(path)
+    }
+
+    /// Remove meshes, materials and effects that no object references.
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune(&mut self) -> usize {
+        let mut meshes = vec![false; self.meshes.len()];
+        let mut materials = vec![false; self.materials.len()];
+        let mut effects = vec![false; self.effects.len()];
+
+        for object in &self.objects {
+            for part in &object.parts {
+                if let Some(used) = meshes.get_mut(part.mesh_id as usize) {
+                    *used = true;
+                }
+                if let Some(used) = materials.get_mut(part.material_id as usize) {
+                    *used = true;
+                }
+            }
+            for effect in &object.effects {
+                if let Some(used) = effects.get_mut(effect.effect_id as usize) {
+                    *used = true;
+                }
+            }
+        }
+
+        let before = self.meshes.len() + self.materials.len() + self.effects.len();
+        self.retain_meshes(&meshes);
+        self.retain_materials(&materials);
+        self.retain_effects(&effects);
+        before - (self.meshes.len() + self.materials.len() + self.effects.len())
+    }
+
+    /// Append the objects of `other`, reusing meshes and effects with the same
+    /// path and identical materials.
+    ///
+    /// Returns the index of the first appended object.
+    pub fn merge(&mut self, other: &Scene) -> Result<usize, Error> {
+        let mut scene = self.clone();
+
+        let meshes = other
+            .meshes
+            .iter()
+            .map(|m| scene.add_mesh(m.clone()))
+            .collect::<Result<Vec<u16>, Error>>()?;
+        let materials = other
+            .materials
+            .iter()
+            .map(|m| scene.add_material(m.clone()))
+            .collect::<Result<Vec<u16>, Error>>()?;
+        let effects = other
+            .effects
+            .iter()
+            .map(|e| scene.add_effect(e.clone()))
+            .collect::<Result<Vec<u16>, Error>>()?;
+
+        let first = scene.objects.len();
+        if first + other.objects.len() > u16::MAX as usize {
+            bail!("Scene can't have more than {} objects", u16::MAX);
+        }
+
+        for object in &other.objects {
+            let mut object = object.clone();
+            for part in &mut object.parts {
+                part.mesh_id = remap(&meshes, part.mesh_id);
+                part.material_id = remap(&materials, part.material_id);
+            }
+            for effect in &mut object.effects {
+                effect.effect_id = remap(&effects, effect.effect_id);
+            }
+            scene.objects.push(object);
+        }
+
+        *self = scene;
+        Ok(first)
+    }
+
+    fn retain_meshes(&mut self, keep: &[bool]) {
+        let map = retain(&mut self.meshes, keep);
+        for part in self.objects.iter_mut().flat_map(|o| o.parts.iter_mut()) {
+            part.mesh_id = remap(&map, part.mesh_id);
+        }
+    }
+
+    fn retain_materials(&mut self, keep: &[bool]) {
+        let map = retain(&mut self.materials, keep);
+        for part in self.objects.iter_mut().flat_map(|o| o.parts.iter_mut()) {
+            part.material_id = remap(&map, part.material_id);
+        }
+    }
+
+    fn retain_effects(&mut self, keep: &[bool]) {
+        let map = retain(&mut self.effects, keep);
+        for effect in self.objects.iter_mut().flat_map(|o| o.effects.iter_mut()) {
+            effect.effect_id = remap(&map, effect.effect_id);
+        }
+    }
+}
+
+/// Paths are compared the way the client looks them up: case-insensitive and
+/// with either separator
+fn same_path(a: &PathBuf, b: &PathBuf) -> bool {
+    let normalize = |p: &PathBuf| p.to_string_lossy().replace('\\', "/").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+fn same_material(a: &SceneMaterial, b: &SceneMaterial) -> bool {
+    same_path(&a.path, &b.path)
+        && SceneMaterial {
+            path: b.path.clone(),
+            ..a.clone()
+        } == *b
+}
+
+fn push_entry<T>(entries: &mut Vec<T>, entry: T, name: &str) -> Result<u16, Error> {
+    if entries.len() >= u16::MAX as usize {
+        bail!("Scene can't have more than {} {}", u16::MAX, name);
+    }
+    entries.push(entry);
+    Ok((entries.len() - 1) as u16)
+}
+
+/// Keep the entries marked in `keep`, returning the new index of every old one
+fn retain<T>(entries: &mut Vec<T>, keep: &[bool]) -> Vec<u16> {
+    let mut map = Vec::with_capacity(keep.len());
+    let mut next = 0;
+    for &k in keep {
+        map.push(next);
+        if k {
+            next += 1;
+        }
+    }
+
+    let mut keep = keep.iter();
+    entries.retain(|_| *keep.next().unwrap_or(&true));
+    map
+}
+
+/// New index for `id`, ids outside the map are left alone
+fn remap(map: &[u16], id: u16) -> u16 {
+    map.get(id as usize).cloned().unwrap_or(id)
+}
+
 impl RoseFile for Scene {
     fn new() -> Scene {
         Self::default()
@@ -76,11 +305,21 @@ impl RoseFile for Scene {
                 part.material_id = reader.read_u16()?;
 
                 loop {
-                    let flag = SceneObjectProperty::try_from(reader.read_u8()?)?;
-                    if flag == SceneObjectProperty::None {
+                    let id = reader.read_u8()?;
+                    if id == SceneObjectProperty::None.into() {
                         break;
                     }
                     let size = reader.read_u8()?;
+                    part.property_order.push(id);
+
+                    let flag = match SceneObjectProperty::try_from(id) {
+                        Ok(flag) => flag,
+                        Err(_) => {
+                            part.unknown_properties
+                                .push(SceneRawProperty::read(reader, id, size)?);
+                            continue;
+                        }
+                    };
 
                     match flag {
                         SceneObjectProperty::None => break,
@@ -97,8 +336,9 @@ impl RoseFile for Scene {
                         SceneObjectProperty::BoneIndex => part.bone_index = reader.read_i16()?,
                         SceneObjectProperty::DummyIndex => part.dummy_index = reader.read_i16()?,
                         SceneObjectProperty::Parent => part.parent = reader.read_u16()?,
-                        //SceneObjectProperty::Collision => part.collision = SceneCollisionType::try_from(reader.read_u16()?)?,
-                        SceneObjectProperty::Collision => part.collision = reader.read_u16()?,
+                        SceneObjectProperty::Collision => {
+                            part.collision = SceneCollision::from(reader.read_u16()?)
+                        }
                         SceneObjectProperty::AnimationPath => {
                             part.animation_path = PathBuf::from(reader.read_string(size as u64)?)
                         }
@@ -107,7 +347,7 @@ impl RoseFile for Scene {
                             part.use_lightmap = reader.read_bool16()?
                         }
                         SceneObjectProperty::Animation => {
-                            bail!("Animation scene object property found but no handler.")
+                            part.animation = Some(SceneRawProperty::read(reader, id, size)?.data)
                         }
                     }
                 }
@@ -123,27 +363,39 @@ impl RoseFile for Scene {
                 object_effect.effect_type = SceneEffectType::try_from(reader.read_u16()?)?;
 
                 loop {
-                    let flag = SceneObjectProperty::try_from(reader.read_u8()?)?;
-                    if flag == SceneObjectProperty::None {
+                    let id = reader.read_u8()?;
+                    if id == SceneObjectProperty::None.into() {
                         break;
                     }
                     let size = reader.read_u8()?;
+                    object_effect.property_order.push(id);
 
-                    match flag {
-                        SceneObjectProperty::None => break,
-                        SceneObjectProperty::Position => {
+                    // Part-only properties are kept as they are
+                    match SceneObjectProperty::try_from(id) {
+                        Ok(SceneObjectProperty::Position) => {
                             object_effect.position = reader.read_vector3_f32()?
                         }
-                        SceneObjectProperty::Rotation => {
+                        Ok(SceneObjectProperty::Rotation) => {
                             object_effect.rotation = reader.read_quaternion_wxyz()?
                         }
-                        SceneObjectProperty::Scale => {
+                        Ok(SceneObjectProperty::Scale) => {
                             object_effect.scale = reader.read_vector3_f32()?
                         }
-                        SceneObjectProperty::Parent => object_effect.parent = reader.read_u16()?,
-                        _ => {
-                            reader.seek(SeekFrom::Current(size as i64))?;
+                        Ok(SceneObjectProperty::AxisRotation) => {
+                            object_effect.axis_rotation = reader.read_quaternion_wxyz()?
                         }
+                        Ok(SceneObjectProperty::BoneIndex) => {
+                            object_effect.bone_index = reader.read_i16()?
+                        }
+                        Ok(SceneObjectProperty::DummyIndex) => {
+                            object_effect.dummy_index = reader.read_i16()?
+                        }
+                        Ok(SceneObjectProperty::Parent) => {
+                            object_effect.parent = reader.read_u16()?
+                        }
+                        _ => object_effect
+                            .unknown_properties
+                            .push(SceneRawProperty::read(reader, id, size)?),
                     }
                 }
 
@@ -201,50 +453,69 @@ impl RoseFile for Scene {
                 writer.write_u16(part.mesh_id)?;
                 writer.write_u16(part.material_id)?;
 
-                writer.write_u8(SceneObjectProperty::Position.into())?;
-                writer.write_u8(12)?;
-                writer.write_vector3_f32(&part.position)?;
-
-                writer.write_u8(SceneObjectProperty::Rotation.into())?;
-                writer.write_u8(16)?;
-                writer.write_quaternion_wxyz(&part.rotation)?;
-
-                writer.write_u8(SceneObjectProperty::Scale.into())?;
-                writer.write_u8(12)?;
-                writer.write_vector3_f32(&part.scale)?;
-
-                writer.write_u8(SceneObjectProperty::AxisRotation.into())?;
-                writer.write_u8(16)?;
-                writer.write_quaternion_wxyz(&part.axis_rotation)?;
-
-                writer.write_u8(SceneObjectProperty::BoneIndex.into())?;
-                writer.write_u8(2)?;
-                writer.write_i16(part.bone_index)?;
-
-                writer.write_u8(SceneObjectProperty::DummyIndex.into())?;
-                writer.write_u8(2)?;
-                writer.write_i16(part.dummy_index)?;
-
-                writer.write_u8(SceneObjectProperty::Parent.into())?;
-                writer.write_u8(2)?;
-                writer.write_u16(part.parent)?;
-
-                writer.write_u8(SceneObjectProperty::Collision.into())?;
-                writer.write_u8(2)?;
-                writer.write_u16(part.collision)?;
-
-                let path = part.animation_path.to_str().unwrap();
-                writer.write_u8(SceneObjectProperty::AnimationPath.into())?;
-                writer.write_u8(path.len() as u8)?;
-                writer.write_string(&path, path.len() as i32)?;
-
-                writer.write_u8(SceneObjectProperty::Range.into())?;
-                writer.write_u8(2)?;
-                writer.write_u16(part.range)?;
-
-                writer.write_u8(SceneObjectProperty::UseLightmap.into())?;
-                writer.write_u8(2)?;
-                writer.write_bool16(part.use_lightmap)?;
+                let mut unknown = part.unknown_properties.iter();
+                for id in part.properties() {
+                    match SceneObjectProperty::try_from(id) {
+                        Ok(SceneObjectProperty::Position) => {
+                            write_property_header(writer, id, 12)?;
+                            writer.write_vector3_f32(&part.position)?;
+                        }
+                        Ok(SceneObjectProperty::Rotation) => {
+                            write_property_header(writer, id, 16)?;
+                            writer.write_quaternion_wxyz(&part.rotation)?;
+                        }
+                        Ok(SceneObjectProperty::Scale) => {
+                            write_property_header(writer, id, 12)?;
+                            writer.write_vector3_f32(&part.scale)?;
+                        }
+                        Ok(SceneObjectProperty::AxisRotation) => {
+                            write_property_header(writer, id, 16)?;
+                            writer.write_quaternion_wxyz(&part.axis_rotation)?;
+                        }
+                        Ok(SceneObjectProperty::BoneIndex) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_i16(part.bone_index)?;
+                        }
+                        Ok(SceneObjectProperty::DummyIndex) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_i16(part.dummy_index)?;
+                        }
+                        Ok(SceneObjectProperty::Parent) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_u16(part.parent)?;
+                        }
+                        Ok(SceneObjectProperty::Collision) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_u16(part.collision.into())?;
+                        }
+                        Ok(SceneObjectProperty::AnimationPath) => {
+                            let path = part.animation_path.to_str().unwrap();
+                            if path.len() > u8::MAX as usize {
+                                bail!("Animation path is too long: {}", path);
+                            }
+                            write_property_header(writer, id, path.len())?;
+                            writer.write_string(&path, path.len() as i32)?;
+                        }
+                        Ok(SceneObjectProperty::Range) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_u16(part.range)?;
+                        }
+                        Ok(SceneObjectProperty::UseLightmap) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_bool16(part.use_lightmap)?;
+                        }
+                        Ok(SceneObjectProperty::Animation) => {
+                            let data = part.animation.as_ref().map_or(&[][..], |d| &d[..]);
+                            write_property_header(writer, id, data.len())?;
+                            writer.write_all(data)?;
+                        }
+                        Ok(SceneObjectProperty::None) | Err(_) => {
+                            if let Some(property) = unknown.next() {
+                                property.write(writer)?;
+                            }
+                        }
+                    }
+                }
 
                 writer.write_u8(SceneObjectProperty::None.into())?;
             }
@@ -254,21 +525,44 @@ impl RoseFile for Scene {
                 writer.write_u16(effect.effect_id)?;
                 writer.write_u16(effect.effect_type.into())?;
 
-                writer.write_u8(SceneObjectProperty::Position.into())?;
-                writer.write_u8(12)?;
-                writer.write_vector3_f32(&effect.position)?;
-
-                writer.write_u8(SceneObjectProperty::Rotation.into())?;
-                writer.write_u8(16)?;
-                writer.write_quaternion_wxyz(&effect.rotation)?;
-
-                writer.write_u8(SceneObjectProperty::Scale.into())?;
-                writer.write_u8(12)?;
-                writer.write_vector3_f32(&effect.scale)?;
-
-                writer.write_u8(SceneObjectProperty::Parent.into())?;
-                writer.write_u8(2)?;
-                writer.write_u16(effect.parent)?;
+                let mut unknown = effect.unknown_properties.iter();
+                for id in effect.properties() {
+                    match SceneObjectProperty::try_from(id) {
+                        Ok(SceneObjectProperty::Position) => {
+                            write_property_header(writer, id, 12)?;
+                            writer.write_vector3_f32(&effect.position)?;
+                        }
+                        Ok(SceneObjectProperty::Rotation) => {
+                            write_property_header(writer, id, 16)?;
+                            writer.write_quaternion_wxyz(&effect.rotation)?;
+                        }
+                        Ok(SceneObjectProperty::Scale) => {
+                            write_property_header(writer, id, 12)?;
+                            writer.write_vector3_f32(&effect.scale)?;
+                        }
+                        Ok(SceneObjectProperty::AxisRotation) => {
+                            write_property_header(writer, id, 16)?;
+                            writer.write_quaternion_wxyz(&effect.axis_rotation)?;
+                        }
+                        Ok(SceneObjectProperty::BoneIndex) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_i16(effect.bone_index)?;
+                        }
+                        Ok(SceneObjectProperty::DummyIndex) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_i16(effect.dummy_index)?;
+                        }
+                        Ok(SceneObjectProperty::Parent) => {
+                            write_property_header(writer, id, 2)?;
+                            writer.write_u16(effect.parent)?;
+                        }
+                        _ => {
+                            if let Some(property) = unknown.next() {
+                                property.write(writer)?;
+                            }
+                        }
+                    }
+                }
 
                 writer.write_u8(SceneObjectProperty::None.into())?;
             }
@@ -282,7 +576,7 @@ impl RoseFile for Scene {
 }
 
 /// Scene Material
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct SceneMaterial {
     pub path: PathBuf,
     pub is_skin: bool,
@@ -300,7 +594,7 @@ pub struct SceneMaterial {
 }
 
 /// Scene Object
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct SceneObject {
     pub bounding_cylinder: BoundingCylinder,
     pub bounding_box: BoundingBox<f32>,
@@ -309,7 +603,7 @@ pub struct SceneObject {
 }
 
 /// Scene Object Part
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct SceneObjectPart {
     pub mesh_id: u16,
     pub material_id: u16,
@@ -320,33 +614,86 @@ pub struct SceneObjectPart {
     pub bone_index: i16,
     pub dummy_index: i16,
     pub parent: u16,
-    /*
-    TODO: Convert collision to an enum? (collision_info)
-
-    :ENUM[WORD] collision_type
-    NONE = 0
-    SPHERE = 1
-    AXISALIGNEDBOUNDINGBOX = 2
-    ORIENTEDBOUNDINGBOX = 3
-    POLYGON = 4
-    :ENDENUM
-
-    :ENUM[WORD] collisionpick_type
-        NONE = 0
-        NOTMOVABLE = 8
-        NOTPICKABLE = 16
-        HEIGHTONLY = 32
-        NOCAMERACOLLISION = 64
-    :ENDENUM
-
-    :TYPEDEF[WORD] collision_info
-        collision_type | collisionpick_type
-    */
-    pub collision: u16,
-    //collision: SceneCollisionType,
+    pub collision: SceneCollision,
     pub animation_path: PathBuf,
     pub range: u16,
     pub use_lightmap: bool,
+
+    /// Payload of the animation property (id 8), kept as-is so it is
+    /// written back unchanged.
+    ///
+    /// The property is not decoded yet: its layout is undocumented and needs
+    /// a retail scene that uses it to work out.
+    #[serde(default)]
+    pub animation: Option<Vec<u8>>,
+
+    /// Property ids in the order they were read
+    #[serde(default)]
+    pub property_order: Vec<u8>,
+
+    /// Properties that aren't decoded
+    #[serde(default)]
+    pub unknown_properties: Vec<SceneRawProperty>,
+}
+
+impl SceneObjectPart {
+    /// Property ids to write, in order.
+    ///
+    /// Parts that were read keep their original properties, followed by any
+    /// property that has since been changed from its default. New parts get
+    /// every property.
+    pub fn properties(&self) -> Vec<u8> {
+        let defaults = SceneObjectPart::default();
+        let properties = [
+            (
+                SceneObjectProperty::Position,
+                self.position != defaults.position,
+            ),
+            (
+                SceneObjectProperty::Rotation,
+                self.rotation != defaults.rotation,
+            ),
+            (SceneObjectProperty::Scale, self.scale != defaults.scale),
+            (
+                SceneObjectProperty::AxisRotation,
+                self.axis_rotation != defaults.axis_rotation,
+            ),
+            (
+                SceneObjectProperty::BoneIndex,
+                self.bone_index != defaults.bone_index,
+            ),
+            (
+                SceneObjectProperty::DummyIndex,
+                self.dummy_index != defaults.dummy_index,
+            ),
+            (SceneObjectProperty::Parent, self.parent != defaults.parent),
+            (
+                SceneObjectProperty::Collision,
+                self.collision != defaults.collision,
+            ),
+            (
+                SceneObjectProperty::AnimationPath,
+                self.animation_path != defaults.animation_path,
+            ),
+            (SceneObjectProperty::Range, self.range != defaults.range),
+            (
+                SceneObjectProperty::UseLightmap,
+                self.use_lightmap != defaults.use_lightmap,
+            ),
+        ];
+
+        let mut order = property_order(
+            &self.property_order,
+            &properties,
+            &self.unknown_properties,
+            |id| SceneObjectProperty::try_from(id).is_ok(),
+        );
+        let animation: u8 = SceneObjectProperty::Animation.into();
+        if self.animation.is_some() && !order.contains(&animation) {
+            order.push(animation);
+        }
+        order
+    }
 }
 
 impl Default for SceneObjectPart {
@@ -361,17 +708,19 @@ impl Default for SceneObjectPart {
             bone_index: -1,
             dummy_index: -1,
             parent: 0,
-            collision: 0,
-            //collision: SceneCollisionType,
+            collision: SceneCollision::default(),
             animation_path: PathBuf::new(),
             range: 0,
             use_lightmap: false,
+            animation: None,
+            property_order: Vec::new(),
+            unknown_properties: Vec::new(),
         }
     }
 }
 
 /// Scene Object Effect
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct SceneObjectEffect {
     pub effect_id: u16,
     pub effect_type: SceneEffectType,
@@ -379,6 +728,158 @@ pub struct SceneObjectEffect {
     pub rotation: Quaternion,
     pub scale: Vector3<f32>,
     pub parent: u16,
+
+    #[serde(default)]
+    pub axis_rotation: Quaternion,
+    #[serde(default = "default_index")]
+    pub bone_index: i16,
+    #[serde(default = "default_index")]
+    pub dummy_index: i16,
+
+    /// Property ids in the order they were read
+    #[serde(default)]
+    pub property_order: Vec<u8>,
+
+    /// Properties that aren't decoded, including part-only properties
+    #[serde(default)]
+    pub unknown_properties: Vec<SceneRawProperty>,
+}
+
+impl SceneObjectEffect {
+    /// Property ids to write, in order. See `SceneObjectPart::properties`.
+    pub fn properties(&self) -> Vec<u8> {
+        let defaults = SceneObjectEffect::default();
+        let properties = [
+            (
+                SceneObjectProperty::Position,
+                self.position != defaults.position,
+            ),
+            (
+                SceneObjectProperty::Rotation,
+                self.rotation != defaults.rotation,
+            ),
+            (SceneObjectProperty::Scale, self.scale != defaults.scale),
+            (SceneObjectProperty::Parent, self.parent != defaults.parent),
+            (
+                SceneObjectProperty::AxisRotation,
+                self.axis_rotation != defaults.axis_rotation,
+            ),
+            (
+                SceneObjectProperty::BoneIndex,
+                self.bone_index != defaults.bone_index,
+            ),
+            (
+                SceneObjectProperty::DummyIndex,
+                self.dummy_index != defaults.dummy_index,
+            ),
+        ];
+
+        // New effects always get the first 4
+        let mut order = self.property_order.clone();
+        if order.is_empty() {
+            for (property, _) in &properties[..4] {
+                order.push((*property).into());
+            }
+        }
+        property_order(
+            &order,
+            &properties,
+            &self.unknown_properties,
+            is_effect_property,
+        )
+    }
+}
+
+impl Default for SceneObjectEffect {
+    fn default() -> SceneObjectEffect {
+        SceneObjectEffect {
+            effect_id: 0,
+            effect_type: SceneEffectType::default(),
+            position: Vector3::default(),
+            rotation: Quaternion::default(),
+            scale: Vector3::default(),
+            parent: 0,
+            axis_rotation: Quaternion::default(),
+            bone_index: -1,
+            dummy_index: -1,
+            property_order: Vec::new(),
+            unknown_properties: Vec::new(),
+        }
+    }
+}
+
+/// Property ids from `read`, then any changed property missing from it, then
+/// unknown properties that weren't read. Empty `read` includes every property.
+fn property_order<F: Fn(u8) -> bool>(
+    read: &[u8],
+    properties: &[(SceneObjectProperty, bool)],
+    unknown: &[SceneRawProperty],
+    decoded: F,
+) -> Vec<u8> {
+    let new = read.is_empty();
+    let mut order = read.to_vec();
+    for &(property, changed) in properties {
+        let id: u8 = property.into();
+        if (new || changed) && !order.contains(&id) {
+            order.push(id);
+        }
+    }
+
+    let read_unknown = read.iter().filter(|&&id| !decoded(id)).count();
+    order.extend(unknown.iter().skip(read_unknown).map(|p| p.id));
+    order
+}
+
+fn default_index() -> i16 {
+    -1
+}
+
+/// Properties decoded for effects
+fn is_effect_property(id: u8) -> bool {
+    matches!(
+        SceneObjectProperty::try_from(id),
+        Ok(SceneObjectProperty::Position)
+            | Ok(SceneObjectProperty::Rotation)
+            | Ok(SceneObjectProperty::Scale)
+            | Ok(SceneObjectProperty::AxisRotation)
+            | Ok(SceneObjectProperty::BoneIndex)
+            | Ok(SceneObjectProperty::DummyIndex)
+            | Ok(SceneObjectProperty::Parent)
+    )
+}
+
+fn write_property_header<W: WriteRoseExt>(
+    writer: &mut W,
+    id: u8,
+    size: usize,
+) -> Result<(), Error> {
+    if size > u8::MAX as usize {
+        bail!("Scene property {} is too large: {} bytes", id, size);
+    }
+    writer.write_u8(id)?;
+    writer.write_u8(size as u8)?;
+    Ok(())
+}
+
+/// Scene object property stored as raw bytes
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SceneRawProperty {
+    pub id: u8,
+    pub data: Vec<u8>,
+}
+
+impl SceneRawProperty {
+    fn read<R: ReadRoseExt>(reader: &mut R, id: u8, size: u8) -> Result<SceneRawProperty, Error> {
+        let mut data = vec![0u8; size as usize];
+        reader.read_exact(&mut data)?;
+        Ok(SceneRawProperty { id, data })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        write_property_header(writer, self.id, self.data.len())?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
@@ -472,40 +973,100 @@ impl TryFrom<u16> for SceneGlowType {
     }
 }
 
+/// Scene part collision, a shape in the low 3 bits combined with pick flags
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SceneCollision {
+    pub shape: SceneCollisionShape,
+    pub flags: SceneCollisionFlags,
+
+    /// Flag bits and shape values that aren't decoded, written back as-is
+    #[serde(default)]
+    pub unknown_bits: u16,
+}
+
+const COLLISION_SHAPE_MASK: u16 = 0x7;
+
+impl From<SceneCollision> for u16 {
+    fn from(collision: SceneCollision) -> u16 {
+        let shape: u16 = collision.shape.into();
+        shape | collision.flags.bits() | collision.unknown_bits
+    }
+}
+
+impl From<u16> for SceneCollision {
+    fn from(value: u16) -> SceneCollision {
+        let flags = SceneCollisionFlags::from_bits_truncate(value);
+        let mut unknown_bits = value & !COLLISION_SHAPE_MASK & !flags.bits();
+
+        // Unknown shapes are kept with the unknown bits
+        let shape = match SceneCollisionShape::try_from(value & COLLISION_SHAPE_MASK) {
+            Ok(shape) => shape,
+            Err(_) => {
+                unknown_bits |= value & COLLISION_SHAPE_MASK;
+                SceneCollisionShape::None
+            }
+        };
+
+        SceneCollision {
+            shape,
+            flags,
+            unknown_bits,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
-pub enum SceneCollisionType {
+pub enum SceneCollisionShape {
     None = 0,
-    BoundingBox = 3,
-    Polygon = 1 << 2,
-    NotMovable = 1 << 3,
-    NotPickable = 1 << 4,
-    HeightOnly = 1 << 5,
-    NoCameraCollision = 1 << 6,
+    Sphere = 1,
+    AxisAlignedBoundingBox = 2,
+    OrientedBoundingBox = 3,
+    Polygon = 4,
 }
 
-impl Default for SceneCollisionType {
+impl Default for SceneCollisionShape {
     fn default() -> Self {
-        SceneCollisionType::None
+        SceneCollisionShape::None
     }
 }
 
-impl TryFrom<u16> for SceneCollisionType {
+impl From<SceneCollisionShape> for u16 {
+    fn from(shape: SceneCollisionShape) -> u16 {
+        match shape {
+            SceneCollisionShape::None => 0,
+            SceneCollisionShape::Sphere => 1,
+            SceneCollisionShape::AxisAlignedBoundingBox => 2,
+            SceneCollisionShape::OrientedBoundingBox => 3,
+            SceneCollisionShape::Polygon => 4,
+        }
+    }
+}
+
+impl TryFrom<u16> for SceneCollisionShape {
     type Error = failure::Error;
 
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
-            0 => Ok(SceneCollisionType::None),
-            3 => Ok(SceneCollisionType::BoundingBox),
-            4 => Ok(SceneCollisionType::Polygon),
-            8 => Ok(SceneCollisionType::NotMovable),
-            16 => Ok(SceneCollisionType::NotPickable),
-            32 => Ok(SceneCollisionType::HeightOnly),
-            64 => Ok(SceneCollisionType::NoCameraCollision),
-            _ => bail!("Invalid SceneCollisionType: {}", value),
+            0 => Ok(SceneCollisionShape::None),
+            1 => Ok(SceneCollisionShape::Sphere),
+            2 => Ok(SceneCollisionShape::AxisAlignedBoundingBox),
+            3 => Ok(SceneCollisionShape::OrientedBoundingBox),
+            4 => Ok(SceneCollisionShape::Polygon),
+            _ => bail!("Invalid SceneCollisionShape: {}", value),
         }
     }
 }
 
+bitflags! {
+    #[derive(Default, Serialize, Deserialize)]
+    pub struct SceneCollisionFlags: u16 {
+        const NOT_MOVABLE = 1 << 3;
+        const NOT_PICKABLE = 1 << 4;
+        const HEIGHT_ONLY = 1 << 5;
+        const NO_CAMERA_COLLISION = 1 << 6;
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum SceneObjectProperty {
     None = 0,
@@ -3,6 +3,7 @@
 mod file;
 mod path;
 mod reader;
+pub(crate) mod record;
 mod writer;
 
 pub use self::file::RoseFile;
@@ -0,0 +1,75 @@
+//! Size-prefixed records used by script formats
+use std::io::Cursor;
+
+use failure::Error;
+
+use crate::io::{ReadRoseExt, WriteRoseExt};
+
+/// Records are prefixed with their total size and type id
+pub(crate) fn read_record<R: ReadRoseExt>(reader: &mut R) -> Result<(u32, Vec<u8>), Error> {
+    let size = reader.read_u32()?;
+    let id = reader.read_u32()?;
+    if size < 8 {
+        bail!("Invalid record size: {}", size);
+    }
+
+    let mut data = vec![0u8; size as usize - 8];
+    reader.read_exact(&mut data)?;
+    Ok((id, data))
+}
+
+pub(crate) fn write_record<W: WriteRoseExt>(
+    writer: &mut W,
+    id: u32,
+    data: &[u8],
+) -> Result<(), Error> {
+    writer.write_u32(data.len() as u32 + 8)?;
+    writer.write_u32(id)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+pub(crate) fn skip<R: ReadRoseExt>(reader: &mut R, n: usize) -> Result<(), Error> {
+    for _ in 0..n {
+        reader.read_u8()?;
+    }
+    Ok(())
+}
+
+pub(crate) fn pad<W: WriteRoseExt>(writer: &mut W, n: usize) -> Result<(), Error> {
+    for _ in 0..n {
+        writer.write_u8(0)?;
+    }
+    Ok(())
+}
+
+/// Decode a record payload, returning `None` unless the payload is exactly
+/// the size of the typed record (rounded up to 4 bytes)
+pub(crate) fn decode_record<T, F>(data: &[u8], decode: F) -> Option<T>
+where
+    F: FnOnce(&mut Cursor<&[u8]>) -> Result<T, Error>,
+{
+    let mut cursor = Cursor::new(data);
+    let value = decode(&mut cursor).ok()?;
+    let used = cursor.position() as usize;
+    let aligned = used.next_multiple_of(4);
+    if used == data.len() || aligned == data.len() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Encode a record payload padded to 4 bytes
+pub(crate) fn encode_record<F>(encode: F) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce(&mut Cursor<Vec<u8>>) -> Result<(), Error>,
+{
+    let mut cursor = Cursor::new(Vec::new());
+    encode(&mut cursor)?;
+    let mut data = cursor.into_inner();
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    Ok(data)
+}
@@ -1,4 +1,6 @@
 #[macro_use]
+extern crate bitflags;
+#[macro_use]
 extern crate failure;
 extern crate byteorder;
 extern crate encoding_rs;
@@ -6,9 +8,12 @@ extern crate lazy_static;
 extern crate rusqlite;
 extern crate serde;
 
+pub mod ai;
 pub mod files;
 pub mod io;
+pub mod quest;
 pub mod sqlite;
+pub mod terrain;
 pub mod utils;
 
 pub use failure::Error;
@@ -0,0 +1,688 @@
+//! Quest script evaluation
+//!
+//! Runs QSD triggers against a mock character: conditions are checked in
+//! order and, when they all pass, the actions are applied to the character.
+//! Every step is recorded in a `QuestTrace`.
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::files::qsd::{QuestAction, QuestCondition, QuestGroup, QuestVariable};
+use crate::files::QSD;
+use crate::utils::{compare, in_range, in_time, Vector3};
+
+/// Ability type of the character job
+pub const ABILITY_JOB: i32 = 4;
+
+/// Ability type of the character level
+pub const ABILITY_LEVEL: i32 = 31;
+
+/// Variable type of the selected quest's variables
+pub const VARIABLE_QUEST: u16 = 0x0000;
+
+/// Variable type of the selected quest's switches
+pub const VARIABLE_QUEST_SWITCH: u16 = 0x0100;
+
+/// Maximum number of triggers run by one call, guards against loops
+const MAX_TRIGGERS: usize = 256;
+
+/// Character state quests are evaluated against
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct QuestCharacter {
+    /// Ability values by ability type, see `ABILITY_LEVEL` and `ABILITY_JOB`
+    pub abilities: BTreeMap<i32, i32>,
+    pub experience: i64,
+    pub money: i64,
+
+    /// Item counts by item number
+    pub inventory: BTreeMap<u32, i32>,
+    pub skills: BTreeSet<i32>,
+
+    pub quests: Vec<QuestProgress>,
+
+    /// Index into `quests` chosen by `SelectQuest`
+    pub selected_quest: Option<usize>,
+
+    /// Variables other than quest variables, by variable type and index
+    pub variables: BTreeMap<u16, BTreeMap<u16, i16>>,
+    pub switches: BTreeMap<i16, i16>,
+
+    /// NPC chosen by `SelectNpc`
+    pub npc: Option<i32>,
+
+    /// NPC variables by NPC number and variable index
+    pub npc_variables: BTreeMap<i32, BTreeMap<u16, i32>>,
+
+    pub zone: i32,
+    pub position: Vector3<f32>,
+    pub party: Option<QuestParty>,
+    pub team: i32,
+    pub channel: u16,
+
+    pub world_time: u32,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+
+    /// Roll compared by `RandomPercent`, fixed so runs are repeatable
+    pub random_percent: u8,
+}
+
+/// Quest in the character's journal
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct QuestProgress {
+    pub quest: u32,
+    pub variables: BTreeMap<u16, i16>,
+    pub switches: BTreeSet<u16>,
+
+    /// Seconds left to complete the quest
+    pub time_remaining: u32,
+}
+
+impl QuestProgress {
+    pub fn new(quest: u32) -> QuestProgress {
+        QuestProgress {
+            quest,
+            ..Default::default()
+        }
+    }
+}
+
+/// Party the character is in
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestParty {
+    pub is_leader: bool,
+    pub level: i32,
+    pub members: i16,
+}
+
+/// Something a quest action gave to or took from the character
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum QuestReward {
+    Experience(i64),
+    Money(i64),
+    Item { item: u32, count: i32 },
+    Ability { ability_type: i32, value: i32 },
+    Quest { quest: u32, option: u8 },
+    Skill { skill: i32, added: bool },
+    RestoreHpMp { hp_percent: i32, mp_percent: i32 },
+    Teleport { zone: u32, x: i32, y: i32 },
+}
+
+/// Result of checking a condition
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum QuestCheck {
+    Passed,
+    Failed,
+
+    /// The mock character has no state for this condition, counts as failed
+    Unsupported,
+}
+
+/// Step of a quest run
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum QuestEvent {
+    Trigger {
+        name: String,
+    },
+    Condition {
+        condition: QuestCondition,
+        result: QuestCheck,
+    },
+    /// `applied` is false for actions the mock character can't represent
+    Action {
+        action: QuestAction,
+        applied: bool,
+    },
+    Reward(QuestReward),
+    /// Trigger that would run after a delay, it is not run
+    Scheduled {
+        trigger: String,
+        seconds: u32,
+    },
+}
+
+/// Record of a quest run
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuestTrace {
+    /// Whether the requested trigger, or one it fell through to, passed
+    pub success: bool,
+    pub events: Vec<QuestEvent>,
+}
+
+impl QuestTrace {
+    /// Rewards granted during the run
+    pub fn rewards(&self) -> Vec<&QuestReward> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                QuestEvent::Reward(reward) => Some(reward),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of the triggers that were evaluated
+    pub fn triggers(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                QuestEvent::Trigger { name } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Apply a set (5), add (6) or subtract (7) operation
+fn change(op: u8, value: i64, amount: i64) -> i64 {
+    match op {
+        5 => amount,
+        6 => value + amount,
+        7 => value - amount,
+        _ => value,
+    }
+}
+
+impl QuestCharacter {
+    pub fn new() -> QuestCharacter {
+        Self::default()
+    }
+
+    pub fn level(&self) -> i32 {
+        self.ability(ABILITY_LEVEL)
+    }
+
+    pub fn job(&self) -> i32 {
+        self.ability(ABILITY_JOB)
+    }
+
+    pub fn ability(&self, ability_type: i32) -> i32 {
+        self.abilities.get(&ability_type).cloned().unwrap_or(0)
+    }
+
+    pub fn item_count(&self, item: u32) -> i32 {
+        self.inventory.get(&item).cloned().unwrap_or(0)
+    }
+
+    /// Progress of a quest in the journal
+    pub fn quest(&self, quest: u32) -> Option<&QuestProgress> {
+        self.quests.iter().find(|q| q.quest == quest)
+    }
+
+    /// Value of a quest variable, quest variables and switches read from
+    /// the selected quest
+    pub fn variable(&self, variable_type: u16, index: u16) -> i16 {
+        match variable_type {
+            VARIABLE_QUEST => self
+                .selected()
+                .and_then(|q| q.variables.get(&index).cloned())
+                .unwrap_or(0),
+            VARIABLE_QUEST_SWITCH => self
+                .selected()
+                .map(|q| q.switches.contains(&index) as i16)
+                .unwrap_or(0),
+            _ => self
+                .variables
+                .get(&variable_type)
+                .and_then(|v| v.get(&index).cloned())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Set a quest variable, returns false if it needs a selected quest and
+    /// none is selected
+    pub fn set_variable(&mut self, variable_type: u16, index: u16, value: i16) -> bool {
+        match variable_type {
+            VARIABLE_QUEST | VARIABLE_QUEST_SWITCH => {
+                let quest = match self.selected_quest.and_then(|i| self.quests.get_mut(i)) {
+                    Some(quest) => quest,
+                    None => return false,
+                };
+                if variable_type == VARIABLE_QUEST {
+                    quest.variables.insert(index, value);
+                } else if value != 0 {
+                    quest.switches.insert(index);
+                } else {
+                    quest.switches.remove(&index);
+                }
+            }
+            _ => {
+                self.variables
+                    .entry(variable_type)
+                    .or_default()
+                    .insert(index, value);
+            }
+        }
+        true
+    }
+
+    fn selected(&self) -> Option<&QuestProgress> {
+        self.selected_quest.and_then(|i| self.quests.get(i))
+    }
+
+    fn npc_variable(&self, npc: i32, index: u16) -> i32 {
+        self.npc_variables
+            .get(&npc)
+            .and_then(|v| v.get(&index).cloned())
+            .unwrap_or(0)
+    }
+
+    fn check_variables(&self, variables: &[QuestVariable]) -> bool {
+        variables.iter().all(|v| {
+            compare(
+                v.op,
+                i64::from(self.variable(v.variable_type, v.index)),
+                i64::from(v.value),
+            )
+        })
+    }
+
+    fn change_variables(&mut self, variables: &[QuestVariable]) -> bool {
+        variables.iter().all(|v| {
+            let value = change(
+                v.op,
+                i64::from(self.variable(v.variable_type, v.index)),
+                i64::from(v.value),
+            );
+            self.set_variable(v.variable_type, v.index, value as i16)
+        })
+    }
+
+    /// Check a condition, selecting a quest or NPC updates the character
+    pub fn check(&mut self, condition: &QuestCondition) -> QuestCheck {
+        let passed = match condition {
+            QuestCondition::SelectQuest { quest } => {
+                self.selected_quest = self.quests.iter().position(|q| q.quest as i32 == *quest);
+                self.selected_quest.is_some()
+            }
+            QuestCondition::QuestData(variables) | QuestCondition::QuestVariable(variables) => {
+                self.check_variables(variables)
+            }
+            QuestCondition::AbilityValue(abilities) => abilities.iter().all(|a| {
+                compare(
+                    a.op,
+                    i64::from(self.ability(a.ability_type)),
+                    i64::from(a.value),
+                )
+            }),
+            QuestCondition::QuestItems(items) => items
+                .iter()
+                .all(|i| compare(i.op, i64::from(self.item_count(i.item)), i64::from(i.count))),
+            QuestCondition::Party {
+                is_leader,
+                level,
+                reverse,
+            } => {
+                let in_party = match &self.party {
+                    Some(party) => (!is_leader || party.is_leader) && party.level >= *level,
+                    None => false,
+                };
+                in_party != *reverse
+            }
+            QuestCondition::Position {
+                zone, x, y, radius, ..
+            } => {
+                let dx = self.position.x - *x as f32;
+                let dy = self.position.y - *y as f32;
+                self.zone == *zone && (dx * dx + dy * dy).sqrt() <= *radius as f32
+            }
+            QuestCondition::WorldTime { start, end } => in_range(self.world_time, *start, *end),
+            QuestCondition::QuestTimeRemaining { time, op } => match self.selected() {
+                Some(quest) => compare(*op, i64::from(quest.time_remaining), i64::from(*time)),
+                None => false,
+            },
+            QuestCondition::HasSkill { start, end, op } => {
+                let has_skill = self.skills.iter().any(|s| in_range(*s, *start, *end));
+                has_skill == (*op != 0)
+            }
+            QuestCondition::RandomPercent { min, max } => in_range(self.random_percent, *min, *max),
+            QuestCondition::ObjectVariable {
+                object: 0,
+                variable,
+                value,
+                op,
+            } => match self.npc {
+                Some(npc) => compare(
+                    *op,
+                    i64::from(self.npc_variable(npc, *variable)),
+                    i64::from(*value),
+                ),
+                None => false,
+            },
+            QuestCondition::SelectNpc { npc } => {
+                self.npc = Some(*npc);
+                true
+            }
+            QuestCondition::SwitchBit { switch, value } => {
+                self.switches.get(switch).cloned().unwrap_or(0) == *value
+            }
+            QuestCondition::PartyMemberCount { min, max } => match &self.party {
+                Some(party) => in_range(party.members, *min, *max),
+                None => false,
+            },
+            QuestCondition::CompareNpcVariables { first, second, op } => compare(
+                *op,
+                i64::from(self.npc_variable(first.npc, first.variable as u16)),
+                i64::from(self.npc_variable(second.npc, second.variable as u16)),
+            ),
+            QuestCondition::MonthDayTime {
+                day,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                (*day == 0 || *day == self.day)
+                    && in_time(
+                        (self.hour, self.minute),
+                        (*start_hour, *start_minute),
+                        (*end_hour, *end_minute),
+                    )
+            }
+            QuestCondition::WeekDayTime {
+                weekday,
+                start_hour,
+                start_minute,
+                end_hour,
+                end_minute,
+            } => {
+                *weekday == self.weekday
+                    && in_time(
+                        (self.hour, self.minute),
+                        (*start_hour, *start_minute),
+                        (*end_hour, *end_minute),
+                    )
+            }
+            QuestCondition::TeamNumber { min, max } => in_range(self.team, *min, *max),
+            QuestCondition::ServerChannelNumber { min, max } => in_range(self.channel, *min, *max),
+            _ => return QuestCheck::Unsupported,
+        };
+
+        if passed {
+            QuestCheck::Passed
+        } else {
+            QuestCheck::Failed
+        }
+    }
+
+    /// Apply an action, returns false if the mock character can't represent
+    /// it. Rewards are appended to `rewards`.
+    ///
+    /// Calculated rewards ignore the reward equation and grant
+    /// `base * percent / 100` experience (target 0), money (target 1) or
+    /// items (target 2).
+    pub fn apply(&mut self, action: &QuestAction, rewards: &mut Vec<QuestReward>) -> bool {
+        match action {
+            QuestAction::SetQuest { quest, option } => {
+                let selected = self.selected_quest.filter(|i| *i < self.quests.len());
+                match (option, selected) {
+                    (0, _) => {
+                        self.quests.retain(|q| q.quest != *quest);
+                        self.selected_quest = None;
+                    }
+                    (1, _) => {
+                        self.quests.push(QuestProgress::new(*quest));
+                        self.selected_quest = Some(self.quests.len() - 1);
+                    }
+                    (2, Some(i)) => self.quests[i].quest = *quest,
+                    (3, Some(i)) => self.quests[i] = QuestProgress::new(*quest),
+                    (4, _) => {
+                        self.selected_quest = self.quests.iter().position(|q| q.quest == *quest)
+                    }
+                    _ => return false,
+                }
+                rewards.push(QuestReward::Quest {
+                    quest: *quest,
+                    option: *option,
+                });
+            }
+            QuestAction::UpdateItem {
+                item, op, count, ..
+            } => {
+                let count = if *op == 0 {
+                    -i32::from(*count)
+                } else {
+                    i32::from(*count)
+                };
+                let total = self.inventory.entry(*item).or_insert(0);
+                *total = (*total + count).max(0);
+                if *total == 0 {
+                    self.inventory.remove(item);
+                }
+                rewards.push(QuestReward::Item { item: *item, count });
+            }
+            QuestAction::SetQuestData(variables) | QuestAction::SetQuestVariable(variables) => {
+                return self.change_variables(variables);
+            }
+            QuestAction::AbilityValue { abilities, .. } => {
+                for ability in abilities.iter() {
+                    let current = self.ability(ability.ability_type);
+                    let value =
+                        change(ability.op, i64::from(current), i64::from(ability.value)) as i32;
+                    self.abilities.insert(ability.ability_type, value);
+                    rewards.push(QuestReward::Ability {
+                        ability_type: ability.ability_type,
+                        value: value - current,
+                    });
+                }
+            }
+            QuestAction::CalculatedReward {
+                target,
+                base,
+                percent,
+                item,
+                ..
+            } => {
+                let amount = i64::from(*base) * i64::from(*percent) / 100;
+                match target {
+                    0 => {
+                        self.experience += amount;
+                        rewards.push(QuestReward::Experience(amount));
+                    }
+                    1 => {
+                        self.money += amount;
+                        rewards.push(QuestReward::Money(amount));
+                    }
+                    2 => {
+                        *self.inventory.entry(*item).or_insert(0) += amount as i32;
+                        rewards.push(QuestReward::Item {
+                            item: *item,
+                            count: amount as i32,
+                        });
+                    }
+                    _ => return false,
+                }
+            }
+            QuestAction::RestoreHpMp {
+                hp_percent,
+                mp_percent,
+                ..
+            } => rewards.push(QuestReward::RestoreHpMp {
+                hp_percent: *hp_percent,
+                mp_percent: *mp_percent,
+            }),
+            QuestAction::Teleport { zone, x, y, .. } => {
+                self.zone = *zone as i32;
+                self.position = Vector3 {
+                    x: *x as f32,
+                    y: *y as f32,
+                    z: 0.0,
+                };
+                rewards.push(QuestReward::Teleport {
+                    zone: *zone,
+                    x: *x,
+                    y: *y,
+                });
+            }
+            QuestAction::ObjectVariable {
+                object: 0,
+                variable,
+                value,
+                op,
+            } => {
+                let npc = match self.npc {
+                    Some(npc) => npc,
+                    None => return false,
+                };
+                let current = i64::from(self.npc_variable(npc, *variable));
+                self.npc_variables
+                    .entry(npc)
+                    .or_default()
+                    .insert(*variable, change(*op, current, i64::from(*value)) as i32);
+            }
+            QuestAction::Skill { op, skill } => {
+                let added = *op != 0;
+                if added {
+                    self.skills.insert(*skill);
+                } else {
+                    self.skills.remove(skill);
+                }
+                rewards.push(QuestReward::Skill {
+                    skill: *skill,
+                    added,
+                });
+            }
+            QuestAction::ResetSkills => self.skills.clear(),
+            QuestAction::SetSwitch { switch, value } => {
+                self.switches.insert(*switch, *value);
+            }
+            QuestAction::ClearAllSwitches => self.switches.clear(),
+            // Triggers are handled by the engine
+            QuestAction::NextTrigger { .. } | QuestAction::TriggerAfterDelay { .. } => {}
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Runs triggers from a set of quest scripts
+///
+/// Trigger names are shared by every script, the first match is used.
+pub struct QuestEngine<'a> {
+    scripts: &'a [QSD],
+}
+
+impl<'a> QuestEngine<'a> {
+    pub fn new(scripts: &'a [QSD]) -> QuestEngine<'a> {
+        QuestEngine { scripts }
+    }
+
+    fn find(&self, name: &str) -> Option<(&'a QuestGroup, usize)> {
+        self.scripts
+            .iter()
+            .flat_map(|s| s.groups.iter())
+            .find_map(|g| {
+                g.triggers
+                    .iter()
+                    .position(|t| t.name == name)
+                    .map(|i| (g, i))
+            })
+    }
+
+    /// Run a trigger against `character`.
+    ///
+    /// When a condition fails and the trigger has `check_next` set the next
+    /// trigger in the group is tried. Triggers started with `NextTrigger`
+    /// run after the current one, delayed triggers are only recorded.
+    pub fn run(&self, name: &str, character: &mut QuestCharacter) -> Result<QuestTrace, Error> {
+        let mut trace = QuestTrace::default();
+        let mut pending = vec![name.to_string()];
+        let mut count = 0;
+        let mut requested = true;
+
+        while !pending.is_empty() {
+            let next = pending.remove(0);
+            let (group, mut index) = match self.find(&next) {
+                Some(found) => found,
+                None => bail!("Unknown quest trigger: {}", next),
+            };
+
+            loop {
+                count += 1;
+                if count > MAX_TRIGGERS {
+                    bail!("Too many quest triggers run from: {}", name);
+                }
+
+                let trigger = &group.triggers[index];
+                trace.events.push(QuestEvent::Trigger {
+                    name: trigger.name.clone(),
+                });
+
+                let mut passed = true;
+                for condition in trigger.conditions.iter() {
+                    let result = character.check(condition);
+                    trace.events.push(QuestEvent::Condition {
+                        condition: condition.clone(),
+                        result,
+                    });
+                    if result != QuestCheck::Passed {
+                        passed = false;
+                        break;
+                    }
+                }
+
+                if passed {
+                    trace.success |= requested;
+                    for action in trigger.actions.iter() {
+                        let mut rewards = Vec::new();
+                        let applied = character.apply(action, &mut rewards);
+                        trace.events.push(QuestEvent::Action {
+                            action: action.clone(),
+                            applied,
+                        });
+                        trace
+                            .events
+                            .extend(rewards.into_iter().map(QuestEvent::Reward));
+
+                        match action {
+                            QuestAction::NextTrigger { trigger } => pending.push(trigger.clone()),
+                            QuestAction::TriggerAfterDelay {
+                                trigger, seconds, ..
+                            } => trace.events.push(QuestEvent::Scheduled {
+                                trigger: trigger.clone(),
+                                seconds: *seconds,
+                            }),
+                            _ => {}
+                        }
+                    }
+                    break;
+                }
+
+                if !trigger.check_next || index + 1 >= group.triggers.len() {
+                    break;
+                }
+                index += 1;
+            }
+            requested = false;
+        }
+
+        Ok(trace)
+    }
+}
+
+/// Character and triggers to run, quest designers keep these as JSON files
+/// to test quest chains
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct QuestScenario {
+    pub character: QuestCharacter,
+    pub triggers: Vec<String>,
+}
+
+impl QuestScenario {
+    /// Run every trigger in order, returning the final character and a
+    /// trace per trigger
+    pub fn run(&self, engine: &QuestEngine) -> Result<(QuestCharacter, Vec<QuestTrace>), Error> {
+        let mut character = self.character.clone();
+        let mut traces = Vec::new();
+        for trigger in self.triggers.iter() {
+            traces.push(engine.run(trigger, &mut character)?);
+        }
+        Ok((character, traces))
+    }
+}
@@ -0,0 +1,192 @@
+//! Ground height of a whole map
+//!
+//! Loads the heightmap of every block of a map and samples it at world
+//! coordinates. Block `X_Y` covers `X * block_size` to `(X + 1) * block_size`
+//! on the X axis and counts down from `(65 - Y) * block_size` on the Y axis,
+//! as heightmap rows go south.
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use failure::Error;
+
+use crate::files::{HIM, ZON};
+use crate::io::RoseFile;
+use crate::utils::Vector3;
+
+/// World Y of a block is counted down from this block row
+pub const BLOCK_ROWS: i32 = 65;
+
+/// Height and surface normal at a point of the terrain
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TerrainSample {
+    pub height: f32,
+    pub normal: Vector3<f32>,
+}
+
+/// Heightmaps of a map by block coordinates
+#[derive(Debug, Default)]
+pub struct Terrain {
+    /// World distance between heightmap vertices
+    pub grid_size: f32,
+
+    /// Heightmap grids per block side
+    pub block_grids: usize,
+
+    pub blocks: HashMap<(i32, i32), HIM>,
+}
+
+impl Terrain {
+    /// Empty terrain with the grid size of a zone
+    pub fn new(zone: &ZON) -> Terrain {
+        Terrain {
+            grid_size: zone.grid_size,
+            block_grids: 0,
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Load the zone `<dir name>.ZON` and every `X_Y.HIM` of a map directory
+    pub fn from_dir(map_dir: &Path) -> Result<Terrain, Error> {
+        let mut terrain = Terrain::default();
+
+        for entry in fs::read_dir(map_dir)? {
+            let path = entry?.path();
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+            if extension == "zon" {
+                let map_name = map_dir.file_name().unwrap_or_default().to_string_lossy();
+                if stem.eq_ignore_ascii_case(&map_name) {
+                    terrain.grid_size = ZON::from_path(&path)?.grid_size;
+                }
+                continue;
+            }
+
+            if extension != "him" {
+                continue;
+            }
+
+            let coords: Vec<&str> = stem.split('_').collect();
+            let (x, y) = match coords.as_slice() {
+                [x, y] => (x.parse()?, y.parse()?),
+                _ => bail!("Expected a heightmap named X_Y.HIM: {}", path.display()),
+            };
+            terrain.insert(x, y, HIM::from_path(&path)?)?;
+        }
+
+        if terrain.blocks.is_empty() {
+            bail!("No heightmaps found in {}", map_dir.display());
+        }
+
+        Ok(terrain)
+    }
+
+    /// Add the heightmap of block `X_Y`.
+    ///
+    /// All heightmaps must have the same size. Without a zone the grid size
+    /// is the heightmap scale.
+    pub fn insert(&mut self, x: i32, y: i32, heightmap: HIM) -> Result<(), Error> {
+        let width = heightmap.width.max(0) as usize;
+        let length = heightmap.length.max(0) as usize;
+        if width < 2 || width != length || heightmap.heights.len() != width * length {
+            bail!(
+                "Invalid heightmap of block {}_{} ({}x{})",
+                x,
+                y,
+                heightmap.width,
+                heightmap.length
+            );
+        }
+
+        if self.block_grids == 0 {
+            self.block_grids = width - 1;
+        } else if self.block_grids != width - 1 {
+            bail!(
+                "Heightmap of block {}_{} is {}x{}, expected {}x{}",
+                x,
+                y,
+                width,
+                length,
+                self.block_grids + 1,
+                self.block_grids + 1
+            );
+        }
+
+        if self.grid_size <= 0.0 {
+            self.grid_size = heightmap.scale;
+        }
+
+        self.blocks.insert((x, y), heightmap);
+        Ok(())
+    }
+
+    /// World width and length of a block
+    pub fn block_size(&self) -> f32 {
+        self.block_grids as f32 * self.grid_size
+    }
+
+    /// Block coordinates of a world position and the grid position within it
+    pub fn block_position(&self, x: f32, y: f32) -> Option<((i32, i32), f32, f32)> {
+        let block_size = self.block_size();
+        if block_size <= 0.0 || !x.is_finite() || !y.is_finite() {
+            return None;
+        }
+
+        let south = BLOCK_ROWS as f32 * block_size - y;
+        let block_x = (x / block_size).floor();
+        let block_y = (south / block_size).floor();
+
+        let col = (x - block_x * block_size) / self.grid_size;
+        let row = (south - block_y * block_size) / self.grid_size;
+
+        Some(((block_x as i32, block_y as i32), col, row))
+    }
+
+    /// Bilinear height and normal at a world position, `None` outside the
+    /// loaded blocks
+    pub fn sample(&self, x: f32, y: f32) -> Option<TerrainSample> {
+        let (block, col, row) = self.block_position(x, y)?;
+        let heightmap = self.blocks.get(&block)?;
+
+        // Points on the far edges of a block fall in its last cell
+        let last = self.block_grids - 1;
+        let cell_x = (col.floor().max(0.0) as usize).min(last);
+        let cell_y = (row.floor().max(0.0) as usize).min(last);
+        let (fx, fy) = (col - cell_x as f32, row - cell_y as f32);
+
+        let h00 = heightmap.height(cell_x, cell_y);
+        let h10 = heightmap.height(cell_x + 1, cell_y);
+        let h01 = heightmap.height(cell_x, cell_y + 1);
+        let h11 = heightmap.height(cell_x + 1, cell_y + 1);
+
+        let top = h00 + (h10 - h00) * fx;
+        let bottom = h01 + (h11 - h01) * fx;
+        let height = top + (bottom - top) * fy;
+
+        // Rows go towards -Y
+        let dx = ((h10 - h00) * (1.0 - fy) + (h11 - h01) * fy) / self.grid_size;
+        let dy = -((h01 - h00) * (1.0 - fx) + (h11 - h10) * fx) / self.grid_size;
+        let normal = Vector3 {
+            x: -dx,
+            y: -dy,
+            z: 1.0,
+        }
+        .normalize();
+
+        Some(TerrainSample { height, normal })
+    }
+
+    /// Ground height at a world position, `None` outside the loaded blocks
+    pub fn height(&self, x: f32, y: f32) -> Option<f32> {
+        self.sample(x, y).map(|s| s.height)
+    }
+
+    /// Surface normal at a world position, `None` outside the loaded blocks
+    pub fn normal(&self, x: f32, y: f32) -> Option<Vector3<f32>> {
+        self.sample(x, y).map(|s| s.normal)
+    }
+}
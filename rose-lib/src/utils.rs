@@ -47,6 +47,13 @@ impl Vector2<f32> {
     pub fn new() -> Vector2<f32> {
         Self::default()
     }
+
+    pub fn lerp(&self, other: &Vector2<f32>, t: f32) -> Vector2<f32> {
+        Vector2 {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+        }
+    }
 }
 
 impl Vector2<u32> {
@@ -76,6 +83,63 @@ impl Vector3<f32> {
             z: 0.0,
         }
     }
+
+    pub fn lerp(&self, other: &Vector3<f32>, t: f32) -> Vector3<f32> {
+        Vector3 {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            z: self.z + (other.z - self.z) * t,
+        }
+    }
+
+    pub fn add(&self, other: &Vector3<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    pub fn sub(&self, other: &Vector3<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+
+    pub fn scale(&self, s: f32) -> Vector3<f32> {
+        Vector3 {
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+
+    pub fn dot(&self, other: &Vector3<f32>) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Vector3<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Unit vector in the same direction, zero vectors are returned as-is
+    pub fn normalize(&self) -> Vector3<f32> {
+        let len = self.length();
+        if len == 0.0 {
+            return *self;
+        }
+        self.scale(1.0 / len)
+    }
 }
 
 impl Vector3<i16> {
@@ -101,6 +165,93 @@ impl Vector4<f32> {
             z: 0.0,
         }
     }
+
+    /// Quaternion with no rotation
+    pub fn identity() -> Quaternion {
+        Vector4 {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    /// Quaternion rotating `angle` radians around `axis`
+    pub fn from_axis_angle(axis: Vector3<f32>, angle: f32) -> Quaternion {
+        let len = (axis.x * axis.x + axis.y * axis.y + axis.z * axis.z).sqrt();
+        if len == 0.0 {
+            return Quaternion::identity();
+        }
+
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        let s = sin / len;
+        Vector4 {
+            w: cos,
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+        }
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Hamilton product, `self` is applied after `other`
+    pub fn multiply(&self, other: &Quaternion) -> Quaternion {
+        Vector4 {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let len = self.dot(self).sqrt();
+        if len == 0.0 {
+            return Quaternion::identity();
+        }
+
+        Vector4 {
+            w: self.w / len,
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+        }
+    }
+
+    /// Spherical linear interpolation along the shortest path
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            cos = -cos;
+            other = Vector4 {
+                w: -other.w,
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+            };
+        }
+
+        // Fall back to a normalized lerp when the rotations are nearly equal
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Vector4 {
+            w: self.w * a + other.w * b,
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+        }
+        .normalize()
+    }
 }
 
 impl Vector4<i16> {
@@ -114,6 +265,84 @@ impl Vector4<i16> {
     }
 }
 
+/// 4x4 matrix stored as `m[row][column]`, transforming column vectors
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix4 { m }
+    }
+
+    /// Translation * rotation * uniform scale
+    pub fn from_transform(
+        translation: &Vector3<f32>,
+        rotation: &Quaternion,
+        scale: f32,
+    ) -> Matrix4 {
+        let q = rotation.normalize();
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+
+        Matrix4 {
+            m: [
+                [
+                    (1.0 - 2.0 * (y * y + z * z)) * scale,
+                    2.0 * (x * y - w * z) * scale,
+                    2.0 * (x * z + w * y) * scale,
+                    translation.x,
+                ],
+                [
+                    2.0 * (x * y + w * z) * scale,
+                    (1.0 - 2.0 * (x * x + z * z)) * scale,
+                    2.0 * (y * z - w * x) * scale,
+                    translation.y,
+                ],
+                [
+                    2.0 * (x * z - w * y) * scale,
+                    2.0 * (y * z + w * x) * scale,
+                    (1.0 - 2.0 * (x * x + y * y)) * scale,
+                    translation.z,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// `self * other`, `other` is applied first
+    pub fn multiply(&self, other: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[r][k] * other.m[k][c]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+
+    pub fn transform_point(&self, p: &Vector3<f32>) -> Vector3<f32> {
+        let m = &self.m;
+        Vector3 {
+            x: m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            y: m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            z: m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        }
+    }
+
+    pub fn translation(&self) -> Vector3<f32> {
+        Vector3 {
+            x: self.m[0][3],
+            y: self.m[1][3],
+            z: self.m[2][3],
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct BoundingBox<T> {
     pub min: Vector3<T>,
@@ -125,3 +354,48 @@ pub struct BoundingCylinder {
     pub center: Vector2<i32>,
     pub radius: f32,
 }
+
+/// Deterministic xorshift generator so simulations can be repeated
+#[derive(Clone, Debug)]
+pub(crate) struct Random(u32);
+
+impl Random {
+    pub(crate) fn new(seed: u32) -> Random {
+        // Scramble the seed so nearby seeds diverge, the state must be non-zero
+        let state = seed.wrapping_mul(0x9E37_79B9) ^ 0x5EED_1234;
+        Random(if state == 0 { 1 } else { state })
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+}
+
+/// Script comparison: equal (0), greater (1), greater or equal (2), less (3),
+/// less or equal (4) or not equal (10)
+pub(crate) fn compare(op: u8, value: i64, target: i64) -> bool {
+    match op {
+        0 => value == target,
+        1 => value > target,
+        2 => value >= target,
+        3 => value < target,
+        4 => value <= target,
+        10 => value != target,
+        _ => false,
+    }
+}
+
+pub(crate) fn in_range<T: PartialOrd>(value: T, min: T, max: T) -> bool {
+    min <= value && value <= max
+}
+
+/// Whether an `(hour, minute)` time is between `start` and `end`
+pub(crate) fn in_time(time: (u8, u8), start: (u8, u8), end: (u8, u8)) -> bool {
+    let minutes = |(h, m): (u8, u8)| u32::from(h) * 60 + u32::from(m);
+    in_range(minutes(time), minutes(start), minutes(end))
+}
@@ -0,0 +1,202 @@
+use std::io::Cursor;
+
+use roselib::ai::{AiCheck, AiCommand, AiEntity, AiInterpreter, AiWorld};
+use roselib::files::aip::{
+    AiAction, AiCondition, AiEvent, AiPattern, AI_EVENT_DAMAGED, AI_EVENT_DEAD, AI_EVENT_IDLE,
+    AI_EVENT_SPAWN,
+};
+use roselib::files::AIP;
+use roselib::io::RoseFile;
+use roselib::utils::Vector3;
+
+fn pattern(name: &str, conditions: Vec<AiCondition>, actions: Vec<AiAction>) -> AiPattern {
+    AiPattern {
+        name: String::from(name),
+        conditions,
+        actions,
+    }
+}
+
+fn event(name: &str, patterns: Vec<AiPattern>) -> AiEvent {
+    AiEvent {
+        name: String::from(name),
+        patterns,
+    }
+}
+
+/// Wander while idle, attack nearby players and flee when hurt
+fn jelly_bean() -> AIP {
+    let mut aip = AIP::new();
+    aip.name = String::from("Jelly Bean");
+    aip.idle_interval = 5;
+    aip.damage_rate = 100;
+    aip.events = vec![
+        event(
+            "spawn",
+            vec![pattern(
+                "greet",
+                vec![],
+                vec![AiAction::Emote { motion: 1 }],
+            )],
+        ),
+        event(
+            "idle",
+            vec![
+                pattern(
+                    "aggro",
+                    vec![AiCondition::FindNearest {
+                        distance: 500,
+                        level_min: -5,
+                        level_max: 5,
+                        is_allied: false,
+                    }],
+                    vec![AiAction::AttackNearest],
+                ),
+                pattern(
+                    "wander",
+                    vec![],
+                    vec![AiAction::MoveRandomFromSpawn {
+                        distance: 200,
+                        speed: 0,
+                    }],
+                ),
+            ],
+        ),
+        event("attack_move", vec![]),
+        event(
+            "damaged",
+            vec![
+                pattern(
+                    "flee",
+                    vec![AiCondition::HealthPercent {
+                        percent: 30,
+                        more_less: 1,
+                    }],
+                    vec![
+                        AiAction::Say { string_id: 12 },
+                        AiAction::RunAway { distance: 1000 },
+                    ],
+                ),
+                pattern("retaliate", vec![], vec![AiAction::AttackAttacker]),
+            ],
+        ),
+        event("kill", vec![]),
+        event(
+            "dead",
+            vec![pattern(
+                "drop",
+                vec![AiCondition::Unknown {
+                    id: 99,
+                    data: vec![1, 2, 3, 4],
+                }],
+                vec![AiAction::DropItem {
+                    items: [101, 0, 0, 0, 0],
+                    to_owner: false,
+                }],
+            )],
+        ),
+    ];
+    aip
+}
+
+fn player(x: f32, level: i32) -> AiEntity {
+    AiEntity {
+        position: Vector3 { x, y: 0.0, z: 0.0 },
+        level,
+        health: 100,
+        max_health: 100,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn write_aip() {
+    let mut orig_aip = jelly_bean();
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_aip.write(&mut cursor).unwrap();
+    let data = cursor.into_inner();
+
+    let mut new_aip = AIP::new();
+    new_aip.read(&mut Cursor::new(data.clone())).unwrap();
+    assert_eq!(orig_aip, new_aip);
+
+    let mut cursor = Cursor::new(Vec::new());
+    new_aip.write(&mut cursor).unwrap();
+    assert_eq!(data, cursor.into_inner());
+}
+
+#[test]
+fn write_aip_long_name() {
+    let mut aip = jelly_bean();
+    aip.events[0].name = "a".repeat(40);
+    assert!(aip.write(&mut Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn run_aip() {
+    let aip = jelly_bean();
+
+    let world = AiWorld {
+        monster: player(0.0, 10),
+        entities: vec![player(2000.0, 10), player(300.0, 30)],
+        ..Default::default()
+    };
+
+    let mut ai = AiInterpreter::new(&aip, world, 1);
+
+    let step = ai.step(AI_EVENT_SPAWN);
+    assert_eq!(step.commands, vec![AiCommand::Emote(1)]);
+
+    // Players are too far away or too high level, wander around the spawn
+    let steps = ai.advance(11.0);
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0].pattern, Some(1));
+    assert_eq!(steps[0].conditions[0].result, AiCheck::Failed);
+    match steps[1].commands[0] {
+        AiCommand::Move { position, .. } => {
+            assert!((position.x * position.x + position.y * position.y).sqrt() <= 200.0)
+        }
+        ref command => panic!("Unexpected command: {:?}", command),
+    }
+
+    // A player walks up
+    ai.world.entities[0].position.x = 100.0;
+    let steps = ai.advance(5.0);
+    assert_eq!(steps[0].pattern, Some(0));
+    assert_eq!(steps[0].commands, vec![AiCommand::Attack { target: 0 }]);
+    assert_eq!(ai.world.target, Some(0));
+
+    // No idle events while fighting
+    assert!(ai.advance(20.0).is_empty());
+
+    let step = ai.damage(1, 10).unwrap();
+    assert_eq!(step.event, AI_EVENT_DAMAGED);
+    assert_eq!(step.commands, vec![AiCommand::Attack { target: 1 }]);
+
+    let step = ai.damage(1, 70).unwrap();
+    assert_eq!(step.pattern, Some(0));
+    assert_eq!(step.commands[0], AiCommand::Say(12));
+    assert!(ai.world.target.is_none());
+    assert!(ai.world.monster.position.x < -500.0);
+
+    // Unsupported conditions fail
+    let step = ai.step(AI_EVENT_DEAD);
+    assert_eq!(step.pattern, None);
+    assert_eq!(step.conditions[0].result, AiCheck::Unsupported);
+
+    // Missing events do nothing
+    assert!(ai.step(AI_EVENT_IDLE + 10).commands.is_empty());
+}
+
+#[test]
+fn aip_random_seed() {
+    // This seed scrambles to a zero state, which would roll 0 forever
+    let aip = jelly_bean();
+    let mut ai = AiInterpreter::new(&aip, AiWorld::default(), 0x5DAC_EDD4);
+    let condition = AiCondition::RandomPercent { percent: 1 };
+    let passed = (0..100)
+        .filter(|_| ai.check(&condition) == AiCheck::Passed)
+        .count();
+    assert!(passed < 10);
+}
@@ -0,0 +1,91 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use roselib::files::chr::{Character, CharacterEffect, CharacterMotion};
+use roselib::files::CHR;
+use roselib::io::{RoseFile, WriteRoseExt};
+
+fn character_list() -> CHR {
+    let mut chr = CHR::new();
+    chr.skeletons.push(PathBuf::from("3DData\\NPC\\jelly.zmd"));
+    chr.motions
+        .push(PathBuf::from("3DData\\Motion\\jelly_stop.zmo"));
+    chr.motions
+        .push(PathBuf::from("3DData\\Motion\\jelly_walk.zmo"));
+    chr.effects.push(PathBuf::from("3DData\\Effect\\jelly.eft"));
+
+    chr.characters.push(None);
+    chr.characters.push(Some(Character {
+        skeleton_id: 0,
+        name: String::from("Jelly Bean"),
+        objects: vec![1, 2],
+        motions: vec![
+            CharacterMotion {
+                action_id: 0,
+                motion_id: 0,
+            },
+            CharacterMotion {
+                action_id: 1,
+                motion_id: 1,
+            },
+        ],
+        effects: vec![CharacterEffect {
+            bone_index: 3,
+            effect_id: 0,
+        }],
+    }));
+    chr
+}
+
+#[test]
+fn read_chr() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u16(1).unwrap();
+    cursor.write_cstring("skeleton.zmd").unwrap();
+    cursor.write_u16(1).unwrap();
+    cursor.write_cstring("motion.zmo").unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_u16(2).unwrap();
+    cursor.write_bool(false).unwrap();
+    cursor.write_bool(true).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_cstring("Monster").unwrap();
+    cursor.write_u16(1).unwrap();
+    cursor.write_u16(7).unwrap();
+    cursor.write_u16(1).unwrap();
+    cursor.write_u16(2).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.set_position(0);
+
+    let mut chr = CHR::new();
+    chr.read(&mut cursor).unwrap();
+
+    assert_eq!(chr.characters.len(), 2);
+    assert!(chr.character(0).is_none());
+
+    let character = chr.character(1).unwrap();
+    assert_eq!(character.name, "Monster");
+    assert_eq!(character.objects, vec![7]);
+    assert_eq!(chr.skeleton(1), Some(&PathBuf::from("skeleton.zmd")));
+    assert_eq!(chr.motion(1, 2), Some(&PathBuf::from("motion.zmo")));
+    assert_eq!(chr.motion(1, 0), None);
+}
+
+#[test]
+fn write_chr() {
+    let mut orig_chr = character_list();
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_chr.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_chr = CHR::new();
+    new_chr.read(&mut cursor).unwrap();
+
+    assert_eq!(orig_chr, new_chr);
+    assert_eq!(
+        new_chr.effects(1),
+        vec![(3, &PathBuf::from("3DData\\Effect\\jelly.eft"))]
+    );
+}
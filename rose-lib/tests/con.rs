@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use roselib::files::con::{
+    ConversationMenu, ConversationMessage, MESSAGE_CLOSE, MESSAGE_JUMP_SELECT,
+    MESSAGE_PLAYER_SELECT,
+};
+use roselib::files::CON;
+use roselib::io::RoseFile;
+
+fn message(id: u32, message_type: u32, value: u32, condition: &str) -> ConversationMessage {
+    ConversationMessage {
+        id,
+        message_type,
+        value,
+        condition: String::from(condition),
+        action: format!("action_{}", id),
+        string_id: id * 10,
+    }
+}
+
+fn conversation() -> CON {
+    let mut con = CON::new();
+    con.mask = 3;
+    con.messages.push(message(0, MESSAGE_JUMP_SELECT, 1, ""));
+    con.menus.push(ConversationMenu {
+        messages: vec![message(1, MESSAGE_CLOSE, 0, "")],
+    });
+    con.menus.push(ConversationMenu {
+        messages: vec![
+            message(2, MESSAGE_PLAYER_SELECT, 0, "has_quest"),
+            message(3, MESSAGE_CLOSE, 0, ""),
+        ],
+    });
+    con.script = b"function has_quest() return true end".to_vec();
+    con
+}
+
+#[test]
+fn write_con() {
+    let mut orig_con = conversation();
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_con.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_con = CON::new();
+    new_con.read(&mut cursor).unwrap();
+
+    assert_eq!(orig_con, new_con);
+    assert!(!new_con.script_compiled());
+
+    let menu = new_con.jump_menu(&new_con.messages[0]).unwrap();
+    assert_eq!(menu.messages[0].condition, "has_quest");
+    assert!(new_con.jump_menu(&menu.messages[0]).is_none());
+}
+
+#[test]
+fn read_con_function_name_garbage() {
+    let con = conversation();
+    let mut cursor = Cursor::new(Vec::new());
+    con.clone().write(&mut cursor).unwrap();
+
+    // Fill the rest of a function name after its terminator
+    let mut data = cursor.into_inner();
+    let start = data.windows(10).position(|w| w == b"has_quest\0").unwrap();
+    data[start + 10..start + 16].copy_from_slice(b"_old\0x");
+
+    let mut new_con = CON::new();
+    new_con.read(&mut Cursor::new(data)).unwrap();
+    assert_eq!(new_con, con);
+}
+
+#[test]
+fn read_con_invalid_offsets() {
+    let mut cursor = Cursor::new(Vec::new());
+    conversation().write(&mut cursor).unwrap();
+
+    // First menu offset relative to the menu table
+    let mut data = cursor.into_inner();
+    let menus_offset = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let mut bad_menu = data.clone();
+    bad_menu[menus_offset + 4..menus_offset + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let mut con = CON::new();
+    assert!(con.read(&mut Cursor::new(bad_menu)).is_err());
+
+    // Script size past the end of the file
+    let script_offset = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    data[script_offset..script_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let mut con = CON::new();
+    assert!(con.read(&mut Cursor::new(data)).is_err());
+}
+
+#[test]
+fn write_con_long_function_name() {
+    let mut con = conversation();
+    con.messages[0].action = "a".repeat(40);
+
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(con.write(&mut cursor).is_err());
+}
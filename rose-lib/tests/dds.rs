@@ -0,0 +1,123 @@
+use std::io::Cursor;
+
+use roselib::files::dds::DdsFormat;
+use roselib::files::DDS;
+use roselib::io::RoseFile;
+
+/// Four 4x4 quadrants of solid colors, the bottom right one transparent
+fn quadrants(width: u32, height: u32) -> Vec<u8> {
+    let mut rgba = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = match (x < width / 2, y < height / 2) {
+                (true, true) => [255, 0, 0, 255],
+                (false, true) => [0, 255, 0, 255],
+                (true, false) => [0, 0, 255, 255],
+                (false, false) => [0, 0, 0, 0],
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+    rgba
+}
+
+fn write_read(dds: &mut DDS) -> DDS {
+    let mut cursor = Cursor::new(Vec::new());
+    dds.write(&mut cursor).unwrap();
+    let data = cursor.into_inner();
+
+    let mut new_dds = DDS::new();
+    new_dds.read(&mut Cursor::new(data.clone())).unwrap();
+
+    let mut cursor = Cursor::new(Vec::new());
+    new_dds.write(&mut cursor).unwrap();
+    assert_eq!(data, cursor.into_inner());
+
+    new_dds
+}
+
+#[test]
+fn write_dds() {
+    for format in DdsFormat::all() {
+        let mut dds = DDS::encode(16, 8, &quadrants(16, 8), *format, true).unwrap();
+        assert_eq!(dds.mipmap_count(), 5);
+        assert_eq!(dds.level_dimensions(4), (1, 1));
+
+        let new_dds = write_read(&mut dds);
+        assert_eq!(dds, new_dds);
+    }
+}
+
+#[test]
+fn dds_decode() {
+    let rgba = quadrants(8, 8);
+
+    // Formats that can represent the image exactly
+    for format in &[
+        DdsFormat::Dxt1,
+        DdsFormat::Dxt3,
+        DdsFormat::Dxt5,
+        DdsFormat::A8R8G8B8,
+        DdsFormat::A1R5G5B5,
+        DdsFormat::A4R4G4B4,
+    ] {
+        let dds = DDS::encode(8, 8, &rgba, *format, false).unwrap();
+        assert_eq!(dds.mipmap_count(), 1);
+        assert_eq!(dds.decode(0).unwrap(), rgba, "{}", format);
+    }
+
+    // Formats without alpha are opaque
+    let dds = DDS::encode(8, 8, &rgba, DdsFormat::R5G6B5, false).unwrap();
+    let decoded = dds.decode(0).unwrap();
+    assert_eq!(&decoded[..4], &[255, 0, 0, 255]);
+    assert_eq!(&decoded[decoded.len() - 4..], &[0, 0, 0, 255]);
+
+    assert!(dds.decode(1).is_err());
+}
+
+#[test]
+fn dds_mipmaps() {
+    // Partial blocks and non-square mipmaps
+    let rgba: Vec<u8> = (0..6 * 3)
+        .flat_map(|i| vec![i as u8 * 10, 128, 64, 255])
+        .collect();
+    let dds = DDS::encode(6, 3, &rgba, DdsFormat::Dxt5, true).unwrap();
+    assert_eq!(dds.mipmap_count(), 3);
+    assert_eq!(dds.mipmaps[0].len(), 2 * 16);
+    assert_eq!(dds.level_dimensions(1), (3, 1));
+
+    let level = dds.decode(2).unwrap();
+    assert_eq!(level.len(), 4);
+    assert_eq!(level[3], 255);
+
+    assert!(DDS::encode(6, 3, &rgba[4..], DdsFormat::Dxt5, true).is_err());
+}
+
+#[test]
+fn dds_gradient() {
+    // Lossy compression stays close to the source along a color ramp
+    let rgba: Vec<u8> = (0..16 * 16)
+        .flat_map(|i| vec![(i % 16) as u8 * 16, 255 - (i % 16) as u8 * 8, 96, 255])
+        .collect();
+
+    for format in &[DdsFormat::Dxt1, DdsFormat::Dxt5] {
+        let dds = DDS::encode(16, 16, &rgba, *format, false).unwrap();
+        let decoded = dds.decode(0).unwrap();
+        let max_error = rgba
+            .iter()
+            .zip(decoded.iter())
+            .map(|(a, b)| (*a as i32 - *b as i32).abs())
+            .max()
+            .unwrap();
+        assert!(max_error <= 8, "{}: {}", format, max_error);
+    }
+}
+
+#[test]
+fn dds_invalid() {
+    let mut dds = DDS::new();
+    assert!(dds.read(&mut Cursor::new(b"PNG ".to_vec())).is_err());
+    assert!(dds.write(&mut Cursor::new(Vec::new())).is_err());
+    assert!("dxt2".parse::<DdsFormat>().is_err());
+    assert_eq!("DXT5".parse::<DdsFormat>().unwrap(), DdsFormat::Dxt5);
+}
@@ -0,0 +1,61 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use roselib::files::eft::{EffectAnimation, EffectLink, EffectMesh, EffectParticle};
+use roselib::files::EFT;
+use roselib::io::RoseFile;
+use roselib::utils::{Quaternion, Vector3};
+
+#[test]
+fn write_eft() {
+    let mut orig_eft = EFT::new();
+    orig_eft.name = String::from("fire");
+    orig_eft.sound_enabled = true;
+    orig_eft.sound_path = PathBuf::from("3DData\\Sound\\fire.wav");
+    orig_eft.sound_repeat_count = 2;
+
+    orig_eft.particles.push(EffectParticle {
+        name: String::from("flame"),
+        unique_name: String::from("flame_01"),
+        particle_index: 1,
+        particle_path: PathBuf::from("3DData\\Effect\\flame.ptl"),
+        animation: EffectAnimation {
+            enabled: true,
+            path: PathBuf::from("3DData\\Effect\\flame.zmo"),
+            repeat_count: 0,
+        },
+        link: EffectLink {
+            point_index: 2,
+            position: Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 50.0,
+            },
+            rotation: Quaternion::identity(),
+            start_delay: 100,
+            linked: true,
+        },
+    });
+
+    orig_eft.meshes.push(EffectMesh {
+        name: String::from("ring"),
+        mesh_path: PathBuf::from("3DData\\Effect\\ring.zms"),
+        texture_path: PathBuf::from("3DData\\Effect\\ring.dds"),
+        alpha_enabled: true,
+        src_blend: 5,
+        dst_blend: 2,
+        blend_op: 1,
+        repeat_count: 3,
+        ..Default::default()
+    });
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_eft.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_eft = EFT::new();
+    new_eft.read(&mut cursor).unwrap();
+
+    assert_eq!(orig_eft, new_eft);
+    assert_eq!(cursor.position(), cursor.get_ref().len() as u64);
+}
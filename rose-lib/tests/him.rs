@@ -1,5 +1,7 @@
+use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::files::him::HeightmapPatch;
 use roselib::files::HIM;
 use roselib::io::RoseFile;
 
@@ -19,3 +21,74 @@ fn read_him() {
     assert_eq!(him.min_height, 0.0);
     assert_eq!(him.max_height, 5463.6577);
 }
+
+#[test]
+fn write_him() {
+    let mut him = HIM::new();
+    him.width = 9;
+    him.length = 9;
+    him.grid_count = 2;
+    him.scale = 250.0;
+    him.heights = (0..81).map(|i| (i % 9 + i / 9 * 100) as f32).collect();
+    him.update_patches().unwrap();
+
+    assert_eq!(him.min_height, 0.0);
+    assert_eq!(him.max_height, 808.0);
+
+    // 4x4 patches sharing their edges
+    assert_eq!(him.patches.len(), 16);
+    assert_eq!(
+        him.patches[5],
+        HeightmapPatch {
+            max_height: 404.0,
+            min_height: 202.0,
+        }
+    );
+
+    // Quadtree with the whole heightmap and its quarters
+    assert_eq!(him.quad_patches.len(), 5);
+    assert_eq!(him.quad_patches[0].max_height, 808.0);
+    assert_eq!(him.quad_patches[4].min_height, 404.0);
+
+    let mut cursor = Cursor::new(Vec::new());
+    him.write(&mut cursor).unwrap();
+    let data = cursor.into_inner();
+
+    let mut new_him = HIM::new();
+    new_him.read(&mut Cursor::new(data.clone())).unwrap();
+    assert_eq!(new_him.heights, him.heights);
+    assert_eq!(new_him.patches, him.patches);
+    assert_eq!(new_him.quad_patches, him.quad_patches);
+
+    let mut cursor = Cursor::new(Vec::new());
+    new_him.write(&mut cursor).unwrap();
+    assert_eq!(data, cursor.into_inner());
+
+    him.grid_count = 3;
+    assert!(him.update_patches().is_err());
+    him.heights.pop();
+    assert!(him.write(&mut Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn read_him_patch_count() {
+    let mut him = HIM::new();
+    him.width = 9;
+    him.length = 9;
+    him.grid_count = 2;
+    him.heights = vec![0.0; 81];
+    him.update_patches().unwrap();
+
+    let mut cursor = Cursor::new(Vec::new());
+    him.write(&mut cursor).unwrap();
+    let mut data = cursor.into_inner();
+
+    // Patch count claiming far more patches than the file has
+    let name_len = data[16 + 81 * 4] as usize;
+    let patch_count = 16 + 81 * 4 + 1 + name_len;
+    data.truncate(patch_count);
+    data.extend_from_slice(&i32::MAX.to_le_bytes());
+
+    let mut new_him = HIM::new();
+    assert!(new_him.read(&mut Cursor::new(data)).is_err());
+}
@@ -2,8 +2,10 @@ use std::fs::File;
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::files::lit::{LightmapPart, LightmapRect};
 use roselib::files::LIT;
 use roselib::io::RoseFile;
+use roselib::utils::Vector2;
 
 #[test]
 fn read_lit() {
@@ -103,3 +105,62 @@ fn write_lit() {
     assert_eq!(last_part.parts_per_width, 16);
     assert_eq!(last_part.part_position, 52);
 }
+
+#[test]
+fn lit_part_rect() {
+    let part = LightmapPart {
+        name: String::from("stonewall03_Object_266_0_32_32_LightingMap.tga"),
+        id: 0,
+        filename: String::from("Object_32_0.dds"),
+        lightmap_index: 0,
+        pixels_per_part: 32,
+        parts_per_width: 16,
+        part_position: 52,
+    };
+
+    let rect = part.rect().unwrap();
+    assert_eq!(
+        rect,
+        LightmapRect {
+            x: 128,
+            y: 96,
+            size: 32,
+            atlas_size: 512,
+        }
+    );
+
+    let (min, max) = part.uv_rect().unwrap();
+    assert_eq!(min, Vector2 { x: 0.25, y: 0.1875 });
+    assert_eq!(max, Vector2 { x: 0.3125, y: 0.25 });
+    assert_eq!(
+        rect.to_atlas_uv(&Vector2 { x: 0.5, y: 1.0 }),
+        Vector2 {
+            x: 0.28125,
+            y: 0.25
+        }
+    );
+
+    let mut lit = LIT::new();
+    lit.filenames.push(String::from("Object_32_0.dds"));
+    assert_eq!(lit.atlas_filename(&part), Some("Object_32_0.dds"));
+
+    let mut unnamed = LightmapPart {
+        filename: String::new(),
+        ..part
+    };
+    assert_eq!(lit.atlas_filename(&unnamed), Some("Object_32_0.dds"));
+
+    unnamed.part_position = 256;
+    assert!(unnamed.rect().is_none());
+
+    // Part counts past i32 don't overflow, atlas sizes past u32 have no rectangle
+    unnamed.parts_per_width = 50000;
+    unnamed.part_position = 2_000_000_000;
+    let rect = unnamed.rect().unwrap();
+    assert_eq!((rect.x, rect.y), (0, 1_280_000));
+
+    unnamed.pixels_per_part = 1 << 20;
+    unnamed.parts_per_width = 1 << 13;
+    unnamed.part_position = 0;
+    assert!(unnamed.rect().is_none());
+}
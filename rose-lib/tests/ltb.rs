@@ -0,0 +1,74 @@
+use std::io::Cursor;
+
+use roselib::files::stl::{
+    ItemRowData, StringTableKey, StringTableLanguage, StringTableLanguageTable, StringTableRow,
+    StringTableType,
+};
+use roselib::files::{LTB, STL};
+use roselib::io::RoseFile;
+
+fn row(cells: &[&str]) -> Vec<String> {
+    cells.iter().map(|s| s.to_string()).collect()
+}
+
+fn language_table() -> LTB {
+    let mut ltb = LTB::new();
+    ltb.column_count = 3;
+    ltb.rows.push(row(&["UI_OK", "OK", "확인"]));
+    ltb.rows.push(row(&["UI_CANCEL", "Cancel", "취소"]));
+    ltb.rows.push(row(&["UI_EMPTY", "", ""]));
+    ltb
+}
+
+#[test]
+fn write_ltb() {
+    let mut orig_ltb = language_table();
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_ltb.write(&mut cursor).unwrap();
+    let data = cursor.into_inner();
+
+    let mut new_ltb = LTB::new();
+    new_ltb.read(&mut Cursor::new(data.clone())).unwrap();
+    assert_eq!(orig_ltb, new_ltb);
+    assert_eq!(new_ltb.get(1, 2), "취소");
+
+    let mut cursor = Cursor::new(Vec::new());
+    new_ltb.write(&mut cursor).unwrap();
+    assert_eq!(data, cursor.into_inner());
+}
+
+#[test]
+fn ltb_string_table() {
+    let ltb = language_table();
+
+    let stl = ltb.to_string_table(StringTableType::Normal).unwrap();
+    assert_eq!(stl.language_count(), 2);
+    assert_eq!(stl.row_count(), 3);
+    assert_eq!(stl.keys[1].name, "UI_CANCEL");
+    assert_eq!(LTB::from(&stl), ltb);
+
+    assert!(ltb.to_string_table(StringTableType::Quest).is_err());
+}
+
+#[test]
+fn ltb_from_string_table() {
+    let mut stl = STL::new();
+    stl.format = StringTableType::Item;
+    stl.keys.push(StringTableKey {
+        id: 0,
+        name: String::from("ITEM_CAP"),
+    });
+    stl.language_tables.push(StringTableLanguageTable {
+        language: StringTableLanguage::from(0),
+        rows: vec![StringTableRow::ItemRow(ItemRowData {
+            text: String::from("Cap"),
+            description: String::from("A simple cap"),
+        })],
+    });
+
+    let ltb = LTB::from(&stl);
+    assert_eq!(ltb.column_count, 3);
+    assert_eq!(ltb.rows[0], row(&["ITEM_CAP", "Cap", "A simple cap"]));
+    assert_eq!(ltb.to_string_table(StringTableType::Item).unwrap(), stl);
+}
@@ -0,0 +1,240 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use roselib::files::ptl::{
+    ParticleEmitter, ParticleEvent, ParticleEventData, ParticleRange, ParticleSequence,
+};
+use roselib::files::PTL;
+use roselib::io::{RoseFile, WriteRoseExt};
+use roselib::utils::{Color4, Vector2, Vector3};
+
+#[test]
+fn write_ptl() {
+    let mut sequence = ParticleSequence::new();
+    sequence.name = String::from("sparks");
+    sequence.lifetime = ParticleRange { min: 1.0, max: 2.0 };
+    sequence.emit_rate = ParticleRange {
+        min: 10.0,
+        max: 20.0,
+    };
+    sequence.texture_path = PathBuf::from("3DData\\Effect\\spark.dds");
+    sequence.particle_count = 50;
+    sequence.texture_columns = 2;
+    sequence.texture_rows = 2;
+
+    sequence.events.push(ParticleEvent {
+        start_time: ParticleRange::default(),
+        fade: false,
+        data: ParticleEventData::Size(ParticleRange {
+            min: Vector2 { x: 1.0, y: 1.0 },
+            max: Vector2 { x: 2.0, y: 2.0 },
+        }),
+    });
+    sequence.events.push(ParticleEvent {
+        start_time: ParticleRange { min: 0.5, max: 0.5 },
+        fade: true,
+        data: ParticleEventData::Color(ParticleRange {
+            min: Color4 {
+                r: 1.0,
+                g: 0.5,
+                b: 0.0,
+                a: 1.0,
+            },
+            max: Color4 {
+                r: 1.0,
+                g: 1.0,
+                b: 0.0,
+                a: 0.0,
+            },
+        }),
+    });
+    sequence.events.push(ParticleEvent {
+        start_time: ParticleRange { min: 1.0, max: 1.0 },
+        fade: true,
+        data: ParticleEventData::Rotation(ParticleRange {
+            min: 0.0,
+            max: 90.0,
+        }),
+    });
+
+    let mut orig_ptl = PTL::new();
+    orig_ptl.sequences.push(sequence);
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_ptl.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_ptl = PTL::new();
+    new_ptl.read(&mut cursor).unwrap();
+
+    assert_eq!(orig_ptl, new_ptl);
+    assert_eq!(cursor.position(), cursor.get_ref().len() as u64);
+}
+
+#[test]
+fn read_ptl_invalid_event() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u32(1).unwrap();
+    cursor.write_string_u32("bad").unwrap();
+    for _ in 0..(2 + 2 + 1 + 6 * 3) {
+        cursor.write_u32(0).unwrap();
+    }
+    cursor.write_string_u32("").unwrap();
+    for _ in 0..8 {
+        cursor.write_u32(0).unwrap();
+    }
+    cursor.write_u32(1).unwrap();
+    cursor.write_u32(99).unwrap();
+    cursor.set_position(0);
+
+    let mut ptl = PTL::new();
+    assert!(ptl.read(&mut cursor).is_err());
+}
+
+fn emitter_sequence() -> ParticleSequence {
+    let mut sequence = ParticleSequence::new();
+    sequence.lifetime = ParticleRange { min: 1.0, max: 1.0 };
+    sequence.emit_rate = ParticleRange {
+        min: 10.0,
+        max: 10.0,
+    };
+    sequence.particle_count = 100;
+    sequence.gravity = ParticleRange {
+        min: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -10.0,
+        },
+        max: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -10.0,
+        },
+    };
+    sequence.events.push(ParticleEvent {
+        start_time: ParticleRange::default(),
+        fade: false,
+        data: ParticleEventData::Alpha(ParticleRange { min: 1.0, max: 1.0 }),
+    });
+    sequence.events.push(ParticleEvent {
+        start_time: ParticleRange { min: 1.0, max: 1.0 },
+        fade: true,
+        data: ParticleEventData::Alpha(ParticleRange { min: 0.0, max: 0.0 }),
+    });
+    sequence
+}
+
+#[test]
+fn simulate_ptl() {
+    let mut ptl = PTL::new();
+    ptl.sequences.push(emitter_sequence());
+
+    let frames = ptl.simulate(10.0, 21, 7).unwrap();
+    assert_eq!(frames.len(), 21);
+    assert!(frames[0].is_empty());
+
+    // One particle per step at 10 particles per second
+    assert_eq!(frames[1].len(), 1);
+    assert_eq!(frames[5].len(), 5);
+
+    // Particles live for one second
+    assert!(frames[20].len() <= 10);
+    assert!(frames[20].iter().all(|p| p.age < 1.0));
+
+    // Alpha fades out over the lifetime and gravity pulls particles down
+    let oldest = frames[5]
+        .iter()
+        .max_by(|a, b| a.age.partial_cmp(&b.age).unwrap())
+        .unwrap();
+    assert!((oldest.color.a - (1.0 - oldest.age)).abs() < 1e-4);
+    assert!(oldest.position.z < 0.0);
+    assert!(oldest.velocity.z < 0.0);
+
+    assert_eq!(frames, ptl.simulate(10.0, 21, 7).unwrap());
+
+    assert!(ptl.simulate(0.0, 21, 7).is_err());
+    assert!(ptl.simulate(-10.0, 21, 7).is_err());
+}
+
+#[test]
+fn simulate_ptl_seed() {
+    let mut sequence = emitter_sequence();
+    sequence.emit_radius = ParticleRange {
+        min: Vector3 {
+            x: -10.0,
+            y: -10.0,
+            z: 0.0,
+        },
+        max: Vector3 {
+            x: 10.0,
+            y: 10.0,
+            z: 0.0,
+        },
+    };
+    let mut ptl = PTL::new();
+    ptl.sequences.push(sequence);
+
+    // The same seed gives the same particles, another seed moves them
+    let frames = ptl.simulate(10.0, 10, 3).unwrap();
+    assert_eq!(frames, ptl.simulate(10.0, 10, 3).unwrap());
+
+    let other = ptl.simulate(10.0, 10, 4).unwrap();
+    assert_eq!(frames[9].len(), other[9].len());
+    assert_ne!(frames[9], other[9]);
+}
+
+#[test]
+fn simulate_ptl_expiry() {
+    let mut sequence = emitter_sequence();
+    sequence.loop_count = 1;
+    sequence.particle_count = 3;
+    let mut ptl = PTL::new();
+    ptl.sequences.push(sequence);
+
+    // Three particles are emitted at 0.1s intervals and live for a second
+    let frames = ptl.simulate(10.0, 20, 1).unwrap();
+    assert_eq!(frames[3].len(), 3);
+    assert_eq!(frames[9].len(), 3);
+    for frame in &frames {
+        assert!(frame.iter().all(|p| p.age < p.lifetime));
+    }
+    assert!(frames[15].is_empty());
+    assert!(frames[19].is_empty());
+}
+
+#[test]
+fn simulate_ptl_gravity() {
+    let mut ptl = PTL::new();
+    ptl.sequences.push(emitter_sequence());
+
+    // The first particle is emitted at rest on frame 1, each step adds
+    // gravity * dt to its velocity before moving it
+    let frames = ptl.simulate(10.0, 6, 1).unwrap();
+    let oldest = frames[5]
+        .iter()
+        .max_by(|a, b| a.age.partial_cmp(&b.age).unwrap())
+        .unwrap();
+    assert!((oldest.age - 0.4).abs() < 1e-5);
+    assert!((oldest.velocity.z - -4.0).abs() < 1e-4);
+    assert!((oldest.position.z - -10.0 * 0.01 * (1 + 2 + 3 + 4) as f32).abs() < 1e-4);
+    assert_eq!(oldest.position.x, 0.0);
+}
+
+#[test]
+fn emitter_loops() {
+    let mut sequence = emitter_sequence();
+    sequence.loop_count = 1;
+    sequence.particle_count = 3;
+
+    let mut emitter = ParticleEmitter::new(&sequence, 0, 0);
+    for _ in 0..5 {
+        emitter.step(0.1);
+    }
+    assert_eq!(emitter.particles().len(), 3);
+    assert!(!emitter.finished());
+
+    for _ in 0..10 {
+        emitter.step(0.1);
+    }
+    assert!(emitter.finished());
+}
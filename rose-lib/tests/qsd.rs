@@ -0,0 +1,141 @@
+use std::io::Cursor;
+
+use roselib::files::qsd::{
+    QuestAbility, QuestAction, QuestCondition, QuestGroup, QuestItemCheck, QuestTrigger,
+    QuestVariable,
+};
+use roselib::files::QSD;
+use roselib::io::RoseFile;
+
+fn quest_script() -> QSD {
+    let mut qsd = QSD::new();
+    qsd.version = 1;
+    qsd.name = String::from("3Ballad");
+
+    let start = QuestTrigger {
+        name: String::from("ballad-01"),
+        check_next: true,
+        conditions: vec![
+            QuestCondition::SelectQuest { quest: 0 },
+            QuestCondition::AbilityValue(vec![QuestAbility {
+                ability_type: 31,
+                value: 10,
+                op: 2,
+            }]),
+            QuestCondition::QuestItems(vec![QuestItemCheck {
+                item: 12_001_005,
+                equipment_index: 0,
+                count: 5,
+                op: 1,
+            }]),
+            QuestCondition::RandomPercent { min: 0, max: 50 },
+            QuestCondition::MonthDayTime {
+                day: 1,
+                start_hour: 8,
+                start_minute: 0,
+                end_hour: 20,
+                end_minute: 30,
+            },
+        ],
+        actions: vec![
+            QuestAction::SetQuest {
+                quest: 1001,
+                option: 1,
+            },
+            QuestAction::SetQuestVariable(vec![QuestVariable {
+                index: 0,
+                variable_type: 0x1000,
+                value: 3,
+                op: 6,
+            }]),
+            QuestAction::CalculatedReward {
+                target: 0,
+                equation: 2,
+                base: 150,
+                percent: 100,
+                item: 0,
+                party: 0,
+                item_option: 0,
+            },
+            QuestAction::NextTrigger {
+                trigger: String::from("ballad-02"),
+            },
+            QuestAction::FormatAnnounceMessage {
+                string_id: 42,
+                arguments: vec![1, 0, 2, 0],
+            },
+            QuestAction::ResetSkills,
+        ],
+    };
+
+    let end = QuestTrigger {
+        name: String::from("ballad-02"),
+        check_next: false,
+        conditions: vec![QuestCondition::Unknown {
+            id: 99,
+            data: vec![1, 2, 3],
+        }],
+        actions: vec![
+            QuestAction::TriggerAfterDelay {
+                object: 1,
+                seconds: 30,
+                trigger: String::from("ballad-01"),
+            },
+            QuestAction::Unknown {
+                id: 99,
+                data: vec![4, 5, 6, 7],
+            },
+        ],
+    };
+
+    qsd.groups.push(QuestGroup {
+        name: String::from("Ballad"),
+        triggers: vec![start, end],
+    });
+    qsd
+}
+
+#[test]
+fn write_qsd() {
+    let mut orig_qsd = quest_script();
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_qsd.write(&mut cursor).unwrap();
+    let data = cursor.into_inner();
+
+    let mut cursor = Cursor::new(data.clone());
+    let mut new_qsd = QSD::new();
+    new_qsd.read(&mut cursor).unwrap();
+    assert_eq!(orig_qsd, new_qsd);
+
+    let trigger = new_qsd.trigger("ballad-02").unwrap();
+    assert!(!trigger.check_next);
+    assert!(new_qsd.trigger("ballad-03").is_none());
+
+    // Writing again produces the same bytes
+    let mut cursor = Cursor::new(Vec::new());
+    new_qsd.write(&mut cursor).unwrap();
+    assert_eq!(data, cursor.into_inner());
+}
+
+#[test]
+fn decode_qsd_records() {
+    let action = QuestAction::SetQuest {
+        quest: 5,
+        option: 4,
+    };
+    let data = action.encode().unwrap();
+    assert_eq!(data.len(), 8);
+    assert_eq!(QuestAction::decode(0, &data), action);
+
+    // Payloads that don't match the typed layout are kept as-is
+    let condition = QuestCondition::decode(0, &data);
+    assert_eq!(
+        condition,
+        QuestCondition::Unknown {
+            id: 0,
+            data: data.clone()
+        }
+    );
+    assert_eq!(condition.encode().unwrap(), data);
+}
@@ -0,0 +1,201 @@
+use roselib::files::qsd::{
+    QuestAbility, QuestAction, QuestCondition, QuestGroup, QuestItemCheck, QuestTrigger,
+    QuestVariable,
+};
+use roselib::files::QSD;
+use roselib::io::RoseFile;
+use roselib::quest::{
+    QuestCharacter, QuestCheck, QuestEngine, QuestEvent, QuestReward, QuestScenario, ABILITY_LEVEL,
+    VARIABLE_QUEST,
+};
+
+const QUEST: u32 = 1001;
+const JELLY: u32 = 12_001_005;
+
+fn trigger(name: &str, check_next: bool) -> QuestTrigger {
+    QuestTrigger {
+        name: String::from(name),
+        check_next,
+        conditions: Vec::new(),
+        actions: Vec::new(),
+    }
+}
+
+fn quest_variable(value: i16, op: u8) -> QuestVariable {
+    QuestVariable {
+        index: 0,
+        variable_type: VARIABLE_QUEST,
+        value,
+        op,
+    }
+}
+
+/// Accept the quest at level 10, then hand in 5 jellies for a reward
+fn jelly_quest() -> QSD {
+    let mut accept = trigger("jelly-accept", false);
+    accept
+        .conditions
+        .push(QuestCondition::AbilityValue(vec![QuestAbility {
+            ability_type: ABILITY_LEVEL,
+            value: 10,
+            op: 2,
+        }]));
+    accept.actions.push(QuestAction::SetQuest {
+        quest: QUEST,
+        option: 1,
+    });
+    accept
+        .actions
+        .push(QuestAction::SetQuestVariable(vec![quest_variable(1, 5)]));
+
+    let mut complete = trigger("jelly-complete", true);
+    complete.conditions.push(QuestCondition::SelectQuest {
+        quest: QUEST as i32,
+    });
+    complete
+        .conditions
+        .push(QuestCondition::QuestVariable(vec![quest_variable(1, 0)]));
+    complete
+        .conditions
+        .push(QuestCondition::QuestItems(vec![QuestItemCheck {
+            item: JELLY,
+            equipment_index: 0,
+            count: 5,
+            op: 2,
+        }]));
+    complete.actions.push(QuestAction::UpdateItem {
+        item: JELLY,
+        op: 0,
+        count: 5,
+        party: 0,
+    });
+    complete.actions.push(QuestAction::CalculatedReward {
+        target: 1,
+        equation: 0,
+        base: 200,
+        percent: 150,
+        item: 0,
+        party: 0,
+        item_option: 0,
+    });
+    complete.actions.push(QuestAction::NextTrigger {
+        trigger: String::from("jelly-finish"),
+    });
+
+    let mut not_done = trigger("jelly-not-done", false);
+    not_done.actions.push(QuestAction::NpcMessage {
+        message_type: 0,
+        string_id: 7,
+    });
+
+    let mut finish = trigger("jelly-finish", false);
+    finish.actions.push(QuestAction::SetQuest {
+        quest: QUEST,
+        option: 0,
+    });
+
+    let mut qsd = QSD::new();
+    qsd.groups.push(QuestGroup {
+        name: String::from("Jelly"),
+        triggers: vec![accept, complete, not_done, finish],
+    });
+    qsd
+}
+
+#[test]
+fn run_quest() {
+    let scripts = vec![jelly_quest()];
+    let engine = QuestEngine::new(&scripts);
+
+    let mut character = QuestCharacter::new();
+    character.abilities.insert(ABILITY_LEVEL, 5);
+
+    // Too low level
+    let trace = engine.run("jelly-accept", &mut character).unwrap();
+    assert!(!trace.success);
+    assert!(trace.rewards().is_empty());
+    assert!(character.quest(QUEST).is_none());
+
+    character.abilities.insert(ABILITY_LEVEL, 10);
+    let trace = engine.run("jelly-accept", &mut character).unwrap();
+    assert!(trace.success);
+    assert_eq!(character.variable(VARIABLE_QUEST, 0), 1);
+
+    // Without the items the next trigger in the group runs
+    let trace = engine.run("jelly-complete", &mut character).unwrap();
+    assert!(trace.success);
+    assert_eq!(trace.triggers(), vec!["jelly-complete", "jelly-not-done"]);
+    assert!(trace.events.contains(&QuestEvent::Condition {
+        condition: QuestCondition::QuestItems(vec![QuestItemCheck {
+            item: JELLY,
+            equipment_index: 0,
+            count: 5,
+            op: 2,
+        }]),
+        result: QuestCheck::Failed,
+    }));
+    assert!(trace.events.contains(&QuestEvent::Action {
+        action: QuestAction::NpcMessage {
+            message_type: 0,
+            string_id: 7,
+        },
+        applied: false,
+    }));
+
+    character.inventory.insert(JELLY, 6);
+    let trace = engine.run("jelly-complete", &mut character).unwrap();
+    assert_eq!(trace.triggers(), vec!["jelly-complete", "jelly-finish"]);
+    assert_eq!(
+        trace.rewards(),
+        vec![
+            &QuestReward::Item {
+                item: JELLY,
+                count: -5
+            },
+            &QuestReward::Money(300),
+            &QuestReward::Quest {
+                quest: QUEST,
+                option: 0
+            },
+        ]
+    );
+    assert_eq!(character.item_count(JELLY), 1);
+    assert_eq!(character.money, 300);
+    assert!(character.quest(QUEST).is_none());
+
+    assert!(engine.run("missing", &mut character).is_err());
+}
+
+#[test]
+fn run_quest_scenario() {
+    let scripts = vec![jelly_quest()];
+    let engine = QuestEngine::new(&scripts);
+
+    let mut scenario = QuestScenario::default();
+    scenario.character.abilities.insert(ABILITY_LEVEL, 12);
+    scenario.character.inventory.insert(JELLY, 5);
+    scenario.triggers = vec![String::from("jelly-accept"), String::from("jelly-complete")];
+
+    let (character, traces) = scenario.run(&engine).unwrap();
+    assert!(traces.iter().all(|t| t.success));
+    assert_eq!(character.money, 300);
+    assert!(character.inventory.is_empty());
+}
+
+#[test]
+fn run_quest_loop() {
+    let mut looping = trigger("loop", false);
+    looping.actions.push(QuestAction::NextTrigger {
+        trigger: String::from("loop"),
+    });
+
+    let mut qsd = QSD::new();
+    qsd.groups.push(QuestGroup {
+        name: String::from("Loop"),
+        triggers: vec![looping],
+    });
+
+    let scripts = vec![qsd];
+    let engine = QuestEngine::new(&scripts);
+    assert!(engine.run("loop", &mut QuestCharacter::new()).is_err());
+}
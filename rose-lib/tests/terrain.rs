@@ -0,0 +1,64 @@
+use roselib::files::{HIM, ZON};
+use roselib::io::RoseFile;
+use roselib::terrain::{Terrain, BLOCK_ROWS};
+
+/// 3x3 vertex heightmap where the height grows by `slope` per column
+fn heightmap(base: f32, slope: f32) -> HIM {
+    let mut him = HIM::new();
+    him.width = 3;
+    him.length = 3;
+    him.scale = 100.0;
+    him.heights = (0..9).map(|i| base + (i % 3) as f32 * slope).collect();
+    him
+}
+
+#[test]
+fn heightmap_height() {
+    let mut him = HIM::new();
+    him.width = 3;
+    him.length = 2;
+    him.heights = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+
+    assert_eq!(him.height(2, 0), 2.0);
+    assert_eq!(him.height(0, 1), 3.0);
+    assert_eq!(him.height(2, 1), 5.0);
+    assert_eq!(him.height(3, 0), 0.0);
+    assert_eq!(him.height(0, 2), 0.0);
+}
+
+#[test]
+fn terrain_sample() {
+    let mut zone = ZON::new();
+    zone.grid_size = 250.0;
+
+    let mut terrain = Terrain::new(&zone);
+    terrain.insert(30, 30, heightmap(0.0, 0.0)).unwrap();
+    terrain.insert(31, 30, heightmap(0.0, 250.0)).unwrap();
+    assert_eq!(terrain.block_size(), 500.0);
+
+    let top = (BLOCK_ROWS - 30) as f32 * 500.0;
+
+    // Flat block
+    let sample = terrain.sample(30.0 * 500.0 + 125.0, top - 300.0).unwrap();
+    assert_eq!(sample.height, 0.0);
+    assert_eq!(sample.normal.z, 1.0);
+
+    // Sloped block rising 45 degrees towards +X
+    let x = 31.0 * 500.0 + 125.0;
+    assert_eq!(terrain.height(x, top - 10.0), Some(125.0));
+    assert_eq!(terrain.height(x, top - 490.0), Some(125.0));
+    let normal = terrain.normal(x, top - 250.0).unwrap();
+    assert!((normal.x + 0.5f32.sqrt()).abs() < 1e-6);
+    assert!((normal.z - 0.5f32.sqrt()).abs() < 1e-6);
+    assert_eq!(normal.y, 0.0);
+
+    // Outside of the loaded blocks
+    assert_eq!(terrain.height(29.0 * 500.0 + 125.0, top - 10.0), None);
+    assert_eq!(terrain.height(x, top + 10.0), None);
+    assert_eq!(terrain.height(f32::NAN, top), None);
+
+    // Blocks must have the same size
+    let mut him = heightmap(0.0, 0.0);
+    him.width = 2;
+    assert!(terrain.insert(32, 30, him).is_err());
+}
@@ -0,0 +1,71 @@
+use std::io::Cursor;
+
+use roselib::files::zca::{CameraProjection, CAMERA_EYE_CHANNEL, CAMERA_FOV_CHANNEL};
+use roselib::files::zmo::{Channel, ChannelData, ChannelType};
+use roselib::files::{ZCA, ZMO};
+use roselib::io::RoseFile;
+use roselib::utils::{Matrix4, Vector3};
+
+fn vector(x: f32, y: f32, z: f32) -> Vector3<f32> {
+    Vector3 { x, y, z }
+}
+
+#[test]
+fn write_zca() {
+    let mut orig_zca = ZCA::new();
+    orig_zca.projection = CameraProjection::Orthographic;
+    orig_zca.model_view = Matrix4::identity();
+    orig_zca.projection_matrix.m[0][0] = 0.5;
+    orig_zca.fov = 45.0;
+    orig_zca.aspect_ratio = 4.0 / 3.0;
+    orig_zca.near = 1.0;
+    orig_zca.far = 5000.0;
+    orig_zca.eye = vector(1.0, 2.0, 3.0);
+    orig_zca.up = vector(0.0, 0.0, 1.0);
+
+    let mut cursor = Cursor::new(Vec::new());
+    orig_zca.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_zca = ZCA::new();
+    new_zca.read(&mut cursor).unwrap();
+
+    assert_eq!(orig_zca, new_zca);
+}
+
+#[test]
+fn camera_motion() {
+    let mut zca = ZCA::new();
+    zca.target = vector(0.0, 100.0, 0.0);
+    zca.up = vector(0.0, 0.0, 1.0);
+    zca.fov = 45.0;
+    zca.near = 1.0;
+    zca.far = 1000.0;
+
+    let mut zmo = ZMO::new();
+    zmo.fps = 10;
+    zmo.frames = 2;
+    zmo.channels.push(Channel {
+        typ: ChannelType::Position,
+        index: CAMERA_EYE_CHANNEL,
+        frames: ChannelData::Position(vec![vector(0.0, 0.0, 0.0), vector(10.0, 0.0, 0.0)]),
+    });
+    zmo.channels.push(Channel {
+        typ: ChannelType::Position,
+        index: CAMERA_FOV_CHANNEL,
+        frames: ChannelData::Position(vec![vector(30.0, 2.0, 500.0); 2]),
+    });
+
+    let frames = zca.frames(&zmo);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1].eye, vector(10.0, 0.0, 0.0));
+    assert_eq!(frames[1].fov, 30.0);
+    assert_eq!(frames[1].far, 500.0);
+
+    // Channels missing from the motion come from the camera
+    assert_eq!(frames[0].target, zca.target);
+    assert_eq!(frames[0].up, zca.up);
+
+    let frame = zca.sample(&zmo, 0.05);
+    assert!((frame.eye.x - 5.0).abs() < 1e-5);
+}
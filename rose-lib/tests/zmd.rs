@@ -2,8 +2,10 @@ use std::fs::File;
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::files::zmd::Bone;
 use roselib::files::ZMD;
 use roselib::io::RoseFile;
+use roselib::utils::{Quaternion, Vector3};
 
 #[test]
 fn read_zmd() {
@@ -44,3 +46,39 @@ fn write_zmd() {
 
     assert_eq!(orig_zmd, new_zmd);
 }
+
+#[test]
+fn world_matrices() {
+    let axis = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    };
+
+    let mut zmd = ZMD::new();
+    let mut root = Bone::new();
+    root.rotation = Quaternion::from_axis_angle(axis, std::f32::consts::FRAC_PI_2);
+    zmd.bones.push(root);
+
+    let mut child = Bone::new();
+    child.position.x = 10.0;
+    child.rotation = Quaternion::identity();
+    zmd.bones.push(child);
+
+    let mut dummy = Bone::new();
+    dummy.parent = 1;
+    dummy.position.x = 5.0;
+    dummy.rotation = Quaternion::identity();
+    zmd.dummy_bones.push(dummy);
+
+    let matrices = zmd.bind_matrices();
+    assert_eq!(matrices.len(), 3);
+
+    // The root's rotation turns its children's x offset onto y
+    let child = matrices[1].translation();
+    assert!(child.x.abs() < 1e-4);
+    assert!((child.y - 10.0).abs() < 1e-4);
+
+    let dummy = matrices[2].translation();
+    assert!((dummy.y - 15.0).abs() < 1e-4);
+}
@@ -2,8 +2,10 @@ use std::fs::File;
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::files::zmo::{Channel, ChannelData, ChannelType, MotionEvent};
 use roselib::files::ZMO;
 use roselib::io::RoseFile;
+use roselib::utils::{Quaternion, Vector3};
 
 #[test]
 fn read_zmo() {
@@ -60,3 +62,196 @@ fn write_zmo() {
         assert_eq!(orig_zmo, new_zmo);
     }
 }
+
+/// One second motion at 2 fps moving bone 0 along x and turning it 90
+/// degrees around z
+fn motion() -> ZMO {
+    let mut zmo = ZMO::new();
+    zmo.identifier = String::from("ZMO0002");
+    zmo.fps = 2;
+    zmo.frames = 3;
+
+    let mut position = Channel::from(ChannelType::Position);
+    position.frames = ChannelData::Position(
+        (0..3)
+            .map(|i| Vector3 {
+                x: i as f32,
+                y: 0.0,
+                z: 0.0,
+            })
+            .collect(),
+    );
+
+    let axis = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    };
+    let mut rotation = Channel::from(ChannelType::Rotation);
+    rotation.frames = ChannelData::Rotation(
+        (0..3)
+            .map(|i| Quaternion::from_axis_angle(axis, i as f32 * std::f32::consts::FRAC_PI_4))
+            .collect(),
+    );
+
+    zmo.channels = vec![position, rotation];
+    zmo
+}
+
+#[test]
+fn sample_zmo() {
+    let zmo = motion();
+    assert_eq!(zmo.duration(), 1.0);
+
+    let pose = zmo.sample(0.25);
+    assert_eq!(pose.channels.len(), 2);
+    assert!((pose.position(0).unwrap().x - 0.5).abs() < 1e-5);
+
+    let expected = Quaternion::from_axis_angle(
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        },
+        std::f32::consts::PI / 8.0,
+    );
+    assert!(pose.rotation(0).unwrap().dot(&expected) > 0.99999);
+    assert!(pose.rotation(1).is_none());
+
+    // Sampling is clamped to the motion
+    assert_eq!(zmo.sample(5.0), zmo.sample_frame(2));
+    assert_eq!(zmo.sample(-1.0), zmo.sample_frame(0));
+}
+
+#[test]
+fn edit_zmo() {
+    let zmo = motion();
+
+    let resampled = zmo.resample(4).unwrap();
+    assert_eq!(resampled.fps, 4);
+    assert_eq!(resampled.frames, 5);
+    let pose = resampled.sample_frame(1);
+    assert!((pose.position(0).unwrap().x - 0.5).abs() < 1e-5);
+    assert!(zmo.resample(0).is_err());
+
+    let trimmed = zmo.trim(1..3).unwrap();
+    assert_eq!(trimmed.frames, 2);
+    assert_eq!(trimmed.sample_frame(0), zmo.sample_frame(1));
+    assert!(zmo.trim(2..4).is_err());
+
+    let joined = zmo.concat(&trimmed).unwrap();
+    assert_eq!(joined.frames, 5);
+    assert_eq!(joined.sample_frame(4), zmo.sample_frame(2));
+
+    let mut cursor = Cursor::new(Vec::new());
+    joined.clone().write(&mut cursor).unwrap();
+    cursor.set_position(0);
+    let mut read = ZMO::new();
+    read.read(&mut cursor).unwrap();
+    assert_eq!(read, joined);
+
+    assert!(zmo.concat(&resampled).is_err());
+}
+
+#[test]
+fn zmo_events() {
+    let mut zmo = motion();
+
+    // No footer is written without events
+    let mut cursor = Cursor::new(Vec::new());
+    zmo.write(&mut cursor).unwrap();
+    let plain_size = cursor.get_ref().len();
+    assert!(!cursor.get_ref().ends_with(b"EZMO"));
+
+    zmo.events = vec![
+        MotionEvent {
+            frame: 1,
+            event_id: 21,
+        },
+        MotionEvent {
+            frame: 2,
+            event_id: 10,
+        },
+    ];
+
+    let mut cursor = Cursor::new(Vec::new());
+    zmo.write(&mut cursor).unwrap();
+    // Frame count, an id per frame, offset and tag
+    assert_eq!(cursor.get_ref().len(), plain_size + 2 + 3 * 2 + 4 + 4);
+    assert!(cursor.get_ref().ends_with(b"EZMO"));
+
+    cursor.set_position(0);
+    let mut new_zmo = ZMO::new();
+    new_zmo.read(&mut cursor).unwrap();
+    assert_eq!(new_zmo, zmo);
+
+    zmo.interpolation_interval = Some(500);
+    let mut cursor = Cursor::new(Vec::new());
+    zmo.write(&mut cursor).unwrap();
+    assert!(cursor.get_ref().ends_with(b"3ZMO"));
+
+    cursor.set_position(0);
+    let mut new_zmo = ZMO::new();
+    new_zmo.read(&mut cursor).unwrap();
+    assert_eq!(new_zmo, zmo);
+
+    // Trimming keeps the events inside the range
+    let trimmed = zmo.trim(2..3).unwrap();
+    assert_eq!(
+        trimmed.events,
+        vec![MotionEvent {
+            frame: 0,
+            event_id: 10,
+        }]
+    );
+
+    // Only one event fits on a frame
+    zmo.events.push(MotionEvent {
+        frame: 2,
+        event_id: 11,
+    });
+    assert!(zmo.write(&mut Cursor::new(Vec::new())).is_err());
+
+    zmo.events.pop();
+    zmo.events.push(MotionEvent {
+        frame: 0,
+        event_id: 0,
+    });
+    assert!(zmo.write(&mut Cursor::new(Vec::new())).is_err());
+
+    // Events that resample onto the same frame are spread out
+    let mut zmo = motion().concat(&motion()).unwrap();
+    zmo.fps = 6;
+    zmo.events = vec![
+        MotionEvent {
+            frame: 3,
+            event_id: 5,
+        },
+        MotionEvent {
+            frame: 4,
+            event_id: 6,
+        },
+    ];
+    let mut resampled = zmo.resample(2).unwrap();
+    assert_eq!(resampled.frames, 3);
+    assert_eq!(
+        resampled.events,
+        vec![
+            MotionEvent {
+                frame: 1,
+                event_id: 5,
+            },
+            MotionEvent {
+                frame: 2,
+                event_id: 6,
+            },
+        ]
+    );
+    resampled.write(&mut Cursor::new(Vec::new())).unwrap();
+
+    zmo.events.push(MotionEvent {
+        frame: 5,
+        event_id: 7,
+    });
+    assert!(zmo.resample(2).is_err());
+}
@@ -2,8 +2,10 @@ use std::fs::File;
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::files::zms::{Vertex, VertexFormat};
 use roselib::files::ZMS;
 use roselib::io::RoseFile;
+use roselib::utils::{Vector2, Vector3};
 
 #[test]
 fn read_zms() {
@@ -131,3 +133,308 @@ fn write_zms() {
         assert_eq!(orig_zms, new_zms);
     }
 }
+
+fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+    let mut v = Vertex::new();
+    v.position = Vector3 { x, y, z };
+    v.uv1 = Vector2 { x, y };
+    v
+}
+
+fn face(x: i16, y: i16, z: i16) -> Vector3<i16> {
+    Vector3 { x, y, z }
+}
+
+/// Unit quad in the XY plane made of two triangles with duplicated corners
+fn quad() -> ZMS {
+    let mut zms = ZMS::new();
+    zms.format = VertexFormat::Position as i32 | VertexFormat::UV1 as i32;
+    zms.vertices = vec![
+        vertex(0.0, 0.0, 0.0),
+        vertex(1.0, 0.0, 0.0),
+        vertex(1.0, 1.0, 0.0),
+        vertex(0.0, 0.0, 0.0),
+        vertex(1.0, 1.0, 0.0),
+        vertex(0.0, 1.0, 0.0),
+    ];
+    zms.indices = vec![face(0, 1, 2), face(3, 4, 5)];
+    zms
+}
+
+#[test]
+fn mesh_processing() {
+    let mut zms = quad();
+
+    zms.recompute_bounding_box();
+    assert_eq!(
+        zms.bounding_box.min,
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0
+        }
+    );
+    assert_eq!(
+        zms.bounding_box.max,
+        Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 0.0
+        }
+    );
+
+    assert_eq!(zms.weld_vertices(1e-5), 2);
+    assert_eq!(zms.vertices.len(), 4);
+    assert_eq!(zms.indices[1], face(0, 2, 3));
+
+    zms.recompute_smooth_normals();
+    assert!(zms.normals_enabled());
+    for v in &zms.vertices {
+        assert_eq!(
+            v.normal,
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            }
+        );
+    }
+
+    zms.compute_tangents().unwrap();
+    assert!(zms.tangents_enabled());
+    for v in &zms.vertices {
+        assert!((v.tangent.x - 1.0).abs() < 1e-5);
+        assert!(v.tangent.y.abs() < 1e-5);
+    }
+
+    zms.recompute_flat_normals().unwrap();
+    assert_eq!(zms.vertices.len(), 6);
+    assert_eq!(zms.indices[1], face(3, 4, 5));
+
+    zms.validate().unwrap();
+}
+
+#[test]
+fn mesh_degenerate_triangles() {
+    let mut zms = quad();
+    zms.vertices.push(vertex(2.0, 0.0, 0.0));
+    zms.indices.push(face(0, 0, 1));
+    zms.indices.push(face(0, 1, 6));
+    zms.materials = vec![1, 3];
+
+    assert_eq!(zms.remove_degenerate_triangles(), 2);
+    assert_eq!(zms.indices.len(), 2);
+    assert_eq!(zms.materials, vec![1, 1]);
+}
+
+#[test]
+fn mesh_validate() {
+    let mut zms = quad();
+    zms.indices.push(face(0, 1, 6));
+    assert!(zms.validate().is_err());
+
+    let mut zms = quad();
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    zms.bones = vec![0, 1];
+    for v in &mut zms.vertices {
+        v.bone_weights.w = 0.5;
+        v.bone_weights.x = 0.5;
+        v.bone_indices.x = 1;
+    }
+    zms.validate().unwrap();
+
+    zms.vertices[2].bone_indices.y = 2;
+    assert!(zms.validate().is_err());
+
+    zms.vertices[2].bone_indices.y = 0;
+    zms.vertices[3].bone_weights.x = 0.25;
+    assert!(zms.validate().is_err());
+
+    let mut zms = quad();
+    zms.indices.push(face(0, 1, 6));
+    assert!(zms.recompute_flat_normals().is_err());
+    assert_eq!(zms.vertices.len(), 6);
+
+    // Bone weights don't matter for flat shading
+    let mut zms = quad();
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    zms.vertices[0].bone_weights.w = 0.9;
+    assert!(zms.validate().is_err());
+    zms.recompute_flat_normals().unwrap();
+}
+
+/// Grid of `n` x `n` quads
+fn grid(n: i16) -> ZMS {
+    let mut zms = ZMS::new();
+    zms.format = VertexFormat::Position as i32;
+    for y in 0..=n {
+        for x in 0..=n {
+            zms.vertices.push(vertex(x as f32, y as f32, 0.0));
+        }
+    }
+    for y in 0..n {
+        for x in 0..n {
+            let i = y * (n + 1) + x;
+            zms.indices.push(face(i, i + 1, i + n + 2));
+            zms.indices.push(face(i, i + n + 2, i + n + 1));
+        }
+    }
+    zms
+}
+
+/// Triangles rotated to start at their lowest index, sorted
+fn normalized(faces: &[Vector3<i16>]) -> Vec<(i16, i16, i16)> {
+    let mut out: Vec<(i16, i16, i16)> = faces
+        .iter()
+        .map(|f| {
+            if f.x <= f.y && f.x <= f.z {
+                (f.x, f.y, f.z)
+            } else if f.y <= f.z {
+                (f.y, f.z, f.x)
+            } else {
+                (f.z, f.x, f.y)
+            }
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+#[test]
+fn mesh_strips() {
+    let mut zms = grid(8);
+    zms.generate_strips();
+    assert!(!zms.strips.is_empty());
+    // A strip needs fewer indices than the list
+    assert!(zms.strips.len() < zms.indices.len() * 3);
+
+    assert_eq!(normalized(&zms.strip_triangles()), normalized(&zms.indices));
+
+    let indices = zms.indices.clone();
+    zms.load_strips();
+    assert_eq!(normalized(&zms.indices), normalized(&indices));
+}
+
+#[test]
+fn mesh_vertex_cache() {
+    let mut zms = grid(32);
+    let half = zms.indices.len() / 2;
+    zms.materials = vec![half as i16, half as i16];
+
+    // Scramble the triangle order within each material
+    let mut shuffled = Vec::new();
+    for start in [0, half].iter() {
+        for i in 0..half {
+            shuffled.push(zms.indices[start + (i * 337) % half]);
+        }
+    }
+    zms.indices = shuffled;
+
+    let triangles = |zms: &ZMS, start: usize| {
+        let mut out: Vec<Vec<(i32, i32)>> = zms.indices[start..start + half]
+            .iter()
+            .map(|f| {
+                let mut t: Vec<(i32, i32)> = [f.x, f.y, f.z]
+                    .iter()
+                    .map(|&i| {
+                        let p = zms.vertices[i as usize].position;
+                        (p.x as i32, p.y as i32)
+                    })
+                    .collect();
+                t.sort();
+                t
+            })
+            .collect();
+        out.sort();
+        out
+    };
+
+    let before = zms.average_cache_miss_ratio(16);
+    let materials = [triangles(&zms, 0), triangles(&zms, half)];
+
+    zms.optimize_vertex_cache().unwrap();
+    assert!(zms.average_cache_miss_ratio(16) < 0.8);
+    assert!(zms.average_cache_miss_ratio(16) < before);
+    zms.validate().unwrap();
+
+    // Each material keeps the same triangles
+    assert_eq!(triangles(&zms, 0), materials[0]);
+    assert_eq!(triangles(&zms, half), materials[1]);
+
+    let mut zms = quad();
+    zms.strips = vec![0, 1, 7];
+    assert!(zms.optimize_vertex_cache().is_err());
+
+    let mut zms = quad();
+    zms.indices.push(face(0, 1, 6));
+    assert!(zms.optimize_vertex_cache().is_err());
+
+    // Bone weights don't matter for the triangle order
+    let mut zms = quad();
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    zms.vertices[0].bone_weights.w = 0.9;
+    assert!(zms.validate().is_err());
+    zms.optimize_vertex_cache().unwrap();
+}
+
+#[test]
+fn write_zms_versions() {
+    let mut zms = quad();
+    zms.format |= VertexFormat::Normal as i32
+        | VertexFormat::Color as i32
+        | VertexFormat::BoneWeight as i32
+        | VertexFormat::BoneIndex as i32
+        | VertexFormat::UV2 as i32;
+    zms.bones = vec![3, 7];
+    for (i, v) in zms.vertices.iter_mut().enumerate() {
+        v.normal.z = 1.0;
+        v.color.r = i as f32 / 10.0;
+        v.bone_weights.w = 1.0;
+        v.bone_indices.w = (i % 2) as i16;
+        v.uv2.x = 0.5;
+    }
+    zms.materials = vec![2];
+    zms.generate_strips();
+    zms.pool = 1;
+
+    for version in 5..=8 {
+        let mut cursor = Cursor::new(Vec::new());
+        zms.write_version(&mut cursor, version).unwrap();
+        cursor.set_position(0);
+
+        let mut new_zms = ZMS::new();
+        new_zms.read(&mut cursor).unwrap();
+
+        assert_eq!(new_zms.identifier, format!("ZMS000{}", version));
+        assert_eq!(new_zms.format, zms.format);
+        assert_eq!(new_zms.bones, zms.bones);
+        assert_eq!(new_zms.vertices, zms.vertices);
+        assert_eq!(new_zms.indices, zms.indices);
+
+        let materials: &[i16] = if version >= 6 { &zms.materials } else { &[] };
+        assert_eq!(new_zms.materials, materials);
+
+        let strips: &[i16] = if version >= 7 { &zms.strips } else { &[] };
+        assert_eq!(new_zms.strips, strips);
+
+        assert_eq!(new_zms.pool, if version >= 8 { 1 } else { 0 });
+    }
+
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(zms.write_version(&mut cursor, 4).is_err());
+}
+
+#[test]
+fn write_zms_overflow() {
+    let mut zms = ZMS::new();
+    zms.format = VertexFormat::Position as i32;
+    for _ in 0..40000 {
+        zms.vertices.push(Vertex::new());
+    }
+
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(zms.write(&mut cursor).is_err());
+
+    let mut cursor = Cursor::new(Vec::new());
+    zms.write_version(&mut cursor, 6).unwrap();
+}
@@ -88,4 +88,233 @@ fn write_zsc() {
     new_zsc.read(&mut cursor).unwrap();
 
     assert_eq!(orig_zsc, new_zsc);
-}
\ No newline at end of file
+}
+
+/// Property id, size and data
+fn property(buf: &mut Vec<u8>, id: u8, data: &[u8]) {
+    buf.push(id);
+    buf.push(data.len() as u8);
+    buf.extend_from_slice(data);
+}
+
+/// Scene with one animated object, its part and effect use a mix of known,
+/// part-only and unknown properties in a non-default order. The animation
+/// property payload is made up, only its round trip is checked.
+fn animated_scene() -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    // Meshes, materials and effects
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(b"3DData\\mesh.zms\0");
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(b"3DData\\effect.eft\0");
+
+    // Object
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&100u32.to_le_bytes());
+    buf.extend_from_slice(&5i32.to_le_bytes());
+    buf.extend_from_slice(&6i32.to_le_bytes());
+
+    // Part
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    let position: Vec<u8> = [1.0f32, 2.0, 3.0]
+        .iter()
+        .flat_map(|f| f.to_le_bytes().to_vec())
+        .collect();
+    property(&mut buf, 1, &position);
+    property(&mut buf, 8, &[1, 2, 3, 4]);
+    property(&mut buf, 29, &(3u16 | 8 | 64).to_le_bytes());
+    property(&mut buf, 30, b"3DData\\anim.zmo");
+    property(&mut buf, 40, &[9, 9]);
+    buf.push(0);
+
+    // Effect
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    property(&mut buf, 6, &2i16.to_le_bytes());
+    property(&mut buf, 31, &7u16.to_le_bytes());
+    property(&mut buf, 1, &position);
+    buf.push(0);
+
+    // Bounding box
+    for _ in 0..6 {
+        buf.extend_from_slice(&0f32.to_le_bytes());
+    }
+    buf
+}
+
+#[test]
+fn zsc_properties() {
+    let data = animated_scene();
+
+    let mut zsc = ZSC::new();
+    zsc.read(&mut Cursor::new(&data)).unwrap();
+
+    let part = &zsc.objects[0].parts[0];
+    assert_eq!(part.position.z, 3.0);
+    assert_eq!(part.animation, Some(vec![1, 2, 3, 4]));
+    assert_eq!(
+        part.collision.shape,
+        SceneCollisionShape::OrientedBoundingBox
+    );
+    assert_eq!(
+        part.collision.flags,
+        SceneCollisionFlags::NOT_MOVABLE | SceneCollisionFlags::NO_CAMERA_COLLISION
+    );
+    assert_eq!(part.animation_path, PathBuf::from("3DData\\anim.zmo"));
+    assert_eq!(
+        part.unknown_properties,
+        vec![SceneRawProperty {
+            id: 40,
+            data: vec![9, 9]
+        }]
+    );
+
+    let effect = &zsc.objects[0].effects[0];
+    assert_eq!(effect.effect_type, SceneEffectType::DayNight);
+    assert_eq!(effect.dummy_index, 2);
+    assert_eq!(effect.position.x, 1.0);
+    assert_eq!(effect.unknown_properties[0].id, 31);
+
+    // Writing keeps the file as it was
+    let mut cursor = Cursor::new(Vec::new());
+    zsc.write(&mut cursor).unwrap();
+    assert_eq!(cursor.into_inner(), data);
+
+    // Changed properties are added
+    zsc.objects[0].parts[0].use_lightmap = true;
+    let mut cursor = Cursor::new(Vec::new());
+    zsc.write(&mut cursor).unwrap();
+    cursor.set_position(0);
+
+    let mut new_zsc = ZSC::new();
+    new_zsc.read(&mut cursor).unwrap();
+    assert!(new_zsc.objects[0].parts[0].use_lightmap);
+    assert_eq!(
+        new_zsc.objects[0].parts[0].property_order,
+        vec![1, 8, 29, 30, 40, 32]
+    );
+}
+
+#[test]
+fn zsc_collision() {
+    let collision = SceneCollision::from(4 | 16);
+    assert_eq!(collision.shape, SceneCollisionShape::Polygon);
+    assert_eq!(collision.flags, SceneCollisionFlags::NOT_PICKABLE);
+    assert_eq!(collision.unknown_bits, 0);
+    let value: u16 = collision.into();
+    assert_eq!(value, 20);
+
+    // Unknown shapes and flags are kept
+    let collision = SceneCollision::from(5 | 8);
+    assert_eq!(collision.shape, SceneCollisionShape::None);
+    assert_eq!(collision.flags, SceneCollisionFlags::NOT_MOVABLE);
+    assert_eq!(collision.unknown_bits, 5);
+    assert_eq!(u16::from(collision), 13);
+
+    let collision = SceneCollision::from(2 | 1 << 8);
+    assert_eq!(collision.shape, SceneCollisionShape::AxisAlignedBoundingBox);
+    assert_eq!(collision.unknown_bits, 1 << 8);
+    assert_eq!(u16::from(collision), 2 | 1 << 8);
+
+    // and survive reading and writing a scene
+    let mut data = animated_scene();
+    let collision = (3u16 | 8 | 64).to_le_bytes();
+    let start = data
+        .windows(4)
+        .position(|w| w == [29, 2, collision[0], collision[1]])
+        .unwrap();
+    data[start + 2..start + 4].copy_from_slice(&(3u16 | 8 | 1 << 12).to_le_bytes());
+
+    let mut zsc = ZSC::new();
+    zsc.read(&mut Cursor::new(&data)).unwrap();
+    assert_eq!(zsc.objects[0].parts[0].collision.unknown_bits, 1 << 12);
+
+    let mut cursor = Cursor::new(Vec::new());
+    zsc.write(&mut cursor).unwrap();
+    assert_eq!(cursor.into_inner(), data);
+}
+
+fn scene_object(parts: &[(u16, u16)], effects: &[u16]) -> SceneObject {
+    let mut object = SceneObject::default();
+    for &(mesh_id, material_id) in parts {
+        object.parts.push(SceneObjectPart {
+            mesh_id,
+            material_id,
+            ..Default::default()
+        });
+    }
+    for &effect_id in effects {
+        object.effects.push(SceneObjectEffect {
+            effect_id,
+            ..Default::default()
+        });
+    }
+    object
+}
+
+fn scene_material(path: &str) -> SceneMaterial {
+    SceneMaterial {
+        path: PathBuf::from(path),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn zsc_editing() {
+    let mut zsc = ZSC::new();
+    assert_eq!(zsc.add_mesh(PathBuf::from("a.zms")).unwrap(), 0);
+    assert_eq!(zsc.add_mesh(PathBuf::from("b.zms")).unwrap(), 1);
+    assert_eq!(zsc.add_mesh(PathBuf::from("c.zms")).unwrap(), 2);
+    assert_eq!(zsc.add_mesh(PathBuf::from("B.ZMS")).unwrap(), 1);
+    assert_eq!(zsc.add_material(scene_material("a.dds")).unwrap(), 0);
+    assert_eq!(zsc.add_material(scene_material("b.dds")).unwrap(), 1);
+    assert_eq!(zsc.add_effect(PathBuf::from("a.eft")).unwrap(), 0);
+    assert_eq!(zsc.add_effect(PathBuf::from("b.eft")).unwrap(), 1);
+
+    zsc.objects.push(scene_object(&[(2, 1)], &[1]));
+
+    // Used entries can't be removed
+    assert!(zsc.remove_mesh(2).is_err());
+    assert!(zsc.remove_material(1).is_err());
+    assert!(zsc.remove_effect(1).is_err());
+
+    assert_eq!(zsc.remove_mesh(0).unwrap(), PathBuf::from("a.zms"));
+    assert_eq!(zsc.objects[0].parts[0].mesh_id, 1);
+    assert_eq!(zsc.remove_effect(0).unwrap(), PathBuf::from("a.eft"));
+    assert_eq!(zsc.objects[0].effects[0].effect_id, 0);
+
+    assert_eq!(zsc.prune(), 2);
+    assert_eq!(zsc.meshes, vec![PathBuf::from("c.zms")]);
+    assert_eq!(zsc.materials, vec![scene_material("b.dds")]);
+    assert_eq!(zsc.objects[0].parts[0].mesh_id, 0);
+    assert_eq!(zsc.objects[0].parts[0].material_id, 0);
+}
+
+#[test]
+fn zsc_merge() {
+    let mut zsc = ZSC::new();
+    zsc.meshes = vec![PathBuf::from("3DData\\a.zms"), PathBuf::from("3DData\\b.zms")];
+    zsc.materials = vec![scene_material("a.dds")];
+    zsc.objects.push(scene_object(&[(0, 0), (1, 0)], &[]));
+
+    let mut other = ZSC::new();
+    other.meshes = vec![PathBuf::from("3DData\\new.zms"), PathBuf::from("3ddata/B.zms")];
+    other.materials = vec![scene_material("new.dds"), scene_material("a.dds")];
+    other.effects = vec![PathBuf::from("new.eft")];
+    other.objects.push(scene_object(&[(0, 0), (1, 1)], &[0]));
+
+    assert_eq!(zsc.merge(&other).unwrap(), 1);
+    assert_eq!(zsc.meshes.len(), 3);
+    assert_eq!(zsc.materials.len(), 2);
+    assert_eq!(zsc.effects.len(), 1);
+
+    let parts = &zsc.objects[1].parts;
+    assert_eq!((parts[0].mesh_id, parts[0].material_id), (2, 1));
+    assert_eq!((parts[1].mesh_id, parts[1].material_id), (1, 0));
+    assert_eq!(zsc.objects[1].effects[0].effect_id, 0);
+}
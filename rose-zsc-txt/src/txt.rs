@@ -0,0 +1,636 @@
+//! 3ds Max TXT object descriptions
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use roselib::files::zsc::{
+    SceneCollision, SceneEffectType, SceneMaterial, SceneObject, SceneObjectEffect, SceneObjectPart,
+};
+use roselib::files::ZSC;
+use roselib::utils::{BoundingBox, BoundingCylinder, Matrix4, Quaternion, Vector2, Vector3};
+
+/// Object described by a TXT file
+#[derive(Debug, Default, PartialEq)]
+pub struct TxtObject {
+    pub parts: Vec<TxtPart>,
+    pub points: Vec<TxtPoint>,
+}
+
+/// `obj` entry
+#[derive(Debug, Default, PartialEq)]
+pub struct TxtPart {
+    pub mesh: PathBuf,
+    pub material: PathBuf,
+    pub is_skin: bool,
+    pub alpha_enabled: bool,
+    pub two_sided: bool,
+    pub parent: u16,
+    pub bone_index: Option<i16>,
+    pub dummy_index: Option<i16>,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion,
+    pub scale: Vector3<f32>,
+    pub collision: u16,
+    pub use_lightmap: bool,
+    pub range: u16,
+    pub animation: Option<PathBuf>,
+}
+
+/// `point` entry
+#[derive(Debug, Default, PartialEq)]
+pub struct TxtPoint {
+    pub effect: PathBuf,
+    pub effect_type: u16,
+    pub parent: u16,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion,
+    pub scale: Vector3<f32>,
+}
+
+fn invalid(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Line {}: {}", line, msg))
+}
+
+fn number<T: std::str::FromStr>(line: usize, value: &str) -> Result<T, Error> {
+    value
+        .parse::<T>()
+        .map_err(|_| invalid(line, &format!("Invalid number: {}", value)))
+}
+
+fn numbers(line: usize, value: &str, count: usize) -> Result<Vec<f32>, Error> {
+    let values = value
+        .split_whitespace()
+        .map(|v| number::<f32>(line, v))
+        .collect::<Result<Vec<f32>, Error>>()?;
+    if values.len() != count {
+        return Err(invalid(
+            line,
+            &format!("Expected {} values, found {}", count, values.len()),
+        ));
+    }
+    Ok(values)
+}
+
+fn vector3(line: usize, value: &str) -> Result<Vector3<f32>, Error> {
+    let v = numbers(line, value, 3)?;
+    Ok(Vector3 {
+        x: v[0],
+        y: v[1],
+        z: v[2],
+    })
+}
+
+/// Rotations are written as `w x y z`
+fn quaternion(line: usize, value: &str) -> Result<Quaternion, Error> {
+    let v = numbers(line, value, 4)?;
+    Ok(Quaternion {
+        w: v[0],
+        x: v[1],
+        y: v[2],
+        z: v[3],
+    })
+}
+
+fn flag(line: usize, value: &str) -> Result<bool, Error> {
+    Ok(number::<u32>(line, value)? != 0)
+}
+
+/// Parse a TXT object description
+pub fn parse(s: &str) -> Result<TxtObject, Error> {
+    let mut object = TxtObject::default();
+    let mut part_count = None;
+    let mut point_count = None;
+
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // Paths may contain spaces so values are the rest of the line
+        let (key, value) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], line[pos..].trim()),
+            None => (line, ""),
+        };
+
+        match key.to_lowercase().as_str() {
+            "numobj" => part_count = Some(number::<usize>(line_number, value)?),
+            "obj" => object.parts.push(TxtPart::default()),
+            "numpoint" => point_count = Some(number::<usize>(line_number, value)?),
+            "point" => object.points.push(TxtPoint::default()),
+            key => {
+                // Keys apply to the last `obj` or `point` entry
+                if point_count.is_some() {
+                    let point = match object.points.last_mut() {
+                        Some(point) => point,
+                        None => return Err(invalid(line_number, "Value outside of a point")),
+                    };
+                    match key {
+                        "effect" => point.effect = PathBuf::from(value),
+                        "type" => point.effect_type = number(line_number, value)?,
+                        "parent" => point.parent = number(line_number, value)?,
+                        "pos" => point.position = vector3(line_number, value)?,
+                        "rot" => point.rotation = quaternion(line_number, value)?,
+                        "scale" => point.scale = vector3(line_number, value)?,
+                        _ => return Err(invalid(line_number, &format!("Unknown key: {}", key))),
+                    }
+                } else {
+                    let part = match object.parts.last_mut() {
+                        Some(part) => part,
+                        None => return Err(invalid(line_number, "Value outside of an obj")),
+                    };
+                    match key {
+                        "mesh" => part.mesh = PathBuf::from(value),
+                        "mat" => part.material = PathBuf::from(value),
+                        "isskin" => part.is_skin = flag(line_number, value)?,
+                        "alpha" => part.alpha_enabled = flag(line_number, value)?,
+                        "twoside" => part.two_sided = flag(line_number, value)?,
+                        "parent" => part.parent = number(line_number, value)?,
+                        "bonenumber" => part.bone_index = Some(number(line_number, value)?),
+                        "linkdummy" => part.dummy_index = Some(number(line_number, value)?),
+                        "pos" => part.position = vector3(line_number, value)?,
+                        "rot" => part.rotation = quaternion(line_number, value)?,
+                        "scale" => part.scale = vector3(line_number, value)?,
+                        "collision" => part.collision = number(line_number, value)?,
+                        "uselightmap" => part.use_lightmap = flag(line_number, value)?,
+                        "rangeset" => part.range = number(line_number, value)?,
+                        "anim" => part.animation = Some(PathBuf::from(value)),
+                        _ => return Err(invalid(line_number, &format!("Unknown key: {}", key))),
+                    }
+                }
+            }
+        }
+    }
+
+    if part_count.unwrap_or(0) != object.parts.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "numObj is {} but {} objects were found",
+                part_count.unwrap_or(0),
+                object.parts.len()
+            ),
+        ));
+    }
+    if let Some(count) = point_count {
+        if count != object.points.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "numpoint is {} but {} points were found",
+                    count,
+                    object.points.len()
+                ),
+            ));
+        }
+    }
+
+    Ok(object)
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    let normalize = |p: &Path| p.to_string_lossy().replace('\\', "/").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// Find a material with the same path and TXT flags or create one with the
+/// settings used by retail materials
+fn material_id(zsc: &mut ZSC, part: &TxtPart) -> Result<u16, Error> {
+    let existing = zsc.materials.iter().position(|m| {
+        same_path(&m.path, &part.material)
+            && m.is_skin == part.is_skin
+            && m.alpha_enabled == part.alpha_enabled
+            && m.two_sided == part.two_sided
+    });
+    if let Some(id) = existing {
+        return Ok(id as u16);
+    }
+
+    let material = SceneMaterial {
+        path: part.material.clone(),
+        is_skin: part.is_skin,
+        alpha_enabled: part.alpha_enabled,
+        two_sided: part.two_sided,
+        alpha_test_enabled: true,
+        alpha_ref: 128,
+        z_write_enabled: true,
+        z_test_enabled: true,
+        alpha: 1.0,
+        ..Default::default()
+    };
+    zsc.add_material(material).map_err(to_io)
+}
+
+fn to_io(e: roselib::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Translation * rotation * non-uniform scale of a part
+fn part_transform(part: &SceneObjectPart) -> Matrix4 {
+    let mut scale = Matrix4::identity();
+    scale.m[0][0] = part.scale.x;
+    scale.m[1][1] = part.scale.y;
+    scale.m[2][2] = part.scale.z;
+    Matrix4::from_transform(&part.position, &part.rotation, 1.0).multiply(&scale)
+}
+
+/// Bounding box and cylinder of the part meshes placed in the object, `None`
+/// when no mesh bounds are known
+fn object_bounds<F>(
+    zsc: &ZSC,
+    parts: &[SceneObjectPart],
+    mesh_bounds: F,
+) -> Option<(BoundingBox<f32>, BoundingCylinder)>
+where
+    F: Fn(&Path) -> Option<BoundingBox<f32>>,
+{
+    let mut bounds: Option<BoundingBox<f32>> = None;
+    let mut transforms: Vec<Matrix4> = Vec::new();
+    for part in parts {
+        // Parents are 1-based indices of earlier parts
+        let transform = match part.parent as usize {
+            p if p > 0 && p <= transforms.len() => {
+                transforms[p - 1].multiply(&part_transform(part))
+            }
+            _ => part_transform(part),
+        };
+        transforms.push(transform);
+
+        let mesh = match zsc
+            .meshes
+            .get(part.mesh_id as usize)
+            .and_then(|path| mesh_bounds(path))
+        {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+
+        for i in 0..8 {
+            let corner = Vector3 {
+                x: if i & 1 == 0 { mesh.min.x } else { mesh.max.x },
+                y: if i & 2 == 0 { mesh.min.y } else { mesh.max.y },
+                z: if i & 4 == 0 { mesh.min.z } else { mesh.max.z },
+            };
+            let p = transform.transform_point(&corner);
+            let b = bounds.get_or_insert(BoundingBox { min: p, max: p });
+            b.min = Vector3 {
+                x: b.min.x.min(p.x),
+                y: b.min.y.min(p.y),
+                z: b.min.z.min(p.z),
+            };
+            b.max = Vector3 {
+                x: b.max.x.max(p.x),
+                y: b.max.y.max(p.y),
+                z: b.max.z.max(p.z),
+            };
+        }
+    }
+
+    // The cylinder stands on the box's XY rectangle
+    bounds.map(|b| {
+        let (width, length) = (b.max.x - b.min.x, b.max.y - b.min.y);
+        let cylinder = BoundingCylinder {
+            center: Vector2 {
+                x: ((b.min.x + b.max.x) / 2.0).round() as i32,
+                y: ((b.min.y + b.max.y) / 2.0).round() as i32,
+            },
+            radius: (width * width + length * length).sqrt() / 2.0,
+        };
+        (b, cylinder)
+    })
+}
+
+/// Build the object in `zsc` at `index`, adding meshes, materials and effects
+/// as needed. Objects before `index` are created empty.
+///
+/// The bounding box and cylinder are computed from the mesh bounds returned by
+/// `mesh_bounds` for each mesh path. An existing object whose meshes have no
+/// known bounds keeps its bounds.
+pub fn compile<F>(
+    zsc: &mut ZSC,
+    index: usize,
+    object: &TxtObject,
+    mesh_bounds: F,
+) -> Result<(), Error>
+where
+    F: Fn(&Path) -> Option<BoundingBox<f32>>,
+{
+    let mut scene_object = SceneObject::default();
+    if let Some(existing) = zsc.objects.get(index) {
+        scene_object.bounding_box = existing.bounding_box;
+        scene_object.bounding_cylinder = existing.bounding_cylinder;
+    }
+
+    for part in &object.parts {
+        scene_object.parts.push(SceneObjectPart {
+            mesh_id: zsc.add_mesh(part.mesh.clone()).map_err(to_io)?,
+            material_id: material_id(zsc, part)?,
+            position: part.position,
+            rotation: part.rotation,
+            scale: part.scale,
+            bone_index: part.bone_index.unwrap_or(-1),
+            dummy_index: part.dummy_index.unwrap_or(-1),
+            parent: part.parent,
+            collision: SceneCollision::from(part.collision),
+            animation_path: part.animation.clone().unwrap_or_default(),
+            range: part.range,
+            use_lightmap: part.use_lightmap,
+            ..Default::default()
+        });
+    }
+
+    for point in &object.points {
+        scene_object.effects.push(SceneObjectEffect {
+            effect_id: zsc.add_effect(point.effect.clone()).map_err(to_io)?,
+            effect_type: SceneEffectType::try_from(point.effect_type).map_err(to_io)?,
+            position: point.position,
+            rotation: point.rotation,
+            scale: point.scale,
+            parent: point.parent,
+            ..Default::default()
+        });
+    }
+
+    if let Some((bounding_box, bounding_cylinder)) =
+        object_bounds(zsc, &scene_object.parts, mesh_bounds)
+    {
+        scene_object.bounding_box = bounding_box;
+        scene_object.bounding_cylinder = bounding_cylinder;
+    }
+
+    while zsc.objects.len() <= index {
+        zsc.objects.push(SceneObject::default());
+    }
+    zsc.objects[index] = scene_object;
+    Ok(())
+}
+
+/// Describe the object of `zsc` at `index` as TXT with CRLF line endings.
+/// Bone and dummy indices are only written when asked for.
+pub fn export(
+    zsc: &ZSC,
+    index: usize,
+    use_bonenumber: bool,
+    use_linkdummy: bool,
+) -> Result<String, Error> {
+    let zsc_obj = match zsc.objects.get(index) {
+        Some(o) => o,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Object {} is not in the ZSC", index),
+            ))
+        }
+    };
+
+    let mut buf = Vec::new();
+    writeln!(buf, "numObj {}", zsc_obj.parts.len())?;
+    for (part_idx, part) in zsc_obj.parts.iter().enumerate() {
+        let mesh = if part.mesh_id as usize >= zsc.meshes.len() {
+            PathBuf::from("")
+        } else {
+            PathBuf::from(&zsc.meshes[part.mesh_id as usize])
+        };
+
+        let dmat = SceneMaterial::default();
+        let mat = if part.material_id as usize >= zsc.materials.len() {
+            &dmat
+        } else {
+            &zsc.materials[part.material_id as usize]
+        };
+
+        writeln!(buf, "obj {}", part_idx + 1)?;
+        writeln!(buf, "\tmesh {}", mesh.to_string_lossy())?;
+        writeln!(buf, "\tmat {}", mat.path.to_string_lossy())?;
+        writeln!(buf, "\tisskin {}", mat.is_skin as u32)?;
+        writeln!(buf, "\talpha  {}", mat.alpha_enabled as u32)?;
+        writeln!(buf, "\ttwoside {}", mat.two_sided as u32)?;
+        writeln!(buf, "\tparent {}", part.parent)?;
+        if use_bonenumber {
+            writeln!(buf, "\tbonenumber {}", part.bone_index)?;
+        }
+        if use_linkdummy {
+            writeln!(buf, "\tlinkdummy {}", part.dummy_index)?;
+        }
+        writeln!(
+            buf,
+            "\tpos {} {} {}",
+            part.position.x, part.position.y, part.position.z
+        )?;
+        writeln!(
+            buf,
+            "\trot {} {} {} {}",
+            part.rotation.w, part.rotation.x, part.rotation.y, part.rotation.z
+        )?;
+        writeln!(
+            buf,
+            "\tscale {} {} {}",
+            part.scale.x, part.scale.y, part.scale.z
+        )?;
+        let collision: u16 = part.collision.into();
+        writeln!(buf, "\tcollision {}", collision)?;
+        writeln!(buf, "\tuselightmap {}", part.use_lightmap as u32)?;
+        writeln!(buf, "\trangeset {}", part.range)?;
+        if !part.animation_path.to_string_lossy().is_empty() {
+            writeln!(buf, "\tanim {}", part.animation_path.to_string_lossy())?;
+        }
+    }
+
+    writeln!(buf, "numpoint {}", zsc_obj.effects.len())?;
+    for (effect_idx, effect) in zsc_obj.effects.iter().enumerate() {
+        let effect_path = if effect.effect_id as usize >= zsc.effects.len() {
+            PathBuf::from("")
+        } else {
+            PathBuf::from(&zsc.effects[effect.effect_id as usize])
+        };
+
+        writeln!(buf, "point {}", effect_idx + 1)?;
+        writeln!(buf, "\teffect {}", effect_path.to_string_lossy())?;
+        let effect_type: u16 = effect.effect_type.into();
+        writeln!(buf, "\ttype {}", effect_type)?;
+        writeln!(buf, "\tparent {}", effect.parent)?;
+        writeln!(
+            buf,
+            "\tpos {} {} {}",
+            effect.position.x, effect.position.y, effect.position.z
+        )?;
+        writeln!(
+            buf,
+            "\trot {} {} {} {}",
+            effect.rotation.w, effect.rotation.x, effect.rotation.y, effect.rotation.z
+        )?;
+        writeln!(
+            buf,
+            "\tscale {} {} {}",
+            effect.scale.x, effect.scale.y, effect.scale.z
+        )?;
+    }
+
+    let s = String::from_utf8(buf).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(s.replace("\n", "\r\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXT: &str = "numObj 2\r
+obj 1\r
+\tmesh 3DData\\Deco\\tree 01.zms\r
+\tmat 3DData\\Deco\\tree.dds\r
+\tisskin 0\r
+\talpha  1\r
+\ttwoside 1\r
+\tparent 0\r
+\tbonenumber 3\r
+\tpos 1 2 3\r
+\trot 1 0 0 0\r
+\tscale 1 1 1\r
+\tcollision 11\r
+\tuselightmap 1\r
+\trangeset 2\r
+obj 2\r
+\tmesh 3DData\\Deco\\leaf.zms\r
+\tmat 3DData\\Deco\\tree.dds\r
+\tisskin 0\r
+\talpha  1\r
+\ttwoside 1\r
+\tparent 1\r
+\tlinkdummy 4\r
+\tpos 0 0 10\r
+\trot 1 0 0 0\r
+\tscale 2 2 2\r
+\tcollision 0\r
+\tuselightmap 0\r
+\trangeset 0\r
+\tanim 3DData\\Deco\\leaf.zmo\r
+numpoint 1\r
+point 1\r
+\teffect 3DData\\Effect\\fire.eft\r
+\ttype 1\r
+\tparent 2\r
+\tpos 0 0 5\r
+\trot 1 0 0 0\r
+\tscale 1 1 1\r
+";
+
+    #[test]
+    fn test_parse() {
+        let object = parse(TXT).unwrap();
+        assert_eq!(object.parts.len(), 2);
+        assert_eq!(object.points.len(), 1);
+
+        let part = &object.parts[0];
+        assert_eq!(part.mesh, PathBuf::from("3DData\\Deco\\tree 01.zms"));
+        assert!(part.alpha_enabled);
+        assert_eq!(part.bone_index, Some(3));
+        assert_eq!(part.dummy_index, None);
+        assert_eq!(part.position.z, 3.0);
+        assert_eq!(part.rotation.w, 1.0);
+        assert_eq!(part.collision, 11);
+
+        let part = &object.parts[1];
+        assert_eq!(part.dummy_index, Some(4));
+        assert_eq!(
+            part.animation,
+            Some(PathBuf::from("3DData\\Deco\\leaf.zmo"))
+        );
+
+        assert_eq!(object.points[0].effect_type, 1);
+        assert_eq!(object.points[0].parent, 2);
+
+        assert!(parse("numObj 1\r\n").is_err());
+        assert!(parse("numObj 1\r\nobj 1\r\n\tpos 1 2\r\n").is_err());
+        assert!(parse("numObj 1\r\nobj 1\r\n\tcolour 1\r\n").is_err());
+    }
+
+    #[test]
+    fn test_compile() {
+        let object = parse(TXT).unwrap();
+
+        let mut zsc = ZSC::default();
+        zsc.meshes.push(PathBuf::from("3ddata/deco/leaf.zms"));
+        compile(&mut zsc, 2, &object, |_| None).unwrap();
+
+        assert_eq!(zsc.objects.len(), 3);
+        assert!(zsc.objects[0].parts.is_empty());
+        assert_eq!(zsc.meshes.len(), 2);
+        assert_eq!(zsc.materials.len(), 1);
+        assert_eq!(zsc.effects.len(), 1);
+
+        let parts = &zsc.objects[2].parts;
+        assert_eq!(parts[0].mesh_id, 1);
+        assert_eq!(parts[1].mesh_id, 0);
+        assert_eq!(parts[0].material_id, parts[1].material_id);
+        assert_eq!(parts[0].bone_index, 3);
+        assert_eq!(parts[0].dummy_index, -1);
+        let collision: u16 = parts[0].collision.into();
+        assert_eq!(collision, 11);
+        assert_eq!(
+            zsc.objects[2].effects[0].effect_type,
+            SceneEffectType::DayNight
+        );
+    }
+
+    #[test]
+    fn test_bounds() {
+        let object = parse(TXT).unwrap();
+
+        // Every mesh is a 2x2x2 cube around its origin
+        let mut zsc = ZSC::default();
+        compile(&mut zsc, 0, &object, |_| {
+            Some(BoundingBox {
+                min: Vector3 {
+                    x: -1.0,
+                    y: -1.0,
+                    z: -1.0,
+                },
+                max: Vector3 {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+            })
+        })
+        .unwrap();
+
+        // The leaf is scaled by 2 and placed 10 above the trunk
+        let object = &zsc.objects[0];
+        assert_eq!(
+            object.bounding_box.min,
+            Vector3 {
+                x: -1.0,
+                y: 0.0,
+                z: 2.0
+            }
+        );
+        assert_eq!(
+            object.bounding_box.max,
+            Vector3 {
+                x: 3.0,
+                y: 4.0,
+                z: 15.0
+            }
+        );
+        assert_eq!(object.bounding_cylinder.center, Vector2 { x: 1, y: 2 });
+        assert!((object.bounding_cylinder.radius - 8f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_export_roundtrip() {
+        let object = parse(TXT).unwrap();
+
+        let mut zsc = ZSC::default();
+        compile(&mut zsc, 0, &object, |_| None).unwrap();
+
+        let txt = export(&zsc, 0, true, true).unwrap();
+        assert!(txt.contains("\ttype 1\r\n"));
+
+        let mut new_zsc = ZSC::default();
+        compile(&mut new_zsc, 0, &parse(&txt).unwrap(), |_| None).unwrap();
+        assert_eq!(new_zsc, zsc);
+
+        assert!(export(&zsc, 1, true, true).is_err());
+    }
+}